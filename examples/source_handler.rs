@@ -0,0 +1,58 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Handler written against `SnowflakeSource`, so a fixed one can be injected in tests.
+
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc,
+};
+
+use snowflake_ng::{
+    provider::StdProvider, PersistedSnowflakeGenerator, Snowflake, SnowflakeGenerator,
+    SnowflakeSource,
+};
+
+struct UserService {
+    ids: Arc<dyn SnowflakeSource>,
+}
+
+impl UserService {
+    fn create_user(&self, name: &str) -> String {
+        format!("user {} is {name}", *self.ids.assign_sync())
+    }
+}
+
+/// Handing out 1, 2, 3... for tests.
+struct FixedSource(AtomicI64);
+
+impl SnowflakeSource for FixedSource {
+    #[cfg(feature = "async")]
+    fn assign(&self) -> futures::future::BoxFuture<'_, Snowflake> {
+        Box::pin(async { self.assign_sync() })
+    }
+
+    fn assign_sync(&self) -> Snowflake {
+        let bits = self.0.fetch_add(1, Ordering::Relaxed) + 1;
+        Snowflake::try_from(snowflake_ng::SnowflakeU64::from(bits as u64)).unwrap()
+    }
+}
+
+fn main() {
+    let production = UserService {
+        ids: Arc::new(PersistedSnowflakeGenerator::new(
+            Arc::new(SnowflakeGenerator::default()),
+            Arc::new(StdProvider),
+        )),
+    };
+    println!("{}", production.create_user("alice"));
+
+    let test = UserService {
+        ids: Arc::new(FixedSource(AtomicI64::new(0))),
+    };
+    assert_eq!(test.create_user("bob"), "user 1 is bob");
+}
@@ -0,0 +1,26 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Minimal build without any async machinery:
+//!
+//! ```sh
+//! cargo run --example sync_only --no-default-features --features sync
+//! ```
+
+use snowflake_ng::{provider::STD_PROVIDER, SnowflakeGenerator};
+
+fn main() {
+    let generator = SnowflakeGenerator::default();
+
+    // Exhausting sequence just blocks current thread for a while, no executor needed.
+    let snowflakes = generator.assign_many_sync(&STD_PROVIDER, 10_000);
+    assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+
+    for snowflake in generator.iter_sync(&STD_PROVIDER).take(3) {
+        println!("{snowflake:?}");
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::ops::Range;
+
+use crate::Snowflake;
+
+/// Contiguous run of [`Snowflake`](Snowflake) reserved by [`SnowflakeGenerator::reserve_block`](crate::SnowflakeGenerator::reserve_block).
+///
+/// All of them share the same timestamp and identifier, with consecutive sequence numbers,
+/// so `n`-th one is always `start + n`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SnowflakeBlock {
+    start: i64,
+    len: u16,
+}
+
+impl SnowflakeBlock {
+    pub(crate) fn new(start: i64, len: u16) -> Self {
+        Self { start, len }
+    }
+
+    /// The first [`Snowflake`](Snowflake) of this block.
+    pub fn start(&self) -> Snowflake {
+        Snowflake(self.start)
+    }
+
+    /// How many [`Snowflake`](Snowflake) in this block.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Whether this block is empty, it never is when reserved by generator.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// `index`-th [`Snowflake`](Snowflake) of this block.
+    pub fn get(&self, index: usize) -> Option<Snowflake> {
+        (index < self.len()).then(|| Snowflake(self.start + index as i64))
+    }
+
+    /// Whether `snowflake` belongs to this block.
+    pub fn contains(&self, snowflake: &Snowflake) -> bool {
+        self.range().contains(&snowflake.0)
+    }
+
+    /// Iterating all [`Snowflake`](Snowflake) of this block in increasing order.
+    pub fn iter(&self) -> SnowflakeBlockIter {
+        SnowflakeBlockIter(self.range())
+    }
+
+    fn range(&self) -> Range<i64> {
+        self.start..self.start + self.len as i64
+    }
+}
+
+impl IntoIterator for SnowflakeBlock {
+    type Item = Snowflake;
+    type IntoIter = SnowflakeBlockIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for &SnowflakeBlock {
+    type Item = Snowflake;
+    type IntoIter = SnowflakeBlockIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator of [`SnowflakeBlock`](SnowflakeBlock).
+#[derive(Debug, Clone)]
+pub struct SnowflakeBlockIter(Range<i64>);
+
+impl Iterator for SnowflakeBlockIter {
+    type Item = Snowflake;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Snowflake)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for SnowflakeBlockIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(Snowflake)
+    }
+}
+
+impl ExactSizeIterator for SnowflakeBlockIter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block() {
+        let block = SnowflakeBlock::new(100, 3);
+        assert_eq!(block.len(), 3);
+        assert!(!block.is_empty());
+        assert_eq!(block.start(), Snowflake(100));
+        assert_eq!(block.get(2), Some(Snowflake(102)));
+        assert_eq!(block.get(3), None);
+        assert!(block.contains(&Snowflake(101)));
+        assert!(!block.contains(&Snowflake(103)));
+        assert_eq!(
+            block.iter().rev().collect::<Vec<_>>(),
+            vec![Snowflake(102), Snowflake(101), Snowflake(100)]
+        );
+        assert_eq!(block.into_iter().len(), 3);
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::{
+    GeneratorStats, PersistedSnowflakeGenerator, Sleeper, Snowflake, SnowflakeError, TimeProvider,
+    TimerSleeper,
+};
+
+/// [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) letting at most `max_concurrent` assignments in at once.
+///
+/// With tens of thousands of tasks assigning at once, racing on the state itself is what slows them down,
+/// so the rest wait for a permit of a Tokio semaphore, in FIFO order. It works in any async runtime.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use snowflake_ng::{provider::StdProvider, BoundedSnowflakeGenerator, PersistedSnowflakeGenerator, SnowflakeGenerator};
+/// # futures::executor::block_on(async {
+/// let persisted = PersistedSnowflakeGenerator::new(Arc::new(SnowflakeGenerator::default()), Arc::new(StdProvider));
+/// let bounded = BoundedSnowflakeGenerator::new(persisted, 8);
+///
+/// let snowflake = bounded.assign().await;
+/// assert_eq!(bounded.stats().available_permits, 8);
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct BoundedSnowflakeGenerator<T, S = TimerSleeper> {
+    generator: PersistedSnowflakeGenerator<T, S>,
+    permits: Semaphore,
+    max_concurrent: usize,
+    active: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+/// Statistics of [`BoundedSnowflakeGenerator`](BoundedSnowflakeGenerator).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoundedStats {
+    /// See [`SnowflakeGenerator::stats`](crate::SnowflakeGenerator::stats).
+    pub generator: GeneratorStats,
+    /// How many assignments are let in at once.
+    pub max_concurrent: usize,
+    /// Permits not taken right now.
+    pub available_permits: usize,
+    /// Most assignments ever in at once.
+    pub peak_concurrent: usize,
+}
+
+impl<T, S> BoundedSnowflakeGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+{
+    /// Bounding `generator` to `max_concurrent` assignments at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrent` is 0 or exceeds [`Semaphore::MAX_PERMITS`](Semaphore::MAX_PERMITS).
+    pub fn new(generator: PersistedSnowflakeGenerator<T, S>, max_concurrent: usize) -> Self {
+        assert!(max_concurrent > 0, "`max_concurrent` must be positive");
+
+        Self {
+            generator,
+            permits: Semaphore::new(max_concurrent),
+            max_concurrent,
+            active: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Assign a [`Snowflake`](Snowflake), waiting for a permit first.
+    pub async fn assign(&self) -> Snowflake
+    where
+        S: Sleeper,
+    {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        let _entered = self.enter(permit);
+        self.generator.assign().await
+    }
+
+    /// Assign a [`Snowflake`](Snowflake) if a permit is available, or returning [`SnowflakeError::ConcurrencyLimited`](SnowflakeError::ConcurrencyLimited).
+    ///
+    /// Otherwise it's [`PersistedSnowflakeGenerator::try_assign`](PersistedSnowflakeGenerator::try_assign).
+    pub async fn try_assign(&self) -> Result<Snowflake, SnowflakeError>
+    where
+        S: Sleeper,
+    {
+        let permit =
+            self.permits
+                .try_acquire()
+                .map_err(|_| SnowflakeError::ConcurrencyLimited {
+                    max_concurrent: self.max_concurrent,
+                })?;
+        let _entered = self.enter(permit);
+        self.generator.try_assign().await
+    }
+
+    /// Statistics of this generator, including permits.
+    pub fn stats(&self) -> BoundedStats {
+        BoundedStats {
+            generator: self.generator.generator.stats(),
+            max_concurrent: self.max_concurrent,
+            available_permits: self.permits.available_permits(),
+            peak_concurrent: self.peak.load(Ordering::Relaxed),
+        }
+    }
+
+    fn enter<'a>(&'a self, permit: SemaphorePermit<'a>) -> Entered<'a> {
+        let active = self.active.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak.fetch_max(active, Ordering::Relaxed);
+        Entered {
+            active: &self.active,
+            _permit: permit,
+        }
+    }
+}
+
+/// One assignment let in, giving the permit back once dropped.
+struct Entered<'a> {
+    active: &'a AtomicUsize,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use super::*;
+    use crate::{provider::StdProvider, SnowflakeConfiguration, SnowflakeGenerator};
+
+    /// Recording how many read the clock at once, which is inside the CAS loop.
+    #[derive(Default)]
+    struct CountingTestProvider {
+        inside: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl TimeProvider for CountingTestProvider {
+        fn timestamp(&self) -> u64 {
+            let inside = self.inside.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(inside, Ordering::SeqCst);
+            let timestamp = StdProvider.timestamp();
+            self.inside.fetch_sub(1, Ordering::SeqCst);
+            timestamp
+        }
+    }
+
+    fn bounded(max_concurrent: usize) -> BoundedSnowflakeGenerator<CountingTestProvider> {
+        BoundedSnowflakeGenerator::new(
+            PersistedSnowflakeGenerator::new(
+                Arc::new(SnowflakeGenerator::with_cfg(
+                    SnowflakeConfiguration::with_identifier(3),
+                )),
+                Arc::new(CountingTestProvider::default()),
+            ),
+            max_concurrent,
+        )
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_bounded_concurrent() {
+        let bounded = Arc::new(bounded(4));
+        let tasks = (0..2000)
+            .map(|_| {
+                let bounded = bounded.clone();
+                tokio::spawn(async move {
+                    let mut snowflakes = Vec::new();
+                    for _ in 0..20 {
+                        snowflakes.push(bounded.assign().await);
+                    }
+                    snowflakes
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut snowflakes = HashSet::new();
+        for task in tasks {
+            for snowflake in task.await.unwrap() {
+                assert!(snowflakes.insert(snowflake));
+            }
+        }
+        assert_eq!(snowflakes.len(), 40_000);
+
+        let stats = bounded.stats();
+        assert_eq!(stats.generator.issued, 40_000);
+        assert_eq!(stats.max_concurrent, 4);
+        assert_eq!(stats.available_permits, 4);
+        assert!((1..=4).contains(&stats.peak_concurrent));
+        assert!(bounded.generator.provider.peak.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_bounded_try_assign() {
+        let bounded = bounded(2);
+        let held = bounded.permits.try_acquire_many(2).unwrap();
+        assert_eq!(bounded.stats().available_permits, 0);
+        assert_eq!(
+            bounded.try_assign().await,
+            Err(SnowflakeError::ConcurrencyLimited { max_concurrent: 2 })
+        );
+
+        drop(held);
+        assert!(bounded.try_assign().await.is_ok());
+        assert_eq!(bounded.stats().available_permits, 2);
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+
+use crate::{PersistedSnowflakeGenerator, Sleeper, Snowflake, TimeProvider, TimerSleeper};
+
+/// How long buffered [`Snowflake`](Snowflake) stay good by default, see [`BufferedSnowflakeGenerator::with_max_staleness`](BufferedSnowflakeGenerator::with_max_staleness).
+const DEFAULT_MAX_STALENESS: Duration = Duration::from_millis(100);
+
+/// [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) with [`Snowflake`](Snowflake) generated ahead of time by a background task.
+///
+/// Buffer is topped up to `capacity` all the time, so a burst of consumers doesn't pay the generation inline.
+/// Once the buffer is drained, it falls back to assigning directly, so it never waits for the background task.
+///
+/// [`Snowflake`](Snowflake) buffered longer than [`max_staleness`](BufferedSnowflakeGenerator::with_max_staleness) are thrown away,
+/// so their timestamps never lag far behind the time they are consumed.
+/// They are unique with the others, but not increasing in the order of consuming.
+///
+/// Dropping it aborts the background task.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use snowflake_ng::{provider::StdProvider, BufferedSnowflakeGenerator, PersistedSnowflakeGenerator, SnowflakeGenerator};
+/// # #[tokio::main]
+/// # async fn main() {
+/// let persisted = PersistedSnowflakeGenerator::new(Arc::new(SnowflakeGenerator::default()), Arc::new(StdProvider));
+/// let buffered = BufferedSnowflakeGenerator::spawn(persisted, 1024);
+///
+/// let snowflake = buffered.next().await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BufferedSnowflakeGenerator<T, S = TimerSleeper> {
+    generator: PersistedSnowflakeGenerator<T, S>,
+    buffer: Mutex<mpsc::Receiver<(Snowflake, Instant)>>,
+    max_staleness: Duration,
+    task: JoinHandle<()>,
+}
+
+impl<T, S> BufferedSnowflakeGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync + 'static,
+    S: Sleeper + 'static,
+{
+    /// Spawning the background task on current Tokio runtime, buffering up to `capacity` [`Snowflake`](Snowflake).
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of Tokio runtime, or `capacity` is 0.
+    pub fn spawn(generator: PersistedSnowflakeGenerator<T, S>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let task = tokio::spawn({
+            let generator = generator.clone();
+            async move {
+                // Only generate when there's room, so buffered ones start fresh.
+                while let Ok(permit) = sender.reserve().await {
+                    permit.send((generator.assign().await, Instant::now()));
+                }
+            }
+        });
+
+        Self {
+            generator,
+            buffer: Mutex::new(receiver),
+            max_staleness: DEFAULT_MAX_STALENESS,
+            task,
+        }
+    }
+}
+
+impl<T, S> BufferedSnowflakeGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+    S: Sleeper,
+{
+    /// Throw away buffered [`Snowflake`](Snowflake) older than `max_staleness`, 100 milliseconds by default.
+    pub fn with_max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
+
+    /// Take a [`Snowflake`](Snowflake) from the buffer, or assign one directly if the buffer is drained.
+    pub async fn next(&self) -> Snowflake {
+        if let Some(snowflake) = self.pop() {
+            return snowflake;
+        }
+
+        self.generator.assign().await
+    }
+
+    /// How many [`Snowflake`](Snowflake) in the buffer right now, including stale ones.
+    pub fn buffered(&self) -> usize {
+        self.receiver().len()
+    }
+
+    fn pop(&self) -> Option<Snowflake> {
+        let mut receiver = self.receiver();
+        while let Ok((snowflake, generated_at)) = receiver.try_recv() {
+            if generated_at.elapsed() <= self.max_staleness {
+                return Some(snowflake);
+            }
+        }
+
+        None
+    }
+
+    fn receiver(&self) -> std::sync::MutexGuard<'_, mpsc::Receiver<(Snowflake, Instant)>> {
+        self.buffer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T, S> Drop for BufferedSnowflakeGenerator<T, S> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use crate::{provider::StdProvider, SnowflakeGenerator};
+
+    use super::*;
+
+    fn persisted() -> PersistedSnowflakeGenerator<StdProvider> {
+        PersistedSnowflakeGenerator::new(
+            Arc::new(SnowflakeGenerator::default()),
+            Arc::new(StdProvider),
+        )
+    }
+
+    async fn filled<T, S>(buffered: &BufferedSnowflakeGenerator<T, S>, capacity: usize)
+    where
+        T: TimeProvider + Send + Sync,
+        S: Sleeper,
+    {
+        while buffered.buffered() < capacity {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buffered() {
+        let buffered =
+            BufferedSnowflakeGenerator::spawn(persisted(), 64).with_max_staleness(Duration::MAX);
+        filled(&buffered, 64).await;
+
+        let mut snowflakes = HashSet::new();
+        for _ in 0..10_000 {
+            assert!(snowflakes.insert(buffered.next().await));
+        }
+
+        // Drained by the burst, then topped up again.
+        filled(&buffered, 64).await;
+        for _ in 0..64 {
+            assert!(snowflakes.insert(buffered.next().await));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_buffered_stale() {
+        let buffered = BufferedSnowflakeGenerator::spawn(persisted(), 64)
+            .with_max_staleness(Duration::from_millis(10));
+        filled(&buffered, 64).await;
+
+        tokio::time::advance(Duration::from_millis(20)).await;
+        buffered.next().await;
+        assert_eq!(buffered.buffered(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_buffered_drop() {
+        let persisted = persisted();
+        let buffered = BufferedSnowflakeGenerator::spawn(persisted.clone(), 64);
+        filled(&buffered, 64).await;
+        assert_eq!(Arc::strong_count(&persisted.generator), 3);
+
+        drop(buffered);
+        while Arc::strong_count(&persisted.generator) > 1 {
+            tokio::task::yield_now().await;
+        }
+    }
+}
@@ -0,0 +1,372 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{sync::Arc, time::Duration};
+
+use rand::RngCore;
+
+use crate::{
+    layouts, OverflowPolicy, PersistedSnowflakeGenerator, RollbackPolicy, SnowflakeConfiguration,
+    SnowflakeError, SnowflakeGenerator, SnowflakeLayout, TimeProvider, TimestampValidation,
+    WaitStrategy,
+};
+
+/// Builder of [`SnowflakeGenerator`](SnowflakeGenerator), see [`SnowflakeGenerator::builder`](SnowflakeGenerator::builder).
+///
+/// All validation happens in [`SnowflakeGeneratorBuilder::build`](SnowflakeGeneratorBuilder::build), so setters can be called in any order.
+///
+/// # Clone
+///
+/// Builder is cheap to clone (provider is shared by [`Arc`](Arc)), so a base template can stamp out generators
+/// which differ only in identifier:
+///
+/// ```
+/// # use snowflake_ng::SnowflakeGenerator;
+/// let template = SnowflakeGenerator::builder().epoch(1577836800000);
+///
+/// let shards = (0..4)
+///     .map(|it| template.clone().identifier(it).build())
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SnowflakeGeneratorBuilder<P = ()> {
+    identifier: Option<u64>,
+    epoch: u64,
+    layout: SnowflakeLayout,
+    time_unit: Duration,
+    rollback_tolerance: Duration,
+    rollback_policy: RollbackPolicy,
+    overflow_policy: OverflowPolicy,
+    wait_strategy: WaitStrategy,
+    max_backoff: Duration,
+    backoff_jitter: bool,
+    cas_attempts: u32,
+    timestamp_validation: TimestampValidation,
+    randomize_sequence_start: bool,
+    random_sequence: bool,
+    provider: P,
+}
+
+impl SnowflakeGeneratorBuilder {
+    pub(crate) fn new() -> Self {
+        Self {
+            identifier: None,
+            epoch: 0,
+            layout: layouts::DEFAULT,
+            time_unit: Duration::from_millis(1),
+            rollback_tolerance: Duration::from_millis(5),
+            rollback_policy: RollbackPolicy::Error,
+            overflow_policy: OverflowPolicy::WaitNextMillis,
+            wait_strategy: WaitStrategy::Sleep,
+            max_backoff: Duration::from_millis(250),
+            backoff_jitter: true,
+            cas_attempts: 16,
+            timestamp_validation: TimestampValidation::Window,
+            randomize_sequence_start: false,
+            random_sequence: false,
+            provider: (),
+        }
+    }
+
+    /// Use `provider` as the [`TimeProvider`](TimeProvider), so [`SnowflakeGeneratorBuilder::build`](SnowflakeGeneratorBuilder::build)
+    /// produces [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator).
+    pub fn provider<T>(self, provider: T) -> SnowflakeGeneratorBuilder<Arc<T>>
+    where
+        T: TimeProvider + Send + Sync,
+    {
+        SnowflakeGeneratorBuilder {
+            identifier: self.identifier,
+            epoch: self.epoch,
+            layout: self.layout,
+            time_unit: self.time_unit,
+            rollback_tolerance: self.rollback_tolerance,
+            rollback_policy: self.rollback_policy,
+            overflow_policy: self.overflow_policy,
+            wait_strategy: self.wait_strategy,
+            max_backoff: self.max_backoff,
+            backoff_jitter: self.backoff_jitter,
+            cas_attempts: self.cas_attempts,
+            timestamp_validation: self.timestamp_validation,
+            randomize_sequence_start: self.randomize_sequence_start,
+            random_sequence: self.random_sequence,
+            provider: Arc::new(provider),
+        }
+    }
+
+    /// Build the [`SnowflakeGenerator`](SnowflakeGenerator).
+    pub fn build(self) -> Result<SnowflakeGenerator, SnowflakeError> {
+        SnowflakeGenerator::try_with_cfg(self.cfg())
+    }
+}
+
+impl<T> SnowflakeGeneratorBuilder<Arc<T>>
+where
+    T: TimeProvider + Send + Sync,
+{
+    /// Build the [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator).
+    pub fn build(self) -> Result<PersistedSnowflakeGenerator<T>, SnowflakeError> {
+        let generator = SnowflakeGenerator::try_with_cfg(self.cfg())?;
+        Ok(PersistedSnowflakeGenerator::new(
+            Arc::new(generator),
+            self.provider,
+        ))
+    }
+
+    /// Same as [`SnowflakeGeneratorBuilder::build`](SnowflakeGeneratorBuilder::build), but continuing from state word `state`
+    /// left by an earlier generator of the same identifier.
+    pub(crate) fn build_resumed(
+        self,
+        state: u64,
+    ) -> Result<PersistedSnowflakeGenerator<T>, SnowflakeError> {
+        let mut generator = SnowflakeGenerator::try_with_cfg(self.cfg())?;
+        if state != 0 {
+            generator.resume(state);
+        }
+        Ok(PersistedSnowflakeGenerator::new(
+            Arc::new(generator),
+            self.provider,
+        ))
+    }
+}
+
+impl<P> SnowflakeGeneratorBuilder<P> {
+    /// See [`SnowflakeConfiguration::identifier`](SnowflakeConfiguration::identifier).
+    ///
+    /// Unlike [`SnowflakeConfiguration::with_identifier`](SnowflakeConfiguration::with_identifier), `identifier` out of layout will be rejected.
+    ///
+    /// By default, identifier is randomly chosen for every built generator.
+    pub fn identifier(self, identifier: u64) -> Self {
+        Self {
+            identifier: Some(identifier),
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::epoch`](SnowflakeConfiguration::epoch).
+    pub fn epoch(self, epoch: u64) -> Self {
+        Self { epoch, ..self }
+    }
+
+    /// See [`SnowflakeConfiguration::layout`](SnowflakeConfiguration::layout).
+    pub fn layout(self, layout: SnowflakeLayout) -> Self {
+        Self { layout, ..self }
+    }
+
+    /// See [`SnowflakeConfiguration::time_unit`](SnowflakeConfiguration::time_unit).
+    pub fn time_unit(self, time_unit: Duration) -> Self {
+        Self { time_unit, ..self }
+    }
+
+    /// See [`SnowflakeConfiguration::rollback_tolerance`](SnowflakeConfiguration::rollback_tolerance).
+    pub fn rollback_tolerance(self, rollback_tolerance: Duration) -> Self {
+        Self {
+            rollback_tolerance,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::rollback_policy`](SnowflakeConfiguration::rollback_policy).
+    pub fn rollback_policy(self, rollback_policy: RollbackPolicy) -> Self {
+        Self {
+            rollback_policy,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::overflow_policy`](SnowflakeConfiguration::overflow_policy).
+    pub fn overflow_policy(self, overflow_policy: OverflowPolicy) -> Self {
+        Self {
+            overflow_policy,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::wait_strategy`](SnowflakeConfiguration::wait_strategy).
+    pub fn wait_strategy(self, wait_strategy: WaitStrategy) -> Self {
+        Self {
+            wait_strategy,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::max_backoff`](SnowflakeConfiguration::max_backoff).
+    pub fn max_backoff(self, max_backoff: Duration) -> Self {
+        Self {
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::backoff_jitter`](SnowflakeConfiguration::backoff_jitter).
+    pub fn backoff_jitter(self, backoff_jitter: bool) -> Self {
+        Self {
+            backoff_jitter,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::cas_attempts`](SnowflakeConfiguration::cas_attempts).
+    pub fn cas_attempts(self, cas_attempts: u32) -> Self {
+        Self {
+            cas_attempts,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::timestamp_validation`](SnowflakeConfiguration::timestamp_validation).
+    pub fn timestamp_validation(self, timestamp_validation: TimestampValidation) -> Self {
+        Self {
+            timestamp_validation,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::randomize_sequence_start`](SnowflakeConfiguration::randomize_sequence_start).
+    pub fn randomize_sequence_start(self, randomize_sequence_start: bool) -> Self {
+        Self {
+            randomize_sequence_start,
+            ..self
+        }
+    }
+
+    /// See [`SnowflakeConfiguration::random_sequence`](SnowflakeConfiguration::random_sequence).
+    pub fn random_sequence(self, random_sequence: bool) -> Self {
+        Self {
+            random_sequence,
+            ..self
+        }
+    }
+
+    pub(crate) fn cfg(&self) -> SnowflakeConfiguration {
+        let identifier = self
+            .identifier
+            .unwrap_or_else(|| rand::thread_rng().next_u64() & self.layout.max_identifier());
+
+        SnowflakeConfiguration::with_identifier(identifier)
+            .with_epoch(self.epoch)
+            .with_layout(self.layout)
+            .with_time_unit(self.time_unit)
+            .with_rollback_tolerance(self.rollback_tolerance)
+            .with_rollback_policy(self.rollback_policy)
+            .with_overflow_policy(self.overflow_policy)
+            .with_wait_strategy(self.wait_strategy)
+            .with_max_backoff(self.max_backoff)
+            .with_backoff_jitter(self.backoff_jitter)
+            .with_cas_attempts(self.cas_attempts)
+            .with_timestamp_validation(self.timestamp_validation)
+            .with_randomize_sequence_start(self.randomize_sequence_start)
+            .with_random_sequence(self.random_sequence)
+    }
+}
+
+impl<P: Clone> Clone for SnowflakeGeneratorBuilder<P> {
+    fn clone(&self) -> Self {
+        Self {
+            identifier: self.identifier,
+            epoch: self.epoch,
+            layout: self.layout,
+            time_unit: self.time_unit,
+            rollback_tolerance: self.rollback_tolerance,
+            rollback_policy: self.rollback_policy,
+            overflow_policy: self.overflow_policy,
+            wait_strategy: self.wait_strategy,
+            max_backoff: self.max_backoff,
+            backoff_jitter: self.backoff_jitter,
+            cas_attempts: self.cas_attempts,
+            timestamp_validation: self.timestamp_validation,
+            randomize_sequence_start: self.randomize_sequence_start,
+            random_sequence: self.random_sequence,
+            provider: self.provider.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::provider::{StdProvider, STD_PROVIDER};
+
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let layout = SnowflakeLayout::new(44, 6, 13).unwrap();
+        let generator = SnowflakeGenerator::builder()
+            .identifier(63)
+            .epoch(1577836800000)
+            .layout(layout)
+            .build()
+            .unwrap();
+
+        assert_eq!(generator.identifier(), 63);
+        assert_eq!(generator.config().epoch, 1577836800000);
+        assert_eq!(generator.config().layout, layout);
+
+        let snowflake = generator.assign_sync(&STD_PROVIDER);
+        assert_eq!(layout.identifier_of(&snowflake), 63);
+    }
+
+    #[test]
+    fn test_build_random_identifier() {
+        let layout = SnowflakeLayout::new(56, 2, 5).unwrap();
+        let template = SnowflakeGenerator::builder().layout(layout);
+
+        for _ in 0..100 {
+            assert!(template.clone().build().unwrap().identifier() <= 3);
+        }
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        assert_eq!(
+            SnowflakeGenerator::builder()
+                .identifier(1024)
+                .build()
+                .unwrap_err(),
+            SnowflakeError::IdentifierOutOfRange {
+                identifier: 1024,
+                max: 1023
+            }
+        );
+
+        // Validation doesn't depend on the order of setters.
+        let layout = SnowflakeLayout::new(44, 6, 13).unwrap();
+        assert_eq!(
+            SnowflakeGenerator::builder()
+                .identifier(100)
+                .layout(layout)
+                .build()
+                .unwrap_err(),
+            SnowflakeError::IdentifierOutOfRange {
+                identifier: 100,
+                max: 63
+            }
+        );
+        assert!(SnowflakeGenerator::builder()
+            .identifier(100)
+            .provider(StdProvider)
+            .layout(layout)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_persisted() {
+        let template = SnowflakeGenerator::builder()
+            .epoch(1577836800000)
+            .provider(StdProvider);
+
+        let persisted = (0..4)
+            .map(|it| template.clone().identifier(it).build().unwrap())
+            .collect::<Vec<_>>();
+
+        for (identifier, generator) in persisted.iter().enumerate() {
+            assert_eq!(generator.identifier(), identifier as u64);
+            assert_eq!(generator.assign_sync().identifier(), identifier as u64);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::future::BoxFuture;
+use tokio::{task::JoinHandle, time::Instant};
+
+use crate::{GeneratorEvent, IdError, SnowflakeGenerator};
+
+/// The latest heartbeat of `instance`, at `timestamp` in milliseconds since UNIX epoch of its own clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Heartbeat {
+    pub instance: u128,
+    pub timestamp: u64,
+}
+
+/// Where [`CollisionDetector`](CollisionDetector) heartbeats, like [`RedisHeartbeatStore`](crate::RedisHeartbeatStore)
+/// or [`SqlHeartbeatStore`](crate::SqlHeartbeatStore).
+pub trait HeartbeatStore: Send + Sync + 'static {
+    /// Record `heartbeat` on `identifier`, returning the latest ones of every instance on it, including itself.
+    fn beat(
+        &self,
+        identifier: u64,
+        heartbeat: Heartbeat,
+    ) -> BoxFuture<'_, Result<Vec<Heartbeat>, IdError>>;
+}
+
+/// Watchdog heartbeating an identifier, noticing other live instances using the same one.
+///
+/// Every `interval` (10 seconds by default) it writes a heartbeat of this instance and reads the others.
+/// Timestamps of others are never compared with the local clock, so clock skew between nodes doesn't matter:
+/// another instance is live once its heartbeat changed from an earlier round, and until it stays the same
+/// for three intervals. Leftovers of crashed or previous instances are therefore ignored.
+///
+/// Each other instance getting live is reported once as [`GeneratorEvent::IdentifierConflict`](GeneratorEvent::IdentifierConflict)
+/// to hooks of the generator, see [`SnowflakeGenerator::on_event`](SnowflakeGenerator::on_event).
+///
+/// ```no_run
+/// # use snowflake_ng::{CollisionDetector, GeneratorEvent, HeartbeatStore, SnowflakeGenerator};
+/// # async fn run(store: impl HeartbeatStore) {
+/// let generator = SnowflakeGenerator::default();
+/// generator.on_event(|event| {
+///     if let GeneratorEvent::IdentifierConflict { other_instance } = event {
+///         eprintln!("identifier is also used by {other_instance:032x}");
+///     }
+/// });
+///
+/// // Like `RedisHeartbeatStore` or `SqlHeartbeatStore`.
+/// CollisionDetector::new(store, generator.identifier(), rand::random()).spawn(&generator);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CollisionDetector<H> {
+    store: H,
+    identifier: u64,
+    instance: u128,
+    interval: Duration,
+    /// Heartbeats of this instance always change, even within a millisecond or with the clock stepped back.
+    last_timestamp: u64,
+    /// Heartbeats of others last read, and when they were seen changing.
+    seen: HashMap<u128, (u64, Option<Instant>)>,
+    reported: HashSet<u128>,
+}
+
+impl<H> CollisionDetector<H>
+where
+    H: HeartbeatStore,
+{
+    /// Heartbeating `identifier` as `instance_uuid` to `store`, which should be unique to every process.
+    pub fn new(store: H, identifier: u64, instance_uuid: u128) -> Self {
+        Self {
+            store,
+            identifier,
+            instance: instance_uuid,
+            interval: Duration::from_secs(10),
+            last_timestamp: 0,
+            seen: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Heartbeat every `interval` instead, which should be the same across the fleet.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Heartbeat in a Tokio task until aborted, reporting conflicts to hooks of `generator`.
+    ///
+    /// Failing to heartbeat is skipped, and retried in the next round.
+    pub fn spawn<S>(mut self, generator: &SnowflakeGenerator<S>) -> JoinHandle<()> {
+        let emitter = generator.hooks.emitter();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(self.interval);
+            loop {
+                ticks.tick().await;
+                for other_instance in self.round().await.unwrap_or_default() {
+                    emitter.emit(GeneratorEvent::IdentifierConflict { other_instance });
+                }
+            }
+        })
+    }
+
+    /// Heartbeat once, returning others just getting live.
+    async fn round(&mut self) -> Result<Vec<u128>, IdError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.last_timestamp = timestamp.max(self.last_timestamp.saturating_add(1));
+        let heartbeat = Heartbeat {
+            instance: self.instance,
+            timestamp: self.last_timestamp,
+        };
+        let heartbeats = self.store.beat(self.identifier, heartbeat).await?;
+
+        let now = Instant::now();
+        let mut seen = HashMap::new();
+        for heartbeat in heartbeats {
+            if heartbeat.instance == self.instance {
+                continue;
+            }
+            let changed = match self.seen.get(&heartbeat.instance) {
+                Some((timestamp, _)) if *timestamp != heartbeat.timestamp => Some(now),
+                Some((_, changed)) => *changed,
+                // Nothing to tell from the first sight.
+                None => None,
+            };
+            seen.insert(heartbeat.instance, (heartbeat.timestamp, changed));
+        }
+        self.seen = seen;
+
+        let window = self.interval * 3;
+        let live = self
+            .seen
+            .iter()
+            .filter(|(_, (_, changed))| changed.is_some_and(|it| now - it < window))
+            .map(|(instance, _)| *instance)
+            .collect::<HashSet<_>>();
+        let mut conflicts = live.difference(&self.reported).copied().collect::<Vec<_>>();
+        conflicts.sort();
+        self.reported = live;
+        Ok(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Heartbeats of every identifier and instance.
+    #[derive(Clone, Default)]
+    struct Fake(Arc<Mutex<HashMap<(u64, u128), u64>>>);
+
+    impl HeartbeatStore for Fake {
+        fn beat(
+            &self,
+            identifier: u64,
+            heartbeat: Heartbeat,
+        ) -> BoxFuture<'_, Result<Vec<Heartbeat>, IdError>> {
+            Box::pin(async move {
+                let mut heartbeats = self.0.lock().unwrap();
+                heartbeats.insert((identifier, heartbeat.instance), heartbeat.timestamp);
+                Ok(heartbeats
+                    .iter()
+                    .filter(|((it, _), _)| *it == identifier)
+                    .map(|((_, instance), timestamp)| Heartbeat {
+                        instance: *instance,
+                        timestamp: *timestamp,
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    async fn rounds(detectors: &mut [&mut CollisionDetector<Fake>]) -> Vec<Vec<u128>> {
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let mut conflicts = Vec::new();
+        for detector in detectors {
+            conflicts.push(detector.round().await.unwrap());
+        }
+        conflicts
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_single_owner() {
+        let store = Fake::default();
+        // Leftover of a crashed one, even far ahead of this clock.
+        store.0.lock().unwrap().insert((7, 1), u64::MAX);
+
+        let mut detector = CollisionDetector::new(store.clone(), 7, 2);
+        let mut neighbor = CollisionDetector::new(store.clone(), 8, 3);
+        for _ in 0..5 {
+            assert_eq!(
+                rounds(&mut [&mut detector, &mut neighbor]).await,
+                [vec![], vec![]]
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_conflict() {
+        let store = Fake::default();
+        let mut first = CollisionDetector::new(store.clone(), 7, 1);
+        let mut second = CollisionDetector::new(store.clone(), 7, 2);
+
+        // Each sees the other first, then changing.
+        assert_eq!(rounds(&mut [&mut first]).await, [vec![]]);
+        assert_eq!(
+            rounds(&mut [&mut first, &mut second]).await,
+            [vec![], vec![]]
+        );
+        assert_eq!(
+            rounds(&mut [&mut first, &mut second]).await,
+            [vec![], vec![1]]
+        );
+        assert_eq!(
+            rounds(&mut [&mut first, &mut second]).await,
+            [vec![2], vec![]]
+        );
+        // Reported once.
+        assert_eq!(
+            rounds(&mut [&mut first, &mut second]).await,
+            [vec![], vec![]]
+        );
+
+        // Gone quiet, then back again.
+        for _ in 0..4 {
+            rounds(&mut [&mut first]).await;
+        }
+        assert!(first.reported.is_empty());
+        rounds(&mut [&mut second]).await;
+        assert_eq!(rounds(&mut [&mut first]).await, [vec![2]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spawn() {
+        let generator = SnowflakeGenerator::default();
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        // Registered after spawning still counts.
+        let store = Fake::default();
+        let first = CollisionDetector::new(store.clone(), 7, 1).spawn(&generator);
+        let second = CollisionDetector::new(store.clone(), 7, 2).spawn(&generator);
+        generator.on_event({
+            let reported = reported.clone();
+            move |event| reported.lock().unwrap().push(event)
+        });
+
+        tokio::time::sleep(Duration::from_secs(35)).await;
+        first.abort();
+        second.abort();
+        let mut reported = reported.lock().unwrap().clone();
+        reported.sort_by_key(|it| format!("{it:?}"));
+        assert_eq!(
+            reported,
+            [
+                GeneratorEvent::IdentifierConflict { other_instance: 1 },
+                GeneratorEvent::IdentifierConflict { other_instance: 2 }
+            ]
+        );
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{fs, path::Path};
+
+use crate::{SnowflakeConfiguration, SnowflakeError};
+
+impl SnowflakeConfiguration {
+    /// Loading [`SnowflakeConfiguration`](SnowflakeConfiguration) from TOML.
+    ///
+    /// Fields are the same as [serde](SnowflakeConfiguration#serde), and unknown fields are rejected.
+    ///
+    /// ```toml
+    /// identifier = 42
+    /// epoch = 1577836800000
+    ///
+    /// [layout]
+    /// timestamp_bits = 41
+    /// identifier_bits = 10
+    /// sequence_bits = 12
+    /// ```
+    pub fn from_toml_str(input: &str) -> Result<Self, SnowflakeError> {
+        toml::from_str(input).map_err(|it| SnowflakeError::ConfigFile {
+            path: None,
+            message: it.message().to_string(),
+        })
+    }
+
+    /// Loading [`SnowflakeConfiguration`](SnowflakeConfiguration) from JSON, see [`SnowflakeConfiguration::from_toml_str`](SnowflakeConfiguration::from_toml_str).
+    pub fn from_json_str(input: &str) -> Result<Self, SnowflakeError> {
+        serde_json::from_str(input).map_err(|it| SnowflakeError::ConfigFile {
+            path: None,
+            message: it.to_string(),
+        })
+    }
+
+    /// Loading [`SnowflakeConfiguration`](SnowflakeConfiguration) from file, format is chosen by extension (`.toml` or `.json`).
+    pub fn from_path(path: &Path) -> Result<Self, SnowflakeError> {
+        let with_path = |message: String| SnowflakeError::ConfigFile {
+            path: Some(path.to_path_buf()),
+            message,
+        };
+
+        let parse = match path.extension().and_then(|it| it.to_str()) {
+            Some("toml") => Self::from_toml_str,
+            Some("json") => Self::from_json_str,
+            _ => {
+                return Err(with_path(
+                    "unsupported format, expected `.toml` or `.json`".to_string(),
+                ))
+            }
+        };
+
+        let input = fs::read_to_string(path).map_err(|it| with_path(it.to_string()))?;
+        parse(&input).map_err(|it| match it {
+            SnowflakeError::ConfigFile { message, .. } => with_path(message),
+            other => other,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::SnowflakeLayout;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn message(err: SnowflakeError) -> String {
+        match err {
+            SnowflakeError::ConfigFile { message, .. } => message,
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_good() {
+        let expected = SnowflakeConfiguration::with_identifier(42)
+            .with_epoch(1577836800000)
+            .with_layout(SnowflakeLayout::new(44, 6, 13).unwrap());
+
+        assert_eq!(
+            SnowflakeConfiguration::from_path(&fixture("good.toml")),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            SnowflakeConfiguration::from_path(&fixture("good.json")),
+            Ok(expected)
+        );
+        assert_eq!(
+            SnowflakeConfiguration::from_toml_str("identifier = 1"),
+            Ok(SnowflakeConfiguration::with_identifier(1))
+        );
+        assert_eq!(
+            SnowflakeConfiguration::from_json_str(r#"{"identifier":1}"#),
+            Ok(SnowflakeConfiguration::with_identifier(1))
+        );
+    }
+
+    #[test]
+    fn test_bad() {
+        let path = fixture("typo.toml");
+        let err = SnowflakeConfiguration::from_path(&path).unwrap_err();
+        assert!(err.to_string().contains(&path.display().to_string()));
+        assert!(message(err).contains("identifer"));
+
+        let err = SnowflakeConfiguration::from_path(&fixture("out_of_range.toml")).unwrap_err();
+        assert!(message(err).contains("identifier 64 out of range"));
+
+        let err = SnowflakeConfiguration::from_path(&fixture("invalid_layout.json")).unwrap_err();
+        assert!(message(err).contains("invalid layout 41/10/13"));
+
+        let err = SnowflakeConfiguration::from_path(&fixture("unsupported.yaml")).unwrap_err();
+        assert!(message(err).contains("unsupported format"));
+
+        let path = fixture("missing.toml");
+        assert!(matches!(
+            SnowflakeConfiguration::from_path(&path),
+            Err(SnowflakeError::ConfigFile { path: Some(it), .. }) if it == path
+        ));
+
+        let err =
+            SnowflakeConfiguration::from_json_str(r#"{"identifier":1,"epoc":0}"#).unwrap_err();
+        assert!(message(err).contains("epoc"));
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "sync")]
+use std::thread;
+#[cfg(any(feature = "async", feature = "sync"))]
+use std::time::Duration;
+
+use rand::RngCore;
+
+#[cfg(feature = "async")]
+use crate::{Sleeper, TimerSleeper};
+use crate::{Snowflake, SnowflakeLayout, TimeProvider};
+
+/// [`SnowflakeGeneratorConst`](SnowflakeGeneratorConst) with standard 41/10/12 layout.
+pub type StandardSnowflakeGenerator = SnowflakeGeneratorConst<41, 10, 12>;
+
+/// Generating [`Snowflake`](Snowflake) with bit layout fixed at compile time.
+///
+/// `T`, `I` and `S` are bits of timestamp, identifier and sequence number.
+/// Masks and shifts are all constants, and invalid layout (`T + I + S != 63`) fails to compile:
+///
+/// ```compile_fail
+/// # use snowflake_ng::SnowflakeGeneratorConst;
+/// let generator = SnowflakeGeneratorConst::<41, 10, 13>::new(0, 0);
+/// ```
+///
+/// Apart from the layout, it behaves the same as [`SnowflakeGenerator`](crate::SnowflakeGenerator).
+///
+/// # Thread safety
+///
+/// You can use [`::std::sync::Arc`](::std::sync::Arc) sharing ownership between thread, or make a global static one.
+#[derive(Debug)]
+pub struct SnowflakeGeneratorConst<const T: u32, const I: u32, const S: u32> {
+    timestamp_sequence: AtomicU64,
+    identifier: u64,
+    epoch: u64,
+}
+
+impl<const T: u32, const I: u32, const S: u32> SnowflakeGeneratorConst<T, I, S> {
+    /// Bit layout of this generator.
+    pub const LAYOUT: SnowflakeLayout = {
+        assert!(
+            T > 0 && T + I + S == 63,
+            "timestamp, identifier and sequence bits must sum to 63"
+        );
+        SnowflakeLayout::new_unchecked(T, I, S)
+    };
+
+    const MAX_SEQUENCE: u64 = Self::LAYOUT.max_sequence();
+
+    /// Constructing generator with `identifier` and `epoch`(milliseconds since UNIX epoch).
+    ///
+    /// `identifier` will be truncated to `I` bit.
+    pub const fn new(identifier: u64, epoch: u64) -> Self {
+        Self {
+            timestamp_sequence: AtomicU64::new(0),
+            identifier: identifier & Self::LAYOUT.max_identifier(),
+            epoch,
+        }
+    }
+
+    /// Identifier stamped into generated [`Snowflake`](Snowflake).
+    pub const fn identifier(&self) -> u64 {
+        self.identifier
+    }
+
+    /// Epoch in milliseconds since UNIX epoch.
+    pub const fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Assign a [`Snowflake`](Snowflake) with [`TimeProvider`](TimeProvider)
+    #[cfg(feature = "async")]
+    pub async fn assign<P>(&self, provider: &P) -> Snowflake
+    where
+        P: TimeProvider + Sync + Send,
+    {
+        loop {
+            match self.assign_now(provider) {
+                Some(it) => return it,
+                None => TimerSleeper.sleep(Duration::from_millis(1)).await,
+            }
+        }
+    }
+
+    /// Assign a [`Snowflake`](Snowflake) right now, `None` if it has to wait for next millisecond.
+    fn assign_now<P>(&self, provider: &P) -> Option<Snowflake>
+    where
+        P: TimeProvider + Sync + Send,
+    {
+        loop {
+            let timestamp = provider.timestamp().saturating_sub(self.epoch);
+            let current = self.timestamp_sequence.load(Ordering::Relaxed);
+            let current_timestamp = current >> S;
+            let current_sequence = current & Self::MAX_SEQUENCE;
+
+            let sequence = match current_timestamp.cmp(&timestamp) {
+                std::cmp::Ordering::Less => 0,
+                std::cmp::Ordering::Equal if current_sequence < Self::MAX_SEQUENCE => {
+                    current_sequence + 1
+                }
+                // Sequence reached MAX or clock behind, waiting for next millisecond
+                _ => return None,
+            };
+
+            if self
+                .timestamp_sequence
+                .compare_exchange(
+                    current,
+                    (timestamp << S) | sequence,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok()
+            {
+                return Some(Self::LAYOUT.compose(timestamp, self.identifier, sequence));
+            }
+        }
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn assign_sync<P>(&self, provider: &P) -> Snowflake
+    where
+        P: TimeProvider + Sync + Send,
+    {
+        loop {
+            match self.assign_now(provider) {
+                Some(it) => return it,
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+    }
+}
+
+impl<const T: u32, const I: u32, const S: u32> Default for SnowflakeGeneratorConst<T, I, S> {
+    fn default() -> Self {
+        Self::new(rand::thread_rng().next_u64(), 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "async")]
+    use std::{collections::HashSet, sync::Arc};
+
+    use crate::provider::STD_PROVIDER;
+
+    use super::*;
+
+    static GLOBAL: StandardSnowflakeGenerator = StandardSnowflakeGenerator::new(7, 0);
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(StandardSnowflakeGenerator::LAYOUT, crate::layouts::DEFAULT);
+        assert_eq!(
+            SnowflakeGeneratorConst::<44, 6, 13>::LAYOUT,
+            SnowflakeLayout::new(44, 6, 13).unwrap()
+        );
+        assert_eq!(
+            SnowflakeGeneratorConst::<44, 6, 13>::new(100, 0).identifier(),
+            36
+        );
+    }
+
+    #[test]
+    fn test_assign_sync() {
+        let snowflakes = (0..10000)
+            .map(|_| GLOBAL.assign_sync(&STD_PROVIDER))
+            .collect::<Vec<_>>();
+
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert!(snowflakes.iter().all(|it| it.identifier() == 7));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_assign_multithread() {
+        const EPOCH: u64 = 1577836800000;
+        let generator = Arc::new(SnowflakeGeneratorConst::<44, 6, 13>::new(3, EPOCH));
+        let layout = SnowflakeGeneratorConst::<44, 6, 13>::LAYOUT;
+
+        let tasks = (0..100).map(|_| {
+            let generator = generator.clone();
+            tokio::spawn(async move {
+                let mut snowflakes = Vec::with_capacity(1000);
+                for _ in 0..1000 {
+                    snowflakes.push(generator.assign(&STD_PROVIDER).await);
+                }
+                snowflakes
+            })
+        });
+
+        let snowflakes = futures::future::join_all(tasks)
+            .await
+            .into_iter()
+            .flat_map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        let now = STD_PROVIDER.timestamp();
+        assert!(snowflakes.iter().all(|it| layout.identifier_of(it) == 3));
+        assert!(snowflakes
+            .iter()
+            .all(|it| (layout.timestamp_of(it) + EPOCH).abs_diff(now) < 60_000));
+        assert_eq!(
+            snowflakes.into_iter().collect::<HashSet<_>>().len(),
+            100 * 1000
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+
+use crate::{
+    provider::MockProvider, PersistedSnowflakeGenerator, Snowflake, SnowflakeConfiguration,
+    SnowflakeGenerator, SnowflakeSource,
+};
+
+/// Where [`DeterministicSnowflake`](DeterministicSnowflake) starts, 2024-01-01 in milliseconds since UNIX epoch.
+const DETERMINISTIC_START: u64 = 1_704_067_200_000;
+
+impl SnowflakeGenerator {
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) with identifier fixed by `seed`, for reproducible test runs.
+    ///
+    /// Pair it with [`MockProvider::stepping`](MockProvider::stepping), or just use [`DeterministicSnowflake`](DeterministicSnowflake).
+    pub fn deterministic(seed: u64) -> Self {
+        let cfg = SnowflakeConfiguration::default();
+        let identifier = seed % (cfg.layout.max_identifier() + 1);
+        Self::with_cfg(SnowflakeConfiguration { identifier, ..cfg })
+    }
+}
+
+/// [`SnowflakeGenerator::deterministic`](SnowflakeGenerator::deterministic) with [`MockProvider`](MockProvider)
+/// stepping once sequence of a millisecond is used up, so the N-th [`Snowflake`](Snowflake) of a seed is always the same.
+///
+/// Only assigning from one thread at a time is deterministic.
+///
+/// ```
+/// # use snowflake_ng::DeterministicSnowflake;
+/// let first = DeterministicSnowflake::new(42).generate();
+/// assert_eq!(DeterministicSnowflake::new(42).generate(), first);
+/// ```
+#[derive(Debug)]
+pub struct DeterministicSnowflake {
+    generator: PersistedSnowflakeGenerator<MockProvider>,
+}
+
+impl DeterministicSnowflake {
+    /// Starting from 2024-01-01, identified by `seed`.
+    pub fn new(seed: u64) -> Self {
+        let generator = SnowflakeGenerator::deterministic(seed);
+        let per_millis = generator.config().layout.max_sequence() + 1;
+        Self {
+            generator: PersistedSnowflakeGenerator::new(
+                Arc::new(generator),
+                Arc::new(MockProvider::stepping(DETERMINISTIC_START, per_millis)),
+            ),
+        }
+    }
+
+    /// The next [`Snowflake`](Snowflake), which never waits.
+    pub fn generate(&self) -> Snowflake {
+        self.generator
+            .generator
+            .try_assign_now(&*self.generator.provider)
+            .expect("deterministic generator is assigned concurrently")
+    }
+}
+
+impl SnowflakeSource for DeterministicSnowflake {
+    #[cfg(feature = "async")]
+    fn assign(&self) -> BoxFuture<'_, Snowflake> {
+        Box::pin(std::future::ready(self.generate()))
+    }
+
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake {
+        self.generate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let deterministic = DeterministicSnowflake::new(42);
+        let first = (0..10)
+            .map(|_| *deterministic.generate())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            first,
+            [
+                7147375873228972032,
+                7147375873228972033,
+                7147375873228972034,
+                7147375873228972035,
+                7147375873228972036,
+                7147375873228972037,
+                7147375873228972038,
+                7147375873228972039,
+                7147375873228972040,
+                7147375873228972041,
+            ]
+        );
+
+        // Stepping to the next millisecond once sequence is used up.
+        let rest = (10..4097)
+            .map(|_| deterministic.generate())
+            .collect::<Vec<_>>();
+        assert_eq!(rest[4085].timestamp(), DETERMINISTIC_START);
+        assert_eq!(rest[4085].sequence(), 4095);
+        assert_eq!(rest[4086].timestamp(), DETERMINISTIC_START + 1);
+        assert_eq!(rest[4086].sequence(), 0);
+
+        let other = DeterministicSnowflake::new(43).generate();
+        assert_eq!(other.identifier(), 43);
+        assert_eq!(other.timestamp(), DETERMINISTIC_START);
+    }
+}
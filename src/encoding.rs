@@ -0,0 +1,578 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! String encodings for [`Snowflake`](crate::Snowflake).
+
+use std::{error::Error, fmt, sync::LazyLock};
+
+use crate::Snowflake;
+
+#[cfg(feature = "sqids")]
+mod sqids;
+
+#[cfg(feature = "sqids")]
+pub use sqids::SnowflakeCodec;
+
+/// Bitcoin base58 alphabet, without `0`, `O`, `I` and `l`.
+pub const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Base62 alphabet, ordered by ASCII so fixed width encoding preserves sort order.
+pub const BASE62_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Decimal alphabet, used by [`Snowflake::to_checked_string`](Snowflake::to_checked_string).
+pub const DECIMAL_ALPHABET: &str = "0123456789";
+
+/// Crockford's base32 alphabet, without `I`, `L`, `O` and `U`.
+///
+/// Decoding is case-sensitive and doesn't accept Crockford's aliases.
+pub const BASE32_ALPHABET: &str = "0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+static BASE58: LazyLock<AlphabetEncoder> =
+    LazyLock::new(|| AlphabetEncoder::builtin(BASE58_ALPHABET));
+static BASE62: LazyLock<AlphabetEncoder> =
+    LazyLock::new(|| AlphabetEncoder::builtin(BASE62_ALPHABET));
+static DECIMAL: LazyLock<AlphabetEncoder> =
+    LazyLock::new(|| AlphabetEncoder::builtin(DECIMAL_ALPHABET));
+static BASE32: LazyLock<AlphabetEncoder> =
+    LazyLock::new(|| AlphabetEncoder::builtin(BASE32_ALPHABET));
+
+/// Error produced when decoding a [`Snowflake`](Snowflake) from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Input is empty.
+    Empty,
+    /// Input is longer than any valid encoding.
+    TooLong { len: usize, max: usize },
+    /// Input contains a character outside the alphabet.
+    InvalidCharacter { character: char, index: usize },
+    /// Decoded value doesn't fit in [`Snowflake`](Snowflake).
+    Overflow,
+    /// Input is not produced by this encoding.
+    Malformed,
+    /// Checksum character doesn't match the payload.
+    ChecksumMismatch { expected: char, found: char },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "cannot decode snowflake from empty string"),
+            DecodeError::TooLong { len, max } => {
+                write!(f, "input length {len} exceeds maximum length {max}")
+            }
+            DecodeError::InvalidCharacter { character, index } => {
+                write!(f, "invalid character {character:?} at index {index}")
+            }
+            DecodeError::Overflow => write!(f, "decoded value overflows snowflake"),
+            DecodeError::Malformed => write!(f, "input is not a valid encoded snowflake"),
+            DecodeError::ChecksumMismatch { expected, found } => {
+                write!(
+                    f,
+                    "checksum mismatch, expected {expected:?} but found {found:?}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Error produced when constructing [`AlphabetEncoder`](AlphabetEncoder).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlphabetError {
+    /// Alphabet must have at least 2 characters.
+    TooShort { len: usize },
+    /// Alphabet contains the same character more than once.
+    DuplicateCharacter(char),
+}
+
+impl fmt::Display for AlphabetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlphabetError::TooShort { len } => {
+                write!(f, "alphabet must have at least 2 characters, got {len}")
+            }
+            AlphabetError::DuplicateCharacter(character) => {
+                write!(f, "alphabet contains duplicate character {character:?}")
+            }
+        }
+    }
+}
+
+impl Error for AlphabetError {}
+
+/// Positional encoder for [`Snowflake`](Snowflake) in base of any alphabet.
+///
+/// Alphabet is made of [`char`](char) rather than bytes, so multibyte characters (even emoji) are welcome.
+///
+/// # Fixed width
+///
+/// By default, leading "zero" (the first character of alphabet) are omitted.
+/// With [`AlphabetEncoder::fixed_width`](AlphabetEncoder::fixed_width) the output is padded to the width of [`Snowflake::MAX`](Snowflake::MAX),
+/// so when the alphabet itself is sorted, encoded strings sort the same as [`Snowflake`](Snowflake)s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlphabetEncoder {
+    alphabet: Vec<char>,
+    max_len: usize,
+    fixed_width: bool,
+}
+
+impl AlphabetEncoder {
+    /// Constructing [`AlphabetEncoder`](AlphabetEncoder) with `alphabet`, every character must be unique.
+    pub fn new(alphabet: &str) -> Result<Self, AlphabetError> {
+        let alphabet = alphabet.chars().collect::<Vec<_>>();
+        if alphabet.len() < 2 {
+            return Err(AlphabetError::TooShort {
+                len: alphabet.len(),
+            });
+        }
+
+        for (index, character) in alphabet.iter().enumerate() {
+            if alphabet[..index].contains(character) {
+                return Err(AlphabetError::DuplicateCharacter(*character));
+            }
+        }
+
+        let base = alphabet.len() as u64;
+        let mut max_len = 0;
+        let mut value = Snowflake::MAX.0 as u64;
+        while value > 0 {
+            value /= base;
+            max_len += 1;
+        }
+
+        Ok(Self {
+            alphabet,
+            max_len,
+            fixed_width: false,
+        })
+    }
+
+    fn builtin(alphabet: &str) -> Self {
+        Self::new(alphabet).expect("builtin alphabet is always valid")
+    }
+
+    /// Pad every output to the same width.
+    pub fn fixed_width(mut self) -> Self {
+        self.fixed_width = true;
+        self
+    }
+
+    /// Characters of alphabet, in order of their value.
+    pub fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+
+    /// Longest valid input of [`AlphabetEncoder::decode`](AlphabetEncoder::decode).
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Encode [`Snowflake`](Snowflake) to string.
+    pub fn encode(&self, snowflake: &Snowflake) -> String {
+        let base = self.alphabet.len() as u64;
+        let mut value = snowflake.0 as u64;
+
+        let mut digits = Vec::with_capacity(self.max_len);
+        loop {
+            digits.push(self.alphabet[(value % base) as usize]);
+            value /= base;
+            if value == 0 {
+                break;
+            }
+        }
+
+        if self.fixed_width {
+            digits.resize(self.max_len, self.alphabet[0]);
+        }
+
+        digits.iter().rev().collect()
+    }
+
+    /// Decode [`Snowflake`](Snowflake) from string produced by [`AlphabetEncoder::encode`](AlphabetEncoder::encode).
+    ///
+    /// Leading "zero" are accepted, whether fixed width or not.
+    pub fn decode(&self, input: &str) -> Result<Snowflake, DecodeError> {
+        if input.is_empty() {
+            return Err(DecodeError::Empty);
+        }
+
+        let len = input.chars().count();
+        if len > self.max_len {
+            return Err(DecodeError::TooLong {
+                len,
+                max: self.max_len,
+            });
+        }
+
+        let base = self.alphabet.len() as u64;
+        let mut value = 0u64;
+        for (index, character) in input.chars().enumerate() {
+            let digit = self
+                .digit(character)
+                .ok_or(DecodeError::InvalidCharacter { character, index })?;
+
+            value = value
+                .checked_mul(base)
+                .and_then(|it| it.checked_add(digit as u64))
+                .ok_or(DecodeError::Overflow)?;
+        }
+
+        i64::try_from(value)
+            .map(Snowflake)
+            .map_err(|_| DecodeError::Overflow)
+    }
+
+    /// Encode [`Snowflake`](Snowflake) to string, with a trailing checksum character.
+    ///
+    /// Checksum is calculated by [Luhn mod N algorithm](https://en.wikipedia.org/wiki/Luhn_mod_N_algorithm) over the alphabet.
+    /// It detects every single-character typo and every adjacent transposition,
+    /// except transposition between the first and the last character of alphabet.
+    pub fn encode_checked(&self, snowflake: &Snowflake) -> String {
+        let mut encoded = self.encode(snowflake);
+        let checksum = self.checksum(encoded.chars());
+        encoded.push(checksum);
+        encoded
+    }
+
+    /// Decode [`Snowflake`](Snowflake) from string produced by [`AlphabetEncoder::encode_checked`](AlphabetEncoder::encode_checked).
+    pub fn decode_checked(&self, input: &str) -> Result<Snowflake, DecodeError> {
+        let mut chars = input.chars();
+        let found = chars.next_back().ok_or(DecodeError::Empty)?;
+        let payload = chars.as_str();
+        if payload.is_empty() {
+            return Err(DecodeError::Malformed);
+        }
+
+        let len = payload.chars().count();
+        if len > self.max_len {
+            return Err(DecodeError::TooLong {
+                len: len + 1,
+                max: self.max_len + 1,
+            });
+        }
+
+        for (index, character) in input.chars().enumerate() {
+            if self.digit(character).is_none() {
+                return Err(DecodeError::InvalidCharacter { character, index });
+            }
+        }
+
+        let expected = self.checksum(payload.chars());
+        if expected != found {
+            return Err(DecodeError::ChecksumMismatch { expected, found });
+        }
+
+        self.decode(payload)
+    }
+
+    fn digit(&self, character: char) -> Option<usize> {
+        self.alphabet.iter().position(|it| *it == character)
+    }
+
+    /// Luhn mod N, all characters must be in alphabet.
+    fn checksum(&self, payload: impl DoubleEndedIterator<Item = char>) -> char {
+        let base = self.alphabet.len();
+
+        let mut factor = 2;
+        let mut sum = 0;
+        for character in payload.rev() {
+            let digit = self.digit(character).expect("payload is validated") * factor;
+            sum += digit / base + digit % base;
+            factor = if factor == 2 { 1 } else { 2 };
+        }
+
+        self.alphabet[(base - sum % base) % base]
+    }
+}
+
+impl Snowflake {
+    /// Encode this [`Snowflake`](Snowflake) in decimal with a trailing checksum digit.
+    ///
+    /// See [`AlphabetEncoder::encode_checked`](AlphabetEncoder::encode_checked) for other alphabets such as [`BASE32_ALPHABET`](BASE32_ALPHABET).
+    pub fn to_checked_string(&self) -> String {
+        DECIMAL.encode_checked(self)
+    }
+
+    /// Decode [`Snowflake`](Snowflake) from string encoded by [`Snowflake::to_checked_string`](Snowflake::to_checked_string)
+    pub fn from_checked_string(input: &str) -> Result<Self, DecodeError> {
+        DECIMAL.decode_checked(input)
+    }
+
+    /// Encode this [`Snowflake`](Snowflake) with [`BASE58_ALPHABET`](BASE58_ALPHABET)
+    pub fn to_base58(&self) -> String {
+        BASE58.encode(self)
+    }
+
+    /// Decode [`Snowflake`](Snowflake) from string encoded by [`Snowflake::to_base58`](Snowflake::to_base58)
+    pub fn from_base58(input: &str) -> Result<Self, DecodeError> {
+        BASE58.decode(input)
+    }
+
+    /// Encode this [`Snowflake`](Snowflake) with [`BASE62_ALPHABET`](BASE62_ALPHABET)
+    pub fn to_base62(&self) -> String {
+        BASE62.encode(self)
+    }
+
+    /// Decode [`Snowflake`](Snowflake) from string encoded by [`Snowflake::to_base62`](Snowflake::to_base62)
+    pub fn from_base62(input: &str) -> Result<Self, DecodeError> {
+        BASE62.decode(input)
+    }
+
+    /// Encode this [`Snowflake`](Snowflake) with [`BASE32_ALPHABET`](BASE32_ALPHABET)
+    pub fn to_base32(&self) -> String {
+        BASE32.encode(self)
+    }
+
+    /// Decode [`Snowflake`](Snowflake) from string encoded by [`Snowflake::to_base32`](Snowflake::to_base32)
+    pub fn from_base32(input: &str) -> Result<Self, DecodeError> {
+        BASE32.decode(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_base58_vectors() {
+        let vectors = [
+            (0, "1"),
+            (1, "2"),
+            (57, "z"),
+            (58, "21"),
+            (1234567890123456789, "3sDK21t5nHJ"),
+            (i64::MAX, "NQm6nKp8qFC"),
+        ];
+
+        for (value, encoded) in vectors {
+            assert_eq!(Snowflake(value).to_base58(), encoded);
+            assert_eq!(Snowflake::from_base58(encoded), Ok(Snowflake(value)));
+        }
+
+        assert_eq!(Snowflake::MAX.to_base58(), "NQm6nKp8qFC");
+    }
+
+    #[test]
+    fn test_base58_round_trip() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..10000 {
+            let snowflake = Snowflake(rng.gen_range(0..=i64::MAX));
+            let encoded = snowflake.to_base58();
+            assert_eq!(Snowflake::from_base58(&encoded), Ok(snowflake));
+        }
+    }
+
+    #[test]
+    fn test_base58_invalid() {
+        assert_eq!(Snowflake::from_base58(""), Err(DecodeError::Empty));
+
+        for (input, character, index) in [("0", '0', 0), ("1O", 'O', 1), ("zzI", 'I', 2)] {
+            assert_eq!(
+                Snowflake::from_base58(input),
+                Err(DecodeError::InvalidCharacter { character, index })
+            );
+        }
+        assert_eq!(
+            Snowflake::from_base58("2l"),
+            Err(DecodeError::InvalidCharacter {
+                character: 'l',
+                index: 1
+            })
+        );
+
+        assert_eq!(
+            Snowflake::from_base58("111111111111"),
+            Err(DecodeError::TooLong { len: 12, max: 11 })
+        );
+
+        // One past `i64::MAX`
+        assert_eq!(
+            Snowflake::from_base58("NQm6nKp8qFD"),
+            Err(DecodeError::Overflow)
+        );
+        assert_eq!(
+            Snowflake::from_base58("zzzzzzzzzzz"),
+            Err(DecodeError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_base62_base32_round_trip() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..10000 {
+            let snowflake = Snowflake(rng.gen_range(0..=i64::MAX));
+            assert_eq!(
+                Snowflake::from_base62(&snowflake.to_base62()),
+                Ok(snowflake.clone())
+            );
+            assert_eq!(
+                Snowflake::from_base32(&snowflake.to_base32()),
+                Ok(snowflake)
+            );
+        }
+
+        assert_eq!(Snowflake(0).to_base62(), "0");
+        assert_eq!(Snowflake::MAX.to_base62(), "AzL8n0Y58m7");
+        assert_eq!(Snowflake(0).to_base32(), "0");
+        assert_eq!(Snowflake::MAX.to_base32(), "7ZZZZZZZZZZZZ");
+    }
+
+    #[test]
+    fn test_alphabet_invalid() {
+        assert_eq!(
+            AlphabetEncoder::new("a"),
+            Err(AlphabetError::TooShort { len: 1 })
+        );
+        assert_eq!(
+            AlphabetEncoder::new(""),
+            Err(AlphabetError::TooShort { len: 0 })
+        );
+        assert_eq!(
+            AlphabetEncoder::new("abca"),
+            Err(AlphabetError::DuplicateCharacter('a'))
+        );
+
+        let binary = AlphabetEncoder::new("01").unwrap();
+        assert_eq!(binary.max_len(), 63);
+        assert_eq!(
+            binary.decode("012"),
+            Err(DecodeError::InvalidCharacter {
+                character: '2',
+                index: 2
+            })
+        );
+        // 63 bits all set is `i64::MAX`, but 64 digits is too long
+        assert_eq!(binary.decode(&"1".repeat(63)), Ok(Snowflake::MAX));
+        assert_eq!(
+            binary.decode(&"1".repeat(64)),
+            Err(DecodeError::TooLong { len: 64, max: 63 })
+        );
+    }
+
+    #[test]
+    fn test_alphabet_emoji() {
+        let encoder = AlphabetEncoder::new("❄🌨☃⛄🧊").unwrap();
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..1000 {
+            let snowflake = Snowflake(rng.gen_range(0..=i64::MAX));
+            let encoded = encoder.encode(&snowflake);
+            assert!(encoded.chars().all(|it| encoder.alphabet().contains(&it)));
+            assert_eq!(encoder.decode(&encoded), Ok(snowflake));
+        }
+
+        assert_eq!(encoder.encode(&Snowflake(0)), "❄");
+        assert_eq!(encoder.encode(&Snowflake(7)), "🌨☃");
+    }
+
+    #[test]
+    fn test_alphabet_fixed_width() {
+        let encoder = AlphabetEncoder::new(BASE62_ALPHABET).unwrap().fixed_width();
+        let mut rng = rand::thread_rng();
+
+        let mut snowflakes = (0..1000)
+            .map(|_| Snowflake(rng.gen_range(0..=i64::MAX)))
+            .chain([Snowflake(0), Snowflake::MAX])
+            .collect::<Vec<_>>();
+        snowflakes.sort();
+
+        let encoded = snowflakes
+            .iter()
+            .map(|it| encoder.encode(it))
+            .collect::<Vec<_>>();
+        assert!(encoded.iter().all(|it| it.len() == encoder.max_len()));
+        assert!(encoded.windows(2).all(|it| it[0] <= it[1]));
+
+        for (snowflake, encoded) in snowflakes.into_iter().zip(encoded) {
+            assert_eq!(encoder.decode(&encoded), Ok(snowflake));
+        }
+    }
+
+    /// Every single-character substitution and adjacent transposition of `encoded`.
+    fn typos(alphabet: &[char], encoded: &str) -> Vec<String> {
+        let chars = encoded.chars().collect::<Vec<_>>();
+        let mut typos = vec![];
+
+        for index in 0..chars.len() {
+            for character in alphabet.iter().filter(|it| **it != chars[index]) {
+                let mut typo = chars.clone();
+                typo[index] = *character;
+                typos.push(typo.into_iter().collect());
+            }
+        }
+
+        for index in 0..chars.len() - 1 {
+            let (a, b) = (chars[index], chars[index + 1]);
+            let undetectable = [a, b] == [alphabet[0], alphabet[alphabet.len() - 1]]
+                || [b, a] == [alphabet[0], alphabet[alphabet.len() - 1]];
+            if a == b || undetectable {
+                continue;
+            }
+
+            let mut typo = chars.clone();
+            typo.swap(index, index + 1);
+            typos.push(typo.into_iter().collect());
+        }
+
+        typos
+    }
+
+    #[test]
+    fn test_checked_string() {
+        let snowflake = Snowflake(1234567890123456789);
+        let checked = snowflake.to_checked_string();
+        assert_eq!(checked, "12345678901234567894");
+        assert_eq!(Snowflake::from_checked_string(&checked), Ok(snowflake));
+
+        assert_eq!(
+            Snowflake::from_checked_string("12345678901234567898"),
+            Err(DecodeError::ChecksumMismatch {
+                expected: '4',
+                found: '8'
+            })
+        );
+        assert_eq!(Snowflake::from_checked_string(""), Err(DecodeError::Empty));
+        assert_eq!(
+            Snowflake::from_checked_string("7"),
+            Err(DecodeError::Malformed)
+        );
+        assert_eq!(
+            Snowflake::from_checked_string("12a4"),
+            Err(DecodeError::InvalidCharacter {
+                character: 'a',
+                index: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_checked_typos() {
+        let mut rng = rand::thread_rng();
+
+        for alphabet in [DECIMAL_ALPHABET, BASE32_ALPHABET, BASE62_ALPHABET] {
+            let encoder = AlphabetEncoder::new(alphabet).unwrap();
+
+            for _ in 0..100 {
+                let snowflake = Snowflake(rng.gen_range(0..=i64::MAX));
+                let checked = encoder.encode_checked(&snowflake);
+                assert_eq!(encoder.decode_checked(&checked), Ok(snowflake));
+
+                for typo in typos(encoder.alphabet(), &checked) {
+                    assert!(
+                        encoder.decode_checked(&typo).is_err(),
+                        "typo {typo} of {checked} not detected"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use sqids::Sqids;
+
+use crate::Snowflake;
+
+use super::DecodeError;
+
+/// [Sqids](https://sqids.org) based codec, producing short and non-sequential-looking string for [`Snowflake`](Snowflake).
+///
+/// Different salts shuffle the alphabet differently, so the same [`Snowflake`](Snowflake) will be encoded differently.
+///
+/// # Security
+///
+/// This is **NOT** encryption. It only makes IDs look random, anyone knowing the salt can decode them.
+#[derive(Debug, Clone)]
+pub struct SnowflakeCodec {
+    sqids: Sqids,
+    alphabet: Vec<char>,
+}
+
+impl SnowflakeCodec {
+    /// Constructing [`SnowflakeCodec`](SnowflakeCodec) with default alphabet shuffled by `salt`.
+    pub fn new(salt: &str) -> Self {
+        let mut alphabet = sqids::DEFAULT_ALPHABET.chars().collect::<Vec<_>>();
+        shuffle(&mut alphabet, salt.as_bytes());
+
+        Self::with_chars(alphabet).expect("default alphabet is always valid")
+    }
+
+    /// Constructing [`SnowflakeCodec`](SnowflakeCodec) with custom alphabet.
+    pub fn with_alphabet(alphabet: &str) -> Result<Self, sqids::Error> {
+        Self::with_chars(alphabet.chars().collect())
+    }
+
+    fn with_chars(alphabet: Vec<char>) -> Result<Self, sqids::Error> {
+        let sqids = Sqids::builder().alphabet(alphabet.clone()).build()?;
+        Ok(Self { sqids, alphabet })
+    }
+
+    /// Encode [`Snowflake`](Snowflake) to string.
+    pub fn encode(&self, snowflake: Snowflake) -> String {
+        // Blocklist can only be exhausted after every rotation of alphabet was blocked, which is impossible for one number.
+        self.sqids
+            .encode(&[snowflake.0 as u64])
+            .expect("single number never exhausts sqids blocklist")
+    }
+
+    /// Decode [`Snowflake`](Snowflake) from string produced by [`SnowflakeCodec::encode`](SnowflakeCodec::encode).
+    ///
+    /// Strings produced with another salt are rejected as [`DecodeError::Malformed`](DecodeError::Malformed) where it's detectable,
+    /// because they won't be re-encoded into the same string.
+    pub fn decode(&self, input: &str) -> Result<Snowflake, DecodeError> {
+        if input.is_empty() {
+            return Err(DecodeError::Empty);
+        }
+
+        if let Some((index, character)) = input
+            .chars()
+            .enumerate()
+            .find(|(_, it)| !self.alphabet.contains(it))
+        {
+            return Err(DecodeError::InvalidCharacter { character, index });
+        }
+
+        let [value] = self.sqids.decode(input)[..] else {
+            return Err(DecodeError::Malformed);
+        };
+
+        let snowflake = i64::try_from(value)
+            .map(Snowflake)
+            .map_err(|_| DecodeError::Overflow)?;
+
+        // Only canonical encoding is accepted.
+        if self.encode(snowflake.clone()) != input {
+            return Err(DecodeError::Malformed);
+        }
+
+        Ok(snowflake)
+    }
+}
+
+/// Deterministic shuffle of `alphabet` driven by `salt`, same as `hashids` did.
+fn shuffle(alphabet: &mut [char], salt: &[u8]) {
+    if salt.is_empty() {
+        return;
+    }
+
+    let mut v = 0;
+    let mut p = 0;
+    for i in (1..alphabet.len()).rev() {
+        v %= salt.len();
+        let n = salt[v] as usize;
+        p += n;
+        let j = (n + v + p) % i;
+        alphabet.swap(i, j);
+        v += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = rand::thread_rng();
+        let codec = SnowflakeCodec::new("snowflake-ng");
+
+        for _ in 0..10000 {
+            let snowflake = Snowflake(rng.gen_range(0..=i64::MAX));
+            let encoded = codec.encode(snowflake.clone());
+            assert_eq!(codec.decode(&encoded), Ok(snowflake));
+        }
+
+        for snowflake in [Snowflake(0), Snowflake::MAX] {
+            let encoded = codec.encode(snowflake.clone());
+            assert_eq!(codec.decode(&encoded), Ok(snowflake));
+        }
+    }
+
+    #[test]
+    fn test_different_salt() {
+        let codec0 = SnowflakeCodec::new("salt0");
+        let codec1 = SnowflakeCodec::new("salt1");
+
+        let mut rng = rand::thread_rng();
+        let mut rejected = 0;
+        for _ in 0..1000 {
+            let snowflake = Snowflake(rng.gen_range(0..=i64::MAX));
+            let encoded = codec0.encode(snowflake.clone());
+            assert_ne!(encoded, codec1.encode(snowflake.clone()));
+
+            match codec1.decode(&encoded) {
+                Ok(decoded) => assert_ne!(decoded, snowflake),
+                Err(_) => rejected += 1,
+            }
+        }
+
+        // Same alphabet, so wrong salt can only be detected by canonical check.
+        assert!(rejected > 900, "only {rejected} of 1000 rejected");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let codec = SnowflakeCodec::new("");
+
+        assert_eq!(codec.decode(""), Err(DecodeError::Empty));
+        assert_eq!(
+            codec.decode("ab-c"),
+            Err(DecodeError::InvalidCharacter {
+                character: '-',
+                index: 2
+            })
+        );
+        assert!(SnowflakeCodec::with_alphabet("aa").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{env, str::FromStr};
+
+use crate::{SnowflakeConfiguration, SnowflakeError};
+
+impl SnowflakeConfiguration {
+    /// Constructing [`SnowflakeConfiguration`](SnowflakeConfiguration) from environment variables:
+    ///
+    /// - `SNOWFLAKE_IDENTIFIER`: required, see [`SnowflakeConfiguration::identifier`](SnowflakeConfiguration::identifier)
+    /// - `SNOWFLAKE_EPOCH_MS`: optional, see [`SnowflakeConfiguration::epoch`](SnowflakeConfiguration::epoch)
+    ///
+    /// Missing identifier is an error rather than falling back to a random one.
+    pub fn from_env() -> Result<Self, SnowflakeError> {
+        Self::from_env_with_prefix("SNOWFLAKE_")
+    }
+
+    /// Same as [`SnowflakeConfiguration::from_env`](SnowflakeConfiguration::from_env), but `SNOWFLAKE_` is replaced by `prefix`.
+    ///
+    /// e.g. with `MYAPP_`, `MYAPP_IDENTIFIER` and `MYAPP_EPOCH_MS` are read.
+    pub fn from_env_with_prefix(prefix: &str) -> Result<Self, SnowflakeError> {
+        let identifier_name = format!("{prefix}IDENTIFIER");
+        let identifier = read::<u64>(&identifier_name)?.ok_or(SnowflakeError::MissingEnv {
+            name: identifier_name,
+        })?;
+
+        let epoch = read::<u64>(&format!("{prefix}EPOCH_MS"))?.unwrap_or_default();
+
+        let cfg = Self::with_identifier(identifier).with_epoch(epoch);
+        cfg.validate()?;
+        Ok(cfg)
+    }
+}
+
+fn read<T: FromStr>(name: &str) -> Result<Option<T>, SnowflakeError> {
+    let value = match env::var(name) {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Ok(None),
+        Err(env::VarError::NotUnicode(value)) => {
+            return Err(SnowflakeError::InvalidEnv {
+                name: name.to_string(),
+                value: value.to_string_lossy().into_owned(),
+            })
+        }
+    };
+
+    value
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| SnowflakeError::InvalidEnv {
+            name: name.to_string(),
+            value,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Environment variables are process-wide.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn with_env(vars: &[(&str, Option<&str>)], f: impl FnOnce()) {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|it| it.into_inner());
+
+        for (name, value) in vars {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+
+        f();
+
+        for (name, _) in vars {
+            env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_from_env() {
+        with_env(
+            &[
+                ("SNOWFLAKE_IDENTIFIER", Some("42")),
+                ("SNOWFLAKE_EPOCH_MS", Some("1577836800000")),
+            ],
+            || {
+                let cfg = SnowflakeConfiguration::from_env().unwrap();
+                assert_eq!(cfg.identifier(), 42);
+                assert_eq!(cfg.epoch, 1577836800000);
+            },
+        );
+
+        with_env(
+            &[
+                ("SNOWFLAKE_IDENTIFIER", Some("7")),
+                ("SNOWFLAKE_EPOCH_MS", None),
+            ],
+            || {
+                let cfg = SnowflakeConfiguration::from_env().unwrap();
+                assert_eq!(cfg, SnowflakeConfiguration::with_identifier(7));
+            },
+        );
+    }
+
+    #[test]
+    fn test_from_env_with_prefix() {
+        with_env(
+            &[
+                ("MYAPP_IDENTIFIER", Some("1023")),
+                ("SNOWFLAKE_IDENTIFIER", Some("1")),
+            ],
+            || {
+                let cfg = SnowflakeConfiguration::from_env_with_prefix("MYAPP_").unwrap();
+                assert_eq!(cfg.identifier(), 1023);
+            },
+        );
+    }
+
+    #[test]
+    fn test_from_env_errors() {
+        with_env(&[("SNOWFLAKE_IDENTIFIER", None)], || {
+            assert_eq!(
+                SnowflakeConfiguration::from_env(),
+                Err(SnowflakeError::MissingEnv {
+                    name: "SNOWFLAKE_IDENTIFIER".to_string()
+                })
+            );
+        });
+
+        with_env(&[("SNOWFLAKE_IDENTIFIER", Some("node-1"))], || {
+            assert_eq!(
+                SnowflakeConfiguration::from_env(),
+                Err(SnowflakeError::InvalidEnv {
+                    name: "SNOWFLAKE_IDENTIFIER".to_string(),
+                    value: "node-1".to_string()
+                })
+            );
+        });
+
+        with_env(
+            &[
+                ("SNOWFLAKE_IDENTIFIER", Some("1")),
+                ("SNOWFLAKE_EPOCH_MS", Some("-1")),
+            ],
+            || {
+                assert!(matches!(
+                    SnowflakeConfiguration::from_env(),
+                    Err(SnowflakeError::InvalidEnv { name, .. }) if name == "SNOWFLAKE_EPOCH_MS"
+                ));
+            },
+        );
+
+        with_env(&[("SNOWFLAKE_IDENTIFIER", Some("1024"))], || {
+            assert_eq!(
+                SnowflakeConfiguration::from_env(),
+                Err(SnowflakeError::IdentifierOutOfRange {
+                    identifier: 1024,
+                    max: 1023
+                })
+            );
+        });
+    }
+}
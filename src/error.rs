@@ -0,0 +1,380 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{error::Error, fmt, path::PathBuf, time::Duration};
+
+/// Errors of `snowflake-ng`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SnowflakeError {
+    /// Bit layout occupies more than 63 bits, or has no timestamp bit.
+    InvalidLayout {
+        timestamp_bits: u32,
+        identifier_bits: u32,
+        sequence_bits: u32,
+    },
+    /// Bit layout of `Snowflake128` has no timestamp bit, timestamp and sequence exceed 64 bits, or identifier exceeds 64 bits.
+    InvalidLayout128 {
+        timestamp_bits: u32,
+        identifier_bits: u32,
+        sequence_bits: u32,
+    },
+    /// Identifier doesn't fit in the identifier bits of layout.
+    IdentifierOutOfRange { identifier: u64, max: u64 },
+    /// Either part of [`IdentifierSplit`](crate::IdentifierSplit) exceeds 8 bits, or both of them exceed the identifier bits of layout.
+    InvalidIdentifierSplit {
+        datacenter_bits: u32,
+        worker_bits: u32,
+    },
+    /// Datacenter doesn't fit in [`IdentifierSplit`](crate::IdentifierSplit).
+    DatacenterOutOfRange { datacenter: u8, max: u8 },
+    /// Worker doesn't fit in [`IdentifierSplit`](crate::IdentifierSplit).
+    WorkerOutOfRange { worker: u8, max: u8 },
+    /// [`Snowflake`](crate::Snowflake) of `identifier` is given to the generator of `expected`.
+    IdentifierMismatch { identifier: u64, expected: u64 },
+    /// Bit 63 is set, so it can't be converted between [`Snowflake`](crate::Snowflake) and [`SnowflakeU64`](crate::SnowflakeU64).
+    SignBitSet { bits: u64 },
+    /// Block size is 0 or exceeds sequence numbers of one tick.
+    InvalidBlockSize { count: u16, capacity: u64 },
+    /// Sequence of `timestamp` (milliseconds since UNIX epoch, truncated to tick) is used up.
+    SequenceExhausted { timestamp: u64 },
+    /// `timestamp` is earlier than the last one generated at, both in milliseconds since UNIX epoch (truncated to tick).
+    TimestampInPast {
+        timestamp: u64,
+        high_water_mark: u64,
+    },
+    /// Sequence of current tick is exhausted with [`OverflowPolicy::Error`](crate::OverflowPolicy::Error), next tick starts after `retry_after`.
+    SequenceOverflow { retry_after: Duration },
+    /// Synchronous assignment has to wait `retry_after` on a thread of async runtime, which would block the runtime.
+    BlockingInAsyncRuntime { retry_after: Duration },
+    /// Issuance rate limit of [`RateLimitedGenerator`](crate::RateLimitedGenerator) is reached, next one is allowed after `retry_after`.
+    RateLimited { retry_after: Duration },
+    /// All `max_concurrent` permits of [`BoundedSnowflakeGenerator`](crate::BoundedSnowflakeGenerator) are taken.
+    ConcurrencyLimited { max_concurrent: usize },
+    /// Backfill range below the high-water mark holds only `capacity` sequence numbers.
+    BackfillRangeTooSmall { count: usize, capacity: u64 },
+    /// All identifiers in `start..end` are taken, see [`ThreadLocalSnowflake`](crate::ThreadLocalSnowflake) and [`GeneratorRegistry`](crate::GeneratorRegistry).
+    IdentifierRangeExhausted { start: u64, end: u64 },
+    /// [`GeneratorPool`](crate::GeneratorPool) has no identifier.
+    EmptyPool,
+    /// `identifier` is given more than once to [`GeneratorPool`](crate::GeneratorPool).
+    DuplicateIdentifier { identifier: u64 },
+    /// `timestamp` doesn't fit in the timestamp bits of layout, which runs out after `max`, both in milliseconds since UNIX epoch.
+    TimestampOverflow { timestamp: u64, max: u64 },
+    /// Provider returned `got` (milliseconds since UNIX epoch), rejected by [`TimestampValidation`](crate::TimestampValidation).
+    ImplausibleTimestamp { got: u64 },
+    /// Clock moved backwards beyond tolerance.
+    ClockMovedBackwards { delta: Duration },
+    /// Time unit is zero or not whole microseconds.
+    InvalidTimeUnit { time_unit: Duration },
+    /// [`random_sequence`](crate::SnowflakeConfiguration::random_sequence) is enabled with more than `max` sequence bits.
+    RandomSequenceTooWide { sequence_bits: u32, max: u32 },
+    /// Identifier can't be derived `from` the host, see [`identifier`](crate::identifier).
+    IdentifierUnavailable { from: &'static str, message: String },
+    /// Required environment variable is not set.
+    MissingEnv { name: String },
+    /// Environment variable can't be parsed.
+    InvalidEnv { name: String, value: String },
+    /// Configuration can't be loaded, `path` is absent when loading from string.
+    ConfigFile {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    /// State to restore is at `timestamp`, ahead of the clock at `now` beyond tolerance, both in milliseconds since UNIX epoch.
+    StateInFuture { timestamp: u64, now: u64 },
+    /// High-water mark can't be loaded from or saved to `path`.
+    StateFile { path: PathBuf, message: String },
+    /// Lease of `identifier` expired or is about to, see [`LeasedGenerator`](crate::LeasedGenerator).
+    LeaseExpired { identifier: u64 },
+    /// State shared by processes can't be created at or opened from `path`, see `SharedSnowflakeGenerator`.
+    SharedState { path: PathBuf, message: String },
+    /// `identifier` is locked at `path` by another process, `holder` if it's known, see [`IdentifierGuard`](crate::IdentifierGuard).
+    AlreadyInUse {
+        identifier: u64,
+        path: PathBuf,
+        holder: Option<u32>,
+    },
+    /// Lock of [`IdentifierGuard`](crate::IdentifierGuard) can't be taken at `path`.
+    GuardFile { path: PathBuf, message: String },
+}
+
+impl fmt::Display for SnowflakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnowflakeError::InvalidLayout {
+                timestamp_bits,
+                identifier_bits,
+                sequence_bits,
+            } => write!(
+                f,
+                "invalid layout {timestamp_bits}/{identifier_bits}/{sequence_bits}, \
+                 bits must sum to at most 63 with at least 1 timestamp bit"
+            ),
+            SnowflakeError::InvalidLayout128 {
+                timestamp_bits,
+                identifier_bits,
+                sequence_bits,
+            } => write!(
+                f,
+                "invalid 128bit layout {timestamp_bits}/{identifier_bits}/{sequence_bits}, \
+                 timestamp and sequence bits must sum to at most 64 with at least 1 timestamp bit, \
+                 and identifier bits must be at most 64"
+            ),
+            SnowflakeError::IdentifierOutOfRange { identifier, max } => write!(
+                f,
+                "identifier {identifier} out of range, allowed range is 0..={max}"
+            ),
+            SnowflakeError::InvalidIdentifierSplit {
+                datacenter_bits,
+                worker_bits,
+            } => write!(
+                f,
+                "invalid identifier split {datacenter_bits}/{worker_bits}, \
+                 each part must be at most 8 bits and fit in identifier bits together"
+            ),
+            SnowflakeError::DatacenterOutOfRange { datacenter, max } => write!(
+                f,
+                "datacenter {datacenter} out of range, allowed range is 0..={max}"
+            ),
+            SnowflakeError::WorkerOutOfRange { worker, max } => write!(
+                f,
+                "worker {worker} out of range, allowed range is 0..={max}"
+            ),
+            SnowflakeError::IdentifierMismatch {
+                identifier,
+                expected,
+            } => write!(
+                f,
+                "snowflake of identifier {identifier} given to generator of identifier {expected}"
+            ),
+            SnowflakeError::SignBitSet { bits } => {
+                write!(f, "snowflake bits {bits:#018x} have bit 63 set")
+            }
+            SnowflakeError::InvalidBlockSize { count, capacity } => {
+                write!(
+                    f,
+                    "block size {count} out of range, allowed range is 1..={capacity}"
+                )
+            }
+            SnowflakeError::SequenceExhausted { timestamp } => {
+                write!(f, "sequence exhausted at timestamp {timestamp}")
+            }
+            SnowflakeError::TimestampInPast {
+                timestamp,
+                high_water_mark,
+            } => write!(
+                f,
+                "timestamp {timestamp} is earlier than high-water mark {high_water_mark}"
+            ),
+            SnowflakeError::SequenceOverflow { retry_after } => {
+                write!(f, "sequence exhausted, retry after {retry_after:?}")
+            }
+            SnowflakeError::BlockingInAsyncRuntime { retry_after } => write!(
+                f,
+                "synchronous assignment would block async runtime for {retry_after:?}"
+            ),
+            SnowflakeError::RateLimited { retry_after } => {
+                write!(f, "rate limited, retry after {retry_after:?}")
+            }
+            SnowflakeError::ConcurrencyLimited { max_concurrent } => write!(
+                f,
+                "all {max_concurrent} permits of concurrent assignments are taken"
+            ),
+            SnowflakeError::BackfillRangeTooSmall { count, capacity } => write!(
+                f,
+                "backfill range holds only {capacity} snowflakes, {count} requested"
+            ),
+            SnowflakeError::IdentifierRangeExhausted { start, end } => {
+                write!(f, "all identifiers in {start}..{end} are taken")
+            }
+            SnowflakeError::EmptyPool => write!(f, "generator pool has no identifier"),
+            SnowflakeError::DuplicateIdentifier { identifier } => {
+                write!(f, "identifier {identifier} is given more than once")
+            }
+            SnowflakeError::TimestampOverflow { timestamp, max } => {
+                write!(
+                    f,
+                    "timestamp {timestamp} exceeds the layout ending at {max}"
+                )
+            }
+            SnowflakeError::ImplausibleTimestamp { got } => {
+                write!(f, "implausible timestamp {got} from provider")
+            }
+            SnowflakeError::ClockMovedBackwards { delta } => {
+                write!(f, "clock moved backwards by {delta:?}")
+            }
+            SnowflakeError::InvalidTimeUnit { time_unit } => write!(
+                f,
+                "invalid time unit {time_unit:?}, must be whole microseconds and non-zero"
+            ),
+            SnowflakeError::RandomSequenceTooWide { sequence_bits, max } => write!(
+                f,
+                "random sequence supports at most {max} sequence bits, got {sequence_bits}"
+            ),
+            SnowflakeError::IdentifierUnavailable { from, message } => {
+                write!(f, "identifier can't be derived from {from}: {message}")
+            }
+            SnowflakeError::MissingEnv { name } => {
+                write!(f, "environment variable {name} is not set")
+            }
+            SnowflakeError::InvalidEnv { name, value } => {
+                write!(f, "environment variable {name}={value:?} can't be parsed")
+            }
+            SnowflakeError::ConfigFile {
+                path: Some(path),
+                message,
+            } => write!(
+                f,
+                "failed to load configuration from {}: {message}",
+                path.display()
+            ),
+            SnowflakeError::ConfigFile {
+                path: None,
+                message,
+            } => write!(f, "failed to load configuration: {message}"),
+            SnowflakeError::StateInFuture { timestamp, now } => write!(
+                f,
+                "state at timestamp {timestamp} is ahead of the clock at {now}"
+            ),
+            SnowflakeError::StateFile { path, message } => {
+                write!(f, "state file {}: {message}", path.display())
+            }
+            SnowflakeError::LeaseExpired { identifier } => {
+                write!(f, "lease of identifier {identifier} expired")
+            }
+            SnowflakeError::SharedState { path, message } => {
+                write!(f, "shared state {}: {message}", path.display())
+            }
+            SnowflakeError::AlreadyInUse {
+                identifier,
+                path,
+                holder: Some(holder),
+            } => write!(
+                f,
+                "identifier {identifier} is in use by process {holder}, locked at {}",
+                path.display()
+            ),
+            SnowflakeError::AlreadyInUse {
+                identifier,
+                path,
+                holder: None,
+            } => write!(
+                f,
+                "identifier {identifier} is in use, locked at {}",
+                path.display()
+            ),
+            SnowflakeError::GuardFile { path, message } => {
+                write!(f, "identifier lock {}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for SnowflakeError {}
+
+/// Errors of [`SnowflakeGenerator::try_assign_now`](crate::SnowflakeGenerator::try_assign_now).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TryAssignError {
+    /// Sequence exhausted in current tick, next tick starts after `retry_after`.
+    SequenceExhausted { retry_after: Duration },
+    /// Clock is behind the last timestamp by `behind_by`.
+    ClockBehind { behind_by: Duration },
+    /// Lost too many races to other callers.
+    Contended,
+    /// See [`SnowflakeError::TimestampOverflow`](SnowflakeError::TimestampOverflow).
+    TimestampOverflow { timestamp: u64, max: u64 },
+    /// See [`SnowflakeError::ImplausibleTimestamp`](SnowflakeError::ImplausibleTimestamp).
+    ImplausibleTimestamp { got: u64 },
+    /// See [`SnowflakeError::StateFile`](SnowflakeError::StateFile).
+    StateFile { path: PathBuf, message: String },
+    /// Any other [`SnowflakeError`](SnowflakeError) from the generator.
+    Other(SnowflakeError),
+}
+
+impl fmt::Display for TryAssignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryAssignError::SequenceExhausted { retry_after } => {
+                write!(f, "sequence exhausted, retry after {retry_after:?}")
+            }
+            TryAssignError::ClockBehind { behind_by } => {
+                write!(f, "clock is behind by {behind_by:?}")
+            }
+            TryAssignError::Contended => write!(f, "too much contention"),
+            TryAssignError::ImplausibleTimestamp { got } => {
+                write!(f, "implausible timestamp {got} from provider")
+            }
+            TryAssignError::TimestampOverflow { timestamp, max } => {
+                write!(
+                    f,
+                    "timestamp {timestamp} exceeds the layout ending at {max}"
+                )
+            }
+            TryAssignError::StateFile { path, message } => {
+                write!(f, "state file {}: {message}", path.display())
+            }
+            TryAssignError::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for TryAssignError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TryAssignError::Other(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Errors of [`IdentifierProvider`](crate::IdentifierProvider).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IdError {
+    /// Every identifier is taken.
+    Exhausted,
+    /// Lease of `identifier` is released already.
+    AlreadyReleased { identifier: u64 },
+    /// Acquired identifier can't be used, see [`SnowflakeGenerator::with_identifier_provider`](crate::SnowflakeGenerator::with_identifier_provider).
+    Invalid(SnowflakeError),
+    /// Lease of `identifier` expired or is taken by someone else, stop generating with it.
+    Lost { identifier: u64 },
+    /// Coordination backend failed.
+    Backend { message: String },
+    /// `sequence` of the coordination backend is beyond identifier capacity, which would collide with earlier ones.
+    Wrapped { sequence: u64, capacity: u64 },
+}
+
+impl fmt::Display for IdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdError::Exhausted => write!(f, "every identifier is taken"),
+            IdError::AlreadyReleased { identifier } => {
+                write!(f, "lease of identifier {identifier} is released already")
+            }
+            IdError::Lost { identifier } => {
+                write!(f, "lease of identifier {identifier} is lost")
+            }
+            IdError::Invalid(err) => write!(f, "acquired identifier can't be used: {err}"),
+            IdError::Backend { message } => write!(f, "identifier backend failed: {message}"),
+            IdError::Wrapped { sequence, capacity } => write!(
+                f,
+                "sequence {sequence} wraps around identifier capacity {capacity}"
+            ),
+        }
+    }
+}
+
+impl Error for IdError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IdError::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
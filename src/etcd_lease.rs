@@ -0,0 +1,547 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{collections::HashSet, fmt, ops::Range, sync::Arc, time::Duration};
+
+use etcd_client::{
+    Client, Compare, CompareOp, GetOptions, LeaseKeepAliveStream, LeaseKeeper, PutOptions, Txn,
+    TxnOp,
+};
+use futures::future::BoxFuture;
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::{
+    identifier,
+    lease::keep_alive::{KeepAlive, OnLeaseLost},
+    IdError, IdentifierLease, IdentifierProvider, LeaseWatch,
+};
+
+/// [`IdentifierProvider`](IdentifierProvider) leasing the lowest free identifier from etcd, shared by the fleet.
+///
+/// Identifier `n` is held by key `/snowflake/{namespace}/{n}` attached to an etcd lease of `ttl`,
+/// kept alive by a Tokio task until released. Dropping the lease revokes it too, if Tokio runtime is still there.
+/// The key holds hostname and process ID of the holder, for telling who has it.
+///
+/// Once the lease can't be kept alive before expiring, the key is gone and someone else may claim it:
+/// watch it with [`EtcdIdentifierProvider::acquire_watched`](EtcdIdentifierProvider::acquire_watched)
+/// or [`EtcdIdentifierProvider::on_lease_lost`](EtcdIdentifierProvider::on_lease_lost).
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use snowflake_ng::EtcdIdentifierProvider;
+/// # async fn run() {
+/// let client = etcd_client::Client::connect(["localhost:2379"], None).await.unwrap();
+/// let provider = EtcdIdentifierProvider::new(client, "billing", Duration::from_secs(30))
+///     .with_range(0..4096);
+/// let (lease, mut watch) = provider.acquire_watched().await.unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct EtcdIdentifierProvider {
+    client: Client,
+    leasing: Leasing,
+}
+
+#[derive(Clone)]
+struct Leasing {
+    prefix: String,
+    ttl: Duration,
+    range: Range<u64>,
+    on_lost: Option<OnLeaseLost>,
+}
+
+impl EtcdIdentifierProvider {
+    /// Leasing identifiers `0..1024` under `namespace`, expiring after `ttl` (whole seconds, at least 1) without keep-alive.
+    pub fn new(client: Client, namespace: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            client,
+            leasing: Leasing {
+                prefix: format!("/snowflake/{}/", namespace.into()),
+                ttl: Duration::from_secs(ttl.as_secs().max(1)),
+                range: 0..1024,
+                on_lost: None,
+            },
+        }
+    }
+
+    /// Leasing identifiers in `range` instead, for layout other than the default.
+    pub fn with_range(mut self, range: Range<u64>) -> Self {
+        self.leasing.range = range;
+        self
+    }
+
+    /// Call `callback` with the identifier once a lease is lost.
+    pub fn on_lease_lost(mut self, callback: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.leasing.on_lost = Some(Arc::new(callback));
+        self
+    }
+
+    /// Same as [`IdentifierProvider::acquire`](IdentifierProvider::acquire), but also watching whether the lease is lost.
+    pub async fn acquire_watched(&self) -> Result<(IdentifierLease, LeaseWatch), IdError> {
+        let store = EtcdStore {
+            client: self.client.clone(),
+            keeper: Arc::default(),
+        };
+        acquire_in(store, &self.leasing).await
+    }
+}
+
+impl fmt::Debug for EtcdIdentifierProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EtcdIdentifierProvider")
+            .field("prefix", &self.leasing.prefix)
+            .field("ttl", &self.leasing.ttl)
+            .field("range", &self.leasing.range)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdentifierProvider for EtcdIdentifierProvider {
+    async fn acquire(&self) -> Result<IdentifierLease, IdError> {
+        self.acquire_watched().await.map(|(lease, _)| lease)
+    }
+}
+
+/// Commands of etcd, faked in tests.
+trait LeaseStore: Clone + Send + Sync + 'static {
+    /// Grant a lease of `ttl`, returning its ID.
+    fn grant(&self, ttl: Duration) -> BoxFuture<'_, Result<i64, IdError>>;
+
+    /// Keys under `prefix`.
+    fn keys<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<String>, IdError>>;
+
+    /// Put `key` attached to `lease` only if it's absent, `false` if it's present.
+    fn put_if_absent<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+        lease: i64,
+    ) -> BoxFuture<'a, Result<bool, IdError>>;
+
+    /// Keep `lease` alive, `false` if it's expired already.
+    fn keep_alive(&self, lease: i64) -> BoxFuture<'_, Result<bool, IdError>>;
+
+    /// Revoke `lease`, deleting keys attached to it.
+    fn revoke(&self, lease: i64) -> BoxFuture<'_, Result<(), IdError>>;
+}
+
+/// [`LeaseStore`](LeaseStore) of one lease, keeping its keep-alive stream open.
+#[derive(Clone)]
+struct EtcdStore {
+    client: Client,
+    keeper: Arc<Mutex<Option<(LeaseKeeper, LeaseKeepAliveStream)>>>,
+}
+
+impl LeaseStore for EtcdStore {
+    fn grant(&self, ttl: Duration) -> BoxFuture<'_, Result<i64, IdError>> {
+        let mut client = self.client.clone();
+        Box::pin(async move {
+            let granted = client
+                .lease_grant(ttl.as_secs() as i64, None)
+                .await
+                .map_err(backend)?;
+            Ok(granted.id())
+        })
+    }
+
+    fn keys<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<String>, IdError>> {
+        let mut client = self.client.clone();
+        Box::pin(async move {
+            let options = GetOptions::new().with_prefix().with_keys_only();
+            let found = client.get(prefix, Some(options)).await.map_err(backend)?;
+            Ok(found
+                .kvs()
+                .iter()
+                .filter_map(|it| it.key_str().ok().map(str::to_owned))
+                .collect())
+        })
+    }
+
+    fn put_if_absent<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+        lease: i64,
+    ) -> BoxFuture<'a, Result<bool, IdError>> {
+        let mut client = self.client.clone();
+        Box::pin(async move {
+            let txn = Txn::new()
+                .when([Compare::create_revision(key, CompareOp::Equal, 0)])
+                .and_then([TxnOp::put(
+                    key,
+                    value,
+                    Some(PutOptions::new().with_lease(lease)),
+                )]);
+            let done = client.txn(txn).await.map_err(backend)?;
+            Ok(done.succeeded())
+        })
+    }
+
+    fn keep_alive(&self, lease: i64) -> BoxFuture<'_, Result<bool, IdError>> {
+        Box::pin(async move {
+            let mut keeper = self.keeper.lock().await;
+            if keeper.is_none() {
+                let mut client = self.client.clone();
+                *keeper = Some(client.lease_keep_alive(lease).await.map_err(backend)?);
+            }
+
+            let (sender, stream) = keeper.as_mut().expect("keep-alive stream is opened");
+            let response = match sender.keep_alive().await {
+                Ok(()) => stream.message().await,
+                Err(err) => Err(err),
+            };
+            match response {
+                Ok(Some(it)) => Ok(it.ttl() > 0),
+                // Reopen it next time.
+                Ok(None) => {
+                    *keeper = None;
+                    Err(IdError::Backend {
+                        message: "keep-alive stream closed".to_owned(),
+                    })
+                }
+                Err(err) => {
+                    *keeper = None;
+                    Err(backend(err))
+                }
+            }
+        })
+    }
+
+    fn revoke(&self, lease: i64) -> BoxFuture<'_, Result<(), IdError>> {
+        let mut client = self.client.clone();
+        Box::pin(async move {
+            client.lease_revoke(lease).await.map_err(backend)?;
+            Ok(())
+        })
+    }
+}
+
+async fn acquire_in<S>(
+    store: S,
+    leasing: &Leasing,
+) -> Result<(IdentifierLease, LeaseWatch), IdError>
+where
+    S: LeaseStore,
+{
+    let granted = Instant::now();
+    let lease = store.grant(leasing.ttl).await?;
+    let identifier = match claim(&store, leasing, lease).await {
+        Ok(it) => it,
+        Err(err) => {
+            let _ = store.revoke(lease).await;
+            return Err(err);
+        }
+    };
+
+    let (keep_alive, watch) =
+        KeepAlive::new(identifier, leasing.ttl, granted, leasing.on_lost.clone());
+    let handle = keep_alive.spawn(
+        {
+            let store = store.clone();
+            move || {
+                let store = store.clone();
+                async move { store.keep_alive(lease).await }
+            }
+        },
+        move || {
+            let store = store.clone();
+            Box::pin(async move { store.revoke(lease).await })
+        },
+    );
+    Ok((IdentifierLease::new(identifier, handle), watch))
+}
+
+/// Put the lowest identifier absent under prefix, looking again after losing the race to others.
+async fn claim<S>(store: &S, leasing: &Leasing, lease: i64) -> Result<u64, IdError>
+where
+    S: LeaseStore,
+{
+    let holder = format!("{}/{}", identifier::hostname(), std::process::id());
+    loop {
+        let taken = store
+            .keys(&leasing.prefix)
+            .await?
+            .iter()
+            .filter_map(|it| it.strip_prefix(&leasing.prefix)?.parse().ok())
+            .collect::<HashSet<u64>>();
+        let identifier = leasing
+            .range
+            .clone()
+            .find(|it| !taken.contains(it))
+            .ok_or(IdError::Exhausted)?;
+
+        let key = format!("{}{identifier}", leasing.prefix);
+        if store.put_if_absent(&key, &holder, lease).await? {
+            return Ok(identifier);
+        }
+    }
+}
+
+fn backend(err: etcd_client::Error) -> IdError {
+    IdError::Backend {
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+            Mutex as StdMutex,
+        },
+    };
+
+    use super::*;
+
+    /// Keys and leases of etcd, on the clock of Tokio.
+    #[derive(Clone, Default)]
+    struct Fake {
+        state: Arc<StdMutex<State>>,
+        next_lease: Arc<AtomicI64>,
+        failing: Arc<AtomicBool>,
+        /// Others putting right before, as racing providers.
+        raced: Arc<AtomicUsize>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        keys: BTreeMap<String, i64>,
+        leases: BTreeMap<i64, (Duration, Instant)>,
+    }
+
+    impl State {
+        fn expire(&mut self) {
+            let now = Instant::now();
+            self.leases.retain(|_, (_, expiry)| *expiry > now);
+            let leases = &self.leases;
+            self.keys.retain(|_, lease| leases.contains_key(lease));
+        }
+    }
+
+    impl Fake {
+        fn check(&self) -> Result<(), IdError> {
+            match self.failing.load(Ordering::Relaxed) {
+                true => Err(IdError::Backend {
+                    message: "connection refused".to_owned(),
+                }),
+                false => Ok(()),
+            }
+        }
+
+        fn keys(&self) -> Vec<String> {
+            let mut state = self.state.lock().unwrap();
+            state.expire();
+            state.keys.keys().cloned().collect()
+        }
+    }
+
+    impl LeaseStore for Fake {
+        fn grant(&self, ttl: Duration) -> BoxFuture<'_, Result<i64, IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                let lease = self.next_lease.fetch_add(1, Ordering::Relaxed) + 1;
+                let mut state = self.state.lock().unwrap();
+                state.leases.insert(lease, (ttl, Instant::now() + ttl));
+                Ok(lease)
+            })
+        }
+
+        fn keys<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<String>, IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                Ok(Fake::keys(self)
+                    .into_iter()
+                    .filter(|it| it.starts_with(prefix))
+                    .collect())
+            })
+        }
+
+        fn put_if_absent<'a>(
+            &'a self,
+            key: &'a str,
+            _value: &'a str,
+            lease: i64,
+        ) -> BoxFuture<'a, Result<bool, IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                let mut state = self.state.lock().unwrap();
+                state.expire();
+                if self
+                    .raced
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |it| it.checked_sub(1))
+                    .is_ok()
+                {
+                    state.leases.insert(
+                        0,
+                        (Duration::MAX, Instant::now() + Duration::from_secs(3600)),
+                    );
+                    state.keys.insert(key.to_owned(), 0);
+                    return Ok(false);
+                }
+
+                if state.keys.contains_key(key) || !state.leases.contains_key(&lease) {
+                    return Ok(false);
+                }
+                state.keys.insert(key.to_owned(), lease);
+                Ok(true)
+            })
+        }
+
+        fn keep_alive(&self, lease: i64) -> BoxFuture<'_, Result<bool, IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                let mut state = self.state.lock().unwrap();
+                state.expire();
+                Ok(match state.leases.get_mut(&lease) {
+                    Some((ttl, expiry)) => {
+                        *expiry = Instant::now() + *ttl;
+                        true
+                    }
+                    None => false,
+                })
+            })
+        }
+
+        fn revoke(&self, lease: i64) -> BoxFuture<'_, Result<(), IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                let mut state = self.state.lock().unwrap();
+                state.leases.remove(&lease);
+                state.expire();
+                Ok(())
+            })
+        }
+    }
+
+    fn leasing(range: Range<u64>) -> Leasing {
+        Leasing {
+            prefix: "/snowflake/billing/".to_owned(),
+            ttl: Duration::from_secs(30),
+            range,
+            on_lost: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire_release() {
+        let store = Fake::default();
+        let leasing = leasing(4096..4099);
+        let mut leases = Vec::new();
+        for _ in 0..3 {
+            leases.push(acquire_in(store.clone(), &leasing).await.unwrap().0);
+        }
+        let identifiers = leases.iter().map(|it| it.identifier()).collect::<Vec<_>>();
+        assert_eq!(identifiers, [4096, 4097, 4098]);
+        assert!(matches!(
+            acquire_in(store.clone(), &leasing).await,
+            Err(IdError::Exhausted)
+        ));
+        // Lease of the failed one is revoked.
+        assert_eq!(store.state.lock().unwrap().leases.len(), 3);
+
+        // Kept alive well beyond ttl.
+        tokio::time::sleep(Duration::from_secs(300)).await;
+        assert_eq!(store.keys().len(), 3);
+
+        leases[1].release().await.unwrap();
+        assert_eq!(
+            store.keys(),
+            ["/snowflake/billing/4096", "/snowflake/billing/4098"]
+        );
+        let (reclaimed, _) = acquire_in(store.clone(), &leasing).await.unwrap();
+        assert_eq!(reclaimed.identifier(), 4097);
+
+        // Dropping revokes too.
+        drop(leases);
+        tokio::task::yield_now().await;
+        assert_eq!(store.keys(), ["/snowflake/billing/4097"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_claim_raced() {
+        let store = Fake::default();
+        store.raced.store(2, Ordering::Relaxed);
+        let (lease, _) = acquire_in(store.clone(), &leasing(0..1024)).await.unwrap();
+        // 0 and 1 are taken by others right before putting.
+        assert_eq!(lease.identifier(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_contention() {
+        let store = Fake::default();
+        let leasing = leasing(0..16);
+        let claims = (0..2)
+            .map(|_| {
+                let (store, leasing) = (store.clone(), leasing.clone());
+                tokio::spawn(async move {
+                    let mut leases = Vec::new();
+                    while let Ok((lease, _)) = acquire_in(store.clone(), &leasing).await {
+                        leases.push(lease);
+                    }
+                    leases
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut identifiers = Vec::new();
+        for claim in claims {
+            identifiers.extend(claim.await.unwrap().iter().map(|it| it.identifier()));
+        }
+        identifiers.sort();
+        assert_eq!(identifiers, (0..16).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lease_lost() {
+        let store = Fake::default();
+        let lost = Arc::new(AtomicI64::new(-1));
+        let leasing = Leasing {
+            on_lost: Some(Arc::new({
+                let lost = lost.clone();
+                move |it| lost.store(it as i64, Ordering::Relaxed)
+            })),
+            ..leasing(0..1024)
+        };
+        let (_lease, mut watch) = acquire_in(store.clone(), &leasing).await.unwrap();
+
+        // Partitioned until expired.
+        store.failing.store(true, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        assert_eq!(watch.check(), Ok(()));
+        assert_eq!(watch.lost().await, Some(IdError::Lost { identifier: 0 }));
+        assert_eq!(lost.load(Ordering::Relaxed), 0);
+
+        // Revoked by others.
+        store.failing.store(false, Ordering::Relaxed);
+        let (_lease, mut watch) = acquire_in(store.clone(), &leasing).await.unwrap();
+        store.state.lock().unwrap().leases.clear();
+        assert_eq!(watch.lost().await, Some(IdError::Lost { identifier: 0 }));
+    }
+
+    /// Against a real etcd at `SNOWFLAKE_TEST_ETCD`, like `localhost:2379`.
+    #[tokio::test]
+    async fn test_etcd() {
+        let Ok(endpoint) = std::env::var("SNOWFLAKE_TEST_ETCD") else {
+            return;
+        };
+        let client = Client::connect([endpoint], None).await.unwrap();
+        let namespace = format!("test-{:x}", rand::random::<u32>());
+        let first = EtcdIdentifierProvider::new(client.clone(), &namespace, Duration::from_secs(5))
+            .with_range(0..2);
+        let second = first.clone();
+
+        let (a, b) = tokio::join!(first.acquire(), second.acquire());
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_ne!(a.identifier(), b.identifier());
+        assert!(matches!(first.acquire().await, Err(IdError::Exhausted)));
+
+        a.release().await.unwrap();
+        assert_eq!(second.acquire().await.unwrap().identifier(), a.identifier());
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, PoisonError, RwLock,
+    },
+    time::Duration,
+};
+
+/// Anomalous event of [`SnowflakeGenerator`](crate::SnowflakeGenerator), see [`SnowflakeGenerator::on_event`](crate::SnowflakeGenerator::on_event).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum GeneratorEvent {
+    /// Sequence exhausted, and an assignment waited `waited` in total for the following ticks.
+    SequenceExhausted { waited: Duration },
+    /// The clock is observed behind the last timestamp by `delta`.
+    ClockBackwards { delta: Duration },
+    /// Timestamp runs out of the layout in `remaining`, reported at most once per hour.
+    TimestampOverflowNear { remaining: Duration },
+    /// Another live instance `other_instance` heartbeats with the same identifier, see [`CollisionDetector`](crate::CollisionDetector).
+    IdentifierConflict { other_instance: u128 },
+}
+
+type Hook = Arc<dyn Fn(GeneratorEvent) + Send + Sync>;
+
+/// Registered hooks, copied on write so emitting never holds the lock while calling them.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    hooks: Arc<RwLock<Arc<[Hook]>>>,
+    /// Tick since when [`GeneratorEvent::TimestampOverflowNear`](GeneratorEvent::TimestampOverflowNear) was last reported, plus 1.
+    overflow_reported: AtomicU64,
+}
+
+impl Hooks {
+    pub(crate) fn register(&self, hook: Hook) {
+        let mut hooks = self.hooks.write().unwrap_or_else(PoisonError::into_inner);
+        *hooks = hooks.iter().cloned().chain([hook]).collect();
+    }
+
+    /// Same hooks registered, but nothing reported yet.
+    pub(crate) fn copied(&self) -> Self {
+        Self {
+            hooks: Arc::new(RwLock::new(
+                self.hooks
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            )),
+            overflow_reported: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn emit(&self, event: GeneratorEvent) {
+        emit(&self.hooks, event)
+    }
+
+    /// Emitting to the same hooks, including ones registered later, from outside of the generator.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub(crate) fn emitter(&self) -> Emitter {
+        Emitter {
+            hooks: self.hooks.clone(),
+        }
+    }
+
+    /// Whether overflow near at `tick` should be reported, if `interval` ticks passed since the last time.
+    pub(crate) fn overflow_due(&self, tick: u64, interval: u64) -> bool {
+        let last = self.overflow_reported.load(Ordering::Relaxed);
+        (last == 0 || tick >= (last - 1).saturating_add(interval))
+            && self
+                .overflow_reported
+                .compare_exchange(last, tick + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+    }
+}
+
+/// See [`Hooks::emitter`](Hooks::emitter).
+#[derive(Clone)]
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+pub(crate) struct Emitter {
+    hooks: Arc<RwLock<Arc<[Hook]>>>,
+}
+
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+impl Emitter {
+    pub(crate) fn emit(&self, event: GeneratorEvent) {
+        emit(&self.hooks, event)
+    }
+}
+
+fn emit(hooks: &RwLock<Arc<[Hook]>>, event: GeneratorEvent) {
+    let hooks = hooks.read().unwrap_or_else(PoisonError::into_inner).clone();
+    for hook in hooks.iter() {
+        hook(event);
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hooks = self.hooks.read().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Hooks").field("len", &hooks.len()).finish()
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    collections::VecDeque,
+    future::poll_fn,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+    task::{Poll, Waker},
+};
+
+/// FIFO queue of assignments losing too many times, see [`SnowflakeGenerator::with_fair_admission`](crate::SnowflakeGenerator::with_fair_admission).
+///
+/// Only the head one goes on trying, the next one is woken once it's done.
+#[derive(Debug)]
+pub(crate) struct FairQueue {
+    pub(crate) max_attempts: u32,
+    /// Length of `waiting`, so checking it never locks.
+    len: AtomicUsize,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    next: u64,
+    waiting: VecDeque<(u64, Option<Waker>)>,
+}
+
+/// Place in [`FairQueue`](FairQueue), leaving it once dropped.
+pub(crate) struct Turn<'a> {
+    queue: &'a FairQueue,
+    id: u64,
+}
+
+impl FairQueue {
+    pub(crate) fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            len: AtomicUsize::new(0),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Whether nobody is queued, or in turn.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len.load(Ordering::Acquire) == 0
+    }
+
+    /// Join the end, waiting until it's the head. It's the turn until the returned one is dropped.
+    pub(crate) async fn turn(&self) -> Turn<'_> {
+        let turn = {
+            let mut state = self.lock();
+            let id = state.next;
+            state.next += 1;
+            state.waiting.push_back((id, None));
+            self.len.store(state.waiting.len(), Ordering::Release);
+            Turn { queue: self, id }
+        };
+
+        // Dropping the future leaves with `turn`.
+        poll_fn(|cx| {
+            let mut state = self.lock();
+            let position = state.waiting.iter().position(|(id, _)| *id == turn.id);
+            match position {
+                Some(0) | None => Poll::Ready(()),
+                Some(position) => {
+                    state.waiting[position].1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+        turn
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock();
+        let Some(position) = state.waiting.iter().position(|(id, _)| *id == self.id) else {
+            return;
+        };
+        state.waiting.remove(position);
+        self.queue.len.store(state.waiting.len(), Ordering::Release);
+        if position == 0 {
+            if let Some((_, Some(waker))) = state.waiting.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, sync::Arc, time::Duration};
+
+    use tokio::time::{sleep, timeout};
+
+    use crate::{
+        provider::MockProvider, Sleeper, SnowflakeConfiguration, SnowflakeGenerator,
+        TimestampValidation,
+    };
+
+    /// Sleeping on paused time even without feature `tokio`.
+    struct TokioSleeper;
+
+    impl Sleeper for TokioSleeper {
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+            sleep(duration)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fair_order() {
+        let generator = Arc::new(
+            SnowflakeGenerator::with_cfg(
+                SnowflakeConfiguration::with_identifier(3)
+                    .with_timestamp_validation(TimestampValidation::Off),
+            )
+            .with_fair_admission(2)
+            .with_sleeper(TokioSleeper),
+        );
+        let provider = Arc::new(MockProvider::new(100_000));
+        generator.assign_many(provider.as_ref(), 4096).await;
+
+        // Everyone blocked on exhaustion, queued in the order coming.
+        let mut tasks = Vec::new();
+        for _ in 0..32 {
+            let (generator, provider) = (generator.clone(), provider.clone());
+            tasks.push(tokio::spawn(async move {
+                generator.assign(provider.as_ref()).await
+            }));
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!generator.fair.as_ref().unwrap().is_empty());
+
+        provider.set(100_001);
+        let mut snowflakes = Vec::new();
+        for task in tasks {
+            snowflakes.push(task.await.unwrap());
+        }
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert!(snowflakes.iter().all(|it| it.timestamp() == 100_001));
+        assert!(generator.fair.as_ref().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fair_cancelled() {
+        let generator = SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration::with_identifier(3)
+                .with_timestamp_validation(TimestampValidation::Off),
+        )
+        .with_fair_admission(0)
+        .with_sleeper(TokioSleeper);
+        let provider = MockProvider::new(100_000);
+        generator.assign_many(&provider, 4096).await;
+
+        // Leaving the queue when given up, head or not.
+        let (head, next) = tokio::join!(
+            timeout(Duration::from_millis(7), generator.assign(&provider)),
+            timeout(Duration::from_millis(5), generator.assign(&provider)),
+        );
+        assert!(head.is_err() && next.is_err());
+        assert!(generator.fair.as_ref().unwrap().is_empty());
+
+        provider.set(100_001);
+        assert_eq!(generator.assign(&provider).await.timestamp(), 100_001);
+    }
+}
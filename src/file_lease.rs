@@ -0,0 +1,208 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use futures::future::BoxFuture;
+
+use crate::{IdError, IdentifierLease, IdentifierProvider, LeaseHandle};
+
+/// [`IdentifierProvider`](IdentifierProvider) locking files `0` to `1023` of a directory, for processes on one host.
+///
+/// The first file not locked by others is locked exclusively with [`File::try_lock`](File::try_lock),
+/// `flock` on Unix, and held until the lease is released or dropped. A crashed process releases it too,
+/// as the file is closed by the OS. The files hold ID of the process last locked them, for telling who has it.
+///
+/// The directory is created if missing, and should be on a local filesystem where the locks are honoured.
+///
+/// ```
+/// # use snowflake_ng::{FileIdentifierProvider, IdentifierProvider};
+/// # futures::executor::block_on(async {
+/// # let dir = std::env::temp_dir().join(format!("snowflake-ng-doc-{}", std::process::id()));
+/// let provider = FileIdentifierProvider::new(&dir);
+/// let first = provider.acquire().await.unwrap();
+/// let second = provider.acquire().await.unwrap();
+/// assert_ne!(first.identifier(), second.identifier());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileIdentifierProvider {
+    dir: PathBuf,
+    capacity: u64,
+}
+
+impl FileIdentifierProvider {
+    /// Locking files `0..1024` in `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            capacity: 1024,
+        }
+    }
+
+    /// Locking files `0..capacity` instead, for layout other than the default.
+    pub fn with_capacity(mut self, capacity: u64) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Lock the lowest free one, it's blocking but only for a short while.
+    fn lock(&self) -> Result<(u64, File), IdError> {
+        fs::create_dir_all(&self.dir).map_err(|err| IdError::Backend {
+            message: format!("can't create {}: {err}", self.dir.display()),
+        })?;
+
+        for identifier in 0..self.capacity {
+            let path = self.dir.join(identifier.to_string());
+            let mut file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .map_err(|err| failed(&path, err))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Error(err)) => return Err(failed(&path, err)),
+            }
+
+            // Only telling who has it, locked anyway.
+            let _ = file
+                .set_len(0)
+                .and_then(|_| writeln!(file, "{}", std::process::id()));
+            return Ok((identifier, file));
+        }
+
+        Err(IdError::Exhausted)
+    }
+}
+
+impl IdentifierProvider for FileIdentifierProvider {
+    async fn acquire(&self) -> Result<IdentifierLease, IdError> {
+        let (identifier, file) = self.lock()?;
+        Ok(IdentifierLease::new(
+            identifier,
+            FileHandle {
+                file: Mutex::new(Some(file)),
+            },
+        ))
+    }
+}
+
+/// Closing the file unlocks it, either on releasing or dropping.
+struct FileHandle {
+    file: Mutex<Option<File>>,
+}
+
+impl LeaseHandle for FileHandle {
+    fn release(&self) -> BoxFuture<'_, Result<(), IdError>> {
+        self.file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
+
+fn failed(path: &Path, err: std::io::Error) -> IdError {
+    IdError::Backend {
+        message: format!("can't lock {}: {err}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::testing::temporary;
+
+    #[test]
+    fn test_file_provider() {
+        // Created if missing.
+        let dir = temporary("locks").join("nested");
+        let provider = FileIdentifierProvider::new(&dir).with_capacity(2);
+        let first = block_on(provider.acquire()).unwrap();
+        let second = block_on(provider.acquire()).unwrap();
+        assert_eq!((first.identifier(), second.identifier()), (0, 1));
+        assert_eq!(
+            block_on(provider.acquire()).unwrap_err(),
+            IdError::Exhausted
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("1")).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+
+        block_on(first.release()).unwrap();
+        let reclaimed = block_on(provider.acquire()).unwrap();
+        assert_eq!(reclaimed.identifier(), 0);
+
+        drop(second);
+        assert_eq!(block_on(provider.acquire()).unwrap().identifier(), 1);
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_file_provider_threads() {
+        let dir = temporary("threads");
+        let provider = FileIdentifierProvider::new(&dir).with_capacity(16);
+        let leases = thread::scope(|scope| {
+            let claims = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..4)
+                            .map(|_| block_on(provider.acquire()).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            claims
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut identifiers = leases.iter().map(|it| it.identifier()).collect::<Vec<_>>();
+        identifiers.sort();
+        assert_eq!(identifiers, (0..16).collect::<Vec<_>>());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_provider_unavailable() {
+        // Directory can't be created where a file is.
+        let file = temporary("not-a-directory");
+        fs::write(&file, "").unwrap();
+        let provider = FileIdentifierProvider::new(&file);
+        assert!(matches!(
+            block_on(provider.acquire()),
+            Err(IdError::Backend { .. })
+        ));
+
+        // Nor is a slot where a directory is.
+        let dir = temporary("blocked");
+        fs::create_dir_all(dir.join("0")).unwrap();
+        let provider = FileIdentifierProvider::new(&dir);
+        let Err(IdError::Backend { message }) = block_on(provider.acquire()) else {
+            panic!("slot locked");
+        };
+        assert!(message.contains("can't lock"));
+
+        fs::remove_file(file).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
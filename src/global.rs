@@ -0,0 +1,232 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Process-global [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) with [`StdProvider`](StdProvider), initialized once.
+//!
+//! Call [`init`](init) early on startup. Assigning before that initializes it with [`SnowflakeConfiguration::default`](SnowflakeConfiguration::default)
+//! (random identifier), and [`init`](init) fails afterwards, so it's never replaced once used.
+//! Invalid configuration is rejected by [`init`](init) before anything is initialized.
+//!
+//! ```
+//! # use snowflake_ng::{global, SnowflakeConfiguration};
+//! global::init(SnowflakeConfiguration::with_identifier(1)).unwrap();
+//!
+//! let snowflake = global::assign_sync();
+//! assert_eq!(snowflake.identifier(), 1);
+//! ```
+
+use std::{
+    error::Error,
+    fmt,
+    sync::{Arc, OnceLock},
+};
+
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+
+#[cfg(any(feature = "async", feature = "sync"))]
+use crate::Snowflake;
+use crate::{
+    provider::StdProvider, PersistedSnowflakeGenerator, SnowflakeConfiguration, SnowflakeError,
+    SnowflakeGenerator, SnowflakeSource,
+};
+
+static GENERATOR: OnceLock<PersistedSnowflakeGenerator<StdProvider>> = OnceLock::new();
+
+/// Why [`init`](init) failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InitError {
+    /// Global generator is initialized already, either by [`init`](init) or by assigning.
+    AlreadyInitialized,
+    /// Configuration is rejected by [`SnowflakeConfiguration::validate`](SnowflakeConfiguration::validate).
+    InvalidConfiguration(SnowflakeError),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::AlreadyInitialized => write!(f, "global generator is initialized already"),
+            InitError::InvalidConfiguration(err) => {
+                write!(f, "invalid configuration of global generator: {err}")
+            }
+        }
+    }
+}
+
+impl Error for InitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InitError::InvalidConfiguration(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Initialize global generator with `cfg`, only the first call with valid `cfg` succeeds.
+pub fn init(cfg: SnowflakeConfiguration) -> Result<(), InitError> {
+    init_in(&GENERATOR, cfg)
+}
+
+/// Global generator, `None` if neither initialized nor used yet.
+pub fn try_get() -> Option<&'static PersistedSnowflakeGenerator<StdProvider>> {
+    GENERATOR.get()
+}
+
+/// Assign a [`Snowflake`](Snowflake) with global generator, see [`PersistedSnowflakeGenerator::assign`](PersistedSnowflakeGenerator::assign).
+#[cfg(feature = "async")]
+pub async fn assign() -> Snowflake {
+    get_in(&GENERATOR).assign().await
+}
+
+/// Same as [`assign`](assign), but blocking current thread.
+#[cfg(feature = "sync")]
+pub fn assign_sync() -> Snowflake {
+    get_in(&GENERATOR).assign_sync()
+}
+
+/// [`SnowflakeSource`](SnowflakeSource) of global generator, for injecting it where a source is expected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalSource;
+
+impl SnowflakeSource for GlobalSource {
+    #[cfg(feature = "async")]
+    fn assign(&self) -> BoxFuture<'_, Snowflake> {
+        Box::pin(assign())
+    }
+
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake {
+        assign_sync()
+    }
+}
+
+fn init_in(
+    cell: &OnceLock<PersistedSnowflakeGenerator<StdProvider>>,
+    cfg: SnowflakeConfiguration,
+) -> Result<(), InitError> {
+    cfg.validate().map_err(InitError::InvalidConfiguration)?;
+    let mut cfg = Some(cfg);
+    cell.get_or_init(|| persisted(cfg.take().expect("initialized only once")));
+
+    match cfg {
+        Some(_) => Err(InitError::AlreadyInitialized),
+        None => Ok(()),
+    }
+}
+
+fn get_in(
+    cell: &OnceLock<PersistedSnowflakeGenerator<StdProvider>>,
+) -> &PersistedSnowflakeGenerator<StdProvider> {
+    cell.get_or_init(|| persisted(SnowflakeConfiguration::default()))
+}
+
+fn persisted(cfg: SnowflakeConfiguration) -> PersistedSnowflakeGenerator<StdProvider> {
+    PersistedSnowflakeGenerator::new(
+        Arc::new(SnowflakeGenerator::with_cfg(cfg)),
+        Arc::new(StdProvider),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Barrier};
+
+    use super::*;
+
+    #[test]
+    fn test_init_once() {
+        let cell = OnceLock::new();
+        assert!(cell.get().is_none());
+        assert_eq!(
+            init_in(&cell, SnowflakeConfiguration::with_identifier(3)),
+            Ok(())
+        );
+        assert_eq!(
+            init_in(&cell, SnowflakeConfiguration::with_identifier(4)),
+            Err(InitError::AlreadyInitialized)
+        );
+        assert_eq!(get_in(&cell).identifier(), 3);
+
+        // Using it first initializes with the default.
+        let cell = OnceLock::new();
+        let identifier = get_in(&cell).identifier();
+        assert_eq!(
+            init_in(&cell, SnowflakeConfiguration::with_identifier(4)),
+            Err(InitError::AlreadyInitialized)
+        );
+        assert_eq!(get_in(&cell).identifier(), identifier);
+    }
+
+    #[test]
+    fn test_init_invalid() {
+        let cell = OnceLock::new();
+        assert!(matches!(
+            init_in(&cell, SnowflakeConfiguration::with_identifier(5000)),
+            Err(InitError::InvalidConfiguration(
+                SnowflakeError::IdentifierOutOfRange { .. }
+            ))
+        ));
+        assert!(cell.get().is_none());
+
+        // Still free for a valid one.
+        assert_eq!(
+            init_in(&cell, SnowflakeConfiguration::with_identifier(5)),
+            Ok(())
+        );
+        assert_eq!(get_in(&cell).identifier(), 5);
+    }
+
+    #[test]
+    fn test_concurrent_first_use() {
+        let cell = OnceLock::new();
+        let barrier = Barrier::new(8);
+        let (initialized, snowflakes) = std::thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|identifier| {
+                    let (cell, barrier) = (&cell, &barrier);
+                    scope.spawn(move || {
+                        barrier.wait();
+                        let initialized =
+                            init_in(cell, SnowflakeConfiguration::with_identifier(identifier))
+                                .is_ok();
+                        let snowflakes = (0..1000)
+                            .map(|_| get_in(cell).assign_sync())
+                            .collect::<Vec<_>>();
+                        (initialized, snowflakes)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles.into_iter().map(|it| it.join().unwrap()).fold(
+                (0, Vec::new()),
+                |(count, mut all), (initialized, it)| {
+                    all.extend(it);
+                    (count + initialized as usize, all)
+                },
+            )
+        });
+
+        assert_eq!(initialized, 1);
+        let identifier = cell.get().unwrap().identifier();
+        assert!(snowflakes.iter().all(|it| it.identifier() == identifier));
+        assert_eq!(snowflakes.iter().collect::<HashSet<_>>().len(), 8000);
+    }
+
+    #[test]
+    fn test_global() {
+        // Others may use the real one, but never initialize it.
+        let snowflake = assign_sync();
+        let generator = try_get().unwrap();
+        assert_eq!(snowflake.identifier(), generator.identifier());
+        assert_eq!(
+            init(SnowflakeConfiguration::with_identifier(1)),
+            Err(InitError::AlreadyInitialized)
+        );
+        assert!(assign_sync() > snowflake);
+    }
+}
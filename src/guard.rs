@@ -0,0 +1,217 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{SnowflakeConfiguration, SnowflakeError, SnowflakeGenerator};
+
+/// Exclusive lock of an identifier on this host, so a process started twice can't generate duplicates.
+///
+/// It's `flock` on Unix (taken with [`File::try_lock`](File::try_lock)) of `{identifier}.lock` in a directory,
+/// `snowflake-ng` in the temporary directory by default. The lock is held until dropped, and released by the OS
+/// once the process dies, so a crashed one never blocks the next. The file holds ID of the process last locked it.
+///
+/// Unlike [`FileIdentifierProvider`](crate::FileIdentifierProvider), it's for identifiers configured up front, and picks nothing.
+///
+/// ```
+/// # use snowflake_ng::{IdentifierGuard, SnowflakeError};
+/// # let dir = std::env::temp_dir().join(format!("snowflake-ng-doc-guard-{}", std::process::id()));
+/// let guard = IdentifierGuard::acquire_in(&dir, 7).unwrap();
+/// assert!(matches!(
+///     IdentifierGuard::acquire_in(&dir, 7),
+///     Err(SnowflakeError::AlreadyInUse { identifier: 7, .. })
+/// ));
+///
+/// drop(guard);
+/// assert!(IdentifierGuard::acquire_in(&dir, 7).is_ok());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct IdentifierGuard {
+    identifier: u64,
+    path: PathBuf,
+    /// Closing it unlocks.
+    #[allow(dead_code)]
+    file: File,
+}
+
+impl IdentifierGuard {
+    /// Lock `identifier` in the default directory, or [`SnowflakeError::AlreadyInUse`](SnowflakeError::AlreadyInUse) if someone else has it.
+    pub fn acquire(identifier: u64) -> Result<Self, SnowflakeError> {
+        Self::acquire_in(std::env::temp_dir().join("snowflake-ng"), identifier)
+    }
+
+    /// Same as [`IdentifierGuard::acquire`](IdentifierGuard::acquire), but locking in `dir`, which is created if missing.
+    ///
+    /// Every process has to agree on it, and it should be on a local filesystem where the locks are honoured.
+    pub fn acquire_in(dir: impl AsRef<Path>, identifier: u64) -> Result<Self, SnowflakeError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|err| error(dir, err))?;
+
+        let path = dir.join(format!("{identifier}.lock"));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|err| error(&path, err))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                // Only telling who has it, it may be still writing.
+                let holder = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|it| it.trim().parse().ok());
+                return Err(SnowflakeError::AlreadyInUse {
+                    identifier,
+                    path,
+                    holder,
+                });
+            }
+            Err(TryLockError::Error(err)) => return Err(error(&path, err)),
+        }
+
+        let _ = file
+            .set_len(0)
+            .and_then(|_| writeln!(file, "{}", std::process::id()));
+        Ok(Self {
+            identifier,
+            path,
+            file,
+        })
+    }
+
+    /// The identifier locked.
+    pub fn identifier(&self) -> u64 {
+        self.identifier
+    }
+
+    /// Where the lock is.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SnowflakeGenerator {
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) of `identifier`, holding [`IdentifierGuard`](IdentifierGuard) of it until dropped.
+    ///
+    /// For other configuration or directory, acquire the guard first and keep it as long as generating.
+    /// Clones hold it too, so it's released once all of them are dropped.
+    pub fn with_guarded_identifier(identifier: u64) -> Result<Self, SnowflakeError> {
+        let mut generator =
+            Self::try_with_cfg(SnowflakeConfiguration::with_identifier(identifier))?;
+        generator.guard = Some(Arc::new(IdentifierGuard::acquire(identifier)?));
+        Ok(generator)
+    }
+}
+
+fn error(path: &Path, err: std::io::Error) -> SnowflakeError {
+    SnowflakeError::GuardFile {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        process::{Command, Stdio},
+    };
+
+    use super::*;
+    use crate::testing::temporary;
+
+    /// Run by [`test_guard_processes`](test_guard_processes) in a child, locking identifier 7 as told.
+    #[test]
+    fn test_guard_child() {
+        let Ok(dir) = env::var("SNOWFLAKE_TEST_GUARD") else {
+            return;
+        };
+        let acquired = IdentifierGuard::acquire_in(&dir, 7);
+        match env::var("SNOWFLAKE_TEST_GUARD_EXPECT").unwrap().as_str() {
+            "in-use" => assert!(matches!(
+                acquired,
+                Err(SnowflakeError::AlreadyInUse { identifier: 7, holder: Some(holder), .. })
+                    if holder != std::process::id()
+            )),
+            // Dying without dropping it.
+            _ => {
+                std::mem::forget(acquired.unwrap());
+                std::process::exit(0);
+            }
+        }
+    }
+
+    fn child(dir: &Path, expect: &str) -> bool {
+        Command::new(env::current_exe().unwrap())
+            .args(["--exact", "guard::tests::test_guard_child"])
+            .env("SNOWFLAKE_TEST_GUARD", dir)
+            .env("SNOWFLAKE_TEST_GUARD_EXPECT", expect)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    #[test]
+    fn test_guard_processes() {
+        let dir = temporary("guard");
+        let guard = IdentifierGuard::acquire_in(&dir, 7).unwrap();
+        assert_eq!(guard.path(), dir.join("7.lock"));
+        assert!(child(&dir, "in-use"));
+
+        // Others are free.
+        let other = IdentifierGuard::acquire_in(&dir, 8).unwrap();
+        assert_eq!(other.identifier(), 8);
+
+        // Crashed holder doesn't keep it.
+        drop(guard);
+        assert!(child(&dir, "acquire"));
+        assert!(IdentifierGuard::acquire_in(&dir, 7).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_guarded_identifier() {
+        let identifier = 1000 + std::process::id() as u64 % 24;
+        let generator = SnowflakeGenerator::with_guarded_identifier(identifier).unwrap();
+        assert_eq!(generator.identifier(), identifier);
+        assert!(matches!(
+            SnowflakeGenerator::with_guarded_identifier(identifier),
+            Err(SnowflakeError::AlreadyInUse { .. })
+        ));
+
+        // Clones hold it as long as generating.
+        let cloned = generator.clone();
+        drop(generator);
+        assert!(matches!(
+            IdentifierGuard::acquire(identifier),
+            Err(SnowflakeError::AlreadyInUse { .. })
+        ));
+        drop(cloned);
+        assert!(IdentifierGuard::acquire(identifier).is_ok());
+
+        // Unless generating with another identifier.
+        let generator = SnowflakeGenerator::with_guarded_identifier(identifier).unwrap();
+        let other = generator.clone_with_identifier(identifier + 1);
+        assert!(other.guard.is_none());
+        drop(generator);
+        assert!(IdentifierGuard::acquire(identifier).is_ok());
+
+        assert!(matches!(
+            SnowflakeGenerator::with_guarded_identifier(1024),
+            Err(SnowflakeError::IdentifierOutOfRange { .. })
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Deriving identifiers from stable strings, rather than hard-coding numbers or trusting randomness.
+//!
+//! ```
+//! # use snowflake_ng::{identifier, SnowflakeConfiguration};
+//! let identifier = identifier::derive(&[b"billing", b"az-1", b"billing-7f9c"]);
+//! let cfg = SnowflakeConfiguration::with_identifier(identifier);
+//! assert!(cfg.identifier() < 1024);
+//! ```
+//!
+//! # Collision
+//!
+//! Derived identifiers are as good as random ones of the same width, but the same inputs always give the same one,
+//! so collisions can be checked up front by deriving all of them. With 10bit identifier, `n` services collide
+//! with probability `1 - (1023/1024) * (1022/1024) * ... * ((1024 - n + 1)/1024)`, about `1 - e^(-n(n-1)/2048)`:
+//!
+//! | Services | Probability of any collision |
+//! |----------|------------------------------|
+//! | 2        | 0.1%                         |
+//! | 10       | 4.3%                         |
+//! | 20       | 17%                          |
+//! | 38       | 50%                          |
+//! | 100      | 99.3%                        |
+//!
+//! So beyond a handful of services, check for collisions or assign identifiers explicitly.
+
+use crate::{layouts, SnowflakeError};
+
+mod ip;
+mod k8s;
+#[cfg(feature = "identifier-mac")]
+mod mac;
+
+pub use ip::{from_ip, from_private_ipv4, from_private_ipv4_with, RFC1918};
+pub use k8s::{from_k8s, from_k8s_with, K8sSource, PODINFO_NAME};
+#[cfg(feature = "identifier-mac")]
+pub use mac::{from_mac, from_mac_of};
+
+/// Stable 64bit hash of `seed_components`, the same across runs, platforms and versions of this crate.
+///
+/// Components are length-prefixed, so `["ab", "c"]` and `["a", "bc"]` differ. Pass it to
+/// [`SnowflakeConfiguration::with_identifier`](crate::SnowflakeConfiguration::with_identifier), which truncates it to identifier bits.
+pub fn derive(seed_components: &[&[u8]]) -> u64 {
+    let mut fnv = Fnv::default();
+    for component in seed_components {
+        fnv.write(&(component.len() as u64).to_le_bytes());
+        fnv.write(component);
+    }
+
+    mix(fnv.0)
+}
+
+/// [`derive`](derive) of hostname and process ID, the same for one process.
+///
+/// Hostname is read with `gethostname` on Unix, or from `COMPUTERNAME` on Windows.
+/// Returns [`SnowflakeError::IdentifierUnavailable`](SnowflakeError::IdentifierUnavailable) if it's unknown,
+/// rather than telling processes of such hosts apart by process ID only.
+pub fn from_host_and_process() -> Result<u64, SnowflakeError> {
+    hash_host_and_process(&hostname(), std::process::id())
+}
+
+/// [`derive`](derive) of hostname, within the 10bit identifier of [default layout](crate::layouts::DEFAULT).
+///
+/// Hostname is read the same as [`from_host_and_process`](from_host_and_process), use [`derive`](derive) for wider identifier.
+/// Returns [`SnowflakeError::IdentifierUnavailable`](SnowflakeError::IdentifierUnavailable) if it's unknown,
+/// rather than every such host hashing the same empty name.
+pub fn from_hostname() -> Result<u64, SnowflakeError> {
+    hash_hostname(&hostname())
+}
+
+/// Trailing number of hostname, like `7` of `api-7`, the ordinal of StatefulSet pod in Kubernetes.
+///
+/// Only the first label of a domain name is looked at, so `api-7.example.com` gives 7 as well.
+/// Returns [`SnowflakeError::IdentifierUnavailable`](SnowflakeError::IdentifierUnavailable) if there's no trailing number.
+/// It's **NOT** checked against the layout, pass it to [`SnowflakeConfiguration::try_with_identifier`](crate::SnowflakeConfiguration::try_with_identifier) to reject it.
+pub fn from_hostname_suffix_number() -> Result<u64, SnowflakeError> {
+    suffix_number(&hostname())
+}
+
+fn hash_host_and_process(hostname: &str, process: u32) -> Result<u64, SnowflakeError> {
+    let hostname = known(hostname)?;
+    Ok(derive(&[hostname.as_bytes(), &process.to_le_bytes()]))
+}
+
+fn hash_hostname(hostname: &str) -> Result<u64, SnowflakeError> {
+    let hostname = known(hostname)?;
+    Ok(derive(&[hostname.as_bytes()]) & layouts::DEFAULT.max_identifier())
+}
+
+/// `hostname` unless it's empty, being unknown.
+fn known(hostname: &str) -> Result<&str, SnowflakeError> {
+    match hostname {
+        "" => Err(SnowflakeError::IdentifierUnavailable {
+            from: "hostname",
+            message: "hostname is unknown".to_string(),
+        }),
+        it => Ok(it),
+    }
+}
+
+fn suffix_number(hostname: &str) -> Result<u64, SnowflakeError> {
+    let label = hostname.split('.').next().unwrap_or_default();
+    let digits = &label[label.trim_end_matches(|it: char| it.is_ascii_digit()).len()..];
+
+    digits
+        .parse()
+        .map_err(|_| SnowflakeError::IdentifierUnavailable {
+            from: "hostname",
+            message: match digits.is_empty() {
+                true => format!("{hostname:?} has no trailing number"),
+                false => format!("trailing number of {hostname:?} is too large"),
+            },
+        })
+}
+
+/// Name of this host, empty if unknown.
+#[cfg(unix)]
+pub(crate) fn hostname() -> String {
+    // Names are at most 255 bytes, plus the terminating NUL.
+    let mut name = [0u8; 256];
+    // SAFETY: `name` is valid for writes of its length.
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return String::new();
+    }
+    let len = name.iter().position(|it| *it == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).trim().to_owned()
+}
+
+/// Name of this host, empty if unknown.
+#[cfg(not(unix))]
+pub(crate) fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .map(|it| it.trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// SplitMix64 finalizer, spreading every input bit to low bits.
+pub(crate) fn mix(mut it: u64) -> u64 {
+    it = (it ^ (it >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    it = (it ^ (it >> 27)).wrapping_mul(0x94d049bb133111eb);
+    it ^ (it >> 31)
+}
+
+/// 64bit FNV-1a.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for it in bytes {
+            self.0 = (self.0 ^ *it as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_derive() {
+        // Stable across runs.
+        assert_eq!(derive(&[]), mix(0xcbf29ce484222325));
+        assert_eq!(derive(&[b"billing", b"az-1"]), 0x91484b33245eef19);
+        assert_eq!(
+            derive(&[b"billing", b"az-1"]),
+            derive(&[b"billing", b"az-1"])
+        );
+
+        assert_ne!(derive(&[b"ab", b"c"]), derive(&[b"a", b"bc"]));
+        assert_ne!(derive(&[b"billing"]), derive(&[b"billing", b""]));
+
+        let identifiers = (0..100)
+            .map(|it| derive(&[b"service", format!("pod-{it}").as_bytes()]) & 1023)
+            .collect::<HashSet<_>>();
+        assert!(identifiers.iter().all(|it| *it < 1024));
+        // 100 out of 1024 mostly differ.
+        assert!(identifiers.len() > 90, "{}", identifiers.len());
+
+        assert_eq!(from_host_and_process(), from_host_and_process());
+        assert_eq!(
+            hash_host_and_process("api-7", 42),
+            Ok(derive(&[b"api-7", &42u32.to_le_bytes()]))
+        );
+        assert_ne!(
+            hash_host_and_process("api-7", 42),
+            hash_host_and_process("api-7", 43)
+        );
+        assert!(matches!(
+            hash_host_and_process("", 42),
+            Err(SnowflakeError::IdentifierUnavailable {
+                from: "hostname",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_from_hostname() {
+        assert_eq!(hash_hostname("api-7"), hash_hostname("api-7"));
+        assert_ne!(hash_hostname("api-7"), hash_hostname("api-8"));
+        assert_eq!(hash_hostname("api-7"), Ok(derive(&[b"api-7"]) & 1023));
+        assert!((0..100).all(|it| hash_hostname(&format!("node-{it}")).unwrap() < 1024));
+        assert!(matches!(
+            hash_hostname(""),
+            Err(SnowflakeError::IdentifierUnavailable {
+                from: "hostname",
+                ..
+            })
+        ));
+
+        assert_eq!(suffix_number("api-7"), Ok(7));
+        assert_eq!(suffix_number("statefulset-12"), Ok(12));
+        assert_eq!(suffix_number("web007"), Ok(7));
+        assert_eq!(suffix_number("api-7.example.com"), Ok(7));
+        assert_eq!(suffix_number("12"), Ok(12));
+
+        for hostname in [
+            "api",
+            "api-7-canary",
+            "api.example-3",
+            "",
+            "node-99999999999999999999",
+        ] {
+            assert!(
+                matches!(
+                    suffix_number(hostname),
+                    Err(SnowflakeError::IdentifierUnavailable {
+                        from: "hostname",
+                        ..
+                    })
+                ),
+                "{hostname}"
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use crate::SnowflakeError;
+
+use super::derive;
+
+/// Where local IPv4 addresses are listed, on Linux.
+const FIB_TRIE: &str = "/proc/net/fib_trie";
+
+/// Private IPv4 ranges of [RFC 1918](https://www.rfc-editor.org/rfc/rfc1918) as network and prefix length,
+/// the default preference of [`from_private_ipv4`](from_private_ipv4).
+pub const RFC1918: [(Ipv4Addr, u8); 3] = [
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+];
+
+/// Listing local IPv4 addresses, faked in tests.
+trait Addresses {
+    fn addresses(&self) -> Vec<Ipv4Addr>;
+}
+
+/// Local addresses of `/proc/net/fib_trie`, none on other platforms.
+struct FibTrie;
+
+impl Addresses for FibTrie {
+    fn addresses(&self) -> Vec<Ipv4Addr> {
+        fs::read_to_string(FIB_TRIE)
+            .map(|it| parse_fib_trie(&it))
+            .unwrap_or_default()
+    }
+}
+
+/// Identifier of `address`, its low identifier bits are kept by [`SnowflakeConfiguration::with_identifier`](crate::SnowflakeConfiguration::with_identifier).
+///
+/// IPv4 (and IPv4-mapped IPv6) address is the number itself, `10.0.1.5` gives `0x0a000105`, which is 261 with 10bit identifier.
+/// Other IPv6 addresses are [`derive`](derive)d instead, the low bits of them are mostly the same interface ID of SLAAC
+/// or random of privacy extension, neither unique within a subnet.
+pub fn from_ip(address: IpAddr) -> u64 {
+    match address {
+        IpAddr::V4(it) => u32::from(it) as u64,
+        IpAddr::V6(it) => match it.to_ipv4_mapped() {
+            Some(it) => u32::from(it) as u64,
+            None => derive(&[&it.octets()]),
+        },
+    }
+}
+
+/// [`from_ip`](from_ip) of the first local address in [`RFC1918`](RFC1918) ranges, preferring `10.0.0.0/8`.
+///
+/// The low bits are unique within a subnet of at most that many addresses, like `/22` for 10bit identifier.
+/// Only Linux is supported by now, elsewhere it always fails.
+pub fn from_private_ipv4() -> Result<u64, SnowflakeError> {
+    from_private_ipv4_with(&RFC1918)
+}
+
+/// Same as [`from_private_ipv4`](from_private_ipv4), but picking in ranges of `preference` in order.
+pub fn from_private_ipv4_with(preference: &[(Ipv4Addr, u8)]) -> Result<u64, SnowflakeError> {
+    from_private_ipv4_in(&FibTrie, preference)
+}
+
+fn from_private_ipv4_in(
+    addresses: &impl Addresses,
+    preference: &[(Ipv4Addr, u8)],
+) -> Result<u64, SnowflakeError> {
+    let addresses = addresses.addresses();
+    preference
+        .iter()
+        .find_map(|(network, prefix)| {
+            addresses
+                .iter()
+                .find(|it| contains(*network, *prefix, **it))
+        })
+        .map(|it| from_ip(IpAddr::V4(*it)))
+        .ok_or_else(|| SnowflakeError::IdentifierUnavailable {
+            from: "private IPv4 address",
+            message: format!("no local address in preferred ranges, only {addresses:?}"),
+        })
+}
+
+fn contains(network: Ipv4Addr, prefix: u8, address: Ipv4Addr) -> bool {
+    let mask = u32::MAX
+        .checked_shl(32 - prefix.min(32) as u32)
+        .unwrap_or(0);
+    u32::from(network) & mask == u32::from(address) & mask
+}
+
+/// Leaves of `/32 host LOCAL` are the local addresses, in both main and local tables.
+fn parse_fib_trie(content: &str) -> Vec<Ipv4Addr> {
+    let mut addresses = Vec::new();
+    let mut leaf = None;
+    for line in content.lines().map(str::trim) {
+        if let Some(it) = line.strip_prefix("|-- ") {
+            leaf = it.parse::<Ipv4Addr>().ok();
+        } else if line == "/32 host LOCAL" {
+            if let Some(it) = leaf.filter(|it| !addresses.contains(it)) {
+                addresses.push(it);
+            }
+        }
+    }
+
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    struct Fake(Vec<Ipv4Addr>);
+
+    impl Addresses for Fake {
+        fn addresses(&self) -> Vec<Ipv4Addr> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn test_from_ip() {
+        assert_eq!(from_ip("10.0.1.5".parse().unwrap()), 0x0a000105);
+        assert_eq!(from_ip("10.0.1.5".parse().unwrap()) & 1023, 261);
+        assert_eq!(from_ip("::ffff:10.0.1.5".parse().unwrap()), 0x0a000105);
+
+        let v6 = "fd00::1".parse::<Ipv6Addr>().unwrap();
+        assert_eq!(from_ip(IpAddr::V6(v6)), derive(&[&v6.octets()]));
+        assert_ne!(
+            from_ip("fd00::1".parse().unwrap()),
+            from_ip("fd00:0:0:1::1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_from_private_ipv4() {
+        let addresses = Fake(vec![
+            Ipv4Addr::LOCALHOST,
+            Ipv4Addr::new(203, 0, 113, 7),
+            Ipv4Addr::new(192, 168, 1, 20),
+            Ipv4Addr::new(172, 17, 0, 1),
+            Ipv4Addr::new(10, 0, 3, 250),
+        ]);
+        assert_eq!(from_private_ipv4_in(&addresses, &RFC1918), Ok(0x0a0003fa));
+        assert_eq!(
+            from_private_ipv4_in(&addresses, &[RFC1918[2], RFC1918[0]]),
+            Ok(0xc0a80114)
+        );
+        // 172.17 is in 172.16.0.0/12.
+        assert_eq!(
+            from_private_ipv4_in(&addresses, &RFC1918[1..2]),
+            Ok(0xac110001)
+        );
+        assert!(from_private_ipv4_in(&addresses, &[]).is_err());
+
+        for addresses in [
+            vec![Ipv4Addr::LOCALHOST],
+            vec![],
+            vec![Ipv4Addr::new(172, 32, 0, 1)],
+        ] {
+            assert!(matches!(
+                from_private_ipv4_in(&Fake(addresses), &RFC1918),
+                Err(SnowflakeError::IdentifierUnavailable { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_fib_trie() {
+        let content = "\
+Main:
+  +-- 0.0.0.0/0 3 0 5
+     |-- 0.0.0.0
+        /0 universe UNICAST
+     +-- 10.0.3.0/24 2 0 2
+        |-- 10.0.3.0
+           /24 link UNICAST
+        |-- 10.0.3.250
+           /32 host LOCAL
+     +-- 127.0.0.0/8 2 0 2
+        |-- 127.0.0.1
+           /32 host LOCAL
+Local:
+  +-- 0.0.0.0/0 3 0 5
+     |-- 10.0.3.250
+        /32 host LOCAL
+     |-- 10.0.3.255
+        /32 link BROADCAST
+";
+        assert_eq!(
+            parse_fib_trie(content),
+            [Ipv4Addr::new(10, 0, 3, 250), Ipv4Addr::LOCALHOST]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::SnowflakeError;
+
+use super::suffix_number;
+
+/// Where downward API volume is usually mounted with pod name, `metadata.name` at path `name`.
+pub const PODINFO_NAME: &str = "/etc/podinfo/name";
+
+/// Where [`from_k8s_with`](from_k8s_with) looks for identifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum K8sSource {
+    /// Identifier itself in environment variable, like `SNOWFLAKE_IDENTIFIER` set from downward API.
+    ///
+    /// Being set but not a number fails immediately rather than trying others.
+    Env(String),
+    /// Trailing ordinal of `HOSTNAME`, which is the pod name, `foo-3` of StatefulSet gives 3.
+    HostnameOrdinal,
+    /// Trailing ordinal of pod name in the file, mounted by downward API volume.
+    PodNameFile(PathBuf),
+}
+
+impl K8sSource {
+    /// Order of [`from_k8s`](from_k8s): `SNOWFLAKE_IDENTIFIER`, `HOSTNAME` and [`PODINFO_NAME`](PODINFO_NAME).
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::Env("SNOWFLAKE_IDENTIFIER".to_owned()),
+            Self::HostnameOrdinal,
+            Self::PodNameFile(PODINFO_NAME.into()),
+        ]
+    }
+}
+
+/// Environment variables and files, faked in tests.
+trait Environment {
+    fn var(&self, name: &str) -> Option<String>;
+
+    fn read(&self, path: &Path) -> Option<String>;
+}
+
+/// Environment of this process.
+struct Process;
+
+impl Environment for Process {
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+}
+
+/// Identifier of this pod, from the first of [`K8sSource::defaults`](K8sSource::defaults) present.
+///
+/// Returns [`SnowflakeError::IdentifierUnavailable`](SnowflakeError::IdentifierUnavailable) telling why each of them is skipped.
+/// Like [`from_hostname_suffix_number`](super::from_hostname_suffix_number), it's **NOT** checked against the layout.
+pub fn from_k8s() -> Result<u64, SnowflakeError> {
+    from_k8s_with(&K8sSource::defaults())
+}
+
+/// Same as [`from_k8s`](from_k8s), but trying `sources` in order.
+pub fn from_k8s_with(sources: &[K8sSource]) -> Result<u64, SnowflakeError> {
+    from_k8s_in(&Process, sources)
+}
+
+fn from_k8s_in(env: &impl Environment, sources: &[K8sSource]) -> Result<u64, SnowflakeError> {
+    let mut tried = Vec::new();
+    for source in sources {
+        let found = match source {
+            K8sSource::Env(name) => match env.var(name) {
+                Some(value) => {
+                    return value
+                        .trim()
+                        .parse()
+                        .map_err(|_| SnowflakeError::InvalidEnv {
+                            name: name.clone(),
+                            value,
+                        })
+                }
+                None => Err(format!("{name} is not set")),
+            },
+            K8sSource::HostnameOrdinal => match env.var("HOSTNAME") {
+                Some(hostname) => ordinal(&hostname).map_err(|it| format!("HOSTNAME {it}")),
+                None => Err("HOSTNAME is not set".to_owned()),
+            },
+            K8sSource::PodNameFile(path) => match env.read(path) {
+                Some(name) => ordinal(name.trim()).map_err(|it| format!("{} {it}", path.display())),
+                None => Err(format!("{} can't be read", path.display())),
+            },
+        };
+
+        match found {
+            Ok(it) => return Ok(it),
+            Err(it) => tried.push(it),
+        }
+    }
+
+    Err(SnowflakeError::IdentifierUnavailable {
+        from: "Kubernetes",
+        message: match tried.is_empty() {
+            true => "nothing to try".to_owned(),
+            false => tried.join(", "),
+        },
+    })
+}
+
+fn ordinal(name: &str) -> Result<u64, String> {
+    suffix_number(name).map_err(|err| match err {
+        SnowflakeError::IdentifierUnavailable { message, .. } => message,
+        other => other.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Fake {
+        vars: HashMap<&'static str, &'static str>,
+        files: HashMap<PathBuf, &'static str>,
+    }
+
+    impl Environment for Fake {
+        fn var(&self, name: &str) -> Option<String> {
+            self.vars.get(name).map(|it| it.to_string())
+        }
+
+        fn read(&self, path: &Path) -> Option<String> {
+            self.files.get(path).map(|it| it.to_string())
+        }
+    }
+
+    #[test]
+    fn test_from_k8s() {
+        let defaults = K8sSource::defaults();
+        let mut env = Fake::default();
+        env.vars.insert("SNOWFLAKE_IDENTIFIER", "42");
+        env.vars.insert("HOSTNAME", "foo-3");
+        env.files.insert(PODINFO_NAME.into(), "foo-5\n");
+        assert_eq!(from_k8s_in(&env, &defaults), Ok(42));
+
+        env.vars.remove("SNOWFLAKE_IDENTIFIER");
+        assert_eq!(from_k8s_in(&env, &defaults), Ok(3));
+
+        env.vars.insert("HOSTNAME", "foo");
+        assert_eq!(from_k8s_in(&env, &defaults), Ok(5));
+
+        // Reordered.
+        env.vars.insert("HOSTNAME", "foo-3");
+        let sources = [
+            K8sSource::PodNameFile(PODINFO_NAME.into()),
+            K8sSource::HostnameOrdinal,
+        ];
+        assert_eq!(from_k8s_in(&env, &sources), Ok(5));
+
+        // Misconfigured explicit one isn't skipped.
+        env.vars.insert("SNOWFLAKE_IDENTIFIER", "forty-two");
+        assert!(matches!(
+            from_k8s_in(&env, &defaults),
+            Err(SnowflakeError::InvalidEnv { .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_k8s_unavailable() {
+        let mut env = Fake::default();
+        env.vars.insert("HOSTNAME", "laptop");
+        let Err(SnowflakeError::IdentifierUnavailable { from, message }) =
+            from_k8s_in(&env, &K8sSource::defaults())
+        else {
+            panic!("identifier found");
+        };
+        assert_eq!(from, "Kubernetes");
+        assert_eq!(
+            message,
+            "SNOWFLAKE_IDENTIFIER is not set, \
+             HOSTNAME \"laptop\" has no trailing number, \
+             /etc/podinfo/name can't be read"
+        );
+
+        assert!(from_k8s_in(&env, &[]).is_err());
+    }
+}
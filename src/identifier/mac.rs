@@ -0,0 +1,196 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fs;
+
+use crate::SnowflakeError;
+
+use super::derive;
+
+/// Where network interfaces are listed, `/sys/class/net` on Linux.
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// Network interface with its MAC address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Interface {
+    name: String,
+    mac: [u8; 6],
+    /// Not backed by a device, like bridges, veth pairs and tunnels.
+    virtual_device: bool,
+}
+
+/// Listing network interfaces, faked in tests.
+trait Interfaces {
+    fn interfaces(&self) -> Vec<Interface>;
+}
+
+/// Interfaces of `/sys/class/net`, none on other platforms.
+struct SysClassNet;
+
+impl Interfaces for SysClassNet {
+    fn interfaces(&self) -> Vec<Interface> {
+        let Ok(entries) = fs::read_dir(SYS_CLASS_NET) else {
+            return Vec::new();
+        };
+
+        let mut interfaces = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let path = entry.path();
+                let mac = fs::read_to_string(path.join("address")).ok()?;
+                Some(Interface {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    mac: parse_mac(mac.trim())?,
+                    virtual_device: !path.join("device").exists(),
+                })
+            })
+            .collect::<Vec<_>>();
+        // Directory order is arbitrary, keep the choice stable across boots.
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        interfaces
+    }
+}
+
+/// [`derive`](derive) of the MAC address of primary network interface.
+///
+/// Primary one is the first by name backed by a device, or the first virtual one if none of them (usual in containers),
+/// never loopback. Only Linux is supported by now, elsewhere it always fails.
+pub fn from_mac() -> Result<u64, SnowflakeError> {
+    from_mac_in(&SysClassNet)
+}
+
+/// [`derive`](derive) of the MAC address of network interface named `name`.
+pub fn from_mac_of(name: &str) -> Result<u64, SnowflakeError> {
+    from_mac_of_in(&SysClassNet, name)
+}
+
+fn from_mac_in(interfaces: &impl Interfaces) -> Result<u64, SnowflakeError> {
+    let interfaces = interfaces
+        .interfaces()
+        .into_iter()
+        .filter(|it| !is_loopback(it))
+        .collect::<Vec<_>>();
+
+    interfaces
+        .iter()
+        .find(|it| !it.virtual_device)
+        .or_else(|| interfaces.first())
+        .map(|it| hash_mac(&it.mac))
+        .ok_or_else(|| unavailable("no network interface other than loopback".to_owned()))
+}
+
+fn from_mac_of_in(interfaces: &impl Interfaces, name: &str) -> Result<u64, SnowflakeError> {
+    let interface = interfaces
+        .interfaces()
+        .into_iter()
+        .find(|it| it.name == name)
+        .ok_or_else(|| unavailable(format!("no network interface named {name}")))?;
+
+    match is_loopback(&interface) {
+        true => Err(unavailable(format!("{name} has no MAC address"))),
+        false => Ok(hash_mac(&interface.mac)),
+    }
+}
+
+/// MAC addresses share vendor prefixes, so hash it rather than truncating to the low bits.
+fn hash_mac(mac: &[u8; 6]) -> u64 {
+    derive(&[mac])
+}
+
+/// Loopback has all-zero MAC address.
+fn is_loopback(interface: &Interface) -> bool {
+    interface.name == "lo" || interface.mac == [0; 6]
+}
+
+/// `aa:bb:cc:dd:ee:ff`, `None` for anything else like the longer addresses of InfiniBand.
+fn parse_mac(it: &str) -> Option<[u8; 6]> {
+    let mut mac = [0; 6];
+    let mut octets = it.split(':');
+    for octet in &mut mac {
+        let it = octets.next()?;
+        if it.len() != 2 {
+            return None;
+        }
+        *octet = u8::from_str_radix(it, 16).ok()?;
+    }
+
+    octets.next().is_none().then_some(mac)
+}
+
+fn unavailable(message: String) -> SnowflakeError {
+    SnowflakeError::IdentifierUnavailable {
+        from: "MAC address",
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake(Vec<Interface>);
+
+    impl Interfaces for Fake {
+        fn interfaces(&self) -> Vec<Interface> {
+            self.0.clone()
+        }
+    }
+
+    fn interface(name: &str, mac: [u8; 6], virtual_device: bool) -> Interface {
+        Interface {
+            name: name.to_owned(),
+            mac,
+            virtual_device,
+        }
+    }
+
+    #[test]
+    fn test_from_mac() {
+        let eth0 = [0x02, 0x42, 0xac, 0x11, 0x00, 0x02];
+        let eth1 = [0x02, 0x42, 0xac, 0x11, 0x00, 0x03];
+        let docker0 = [0x02, 0x42, 0x5e, 0x8a, 0x1b, 0x7c];
+        assert_ne!(hash_mac(&eth0), hash_mac(&eth1));
+        // Differing in the last octet still spreads to the low bits.
+        assert_ne!(hash_mac(&eth0) & 1023, hash_mac(&eth1) & 1023);
+
+        let lo = interface("lo", [0; 6], true);
+        let fake = Fake(vec![
+            interface("docker0", docker0, true),
+            interface("eth0", eth0, false),
+            interface("eth1", eth1, false),
+            lo.clone(),
+        ]);
+        assert_eq!(from_mac_in(&fake), Ok(hash_mac(&eth0)));
+        assert_eq!(from_mac_of_in(&fake, "eth1"), Ok(hash_mac(&eth1)));
+        assert_eq!(from_mac_of_in(&fake, "docker0"), Ok(hash_mac(&docker0)));
+        assert!(from_mac_of_in(&fake, "lo").is_err());
+        assert!(from_mac_of_in(&fake, "wlan0").is_err());
+
+        // Containers only have virtual ones.
+        let fake = Fake(vec![lo.clone(), interface("veth0", docker0, true)]);
+        assert_eq!(from_mac_in(&fake), Ok(hash_mac(&docker0)));
+
+        assert!(matches!(
+            from_mac_in(&Fake(vec![lo])),
+            Err(SnowflakeError::IdentifierUnavailable { .. })
+        ));
+        assert!(from_mac_in(&Fake(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_parse_mac() {
+        assert_eq!(
+            parse_mac("02:42:ac:11:00:02"),
+            Some([0x02, 0x42, 0xac, 0x11, 0x00, 0x02])
+        );
+        assert_eq!(parse_mac("02:42:AC:11:00:02").unwrap()[2], 0xac);
+        assert_eq!(parse_mac("02:42:ac:11:00"), None);
+        assert_eq!(parse_mac("02:42:ac:11:00:02:03"), None);
+        assert_eq!(parse_mac("02:42:ac:11:00:2"), None);
+        assert_eq!(parse_mac("02:42:ac:11:00:zz"), None);
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::{Claim, Snowflake, SnowflakeGenerator, TimeProvider, TimerSleeper};
+
+/// Blocking [`Iterator`](Iterator) of [`Snowflake`](Snowflake), see [`SnowflakeGenerator::iter_sync`](SnowflakeGenerator::iter_sync).
+///
+/// It never ends, so always limit it with something like [`Iterator::take`](Iterator::take).
+/// When sequence exhausted or the clock is behind, current thread waits with [`WaitStrategy`](crate::WaitStrategy),
+/// no async runtime or executor involved.
+#[derive(Debug)]
+pub struct SnowflakeIter<'a, T, S = TimerSleeper> {
+    generator: &'a SnowflakeGenerator<S>,
+    provider: &'a T,
+}
+
+impl<'a, T, S> SnowflakeIter<'a, T, S> {
+    pub(crate) fn new(generator: &'a SnowflakeGenerator<S>, provider: &'a T) -> Self {
+        Self {
+            generator,
+            provider,
+        }
+    }
+}
+
+impl<T, S> Iterator for SnowflakeIter<'_, T, S>
+where
+    T: TimeProvider,
+{
+    type Item = Snowflake;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let reservation = self
+            .generator
+            .next_infallible_sync(self.provider, Claim::AtMost(1));
+        Some(Snowflake(reservation.bits(&self.generator.cfg, 0) as i64))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    use crate::provider::STD_PROVIDER;
+
+    use super::*;
+
+    /// Counting calls of [`STD_PROVIDER`].
+    struct CountingProvider(AtomicU64);
+
+    impl TimeProvider for CountingProvider {
+        fn timestamp(&self) -> u64 {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            STD_PROVIDER.timestamp()
+        }
+    }
+
+    #[test]
+    fn test_iter_sync() {
+        let generator = SnowflakeGenerator::default();
+        let provider = CountingProvider(AtomicU64::new(0));
+
+        let snowflakes = generator
+            .iter_sync(&provider)
+            .take(100_000)
+            .collect::<Vec<_>>();
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes.iter().collect::<HashSet<_>>().len(), 100_000);
+
+        // One call per snowflake, plus about one per exhausted millisecond.
+        let calls = provider.0.load(Ordering::Relaxed);
+        assert!(calls < 100_000 + 100_000 / 4096 * 10, "{calls} calls");
+    }
+}
@@ -0,0 +1,618 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Bit layouts of [`Snowflake`](crate::Snowflake).
+
+use crate::{Snowflake, SnowflakeError, SnowflakeU64};
+
+/// Standard layout designed by Twitter(formally X), 41bit timestamp, 10bit identifier and 12bit sequence.
+pub const DEFAULT: SnowflakeLayout = SnowflakeLayout::new_unchecked(41, 10, 12);
+
+/// JavaScript-safe layout, 33bit timestamp, 8bit identifier and 12bit sequence.
+///
+/// It only occupies 53 bits, so [`Snowflake`](Snowflake) never exceeds [`Number.MAX_SAFE_INTEGER`](https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Number/MAX_SAFE_INTEGER)
+/// and survives systems coercing numbers to IEEE doubles.
+///
+/// 33bit milliseconds only last 99 days, pair it with 1 second [`time_unit`](crate::SnowflakeConfiguration::time_unit),
+/// see [`SnowflakeConfiguration::js_safe`](crate::SnowflakeConfiguration::js_safe).
+pub const JS_SAFE_53: SnowflakeLayout = SnowflakeLayout::new_unchecked(33, 8, 12);
+
+/// Microsecond layout, 51bit timestamp, 4bit identifier and 8bit sequence.
+///
+/// Meant for 1 microsecond [`time_unit`](crate::SnowflakeConfiguration::time_unit), e.g. trace and span IDs
+/// which need sub-millisecond ordering. 51bit microseconds last about 71 years, so use a recent epoch.
+pub const MICROSECOND: SnowflakeLayout = SnowflakeLayout::new_unchecked(51, 4, 8);
+
+/// Unsigned layout, 42bit timestamp, 10bit identifier and 12bit sequence.
+///
+/// It occupies all 64 bits including the sign bit, so only [`SnowflakeU64`](SnowflakeU64) can hold it,
+/// see [`SnowflakeGenerator::assign_u64`](crate::SnowflakeGenerator::assign_u64).
+pub const UNSIGNED: SnowflakeLayout = SnowflakeLayout::new_unchecked(42, 10, 12);
+
+/// Bit layout of [`Snowflake`](Snowflake).
+///
+/// From high bits to low bits:
+///
+/// ```text
+/// | sign |                data                      |
+/// |   0  | Timestamp | Identifier | Sequence Number |
+/// | 1bit |   T bit   |    I bit   |     S bit       |
+/// ```
+///
+/// `T + I + S` must be at most 63, bits above `T + I + S` are always zero.
+/// Layout constructed by [`SnowflakeLayout::new_unsigned`](SnowflakeLayout::new_unsigned) may also take the sign bit.
+///
+/// # Rollback flag
+///
+/// [`SnowflakeLayout::with_rollback_flag`](SnowflakeLayout::with_rollback_flag) takes the highest identifier bit as rollback flag,
+/// which is set by generator while the clock is behind, see [`SnowflakeConfiguration::rollback_tolerance`](crate::SnowflakeConfiguration::rollback_tolerance).
+///
+/// ```text
+/// | sign |                      data                           |
+/// |   0  | Timestamp | Rollback | Identifier | Sequence Number |
+/// | 1bit |   T bit   |   1bit   |  I-1 bit   |     S bit       |
+/// ```
+///
+/// # Serde
+///
+/// With feature `serde`, deserialization runs the same validation as [`SnowflakeLayout::new`](SnowflakeLayout::new).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "SerdeLayout")
+)]
+pub struct SnowflakeLayout {
+    timestamp_bits: u32,
+    identifier_bits: u32,
+    sequence_bits: u32,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "is_false"))]
+    rollback_flag: bool,
+}
+
+impl SnowflakeLayout {
+    /// Constructing [`SnowflakeLayout`](SnowflakeLayout) with validation.
+    pub const fn new(
+        timestamp_bits: u32,
+        identifier_bits: u32,
+        sequence_bits: u32,
+    ) -> Result<Self, SnowflakeError> {
+        if timestamp_bits == 0 || timestamp_bits + identifier_bits + sequence_bits > 63 {
+            return Err(SnowflakeError::InvalidLayout {
+                timestamp_bits,
+                identifier_bits,
+                sequence_bits,
+            });
+        }
+
+        Ok(Self::new_unchecked(
+            timestamp_bits,
+            identifier_bits,
+            sequence_bits,
+        ))
+    }
+
+    /// Same as [`SnowflakeLayout::new`](SnowflakeLayout::new), but `T + I + S` can be 64 for [`SnowflakeU64`](SnowflakeU64).
+    ///
+    /// [`SnowflakeLayout::compose`](SnowflakeLayout::compose) with a 64 bits layout produces negative [`Snowflake`](Snowflake)
+    /// once timestamp reaches the sign bit, use [`SnowflakeLayout::compose_u64`](SnowflakeLayout::compose_u64) instead.
+    pub const fn new_unsigned(
+        timestamp_bits: u32,
+        identifier_bits: u32,
+        sequence_bits: u32,
+    ) -> Result<Self, SnowflakeError> {
+        if timestamp_bits == 0 || timestamp_bits + identifier_bits + sequence_bits > 64 {
+            return Err(SnowflakeError::InvalidLayout {
+                timestamp_bits,
+                identifier_bits,
+                sequence_bits,
+            });
+        }
+
+        Ok(Self::new_unchecked(
+            timestamp_bits,
+            identifier_bits,
+            sequence_bits,
+        ))
+    }
+
+    pub(crate) const fn new_unchecked(
+        timestamp_bits: u32,
+        identifier_bits: u32,
+        sequence_bits: u32,
+    ) -> Self {
+        Self {
+            timestamp_bits,
+            identifier_bits,
+            sequence_bits,
+            rollback_flag: false,
+        }
+    }
+
+    /// Taking the highest identifier bit as rollback flag, returning [`SnowflakeError::InvalidLayout`](SnowflakeError::InvalidLayout)
+    /// if there is no identifier bit or flag is already taken.
+    pub const fn with_rollback_flag(self) -> Result<Self, SnowflakeError> {
+        if self.identifier_bits == 0 || self.rollback_flag {
+            return Err(SnowflakeError::InvalidLayout {
+                timestamp_bits: self.timestamp_bits,
+                identifier_bits: self.identifier_bits,
+                sequence_bits: self.sequence_bits,
+            });
+        }
+
+        Ok(Self {
+            identifier_bits: self.identifier_bits - 1,
+            rollback_flag: true,
+            ..self
+        })
+    }
+
+    /// Whether this layout has rollback flag.
+    pub const fn has_rollback_flag(&self) -> bool {
+        self.rollback_flag
+    }
+
+    pub const fn timestamp_bits(&self) -> u32 {
+        self.timestamp_bits
+    }
+
+    pub const fn identifier_bits(&self) -> u32 {
+        self.identifier_bits
+    }
+
+    pub const fn sequence_bits(&self) -> u32 {
+        self.sequence_bits
+    }
+
+    /// Total bits occupied by this layout, including rollback flag.
+    pub const fn total_bits(&self) -> u32 {
+        self.timestamp_bits + self.rollback_flag as u32 + self.identifier_bits + self.sequence_bits
+    }
+
+    /// Largest timestamp can be stored.
+    pub const fn max_timestamp(&self) -> u64 {
+        mask(self.timestamp_bits)
+    }
+
+    /// Largest identifier can be stored.
+    pub const fn max_identifier(&self) -> u64 {
+        mask(self.identifier_bits)
+    }
+
+    /// Largest sequence number can be stored.
+    pub const fn max_sequence(&self) -> u64 {
+        mask(self.sequence_bits)
+    }
+
+    const fn timestamp_shift(&self) -> u32 {
+        self.rollback_shift() + self.rollback_flag as u32
+    }
+
+    const fn rollback_shift(&self) -> u32 {
+        self.identifier_bits + self.sequence_bits
+    }
+
+    const fn identifier_shift(&self) -> u32 {
+        self.sequence_bits
+    }
+
+    /// Filling timestamp by mask
+    pub const fn fill_timestamp(&self, sid: u64, timestamp: u64) -> u64 {
+        fill(sid, timestamp, self.max_timestamp(), self.timestamp_shift())
+    }
+
+    /// Filling identifier by mask
+    pub const fn fill_identifier(&self, sid: u64, identifier: u64) -> u64 {
+        fill(
+            sid,
+            identifier,
+            self.max_identifier(),
+            self.identifier_shift(),
+        )
+    }
+
+    /// Filling sequence by mask
+    pub const fn fill_sequence(&self, sid: u64, sequence: u64) -> u64 {
+        fill(sid, sequence, self.max_sequence(), 0)
+    }
+
+    /// Composing [`Snowflake`](Snowflake) from parts, every part is truncated by mask.
+    pub const fn compose(&self, timestamp: u64, identifier: u64, sequence: u64) -> Snowflake {
+        Snowflake(self.compose_u64(timestamp, identifier, sequence).0 as i64)
+    }
+
+    /// Composing [`SnowflakeU64`](SnowflakeU64) from parts, every part is truncated by mask.
+    pub const fn compose_u64(
+        &self,
+        timestamp: u64,
+        identifier: u64,
+        sequence: u64,
+    ) -> SnowflakeU64 {
+        SnowflakeU64(self.compose_raw(timestamp, identifier, sequence, false))
+    }
+
+    /// Composing raw bits, `rollback` is ignored without rollback flag.
+    pub(crate) const fn compose_raw(
+        &self,
+        timestamp: u64,
+        identifier: u64,
+        sequence: u64,
+        rollback: bool,
+    ) -> u64 {
+        let sid = self.fill_timestamp(0, timestamp);
+        let sid = self.fill_identifier(sid, identifier);
+        let sid = self.fill_sequence(sid, sequence);
+        if rollback && self.rollback_flag {
+            sid | (1 << self.rollback_shift())
+        } else {
+            sid
+        }
+    }
+
+    /// Timestamp part of `snowflake`.
+    pub const fn timestamp_of(&self, snowflake: &Snowflake) -> u64 {
+        (snowflake.0 as u64 >> self.timestamp_shift()) & self.max_timestamp()
+    }
+
+    /// Identifier part of `snowflake`.
+    pub const fn identifier_of(&self, snowflake: &Snowflake) -> u64 {
+        (snowflake.0 as u64 >> self.identifier_shift()) & self.max_identifier()
+    }
+
+    /// Sequence number part of `snowflake`.
+    pub const fn sequence_of(&self, snowflake: &Snowflake) -> u64 {
+        snowflake.0 as u64 & self.max_sequence()
+    }
+
+    /// Whether rollback flag of `snowflake` is set, always `false` without rollback flag.
+    pub const fn rollback_of(&self, snowflake: &Snowflake) -> bool {
+        self.rollback_of_u64(&SnowflakeU64(snowflake.0 as u64))
+    }
+
+    /// Timestamp part of `snowflake`.
+    pub const fn timestamp_of_u64(&self, snowflake: &SnowflakeU64) -> u64 {
+        (snowflake.0 >> self.timestamp_shift()) & self.max_timestamp()
+    }
+
+    /// Identifier part of `snowflake`.
+    pub const fn identifier_of_u64(&self, snowflake: &SnowflakeU64) -> u64 {
+        (snowflake.0 >> self.identifier_shift()) & self.max_identifier()
+    }
+
+    /// Sequence number part of `snowflake`.
+    pub const fn sequence_of_u64(&self, snowflake: &SnowflakeU64) -> u64 {
+        snowflake.0 & self.max_sequence()
+    }
+
+    /// Whether rollback flag of `snowflake` is set, always `false` without rollback flag.
+    pub const fn rollback_of_u64(&self, snowflake: &SnowflakeU64) -> bool {
+        self.rollback_flag && (snowflake.0 >> self.rollback_shift()) & 1 == 1
+    }
+}
+
+impl Default for SnowflakeLayout {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+/// Twitter's split of 10bit identifier, 5bit datacenter and 5bit worker.
+pub const DATACENTER_WORKER: IdentifierSplit = IdentifierSplit {
+    datacenter_bits: 5,
+    worker_bits: 5,
+};
+
+/// Splitting identifier into datacenter (high bits) and worker (low bits), see [`SnowflakeConfiguration::with_datacenter_worker`](crate::SnowflakeConfiguration::with_datacenter_worker).
+///
+/// ```text
+/// |        Identifier       |
+/// | Datacenter |  Worker    |
+/// |   D bit    |   W bit    |
+/// ```
+///
+/// Both of `D` and `W` must be at most 8, and `D + W` at most the identifier bits of layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "SerdeSplit")
+)]
+pub struct IdentifierSplit {
+    datacenter_bits: u32,
+    worker_bits: u32,
+}
+
+impl IdentifierSplit {
+    /// Constructing [`IdentifierSplit`](IdentifierSplit), returning [`SnowflakeError::InvalidIdentifierSplit`](SnowflakeError::InvalidIdentifierSplit)
+    /// if either part exceeds 8 bits.
+    pub const fn new(datacenter_bits: u32, worker_bits: u32) -> Result<Self, SnowflakeError> {
+        if datacenter_bits > 8 || worker_bits > 8 {
+            return Err(SnowflakeError::InvalidIdentifierSplit {
+                datacenter_bits,
+                worker_bits,
+            });
+        }
+
+        Ok(Self {
+            datacenter_bits,
+            worker_bits,
+        })
+    }
+
+    pub const fn datacenter_bits(&self) -> u32 {
+        self.datacenter_bits
+    }
+
+    pub const fn worker_bits(&self) -> u32 {
+        self.worker_bits
+    }
+
+    /// Largest datacenter can be stored.
+    pub const fn max_datacenter(&self) -> u8 {
+        mask(self.datacenter_bits) as u8
+    }
+
+    /// Largest worker can be stored.
+    pub const fn max_worker(&self) -> u8 {
+        mask(self.worker_bits) as u8
+    }
+
+    /// Composing identifier of `datacenter` and `worker`, returning [`SnowflakeError::DatacenterOutOfRange`](SnowflakeError::DatacenterOutOfRange)
+    /// or [`SnowflakeError::WorkerOutOfRange`](SnowflakeError::WorkerOutOfRange) if they don't fit.
+    pub const fn compose(&self, datacenter: u8, worker: u8) -> Result<u64, SnowflakeError> {
+        if datacenter > self.max_datacenter() {
+            return Err(SnowflakeError::DatacenterOutOfRange {
+                datacenter,
+                max: self.max_datacenter(),
+            });
+        }
+        if worker > self.max_worker() {
+            return Err(SnowflakeError::WorkerOutOfRange {
+                worker,
+                max: self.max_worker(),
+            });
+        }
+
+        Ok(((datacenter as u64) << self.worker_bits) | worker as u64)
+    }
+
+    /// Datacenter part of `identifier`.
+    pub const fn datacenter_of(&self, identifier: u64) -> u8 {
+        ((identifier >> self.worker_bits) & mask(self.datacenter_bits)) as u8
+    }
+
+    /// Worker part of `identifier`.
+    pub const fn worker_of(&self, identifier: u64) -> u8 {
+        (identifier & mask(self.worker_bits)) as u8
+    }
+}
+
+impl Default for IdentifierSplit {
+    fn default() -> Self {
+        DATACENTER_WORKER
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeSplit {
+    datacenter_bits: u32,
+    worker_bits: u32,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerdeSplit> for IdentifierSplit {
+    type Error = SnowflakeError;
+
+    fn try_from(value: SerdeSplit) -> Result<Self, Self::Error> {
+        Self::new(value.datacenter_bits, value.worker_bits)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeLayout {
+    timestamp_bits: u32,
+    identifier_bits: u32,
+    sequence_bits: u32,
+    #[serde(default)]
+    rollback_flag: bool,
+}
+
+#[cfg(feature = "serde")]
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerdeLayout> for SnowflakeLayout {
+    type Error = SnowflakeError;
+
+    fn try_from(value: SerdeLayout) -> Result<Self, Self::Error> {
+        // Rollback flag is serialized apart from identifier bits.
+        let identifier_bits = value.identifier_bits + value.rollback_flag as u32;
+        let layout = Self::new(value.timestamp_bits, identifier_bits, value.sequence_bits)?;
+
+        if value.rollback_flag {
+            layout.with_rollback_flag()
+        } else {
+            Ok(layout)
+        }
+    }
+}
+
+const fn mask(bits: u32) -> u64 {
+    if bits == 0 {
+        0
+    } else {
+        u64::MAX >> (64 - bits)
+    }
+}
+
+const fn fill(sid: u64, value: u64, mask: u64, shift: u32) -> u64 {
+    let filled = (value & mask) << shift;
+    (sid & !(mask << shift)) | filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert_eq!(SnowflakeLayout::new(41, 10, 12), Ok(DEFAULT));
+        assert!(SnowflakeLayout::new(44, 6, 13).is_ok());
+        assert!(SnowflakeLayout::new(63, 0, 0).is_ok());
+        assert_eq!(SnowflakeLayout::new(33, 8, 12), Ok(JS_SAFE_53));
+        assert_eq!(JS_SAFE_53.total_bits(), 53);
+
+        for (timestamp_bits, identifier_bits, sequence_bits) in
+            [(41, 10, 13), (42, 11, 11), (0, 51, 12), (64, 0, 0)]
+        {
+            assert_eq!(
+                SnowflakeLayout::new(timestamp_bits, identifier_bits, sequence_bits),
+                Err(SnowflakeError::InvalidLayout {
+                    timestamp_bits,
+                    identifier_bits,
+                    sequence_bits
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_new_unsigned() {
+        assert_eq!(SnowflakeLayout::new_unsigned(42, 10, 12), Ok(UNSIGNED));
+        assert_eq!(UNSIGNED.total_bits(), 64);
+        assert!(SnowflakeLayout::new(42, 10, 12).is_err());
+        assert!(SnowflakeLayout::new_unsigned(64, 0, 0).is_ok());
+        assert!(SnowflakeLayout::new_unsigned(42, 10, 13).is_err());
+        assert!(SnowflakeLayout::new_unsigned(0, 52, 12).is_err());
+    }
+
+    #[test]
+    fn test_compose_u64() {
+        let snowflake = UNSIGNED.compose_u64(u64::MAX, 1023, 4095);
+        assert_eq!(*snowflake, u64::MAX);
+        assert_eq!(UNSIGNED.timestamp_of_u64(&snowflake), (1 << 42) - 1);
+        assert_eq!(UNSIGNED.identifier_of_u64(&snowflake), 1023);
+        assert_eq!(UNSIGNED.sequence_of_u64(&snowflake), 4095);
+
+        let snowflake = SnowflakeLayout::new_unsigned(64, 0, 0)
+            .unwrap()
+            .compose_u64(u64::MAX - 1, 0, 0);
+        assert_eq!(*snowflake, u64::MAX - 1);
+    }
+
+    #[test]
+    fn test_rollback_flag() {
+        let layout = DEFAULT.with_rollback_flag().unwrap();
+        assert!(layout.has_rollback_flag());
+        assert_eq!(layout.identifier_bits(), 9);
+        assert_eq!(layout.total_bits(), 63);
+        assert!(layout.with_rollback_flag().is_err());
+        assert!(SnowflakeLayout::new(51, 0, 12)
+            .unwrap()
+            .with_rollback_flag()
+            .is_err());
+
+        let flagged = Snowflake(layout.compose_raw(123, 511, 7, true) as i64);
+        assert_eq!(*flagged, (123 << 22) | (1 << 21) | (511 << 12) | 7);
+        assert!(layout.rollback_of(&flagged));
+        assert_eq!(layout.timestamp_of(&flagged), 123);
+        assert_eq!(layout.identifier_of(&flagged), 511);
+        assert_eq!(layout.sequence_of(&flagged), 7);
+
+        let snowflake = layout.compose(123, 511, 7);
+        assert!(!layout.rollback_of(&snowflake));
+        assert!(!DEFAULT.rollback_of(&flagged));
+        assert_eq!(
+            DEFAULT.compose_raw(123, 511, 7, true),
+            *DEFAULT.compose_u64(123, 511, 7)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_rollback_flag_serde() {
+        let layout = DEFAULT.with_rollback_flag().unwrap();
+        let json = serde_json::to_string(&layout).unwrap();
+        assert_eq!(
+            json,
+            r#"{"timestamp_bits":41,"identifier_bits":9,"sequence_bits":12,"rollback_flag":true}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<SnowflakeLayout>(&json).unwrap(),
+            layout
+        );
+        assert!(!serde_json::to_string(&DEFAULT)
+            .unwrap()
+            .contains("rollback_flag"));
+    }
+
+    #[test]
+    fn test_js_safe_compose() {
+        let snowflake = JS_SAFE_53.compose(u64::MAX, u64::MAX, u64::MAX);
+        assert_eq!(*snowflake, (1 << 53) - 1);
+        assert_eq!(*snowflake as f64 as i64, *snowflake);
+    }
+
+    #[test]
+    fn test_compose() {
+        let layout = SnowflakeLayout::new(44, 6, 13).unwrap();
+        assert_eq!(layout.max_identifier(), 63);
+        assert_eq!(layout.max_sequence(), 8191);
+
+        let snowflake = layout.compose(123456, 42, 8000);
+        assert_eq!(*snowflake, (123456 << 19) | (42 << 13) | 8000);
+        assert_eq!(layout.timestamp_of(&snowflake), 123456);
+        assert_eq!(layout.identifier_of(&snowflake), 42);
+        assert_eq!(layout.sequence_of(&snowflake), 8000);
+
+        // Truncated by mask
+        let snowflake = layout.compose(0, 64 + 1, 8192 + 2);
+        assert_eq!(layout.identifier_of(&snowflake), 1);
+        assert_eq!(layout.sequence_of(&snowflake), 2);
+    }
+
+    #[test]
+    fn test_identifier_split() {
+        assert_eq!(IdentifierSplit::new(5, 5), Ok(DATACENTER_WORKER));
+        assert_eq!(
+            IdentifierSplit::new(9, 1),
+            Err(SnowflakeError::InvalidIdentifierSplit {
+                datacenter_bits: 9,
+                worker_bits: 1
+            })
+        );
+
+        let split = IdentifierSplit::default();
+        assert_eq!((split.max_datacenter(), split.max_worker()), (31, 31));
+        assert_eq!(split.compose(31, 0), Ok(0b11111_00000));
+        assert_eq!(
+            split.compose(32, 0),
+            Err(SnowflakeError::DatacenterOutOfRange {
+                datacenter: 32,
+                max: 31
+            })
+        );
+        assert_eq!(
+            split.compose(0, 32),
+            Err(SnowflakeError::WorkerOutOfRange {
+                worker: 32,
+                max: 31
+            })
+        );
+
+        let split = IdentifierSplit::new(2, 8).unwrap();
+        let identifier = split.compose(3, 200).unwrap();
+        assert_eq!(split.datacenter_of(identifier), 3);
+        assert_eq!(split.worker_of(identifier), 200);
+    }
+}
@@ -0,0 +1,369 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    future::Future,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+#[cfg(feature = "tokio")]
+use std::{sync::atomic::AtomicU64, time::Duration};
+
+use futures::future::BoxFuture;
+#[cfg(feature = "tokio")]
+use tokio::{sync::watch, time::Instant};
+
+use crate::{IdError, SnowflakeConfiguration, SnowflakeGenerator};
+
+/// Keeping leases of coordination backends alive, only built with one of them.
+#[cfg(all(
+    feature = "tokio",
+    any(
+        test,
+        feature = "redis",
+        feature = "etcd",
+        feature = "zookeeper",
+        feature = "sqlx"
+    )
+))]
+pub(crate) mod keep_alive;
+
+/// Where identifier comes from at startup, for elastic fleets where it can't be configured up front.
+///
+/// ```
+/// # use snowflake_ng::{PoolIdentifierProvider, SnowflakeGenerator};
+/// # futures::executor::block_on(async {
+/// let provider = PoolIdentifierProvider::default();
+/// let (generator, lease) = SnowflakeGenerator::with_identifier_provider(&provider).await.unwrap();
+/// assert_eq!(generator.identifier(), lease.identifier());
+///
+/// // On shutdown.
+/// lease.release().await.unwrap();
+/// # });
+/// ```
+pub trait IdentifierProvider: Send + Sync {
+    /// Claim an identifier, nobody else holds it until released.
+    fn acquire(&self) -> impl Future<Output = Result<IdentifierLease, IdError>> + Send;
+}
+
+/// Backend of [`IdentifierLease`](IdentifierLease), giving the identifier back.
+pub trait LeaseHandle: Send + Sync {
+    /// Called at most once by [`IdentifierLease::release`](IdentifierLease::release).
+    fn release(&self) -> BoxFuture<'_, Result<(), IdError>>;
+}
+
+/// Identifier held from [`IdentifierProvider`](IdentifierProvider) until released.
+///
+/// Dropping it without releasing keeps the identifier taken, unless the backend releases or expires it.
+pub struct IdentifierLease {
+    identifier: u64,
+    handle: Option<Box<dyn LeaseHandle>>,
+    released: AtomicBool,
+}
+
+impl IdentifierLease {
+    /// Lease of `identifier`, given back by `handle`.
+    pub fn new(identifier: u64, handle: impl LeaseHandle + 'static) -> Self {
+        Self {
+            identifier,
+            handle: Some(Box::new(handle)),
+            released: AtomicBool::new(false),
+        }
+    }
+
+    /// Lease of `identifier` which nobody else claims, releasing does nothing.
+    pub fn fixed(identifier: u64) -> Self {
+        Self {
+            identifier,
+            handle: None,
+            released: AtomicBool::new(false),
+        }
+    }
+
+    /// The identifier held.
+    pub fn identifier(&self) -> u64 {
+        self.identifier
+    }
+
+    /// Whether [`IdentifierLease::release`](IdentifierLease::release) is called.
+    pub fn is_released(&self) -> bool {
+        self.released.load(Ordering::Acquire)
+    }
+
+    /// Give the identifier back, stop generating with it before that.
+    ///
+    /// Only the first call reaches the backend, later ones return [`IdError::AlreadyReleased`](IdError::AlreadyReleased),
+    /// so the identifier reclaimed by others is never released again.
+    pub async fn release(&self) -> Result<(), IdError> {
+        if self.released.swap(true, Ordering::AcqRel) {
+            return Err(IdError::AlreadyReleased {
+                identifier: self.identifier,
+            });
+        }
+
+        match &self.handle {
+            Some(handle) => handle.release().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for IdentifierLease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdentifierLease")
+            .field("identifier", &self.identifier)
+            .field("released", &self.is_released())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Whether lease of a coordination backend is lost, like [`RedisIdentifierProvider`](crate::RedisIdentifierProvider).
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct LeaseWatch {
+    receiver: watch::Receiver<Option<IdError>>,
+    expiry: Arc<Expiry>,
+}
+
+#[cfg(feature = "tokio")]
+impl LeaseWatch {
+    /// [`IdError::Lost`](IdError::Lost) once lost.
+    pub fn check(&self) -> Result<(), IdError> {
+        match &*self.receiver.borrow() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait until lost, `None` if it's released or dropped before that.
+    pub async fn lost(&mut self) -> Option<IdError> {
+        self.receiver
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|it| it.clone())
+    }
+
+    /// Time to live of the lease, it's renewed every third of that.
+    pub fn ttl(&self) -> Duration {
+        self.expiry.ttl
+    }
+
+    /// When the lease expires unless renewed, or now once lost.
+    ///
+    /// It's counted from when the last renewal was sent, so the backend never expires it earlier.
+    pub fn valid_until(&self) -> Instant {
+        self.expiry.valid_until()
+    }
+
+    /// Whether it's expired already `margin` before [`LeaseWatch::valid_until`](LeaseWatch::valid_until).
+    pub(crate) fn is_expired(&self, margin: Duration) -> bool {
+        self.expiry.is_expired(margin)
+    }
+}
+
+/// When a lease expires, in milliseconds since `origin` for reading it without locking.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+struct Expiry {
+    origin: Instant,
+    ttl: Duration,
+    until: AtomicU64,
+}
+
+#[cfg(feature = "tokio")]
+impl Expiry {
+    fn valid_until(&self) -> Instant {
+        self.origin + Duration::from_millis(self.until.load(Ordering::Acquire))
+    }
+
+    fn is_expired(&self, margin: Duration) -> bool {
+        let now = Instant::now().duration_since(self.origin) + margin;
+        now.as_millis() as u64 >= self.until.load(Ordering::Acquire)
+    }
+}
+
+/// [`IdentifierProvider`](IdentifierProvider) of one fixed identifier, for where it's configured anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StaticIdentifierProvider(pub u64);
+
+impl IdentifierProvider for StaticIdentifierProvider {
+    async fn acquire(&self) -> Result<IdentifierLease, IdError> {
+        Ok(IdentifierLease::fixed(self.0))
+    }
+}
+
+/// In-memory [`IdentifierProvider`](IdentifierProvider) handing out the lowest free identifier of a range,
+/// reclaiming released ones.
+///
+/// It's only shared within this process, e.g. by several generators of one service.
+#[derive(Debug, Clone)]
+pub struct PoolIdentifierProvider {
+    free: Arc<Mutex<BTreeSet<u64>>>,
+}
+
+impl PoolIdentifierProvider {
+    /// Handing out identifiers in `range`.
+    pub fn new(range: Range<u64>) -> Self {
+        Self {
+            free: Arc::new(Mutex::new(range.collect())),
+        }
+    }
+
+    /// How many identifiers are free.
+    pub fn available(&self) -> usize {
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+impl Default for PoolIdentifierProvider {
+    /// Handing out `0..1024`, all identifiers of the default layout.
+    fn default() -> Self {
+        Self::new(0..1024)
+    }
+}
+
+impl IdentifierProvider for PoolIdentifierProvider {
+    async fn acquire(&self) -> Result<IdentifierLease, IdError> {
+        let identifier = self
+            .free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop_first()
+            .ok_or(IdError::Exhausted)?;
+
+        Ok(IdentifierLease::new(
+            identifier,
+            PoolHandle {
+                identifier,
+                free: self.free.clone(),
+            },
+        ))
+    }
+}
+
+struct PoolHandle {
+    identifier: u64,
+    free: Arc<Mutex<BTreeSet<u64>>>,
+}
+
+impl LeaseHandle for PoolHandle {
+    fn release(&self) -> BoxFuture<'_, Result<(), IdError>> {
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(self.identifier);
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
+
+impl SnowflakeGenerator {
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) of default configuration, with identifier acquired from `provider`.
+    ///
+    /// Keep the lease and release it on shutdown. For other configuration, acquire it first
+    /// and pass [`IdentifierLease::identifier`](IdentifierLease::identifier) to [`SnowflakeConfiguration::with_identifier`](SnowflakeConfiguration::with_identifier).
+    ///
+    /// Identifier exceeding the layout is released again and rejected with [`IdError::Invalid`](IdError::Invalid).
+    pub async fn with_identifier_provider<P>(
+        provider: &P,
+    ) -> Result<(Self, IdentifierLease), IdError>
+    where
+        P: IdentifierProvider,
+    {
+        let lease = provider.acquire().await?;
+        match Self::try_with_cfg(SnowflakeConfiguration::with_identifier(lease.identifier())) {
+            Ok(generator) => Ok((generator, lease)),
+            Err(err) => {
+                lease.release().await?;
+                Err(IdError::Invalid(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::SnowflakeError;
+
+    #[tokio::test]
+    async fn test_static_provider() {
+        let provider = StaticIdentifierProvider(7);
+        let (generator, lease) = SnowflakeGenerator::with_identifier_provider(&provider)
+            .await
+            .unwrap();
+        assert_eq!(generator.identifier(), 7);
+        assert_eq!(provider.acquire().await.unwrap().identifier(), 7);
+
+        assert_eq!(lease.release().await, Ok(()));
+        assert_eq!(
+            lease.release().await,
+            Err(IdError::AlreadyReleased { identifier: 7 })
+        );
+
+        let err = SnowflakeGenerator::with_identifier_provider(&StaticIdentifierProvider(1024))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            IdError::Invalid(SnowflakeError::IdentifierOutOfRange { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pool_provider() {
+        let provider = PoolIdentifierProvider::new(0..4);
+        let mut leases = Vec::new();
+        for _ in 0..4 {
+            leases.push(provider.acquire().await.unwrap());
+        }
+        let identifiers = leases
+            .iter()
+            .map(|it| it.identifier())
+            .collect::<HashSet<_>>();
+        assert_eq!(identifiers, (0..4).collect());
+        assert_eq!(provider.available(), 0);
+        assert!(matches!(provider.acquire().await, Err(IdError::Exhausted)));
+
+        // Released one is handed out again.
+        let second = leases.remove(2);
+        second.release().await.unwrap();
+        assert!(second.is_released());
+        assert_eq!(provider.available(), 1);
+        let reclaimed = provider.acquire().await.unwrap();
+        assert_eq!(reclaimed.identifier(), 2);
+
+        // Releasing twice doesn't free it under the new holder.
+        assert_eq!(
+            second.release().await,
+            Err(IdError::AlreadyReleased { identifier: 2 })
+        );
+        assert_eq!(provider.available(), 0);
+
+        // Shared by clones.
+        let cloned = provider.clone();
+        reclaimed.release().await.unwrap();
+        assert_eq!(cloned.acquire().await.unwrap().identifier(), 2);
+
+        // Out of layout is given back.
+        let provider = PoolIdentifierProvider::new(1024..1025);
+        assert!(SnowflakeGenerator::with_identifier_provider(&provider)
+            .await
+            .is_err());
+        assert_eq!(provider.available(), 1);
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use tokio::{sync::watch, time::Instant};
+
+use super::{Expiry, LeaseHandle, LeaseWatch};
+use crate::IdError;
+
+/// Called with the identifier once its lease is lost.
+pub(crate) type OnLeaseLost = Arc<dyn Fn(u64) + Send + Sync>;
+
+impl Expiry {
+    fn new(ttl: Duration, confirmed: Instant) -> Self {
+        let expiry = Self {
+            origin: confirmed,
+            ttl,
+            until: AtomicU64::new(0),
+        };
+        expiry.confirm(confirmed);
+        expiry
+    }
+
+    /// Expiring `ttl` after `confirmed`, rounded down.
+    fn confirm(&self, confirmed: Instant) {
+        let until = (confirmed + self.ttl).duration_since(self.origin);
+        self.until
+            .store(until.as_millis() as u64, Ordering::Release);
+    }
+
+    fn expire(&self) {
+        self.until.store(0, Ordering::Release);
+    }
+}
+
+/// Refreshing a lease in background, reporting to [`LeaseWatch`](LeaseWatch) once lost.
+pub(crate) struct KeepAlive {
+    identifier: u64,
+    ttl: Duration,
+    confirmed: Instant,
+    on_lost: Option<OnLeaseLost>,
+    sender: watch::Sender<Option<IdError>>,
+    expiry: Arc<Expiry>,
+}
+
+impl KeepAlive {
+    /// Lease of `identifier` expiring `ttl` after `confirmed`.
+    pub(crate) fn new(
+        identifier: u64,
+        ttl: Duration,
+        confirmed: Instant,
+        on_lost: Option<OnLeaseLost>,
+    ) -> (Self, LeaseWatch) {
+        let (sender, receiver) = watch::channel(None);
+        let expiry = Arc::new(Expiry::new(ttl, confirmed));
+        let keep_alive = Self {
+            identifier,
+            ttl,
+            confirmed,
+            on_lost,
+            sender,
+            expiry: expiry.clone(),
+        };
+        (keep_alive, LeaseWatch { receiver, expiry })
+    }
+
+    /// Refresh in a Tokio task until released, with `release` called on releasing or dropping.
+    pub(crate) fn spawn<F, Fut, R>(self, refresh: F, release: R) -> KeptLease<R>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<bool, IdError>> + Send + 'static,
+        R: Fn() -> BoxFuture<'static, Result<(), IdError>> + Send + Sync + 'static,
+    {
+        KeptLease {
+            refreshing: tokio::spawn(self.run(refresh)),
+            release,
+            released: AtomicBool::new(false),
+        }
+    }
+
+    /// Refresh every third of ttl, until `refresh` returns `false` or keeps failing until it expires.
+    ///
+    /// Aborting it drops the sender, so [`LeaseWatch::lost`](LeaseWatch::lost) returns `None`.
+    async fn run<F, Fut>(mut self, mut refresh: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<bool, IdError>>,
+    {
+        loop {
+            tokio::time::sleep(self.ttl / 3).await;
+
+            let attempted = Instant::now();
+            match refresh().await {
+                Ok(true) => {
+                    self.confirmed = attempted;
+                    self.expiry.confirm(attempted);
+                }
+                Ok(false) => break,
+                // Still held until expiring, retry.
+                Err(_) if self.confirmed.elapsed() < self.ttl => {}
+                Err(_) => break,
+            }
+        }
+
+        self.expiry.expire();
+        self.sender.send_replace(Some(IdError::Lost {
+            identifier: self.identifier,
+        }));
+        if let Some(on_lost) = &self.on_lost {
+            on_lost(self.identifier);
+        }
+    }
+}
+
+/// [`LeaseHandle`](LeaseHandle) of [`KeepAlive::spawn`](KeepAlive::spawn).
+pub(crate) struct KeptLease<R>
+where
+    R: Fn() -> BoxFuture<'static, Result<(), IdError>> + Send + Sync + 'static,
+{
+    refreshing: tokio::task::JoinHandle<()>,
+    release: R,
+    released: AtomicBool,
+}
+
+impl<R> LeaseHandle for KeptLease<R>
+where
+    R: Fn() -> BoxFuture<'static, Result<(), IdError>> + Send + Sync + 'static,
+{
+    fn release(&self) -> BoxFuture<'_, Result<(), IdError>> {
+        self.refreshing.abort();
+        self.released.store(true, Ordering::Release);
+        (self.release)()
+    }
+}
+
+impl<R> Drop for KeptLease<R>
+where
+    R: Fn() -> BoxFuture<'static, Result<(), IdError>> + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        self.refreshing.abort();
+        if self.released.load(Ordering::Acquire) {
+            return;
+        }
+
+        // Without runtime, it expires after ttl anyway.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn((self.release)());
+        }
+    }
+}
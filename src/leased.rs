@@ -0,0 +1,291 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::time::Duration;
+
+use crate::{
+    IdError, IdentifierLease, LeaseWatch, PersistedSnowflakeGenerator, Sleeper, Snowflake,
+    SnowflakeError, TimeProvider, TimerSleeper,
+};
+
+/// [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) refusing to generate once its [`IdentifierLease`](IdentifierLease)
+/// expires, so it never overlaps with whoever claims the identifier next.
+///
+/// The lease comes with its [`LeaseWatch`](LeaseWatch), like from [`RedisIdentifierProvider::acquire_watched`](crate::RedisIdentifierProvider::acquire_watched),
+/// whose renewal task in background keeps extending [`LeaseWatch::valid_until`](LeaseWatch::valid_until).
+/// Each assignment only reads it, and fails with [`SnowflakeError::LeaseExpired`](SnowflakeError::LeaseExpired)
+/// from a safety margin before that on, or once the lease is lost or released.
+///
+/// The margin is a fifth of ttl by default. Backend expires the lease no earlier than `ttl` after a renewal is sent,
+/// so slow renewals only shorten the window. It should cover the clock skew between hosts though,
+/// as [`Snowflake`](Snowflake) of the next owner are stamped with its own clock.
+///
+/// ```no_run
+/// # use snowflake_ng::{IdentifierLease, LeaseWatch, LeasedGenerator, PersistedSnowflakeGenerator, SnowflakeConfiguration, SnowflakeGenerator};
+/// # use snowflake_ng::provider::StdProvider;
+/// # use std::sync::Arc;
+/// # async fn run(lease: IdentifierLease, watch: LeaseWatch) {
+/// // Like `RedisIdentifierProvider::acquire_watched`.
+/// let generator = SnowflakeGenerator::with_cfg(SnowflakeConfiguration::with_identifier(lease.identifier()));
+/// let persisted = PersistedSnowflakeGenerator::new(Arc::new(generator), Arc::new(StdProvider));
+/// let leased = LeasedGenerator::new(persisted, lease, watch);
+///
+/// let snowflake = leased.assign().await.unwrap();
+///
+/// // On shutdown.
+/// leased.release().await.unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LeasedGenerator<T, S = TimerSleeper> {
+    generator: PersistedSnowflakeGenerator<T, S>,
+    lease: IdentifierLease,
+    watch: LeaseWatch,
+    margin: Duration,
+}
+
+impl<T, S> LeasedGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+{
+    /// Generating with `generator` while `lease` watched by `watch` is valid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `generator` isn't of the identifier leased.
+    pub fn new(
+        generator: PersistedSnowflakeGenerator<T, S>,
+        lease: IdentifierLease,
+        watch: LeaseWatch,
+    ) -> Self {
+        assert_eq!(
+            generator.identifier(),
+            lease.identifier(),
+            "`generator` must be of the identifier leased"
+        );
+
+        Self {
+            generator,
+            lease,
+            margin: watch.ttl() / 5,
+            watch,
+        }
+    }
+
+    /// Stop generating `margin` before the lease expires instead.
+    pub fn with_margin(self, margin: Duration) -> Self {
+        Self { margin, ..self }
+    }
+
+    /// Identifier leased.
+    pub fn identifier(&self) -> u64 {
+        self.lease.identifier()
+    }
+
+    /// Whether it still generates.
+    pub fn is_valid(&self) -> bool {
+        !self.lease.is_released() && !self.watch.is_expired(self.margin)
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake), or [`SnowflakeError::LeaseExpired`](SnowflakeError::LeaseExpired) once the lease isn't valid.
+    ///
+    /// It's checked again after generating, so waiting for the next tick can't slip past it.
+    pub async fn assign(&self) -> Result<Snowflake, SnowflakeError>
+    where
+        S: Sleeper,
+    {
+        self.check()?;
+        let snowflake = self.generator.assign().await;
+        self.check().map(|_| snowflake)
+    }
+
+    /// Same as [`LeasedGenerator::assign`](LeasedGenerator::assign), but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn assign_sync(&self) -> Result<Snowflake, SnowflakeError> {
+        self.check()?;
+        let snowflake = self.generator.assign_sync();
+        self.check().map(|_| snowflake)
+    }
+
+    /// Give the identifier back, see [`IdentifierLease::release`](IdentifierLease::release). It stops generating right away.
+    pub async fn release(&self) -> Result<(), IdError> {
+        self.lease.release().await
+    }
+
+    fn check(&self) -> Result<(), SnowflakeError> {
+        match self.is_valid() {
+            true => Ok(()),
+            false => Err(SnowflakeError::LeaseExpired {
+                identifier: self.identifier(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        future::ready,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use tokio::time::{sleep, Instant};
+
+    use super::*;
+    use crate::{
+        lease::keep_alive::KeepAlive, SnowflakeConfiguration, SnowflakeGenerator,
+        TimestampValidation,
+    };
+
+    const TTL: Duration = Duration::from_secs(3);
+    const LATENCY: Duration = Duration::from_millis(400);
+
+    struct TokioTestProvider(Instant);
+
+    impl TimeProvider for TokioTestProvider {
+        fn timestamp(&self) -> u64 {
+            100_000 + self.0.elapsed().as_millis() as u64
+        }
+    }
+
+    /// Backend of identifier 7, expiring it `TTL` after a renewal arrives, which takes `LATENCY`.
+    struct Backend {
+        owner: AtomicU64,
+        renewing: AtomicBool,
+        expires_at: Mutex<Instant>,
+    }
+
+    impl Backend {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                owner: AtomicU64::new(0),
+                renewing: AtomicBool::new(true),
+                expires_at: Mutex::new(Instant::now()),
+            })
+        }
+
+        /// Claim it if expired.
+        fn claim(self: &Arc<Self>, origin: Instant) -> Option<LeasedGenerator<TokioTestProvider>> {
+            let mut expires_at = self.expires_at.lock().unwrap();
+            if Instant::now() < *expires_at {
+                return None;
+            }
+            *expires_at = Instant::now() + TTL;
+            let owner = self.owner.fetch_add(1, Ordering::AcqRel) + 1;
+
+            let (keep_alive, watch) = KeepAlive::new(7, TTL, Instant::now(), None);
+            let backend = self.clone();
+            let handle = keep_alive.spawn(
+                move || {
+                    let backend = backend.clone();
+                    async move {
+                        sleep(LATENCY).await;
+                        if backend.owner.load(Ordering::Acquire) != owner {
+                            return Ok(false);
+                        }
+                        if !backend.renewing.load(Ordering::Acquire) {
+                            return Err(IdError::Backend {
+                                message: "timed out".to_string(),
+                            });
+                        }
+                        *backend.expires_at.lock().unwrap() = Instant::now() + TTL;
+                        Ok(true)
+                    }
+                },
+                || Box::pin(ready(Ok(()))),
+            );
+
+            let generator = SnowflakeGenerator::with_cfg(
+                SnowflakeConfiguration::with_identifier(7)
+                    .with_timestamp_validation(TimestampValidation::Off),
+            );
+            Some(LeasedGenerator::new(
+                PersistedSnowflakeGenerator::new(
+                    Arc::new(generator),
+                    Arc::new(TokioTestProvider(origin)),
+                ),
+                IdentifierLease::new(7, handle),
+                watch,
+            ))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fenced_before_handed_over() {
+        let origin = Instant::now();
+        let backend = Backend::new();
+        let first = backend.claim(origin).unwrap();
+        let mut second = None;
+
+        let mut snowflakes = HashSet::new();
+        let mut last_assigned = origin;
+        let mut handed_at = None;
+        while origin.elapsed() < Duration::from_secs(20) {
+            sleep(Duration::from_millis(10)).await;
+            // Renewals of the first time out from then on.
+            if origin.elapsed() >= Duration::from_secs(5) && second.is_none() {
+                backend.renewing.store(false, Ordering::Release);
+            }
+
+            match first.assign().await {
+                Ok(snowflake) => {
+                    assert!(handed_at.is_none(), "generated after handed over");
+                    assert!(snowflakes.insert(snowflake));
+                    last_assigned = Instant::now();
+                }
+                Err(err) => assert_eq!(err, SnowflakeError::LeaseExpired { identifier: 7 }),
+            }
+
+            if second.is_none() {
+                second = backend.claim(origin);
+                if second.is_some() {
+                    handed_at = Some(Instant::now());
+                    backend.renewing.store(true, Ordering::Release);
+                }
+            }
+            if let Some(second) = &second {
+                assert!(snowflakes.insert(second.assign().await.unwrap()));
+            }
+        }
+
+        // Fenced by the margin, and the latency of the last renewal.
+        let handed_at = handed_at.unwrap();
+        assert!(last_assigned > origin + Duration::from_secs(5));
+        assert!(handed_at - last_assigned >= TTL / 5 + LATENCY);
+        assert!(!first.is_valid());
+        assert!(second.unwrap().is_valid());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lost_released() {
+        let origin = Instant::now();
+        let backend = Backend::new();
+        let leased = backend.claim(origin).unwrap();
+        assert!(leased.assign().await.is_ok());
+
+        // Fenced right away once the backend tells it's taken, long before expiring.
+        backend.owner.fetch_add(1, Ordering::AcqRel);
+        sleep(TTL / 3 + LATENCY * 2).await;
+        assert!(leased.watch.valid_until() <= Instant::now());
+        assert_eq!(
+            leased.assign().await,
+            Err(SnowflakeError::LeaseExpired { identifier: 7 })
+        );
+
+        // And once released.
+        sleep(TTL).await;
+        let leased = backend.claim(origin).unwrap();
+        assert!(leased.is_valid());
+        leased.release().await.unwrap();
+        assert!(!leased.is_valid());
+        assert!(leased.assign().await.is_err());
+    }
+}
@@ -6,25 +6,164 @@
 // copied, modified, or distributed except according to those terms.
 
 #![doc = include_str!("../README.md")]
+// Without `async` nor `sync`, only non-waiting ones like `try_assign_now` are left.
+#![cfg_attr(not(any(feature = "async", feature = "sync")), allow(dead_code))]
 
 use std::{
-    ops::Deref,
+    fmt, hint,
+    ops::{Deref, Range},
+    str::FromStr,
     sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
     },
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use futures::executor;
-use futures_timer::Delay;
+#[cfg(feature = "async")]
+use futures::{stream, Stream};
+#[cfg(loom)]
+use loom::sync::atomic::AtomicU64;
 use rand::RngCore;
+#[cfg(not(loom))]
+use std::sync::atomic::AtomicU64;
 
+mod block;
+#[cfg(feature = "tokio")]
+mod bounded;
+#[cfg(feature = "tokio")]
+mod buffered;
+mod builder;
+#[cfg(feature = "tokio")]
+mod collision;
+#[cfg(feature = "config-file")]
+mod config_file;
+mod const_generator;
+#[cfg(feature = "test-util")]
+mod deterministic;
+pub mod encoding;
+mod env;
+mod error;
+#[cfg(feature = "etcd")]
+mod etcd_lease;
+mod event;
+#[cfg(feature = "async")]
+mod fair;
+#[cfg(feature = "async")]
+mod file_lease;
+pub mod global;
+mod guard;
+pub mod identifier;
+#[cfg(feature = "sync")]
+mod iter;
+pub mod layouts;
+#[cfg(feature = "async")]
+mod lease;
+#[cfg(feature = "tokio")]
+mod leased;
+mod local;
+#[cfg(feature = "metrics-prometheus")]
+mod metrics;
+#[cfg(feature = "test-util")]
+mod mock;
+#[cfg(feature = "ntp")]
+mod ntp;
+mod pool;
+mod privacy;
 pub mod provider;
+mod rate_limit;
+#[cfg(feature = "redis")]
+mod redis_lease;
+mod registry;
+#[cfg(all(feature = "shm", unix, not(loom)))]
+mod shm;
+mod snapshot;
+#[cfg(feature = "snowflake128")]
+mod snowflake128;
+mod source;
+#[cfg(feature = "sqlx")]
+mod sql_lease;
+#[cfg(feature = "persistence")]
+mod state_file;
+mod stats;
+#[cfg(test)]
+mod testing;
+mod wait;
+#[cfg(feature = "zookeeper")]
+mod zookeeper_lease;
+
+pub use block::{SnowflakeBlock, SnowflakeBlockIter};
+#[cfg(feature = "tokio")]
+pub use bounded::{BoundedSnowflakeGenerator, BoundedStats};
+#[cfg(feature = "tokio")]
+pub use buffered::BufferedSnowflakeGenerator;
+pub use builder::SnowflakeGeneratorBuilder;
+#[cfg(feature = "tokio")]
+pub use collision::{CollisionDetector, Heartbeat, HeartbeatStore};
+pub use const_generator::{SnowflakeGeneratorConst, StandardSnowflakeGenerator};
+#[cfg(feature = "test-util")]
+pub use deterministic::DeterministicSnowflake;
+pub use error::{IdError, SnowflakeError, TryAssignError};
+#[cfg(feature = "etcd")]
+pub use etcd_lease::EtcdIdentifierProvider;
+pub use event::GeneratorEvent;
+use event::Hooks;
+#[cfg(feature = "async")]
+pub use file_lease::FileIdentifierProvider;
+pub use guard::IdentifierGuard;
+#[cfg(feature = "sync")]
+pub use iter::SnowflakeIter;
+pub use layouts::{IdentifierSplit, SnowflakeLayout};
+#[cfg(feature = "tokio")]
+pub use lease::LeaseWatch;
+#[cfg(feature = "async")]
+pub use lease::{
+    IdentifierLease, IdentifierProvider, LeaseHandle, PoolIdentifierProvider,
+    StaticIdentifierProvider,
+};
+#[cfg(feature = "tokio")]
+pub use leased::LeasedGenerator;
+pub use local::ThreadLocalSnowflake;
+#[cfg(feature = "metrics-prometheus")]
+pub use metrics::SnowflakeMetrics;
+#[cfg(feature = "test-util")]
+pub use mock::{MockSnowflakeSource, ScriptExhausted};
+pub use pool::{GeneratorPool, PoolMemberStats};
+use privacy::UsedSequences;
+pub use rate_limit::RateLimitedGenerator;
+#[cfg(feature = "redis")]
+pub use redis_lease::{RedisHeartbeatStore, RedisIdentifierProvider};
+pub use registry::GeneratorRegistry;
+#[cfg(all(feature = "shm", unix, not(loom)))]
+pub use shm::SharedSnowflakeGenerator;
+pub use snapshot::GeneratorState;
+#[cfg(feature = "snowflake128")]
+pub use snowflake128::{Snowflake128, SnowflakeGenerator128, SnowflakeLayout128};
+pub use source::SnowflakeSource;
+#[cfg(feature = "sqlx")]
+pub use sql_lease::{SqlHeartbeatStore, SqlIdentifierProvider, SqlPool};
+#[cfg(feature = "persistence")]
+use state_file::StateFile;
+use stats::Counters;
+pub use stats::GeneratorStats;
+#[cfg(any(feature = "async", feature = "sync"))]
+use wait::Backoff;
+pub use wait::{Sleeper, TimerSleeper, WaitStrategy};
+#[cfg(feature = "zookeeper")]
+pub use zookeeper_lease::ZooKeeperIdentifierProvider;
 
 pub trait TimeProvider {
-    /// Timestamp fetcher.
+    /// Timestamp fetcher, in milliseconds since UNIX epoch.
     fn timestamp(&self) -> u64;
+
+    /// Timestamp in microseconds since UNIX epoch, used when [`time_unit`](SnowflakeConfiguration::time_unit) isn't whole milliseconds,
+    /// and for how long to wait until the next tick.
+    ///
+    /// By default it's scaled from [`TimeProvider::timestamp`](TimeProvider::timestamp), so precision is still 1 millisecond.
+    /// Override it if the clock can do better.
+    fn timestamp_micros(&self) -> u64 {
+        self.timestamp().saturating_mul(1000)
+    }
 }
 
 /// Generated [`Snowflake`](Snowflake)
@@ -90,6 +229,41 @@ pub trait TimeProvider {
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Snowflake(i64);
 
+impl Snowflake {
+    /// The largest [`Snowflake`](Snowflake), all data bits set.
+    pub const MAX: Snowflake = Snowflake(i64::MAX);
+
+    /// Timestamp part with [default layout](layouts::DEFAULT), relative to the epoch of generator.
+    pub fn timestamp(&self) -> u64 {
+        layouts::DEFAULT.timestamp_of(self)
+    }
+
+    /// Timestamp part, converted back to milliseconds since UNIX epoch with `epoch` of generator.
+    pub fn unix_timestamp(&self, epoch: u64) -> u64 {
+        self.timestamp() + epoch
+    }
+
+    /// Identifier part with [default layout](layouts::DEFAULT).
+    pub fn identifier(&self) -> u64 {
+        layouts::DEFAULT.identifier_of(self)
+    }
+
+    /// Sequence number part with [default layout](layouts::DEFAULT).
+    pub fn sequence(&self) -> u64 {
+        layouts::DEFAULT.sequence_of(self)
+    }
+
+    /// Datacenter part of identifier split by `split`, with [default layout](layouts::DEFAULT).
+    pub fn datacenter(&self, split: IdentifierSplit) -> u8 {
+        split.datacenter_of(self.identifier())
+    }
+
+    /// Worker part of identifier split by `split`, with [default layout](layouts::DEFAULT).
+    pub fn worker(&self, split: IdentifierSplit) -> u8 {
+        split.worker_of(self.identifier())
+    }
+}
+
 /// Type alias for [`i64`](i64)
 pub type SnowflakeId = i64;
 
@@ -113,282 +287,4394 @@ impl AsRef<i64> for Snowflake {
     }
 }
 
-#[derive(Debug)]
-pub struct SnowflakeConfiguration {
-    /// Identifier ID
-    ///
-    /// [`SnowflakeGenerator`](SnowflakeGenerator) will use **_10bit_**
-    ///
-    /// By default, `identifier_id` set to the number generated by `rand` crate.
-    pub identifier: u64,
+impl TryFrom<SnowflakeU64> for Snowflake {
+    type Error = SnowflakeError;
+
+    /// Fails if bit 63 is set.
+    fn try_from(value: SnowflakeU64) -> Result<Self, Self::Error> {
+        i64::try_from(value.0)
+            .map(Snowflake)
+            .map_err(|_| SnowflakeError::SignBitSet { bits: value.0 })
+    }
 }
 
-impl SnowflakeConfiguration {
-    pub fn with_identifier(identifier: u64) -> Self {
-        Self { identifier }
+/// Unsigned [`Snowflake`](Snowflake), reclaiming the sign bit for one more timestamp bit.
+///
+/// Generated by [`SnowflakeGenerator::assign_u64`](SnowflakeGenerator::assign_u64), usually with [`layouts::UNSIGNED`](layouts::UNSIGNED),
+/// for wire formats and databases using unsigned 64bit keys.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnowflakeU64(u64);
+
+impl SnowflakeU64 {
+    /// The largest [`SnowflakeU64`](SnowflakeU64), all bits set.
+    pub const MAX: SnowflakeU64 = SnowflakeU64(u64::MAX);
+
+    /// Timestamp part with [unsigned layout](layouts::UNSIGNED), relative to the epoch of generator.
+    pub fn timestamp(&self) -> u64 {
+        layouts::UNSIGNED.timestamp_of_u64(self)
+    }
+
+    /// Identifier part with [unsigned layout](layouts::UNSIGNED).
+    pub fn identifier(&self) -> u64 {
+        layouts::UNSIGNED.identifier_of_u64(self)
+    }
+
+    /// Sequence number part with [unsigned layout](layouts::UNSIGNED).
+    pub fn sequence(&self) -> u64 {
+        layouts::UNSIGNED.sequence_of_u64(self)
     }
 }
 
-impl Default for SnowflakeConfiguration {
-    fn default() -> Self {
-        Self {
-            identifier: rand::thread_rng().next_u64(),
-        }
+impl From<SnowflakeU64> for u64 {
+    fn from(value: SnowflakeU64) -> Self {
+        value.0
     }
 }
 
-unsafe impl Send for SnowflakeConfiguration {}
+impl From<u64> for SnowflakeU64 {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
 
-/// Filling timestamp by mask  
-fn fill_timestamp(sid: u64, timestamp: u64) -> u64 {
-    const MASK: u64 = (1u64 << 41) - 1;
-    let truncated_timestamp = timestamp & MASK; // Make sure `timestamp` up to 41bit
-    let filled = truncated_timestamp << 22;
-    (sid & !(MASK << 22)) | filled
+impl TryFrom<Snowflake> for SnowflakeU64 {
+    type Error = SnowflakeError;
+
+    /// Fails if [`Snowflake`](Snowflake) is negative.
+    fn try_from(value: Snowflake) -> Result<Self, Self::Error> {
+        u64::try_from(value.0)
+            .map(SnowflakeU64)
+            .map_err(|_| SnowflakeError::SignBitSet {
+                bits: value.0 as u64,
+            })
+    }
 }
 
-/// Filling identifier by mask
-fn fill_identifier(sid: u64, identifier: u64) -> u64 {
-    const MASK: u64 = (1u64 << 10) - 1; // 限定为10位
-    let truncated_identifier = identifier & MASK; // Make sure `identifier` up to 10bit
-    let filled = truncated_identifier << 12;
-    (sid & !(MASK << 12)) | filled
+impl Deref for SnowflakeU64 {
+    type Target = u64;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
 }
 
-/// Filling sequence by mask
-fn fill_sequence(sid: u64, sequence: u64) -> u64 {
-    const MASK: u64 = (1u64 << 12) - 1;
-    let truncated_sequence = sequence & MASK; // // Make sure `sequence` up to 12bit
+impl AsRef<u64> for SnowflakeU64 {
+    fn as_ref(&self) -> &u64 {
+        self
+    }
+}
 
-    // Does not need to shift
-    (sid & !MASK) | truncated_sequence
+impl fmt::Display for SnowflakeU64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
-pub fn filling<T0, T1, T2>(dest: u64, timestamp: T0, identifier: T1, sequence: T2) -> u64
-where
-    T0: Into<u64>,
-    T1: Into<u64>,
-    T2: Into<u64>,
-{
-    let sid = fill_timestamp(dest, timestamp.into());
-    let sid = fill_identifier(sid, identifier.into());
-    fill_sequence(sid, sequence.into())
+impl FromStr for SnowflakeU64 {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
 }
 
-/// Generating [`Snowflake`](Snowflake)
+/// Configuration of [`SnowflakeGenerator`](SnowflakeGenerator).
 ///
-/// Recommended keep this generator single-instance for one instance's SID generation.
+/// # Serde
 ///
-/// # Thread safety
+/// With feature `serde`, deserialization runs [`SnowflakeConfiguration::validate`](SnowflakeConfiguration::validate),
+/// `identifier` is required but others can be omitted, and unknown fields are rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "SerdeConfiguration")
+)]
+pub struct SnowflakeConfiguration {
+    /// Identifier ID
+    ///
+    /// [`SnowflakeGenerator`](SnowflakeGenerator) will use **_10bit_** (or the identifier bits of [`layout`](SnowflakeConfiguration::layout)),
+    /// see [`SnowflakeConfiguration::identifier`](SnowflakeConfiguration::identifier) for the effective value.
+    ///
+    /// By default, `identifier_id` set to the number generated by `rand` crate, masked to **_10bit_**.
+    ///
+    /// Random identifier is only good for a few instances: any two instances collide with probability 1/1024,
+    /// and with 38 instances it's more likely than not that some of them collide.
+    pub identifier: u64,
+
+    /// Epoch in milliseconds since UNIX epoch.
+    ///
+    /// Timestamp embedded in [`Snowflake`](Snowflake) is relative to this epoch,
+    /// so a recent epoch makes 41bit timestamp last much longer than 2039.
+    ///
+    /// If [`TimeProvider`](TimeProvider) returns the time before epoch, timestamp saturates at 0.
+    ///
+    /// By default, `epoch` set to 0 (the UNIX epoch).
+    pub epoch: u64,
+
+    /// Bit layout of generated [`Snowflake`](Snowflake).
+    ///
+    /// By default, `layout` set to [`layouts::DEFAULT`](layouts::DEFAULT).
+    pub layout: SnowflakeLayout,
+
+    /// How identifier is split into datacenter and worker, only for decoding and validation.
+    ///
+    /// It's set by [`SnowflakeConfiguration::with_datacenter_worker`](SnowflakeConfiguration::with_datacenter_worker),
+    /// and [`SnowflakeConfiguration::validate`](SnowflakeConfiguration::validate) checks it fits in the identifier bits of layout.
+    ///
+    /// By default, `identifier_split` set to `None` (not split).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub identifier_split: Option<IdentifierSplit>,
+
+    /// Length of one tick of timestamp, must be whole microseconds.
+    ///
+    /// Timestamp embedded in [`Snowflake`](Snowflake) counts ticks since [`epoch`](SnowflakeConfiguration::epoch),
+    /// coarser tick makes timestamp last longer, but at most `2^sequence_bits` IDs can be generated in one tick.
+    /// e.g. 10 milliseconds (like Sonyflake) makes 41bit timestamp last about 700 years.
+    ///
+    /// Sub-millisecond tick reads [`TimeProvider::timestamp_micros`](TimeProvider::timestamp_micros),
+    /// e.g. 1 microsecond with [`layouts::MICROSECOND`](layouts::MICROSECOND) for trace IDs.
+    ///
+    /// By default, `time_unit` set to 1 millisecond.
+    pub time_unit: Duration,
+
+    /// How far [`TimeProvider`](TimeProvider) can go backwards before it's treated as clock rollback.
+    ///
+    /// Within tolerance, generator waits for the clock catching up, so waiting is bounded by tolerance for a running clock.
+    /// Beyond it, [`rollback_policy`](SnowflakeConfiguration::rollback_policy) is taken, and layout with
+    /// [rollback flag](SnowflakeLayout#rollback-flag) always keeps generating from the last timestamp with the flag set.
+    ///
+    /// Every time the clock is observed behind is counted by [`SnowflakeGenerator::clock_behind_count`](SnowflakeGenerator::clock_behind_count).
+    ///
+    /// By default, `rollback_tolerance` set to 5 milliseconds.
+    pub rollback_tolerance: Duration,
+
+    /// What to do when the clock goes backwards beyond [`rollback_tolerance`](SnowflakeConfiguration::rollback_tolerance).
+    ///
+    /// By default, `rollback_policy` set to [`RollbackPolicy::Error`](RollbackPolicy::Error).
+    pub rollback_policy: RollbackPolicy,
+
+    /// What to do when sequence of current tick is exhausted.
+    ///
+    /// By default, `overflow_policy` set to [`OverflowPolicy::WaitNextMillis`](OverflowPolicy::WaitNextMillis).
+    pub overflow_policy: OverflowPolicy,
+
+    /// How to wait when sequence exhausted or the clock is behind.
+    ///
+    /// By default, `wait_strategy` set to [`WaitStrategy::Sleep`](WaitStrategy::Sleep).
+    pub wait_strategy: WaitStrategy,
+
+    /// Longest sleep waiting for the clock behind, backing off exponentially from 1 millisecond up to it, with jitter.
+    ///
+    /// It's never past the clock catching up, nor shorter than the current tick, so zero waits tick by tick.
+    /// Every backoff is counted by [`GeneratorStats::backoffs`](GeneratorStats::backoffs).
+    ///
+    /// By default, `max_backoff` set to 250 milliseconds.
+    pub max_backoff: Duration,
+
+    /// Whether to jitter the backoff within the upper half, so waiters behind the same clock wake up apart.
+    ///
+    /// Turn it off for the backoff being deterministic, e.g. in tests with time paused.
+    ///
+    /// By default, `backoff_jitter` set to `true`.
+    pub backoff_jitter: bool,
+
+    /// How many attempts of claiming reuse one read of [`TimeProvider`](TimeProvider) when losing races, 0 is treated as 1.
+    ///
+    /// Reading the clock may cost more than the race itself, e.g. with a provider asking NTP. The clock is read again
+    /// after that many, or right away once the state is newer than the clock read.
+    ///
+    /// By default, `cas_attempts` set to 16.
+    pub cas_attempts: u32,
+
+    /// How timestamps of [`TimeProvider`](TimeProvider) are checked before trusting them,
+    /// catching providers of wrong unit (e.g. seconds or nanoseconds) before any garbage [`Snowflake`](Snowflake) generated.
+    ///
+    /// By default, `timestamp_validation` set to [`TimestampValidation::Window`](TimestampValidation::Window).
+    pub timestamp_validation: TimestampValidation,
+
+    /// Start sequence of every tick at a random offset rather than 0, wrapping around.
+    ///
+    /// Otherwise low bits of most [`Snowflake`](Snowflake) are 0, which leaks issuance rate and skews modulo sharding.
+    /// It's still at most `2^sequence_bits` per tick, but [`Snowflake`](Snowflake) of the same tick are no longer increasing,
+    /// and blocks of [`SnowflakeGenerator::reserve_block`](SnowflakeGenerator::reserve_block) are limited to half of one slice so they never wrap.
+    ///
+    /// By default, `randomize_sequence_start` set to `false`.
+    pub randomize_sequence_start: bool,
+
+    /// Privacy mode, filling sequence bits with random unused values rather than counting,
+    /// so observers can't tell how many [`Snowflake`](Snowflake) are generated between two of them.
+    ///
+    /// Used values of the latest tick are tracked in a bitmap, it's still at most `2^sequence_bits` per tick before waiting,
+    /// and timestamp still orders [`Snowflake`](Snowflake) across ticks. It costs a lock per assignment,
+    /// bulk assignments take one at a time, and layout can have at most 20 sequence bits (checked by [`SnowflakeConfiguration::validate`](SnowflakeConfiguration::validate),
+    /// unvalidated constructors panic). It takes precedence over [`randomize_sequence_start`](SnowflakeConfiguration::randomize_sequence_start).
+    ///
+    /// By default, `random_sequence` set to `false`.
+    pub random_sequence: bool,
+}
+
+/// Check on timestamps of [`TimeProvider`](TimeProvider), see [`SnowflakeConfiguration::timestamp_validation`](SnowflakeConfiguration::timestamp_validation).
 ///
-/// You can use [`::std::sync::Arc`](::std::sync::Arc) sharing ownership between thread.
-#[derive(Debug, Default)]
-pub struct SnowflakeGenerator {
-    timestamp_sequence: AtomicU64,
-    cfg: SnowflakeConfiguration,
+/// Implausible timestamp is reported by [`SnowflakeError::ImplausibleTimestamp`](SnowflakeError::ImplausibleTimestamp),
+/// and infallible [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign) panics with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum TimestampValidation {
+    /// Trust every timestamp.
+    Off,
+    /// Reject timestamps before [`epoch`](SnowflakeConfiguration::epoch), before 2024 (when this crate started), or after 3000.
+    #[default]
+    Window,
+    /// Same as [`TimestampValidation::Window`](TimestampValidation::Window), and reject timestamps more than `max_jump`
+    /// ahead of the previous read of the clock.
+    ///
+    /// The rejected one is still taken as read, so the clock staying there is accepted from the next read on.
+    /// So is the first read of a generator idle for longer than `max_jump` rejected, and it recovers right after.
+    WindowAndJump { max_jump: Duration },
 }
-const MAX_SEQUENCE: u16 = 0xFFF; // 12bit sequence
 
-impl SnowflakeGenerator {
-    pub fn with_cfg(cfg: SnowflakeConfiguration) -> Self {
+/// Timestamps (milliseconds since UNIX epoch) before 2024-01-01 are implausible.
+const PLAUSIBLE_SINCE: u64 = 1_704_067_200_000;
+
+/// Timestamps (milliseconds since UNIX epoch) after 3000-01-01 are implausible.
+const PLAUSIBLE_UNTIL: u64 = 32_503_680_000_000;
+
+/// Action taken when the clock goes backwards beyond [`SnowflakeConfiguration::rollback_tolerance`](SnowflakeConfiguration::rollback_tolerance).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum RollbackPolicy {
+    /// [`SnowflakeGenerator::try_assign`](SnowflakeGenerator::try_assign) returns [`SnowflakeError::ClockMovedBackwards`](SnowflakeError::ClockMovedBackwards),
+    /// infallible [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign) keeps waiting for the clock catching up.
+    #[default]
+    Error,
+    /// Panic.
+    Panic,
+    /// Keep generating from the last timestamp, taking following ticks once sequence exhausted.
+    ///
+    /// Output is still monotonic, but timestamp embedded runs ahead of the clock until it catches up.
+    HoldLastTimestamp,
+}
+
+/// Action taken when sequence of current tick is exhausted, see [`SnowflakeConfiguration::overflow_policy`](SnowflakeConfiguration::overflow_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum OverflowPolicy {
+    /// Wait until the next tick with [`wait_strategy`](SnowflakeConfiguration::wait_strategy).
+    #[default]
+    WaitNextMillis,
+    /// Spin with [`hint::spin_loop`](hint::spin_loop) until the next tick, lower latency but burns CPU meanwhile.
+    SpinNextMillis,
+    /// [`SnowflakeGenerator::try_assign`](SnowflakeGenerator::try_assign) returns [`SnowflakeError::SequenceOverflow`](SnowflakeError::SequenceOverflow)
+    /// with the time until the next tick, infallible [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign) keeps waiting.
+    Error,
+    /// Keep going with the next tick ahead of the clock, but at most `max_drift` ahead, then fall back to waiting.
+    ///
+    /// Output is still unique and monotonic, and the clock being behind within `max_drift` isn't treated as rollback.
+    BorrowFuture { max_drift: Duration },
+}
+
+impl SnowflakeConfiguration {
+    /// Constructing [`SnowflakeConfiguration`](SnowflakeConfiguration) with `identifier`.
+    ///
+    /// `identifier` is **NOT** validated, bits exceeding the layout will be silently truncated when generating,
+    /// e.g. `5000` becomes `904` with 10bit identifier. Use [`SnowflakeConfiguration::try_with_identifier`](SnowflakeConfiguration::try_with_identifier) if you want to reject it.
+    pub fn with_identifier(identifier: u64) -> Self {
         Self {
-            cfg,
-            timestamp_sequence: AtomicU64::new(0),
+            identifier,
+            epoch: 0,
+            layout: layouts::DEFAULT,
+            identifier_split: None,
+            time_unit: Duration::from_millis(1),
+            rollback_tolerance: Duration::from_millis(5),
+            rollback_policy: RollbackPolicy::Error,
+            overflow_policy: OverflowPolicy::WaitNextMillis,
+            wait_strategy: WaitStrategy::Sleep,
+            max_backoff: Duration::from_millis(250),
+            backoff_jitter: true,
+            cas_attempts: 16,
+            timestamp_validation: TimestampValidation::Window,
+            randomize_sequence_start: false,
+            random_sequence: false,
         }
     }
 
-    /// Assign a [`Snowflake`](Snowflake) with [`TimeProvider`](TimeProvider)
-    pub async fn assign<T>(&self, provider: &T) -> Snowflake
-    where
-        T: TimeProvider + Sync + Send,
-    {
-        loop {
-            let timestamp = provider.timestamp();
-            let current = self.timestamp_sequence.load(Ordering::Relaxed);
-            let current_timestamp = current >> 16;
-            let current_sequence = (current & 0xFFFF) as u16;
-
-            match current_timestamp.cmp(&timestamp) {
-                std::cmp::Ordering::Less => {
-                    // update timestamp
-                    let new_value = timestamp << 16;
-
-                    if self
-                        .timestamp_sequence
-                        .compare_exchange(current, new_value, Ordering::SeqCst, Ordering::SeqCst)
-                        .is_ok()
-                    {
-                        let sid = fill_timestamp(0, timestamp);
-                        let sid = fill_identifier(sid, self.cfg.identifier);
-                        let sid = fill_sequence(sid, 0);
-                        return Snowflake(sid as i64);
-                    }
-                }
-                std::cmp::Ordering::Equal => {
-                    if current_sequence >= MAX_SEQUENCE {
-                        // Sequence reached MAX, waiting for next millisecond
-                        Delay::new(Duration::from_millis(1)).await;
-                        continue;
-                    }
+    /// Constructing [`SnowflakeConfiguration`](SnowflakeConfiguration) for JavaScript-safe [`Snowflake`](Snowflake),
+    /// which is [`layouts::JS_SAFE_53`](layouts::JS_SAFE_53) with 1 second tick.
+    ///
+    /// Every generated [`Snowflake`](Snowflake) is at most `2^53 - 1` and survives converting to `f64`,
+    /// the 33bit timestamp lasts about 272 years since `epoch`.
+    pub fn js_safe(identifier: u64, epoch: u64) -> Self {
+        Self::with_identifier(identifier)
+            .with_epoch(epoch)
+            .with_layout(layouts::JS_SAFE_53)
+            .with_time_unit(Duration::from_secs(1))
+    }
 
-                    let new_sequence = current_sequence + 1;
-                    let new_value = (timestamp << 16) | new_sequence as u64;
-
-                    if self
-                        .timestamp_sequence
-                        .compare_exchange(current, new_value, Ordering::SeqCst, Ordering::SeqCst)
-                        .is_ok()
-                    {
-                        let sid = fill_timestamp(0, timestamp);
-                        let sid = fill_identifier(sid, self.cfg.identifier);
-                        let sid = fill_sequence(sid, new_sequence as u64);
-                        return Snowflake(sid as i64);
-                    }
-                }
-                std::cmp::Ordering::Greater => Delay::new(Duration::from_millis(1)).await,
-            };
-        }
+    /// Constructing [`SnowflakeConfiguration`](SnowflakeConfiguration) with `identifier`,
+    /// returning [`SnowflakeError::IdentifierOutOfRange`](SnowflakeError::IdentifierOutOfRange) if it doesn't fit in the [default layout](layouts::DEFAULT).
+    pub fn try_with_identifier(identifier: u64) -> Result<Self, SnowflakeError> {
+        let cfg = Self::with_identifier(identifier);
+        cfg.validate()?;
+        Ok(cfg)
     }
 
-    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way.
-    #[cfg(feature = "sync")]
-    pub fn assign_sync<T>(&self, provider: &T) -> Snowflake
-    where
-        T: TimeProvider + Sync + Send,
-    {
-        executor::block_on(self.assign(provider))
+    /// Constructing [`SnowflakeConfiguration`](SnowflakeConfiguration) with identifier derived from this process,
+    /// see [`SnowflakeConfiguration::process_fingerprint`](SnowflakeConfiguration::process_fingerprint).
+    ///
+    /// Unlike [`SnowflakeConfiguration::default`](SnowflakeConfiguration::default), processes overlapping on one host
+    /// (e.g. during a rolling restart) are very unlikely to share the identifier, but every call in one process returns the same one,
+    /// so it's only for one generator per process.
+    pub fn per_process() -> Self {
+        Self::with_identifier(Self::process_fingerprint() & layouts::DEFAULT.max_identifier())
     }
-}
 
-/// Persisted [`SnowflakeGenerator`](SnowflakeGenerator).
-///
-/// Designed for easier contextualization.
-///
-/// # Thread safety
-///
-/// YES. You can use [`::std::sync::Arc`](::std::sync::Arc) to send data between threads safety.
-///
-/// # Clone
-///
-/// Clone is cheap. If you clone it, it equals invoke [`Arc::clone`](Arc::clone) three times.
-#[derive(Debug)]
-pub struct PersistedSnowflakeGenerator<T> {
-    generator: Arc<SnowflakeGenerator>,
-    provider: Arc<T>,
-}
+    /// [`identifier::derive`](identifier::derive) of hostname, process ID and the time it's first called, stable for the life of this process.
+    ///
+    /// Mask it to identifier bits for an identifier, it's handy to log it on startup.
+    pub fn process_fingerprint() -> u64 {
+        static FINGERPRINT: OnceLock<u64> = OnceLock::new();
+        *FINGERPRINT.get_or_init(|| {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |it| it.as_nanos());
+            fingerprint(&identifier::hostname(), std::process::id(), nanos)
+        })
+    }
 
-impl<T> PersistedSnowflakeGenerator<T>
-where
-    T: TimeProvider + Send + Sync,
-{
-    /// Constructing new [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) from already instanced [`SnowflakeGenerator`](SnowflakeGenerator) and [`TimeProvider`](TimeProvider)
+    /// Constructing [`SnowflakeConfiguration`](SnowflakeConfiguration) with identifier of `datacenter` and `worker`,
+    /// split by [`layouts::DATACENTER_WORKER`](layouts::DATACENTER_WORKER) (5bit each) like Twitter's.
     ///
-    /// The cost very low, please relax to constructing your [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator).
+    /// Returning [`SnowflakeError::DatacenterOutOfRange`](SnowflakeError::DatacenterOutOfRange) or
+    /// [`SnowflakeError::WorkerOutOfRange`](SnowflakeError::WorkerOutOfRange) if either doesn't fit.
     ///
-    /// # Thread safety
+    /// ```
+    /// # use snowflake_ng::{provider::StdProvider, SnowflakeConfiguration, SnowflakeGenerator};
+    /// let cfg = SnowflakeConfiguration::with_datacenter_worker(3, 17).unwrap();
+    /// let snowflake = SnowflakeGenerator::with_cfg(cfg.clone()).assign_sync(&StdProvider);
     ///
-    /// Yes, `time_provider` must be send and sync between threads and [`SnowflakeGenerator`](SnowflakeGenerator) are already thread safe.
-    pub fn new(generator: Arc<SnowflakeGenerator>, provider: Arc<T>) -> Self {
-        Self {
-            generator,
-            provider,
-        }
+    /// assert_eq!(cfg.datacenter_of(&snowflake), Some(3));
+    /// assert_eq!(cfg.worker_of(&snowflake), Some(17));
+    /// ```
+    pub fn with_datacenter_worker(datacenter: u8, worker: u8) -> Result<Self, SnowflakeError> {
+        Self::with_split_datacenter_worker(layouts::DATACENTER_WORKER, datacenter, worker)
     }
 
-    /// Assign a new [`Snowflake`](Snowflake)
-    pub async fn assign(&self) -> Snowflake {
-        self.generator.assign(self.provider.as_ref()).await
+    /// Same as [`SnowflakeConfiguration::with_datacenter_worker`](SnowflakeConfiguration::with_datacenter_worker), but split by `split`.
+    pub fn with_split_datacenter_worker(
+        split: IdentifierSplit,
+        datacenter: u8,
+        worker: u8,
+    ) -> Result<Self, SnowflakeError> {
+        let identifier = split.compose(datacenter, worker)?;
+        Ok(Self {
+            identifier_split: Some(split),
+            ..Self::with_identifier(identifier)
+        })
     }
 
-    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way.
-    #[cfg(feature = "sync")]
-    pub fn assign_sync(&self) -> Snowflake {
-        self.generator.assign_sync(self.provider.as_ref())
+    /// Effective identifier stamped into [`Snowflake`](Snowflake), truncated to the identifier bits of layout.
+    pub fn identifier(&self) -> u64 {
+        self.identifier & self.layout.max_identifier()
     }
-}
 
-impl<T> Clone for PersistedSnowflakeGenerator<T> {
-    fn clone(&self) -> Self {
-        Self {
-            generator: self.generator.clone(),
-            provider: self.provider.clone(),
-        }
+    /// Datacenter of `snowflake` by layout and [`identifier_split`](SnowflakeConfiguration::identifier_split), `None` if not split.
+    pub fn datacenter_of(&self, snowflake: &Snowflake) -> Option<u8> {
+        let identifier = self.layout.identifier_of(snowflake);
+        self.identifier_split
+            .map(|split| split.datacenter_of(identifier))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::{collections::HashSet, sync::Arc};
+    /// Worker of `snowflake` by layout and [`identifier_split`](SnowflakeConfiguration::identifier_split), `None` if not split.
+    pub fn worker_of(&self, snowflake: &Snowflake) -> Option<u8> {
+        let identifier = self.layout.identifier_of(snowflake);
+        self.identifier_split
+            .map(|split| split.worker_of(identifier))
+    }
 
-    use parking_lot::RwLock;
-    use provider::{StdProvider, STD_PROVIDER};
+    /// Check all the fields are in range of layout, and `time_unit` is valid.
+    pub fn validate(&self) -> Result<(), SnowflakeError> {
+        let max = self.layout.max_identifier();
+        if self.identifier > max {
+            return Err(SnowflakeError::IdentifierOutOfRange {
+                identifier: self.identifier,
+                max,
+            });
+        }
 
-    use super::*;
+        if self.time_unit.is_zero() || !self.time_unit.subsec_nanos().is_multiple_of(1_000) {
+            return Err(SnowflakeError::InvalidTimeUnit {
+                time_unit: self.time_unit,
+            });
+        }
 
-    #[test]
-    fn test_fill_timestamp() {
-        // Case1
-        let sid = 0u64;
-        let timestamp = 0b101010;
-        let expected = 42 << 22;
-        let result = fill_timestamp(sid, timestamp);
-        assert_eq!(result, expected);
+        if let Some(split) = self.identifier_split {
+            if split.datacenter_bits() + split.worker_bits() > self.layout.identifier_bits() {
+                return Err(SnowflakeError::InvalidIdentifierSplit {
+                    datacenter_bits: split.datacenter_bits(),
+                    worker_bits: split.worker_bits(),
+                });
+            }
+        }
 
-        // Case2
-        let sid = 0u64;
-        let timestamp = (1u64 << 42) - 1;
-        let expected = ((1u64 << 41) - 1) << 22;
-        let result = fill_timestamp(sid, timestamp);
-        assert_eq!(result, expected);
+        let sequence_bits = self.layout.sequence_bits();
+        if self.random_sequence && sequence_bits > privacy::MAX_RANDOM_SEQUENCE_BITS {
+            return Err(SnowflakeError::RandomSequenceTooWide {
+                sequence_bits,
+                max: privacy::MAX_RANDOM_SEQUENCE_BITS,
+            });
+        }
+
+        Ok(())
     }
 
-    #[test]
-    fn test_fill_identifier() {
-        // Case1
-        let sid = 0u64;
-        let identifier = 0b110101;
-        let expected = 53 << 12;
-        let result = fill_identifier(sid, identifier);
-        assert_eq!(result, expected);
+    /// Whether sequence starts at a random offset, privacy mode takes precedence.
+    fn offset_sequence(&self) -> bool {
+        self.randomize_sequence_start && !self.random_sequence
+    }
 
-        // Case2
-        let sid = 0u64;
-        let identifier = (1u64 << 11) - 1;
-        let expected = ((1u64 << 10) - 1) << 12;
-        let result = fill_identifier(sid, identifier);
-        assert_eq!(result, expected);
+    /// Length of `time_unit` in microseconds, at least 1.
+    fn unit_micros(&self) -> u64 {
+        (self.time_unit.as_micros() as u64).max(1)
     }
 
-    #[test]
-    fn test_fill_sequence() {
-        // Case1
-        let sid = 0u64;
-        let sequence = 0b1001;
-        let expected = 9;
-        let result = fill_sequence(sid, sequence);
+    /// Microseconds since UNIX epoch read from `provider`.
+    ///
+    /// Whole millisecond `time_unit` only reads [`TimeProvider::timestamp`](TimeProvider::timestamp).
+    fn now_micros<T>(&self, provider: &T) -> u64
+    where
+        T: TimeProvider + ?Sized,
+    {
+        if self.unit_micros().is_multiple_of(1000) {
+            provider.timestamp().saturating_mul(1000)
+        } else {
+            provider.timestamp_micros()
+        }
+    }
+
+    /// Timestamp embedded in [`Snowflake`](Snowflake) at `now` microseconds since UNIX epoch.
+    fn ticks_of_micros(&self, now: u64) -> u64 {
+        now.saturating_sub(self.epoch.saturating_mul(1000)) / self.unit_micros()
+    }
+
+    /// Plausible `(earliest, latest)` microseconds since UNIX epoch.
+    fn plausible_micros(&self) -> (u64, u64) {
+        match self.timestamp_validation {
+            TimestampValidation::Off => (0, u64::MAX),
+            _ => (
+                PLAUSIBLE_SINCE.max(self.epoch).saturating_mul(1000),
+                PLAUSIBLE_UNTIL * 1000,
+            ),
+        }
+    }
+
+    /// How far timestamp can jump ahead of the previous read, in ticks.
+    fn max_jump_ticks(&self) -> u64 {
+        match self.timestamp_validation {
+            TimestampValidation::WindowAndJump { max_jump } => {
+                max_jump.as_micros() as u64 / self.unit_micros()
+            }
+            _ => u64::MAX,
+        }
+    }
+
+    /// How long until `tick` ends, at least [`MIN_WAIT`](MIN_WAIT).
+    ///
+    /// Always reads [`TimeProvider::timestamp_micros`](TimeProvider::timestamp_micros), so waiting for the next millisecond
+    /// doesn't overshoot by nearly a whole millisecond. It's only called before waiting, keeping the hot path to one read.
+    fn until_tick_end<T>(&self, provider: &T, tick: u64) -> Duration
+    where
+        T: TimeProvider + ?Sized,
+    {
+        let unit = self.unit_micros();
+        let elapsed = provider
+            .timestamp_micros()
+            .saturating_sub(self.epoch.saturating_mul(1000));
+        let remaining = ((tick + 1) * unit).saturating_sub(elapsed).min(unit);
+        Duration::from_micros(remaining).max(MIN_WAIT)
+    }
+
+    /// Timestamp embedded in [`Snowflake`](Snowflake) at `timestamp` milliseconds since UNIX epoch.
+    fn ticks_at(&self, timestamp: u64) -> u64 {
+        timestamp.saturating_sub(self.epoch) * 1000 / self.unit_micros()
+    }
+
+    /// Milliseconds since UNIX epoch at the start of `ticks`.
+    fn unix_timestamp_of_ticks(&self, ticks: u64) -> u64 {
+        (ticks * self.unit_micros()) / 1000 + self.epoch
+    }
+
+    /// `rollback_tolerance` in ticks.
+    fn rollback_tolerance_ticks(&self) -> u64 {
+        self.rollback_tolerance.as_micros() as u64 / self.unit_micros()
+    }
+
+    /// Timestamp of `snowflake` in milliseconds since UNIX epoch, taking `epoch`, `layout` and `time_unit` into account.
+    pub fn unix_timestamp_of(&self, snowflake: &Snowflake) -> u64 {
+        self.unix_timestamp_micros_of(snowflake) / 1000
+    }
+
+    /// Same as [`SnowflakeConfiguration::unix_timestamp_of`](SnowflakeConfiguration::unix_timestamp_of), but in microseconds.
+    pub fn unix_timestamp_micros_of(&self, snowflake: &Snowflake) -> u64 {
+        self.layout.timestamp_of(snowflake) * self.unit_micros() + self.epoch * 1000
+    }
+
+    /// Use `epoch` (milliseconds since UNIX epoch) as the epoch of timestamp.
+    pub fn with_epoch(self, epoch: u64) -> Self {
+        Self { epoch, ..self }
+    }
+
+    /// Use `layout` as the bit layout of [`Snowflake`](Snowflake).
+    pub fn with_layout(self, layout: SnowflakeLayout) -> Self {
+        Self { layout, ..self }
+    }
+
+    /// Use `time_unit` as the length of one tick of timestamp.
+    pub fn with_time_unit(self, time_unit: Duration) -> Self {
+        Self { time_unit, ..self }
+    }
+
+    /// Use `rollback_tolerance` as how far the clock can go backwards before it's treated as clock rollback.
+    pub fn with_rollback_tolerance(self, rollback_tolerance: Duration) -> Self {
+        Self {
+            rollback_tolerance,
+            ..self
+        }
+    }
+
+    /// Use `rollback_policy` when the clock goes backwards beyond tolerance.
+    pub fn with_rollback_policy(self, rollback_policy: RollbackPolicy) -> Self {
+        Self {
+            rollback_policy,
+            ..self
+        }
+    }
+
+    /// Use `overflow_policy` when sequence of current tick is exhausted.
+    pub fn with_overflow_policy(self, overflow_policy: OverflowPolicy) -> Self {
+        Self {
+            overflow_policy,
+            ..self
+        }
+    }
+
+    /// Use `wait_strategy` when sequence exhausted or the clock is behind.
+    pub fn with_wait_strategy(self, wait_strategy: WaitStrategy) -> Self {
+        Self {
+            wait_strategy,
+            ..self
+        }
+    }
+
+    /// Back off up to `max_backoff` waiting for the clock behind.
+    pub fn with_max_backoff(self, max_backoff: Duration) -> Self {
+        Self {
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// Jitter the backoff or not.
+    pub fn with_backoff_jitter(self, backoff_jitter: bool) -> Self {
+        Self {
+            backoff_jitter,
+            ..self
+        }
+    }
+
+    /// Reuse one read of the clock for `cas_attempts` attempts of claiming.
+    pub fn with_cas_attempts(self, cas_attempts: u32) -> Self {
+        Self {
+            cas_attempts,
+            ..self
+        }
+    }
+
+    /// Use `timestamp_validation` on timestamps of [`TimeProvider`](TimeProvider).
+    pub fn with_timestamp_validation(self, timestamp_validation: TimestampValidation) -> Self {
+        Self {
+            timestamp_validation,
+            ..self
+        }
+    }
+
+    /// Start sequence of every tick at a random offset if `randomize_sequence_start`.
+    pub fn with_randomize_sequence_start(self, randomize_sequence_start: bool) -> Self {
+        Self {
+            randomize_sequence_start,
+            ..self
+        }
+    }
+
+    /// Fill sequence bits with random values if `random_sequence`, see [`SnowflakeConfiguration::random_sequence`](SnowflakeConfiguration::random_sequence).
+    pub fn with_random_sequence(self, random_sequence: bool) -> Self {
+        Self {
+            random_sequence,
+            ..self
+        }
+    }
+}
+
+impl Default for SnowflakeConfiguration {
+    /// Random identifier, see [`SnowflakeConfiguration::per_process`](SnowflakeConfiguration::per_process) for one less likely colliding on the same host.
+    fn default() -> Self {
+        Self::with_identifier(rand::thread_rng().next_u64() & layouts::DEFAULT.max_identifier())
+    }
+}
+
+/// See [`SnowflakeConfiguration::process_fingerprint`](SnowflakeConfiguration::process_fingerprint).
+fn fingerprint(hostname: &str, pid: u32, nanos: u128) -> u64 {
+    identifier::derive(&[
+        hostname.as_bytes(),
+        &pid.to_le_bytes(),
+        &nanos.to_le_bytes(),
+    ])
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SerdeConfiguration {
+    identifier: u64,
+    #[serde(default)]
+    epoch: u64,
+    #[serde(default)]
+    layout: SnowflakeLayout,
+    #[serde(default)]
+    identifier_split: Option<IdentifierSplit>,
+    #[serde(default = "default_time_unit")]
+    time_unit: Duration,
+    #[serde(default = "default_rollback_tolerance")]
+    rollback_tolerance: Duration,
+    #[serde(default)]
+    rollback_policy: RollbackPolicy,
+    #[serde(default)]
+    overflow_policy: OverflowPolicy,
+    #[serde(default)]
+    wait_strategy: WaitStrategy,
+    #[serde(default = "default_max_backoff")]
+    max_backoff: Duration,
+    #[serde(default = "default_backoff_jitter")]
+    backoff_jitter: bool,
+    #[serde(default = "default_cas_attempts")]
+    cas_attempts: u32,
+    #[serde(default)]
+    timestamp_validation: TimestampValidation,
+    #[serde(default)]
+    randomize_sequence_start: bool,
+    #[serde(default)]
+    random_sequence: bool,
+}
+
+#[cfg(feature = "serde")]
+fn default_time_unit() -> Duration {
+    Duration::from_millis(1)
+}
+
+#[cfg(feature = "serde")]
+fn default_rollback_tolerance() -> Duration {
+    Duration::from_millis(5)
+}
+
+#[cfg(feature = "serde")]
+fn default_max_backoff() -> Duration {
+    Duration::from_millis(250)
+}
+
+#[cfg(feature = "serde")]
+fn default_backoff_jitter() -> bool {
+    true
+}
+
+#[cfg(feature = "serde")]
+fn default_cas_attempts() -> u32 {
+    16
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerdeConfiguration> for SnowflakeConfiguration {
+    type Error = SnowflakeError;
+
+    fn try_from(value: SerdeConfiguration) -> Result<Self, Self::Error> {
+        let cfg = Self::with_identifier(value.identifier)
+            .with_epoch(value.epoch)
+            .with_layout(value.layout)
+            .with_time_unit(value.time_unit)
+            .with_rollback_tolerance(value.rollback_tolerance)
+            .with_rollback_policy(value.rollback_policy)
+            .with_overflow_policy(value.overflow_policy)
+            .with_wait_strategy(value.wait_strategy)
+            .with_max_backoff(value.max_backoff)
+            .with_backoff_jitter(value.backoff_jitter)
+            .with_cas_attempts(value.cas_attempts)
+            .with_timestamp_validation(value.timestamp_validation)
+            .with_randomize_sequence_start(value.randomize_sequence_start)
+            .with_random_sequence(value.random_sequence);
+        let cfg = Self {
+            identifier_split: value.identifier_split,
+            ..cfg
+        };
+        cfg.validate()?;
+        Ok(cfg)
+    }
+}
+
+/// Filling timestamp by mask
+fn fill_timestamp(sid: u64, timestamp: u64) -> u64 {
+    layouts::DEFAULT.fill_timestamp(sid, timestamp)
+}
+
+/// Filling identifier by mask
+fn fill_identifier(sid: u64, identifier: u64) -> u64 {
+    layouts::DEFAULT.fill_identifier(sid, identifier)
+}
+
+/// Filling sequence by mask
+fn fill_sequence(sid: u64, sequence: u64) -> u64 {
+    layouts::DEFAULT.fill_sequence(sid, sequence)
+}
+
+pub fn filling<T0, T1, T2>(dest: u64, timestamp: T0, identifier: T1, sequence: T2) -> u64
+where
+    T0: Into<u64>,
+    T1: Into<u64>,
+    T2: Into<u64>,
+{
+    let sid = fill_timestamp(dest, timestamp.into());
+    let sid = fill_identifier(sid, identifier.into());
+    fill_sequence(sid, sequence.into())
+}
+
+/// Generating [`Snowflake`](Snowflake)
+///
+/// Recommended keep this generator single-instance for one instance's SID generation.
+///
+/// # Thread safety
+///
+/// You can use [`::std::sync::Arc`](::std::sync::Arc) sharing ownership between thread.
+///
+/// # Sleeper
+///
+/// Waiting goes through [`Sleeper`](Sleeper), which is [`TimerSleeper`](TimerSleeper) by default,
+/// see [`SnowflakeGenerator::with_sleeper`](SnowflakeGenerator::with_sleeper) for using the timer of your runtime.
+///
+/// # Tracing
+///
+/// With feature `tracing`, warning events are emitted when constructed with an identifier exceeding the layout,
+/// when the clock is observed behind (with `delta_ms`), and when an assignment waited longer than
+/// [`SnowflakeGenerator::with_slow_wait_threshold`](SnowflakeGenerator::with_slow_wait_threshold) (with `waited_ms`).
+/// All of them carry `identifier`. Bulk assignments run in debug spans, and nothing is emitted when assigning right away.
+///
+/// # Cancellation
+///
+/// [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign), [`SnowflakeGenerator::try_assign`](SnowflakeGenerator::try_assign)
+/// and [`SnowflakeGenerator::reserve_block`](SnowflakeGenerator::reserve_block) are cancellation-safe: they only await while nothing is reserved,
+/// and return right after reserving, so dropping them (e.g. losing a `select!`) never burns a sequence number.
+///
+/// [`SnowflakeGenerator::assign_many`](SnowflakeGenerator::assign_many) is not, chunks reserved before dropping it are lost and leave a gap.
+/// IDs are still unique, only not dense.
+#[derive(Debug)]
+pub struct SnowflakeGenerator<S = TimerSleeper> {
+    // Hot ones, each on its own cache line so neighbors don't slow it down.
+    //
+    // Nothing else is published through states, and every update is a read-modify-write of one word,
+    // so relaxed ordering is enough for uniqueness (see `loom_tests`).
+    shards: Shards,
+    cfg: SnowflakeConfiguration,
+    stats: CachePadded<Counters>,
+    past_timestamp_sequence: AtomicU64,
+    /// Tick since when timestamp is near the end of layout.
+    overflow_near: u64,
+    /// See [`SnowflakeConfiguration::plausible_micros`](SnowflakeConfiguration::plausible_micros).
+    plausible: (u64, u64),
+    /// See [`SnowflakeConfiguration::max_jump_ticks`](SnowflakeConfiguration::max_jump_ticks).
+    max_jump: u64,
+    /// Latest tick read of the clock, only tracked with `max_jump`.
+    last_read: AtomicU64,
+    /// State shards start with, the clock jumping ahead of it is not checked.
+    initial_state: u64,
+    /// Key of sequence offsets, see [`SnowflakeConfiguration::randomize_sequence_start`](SnowflakeConfiguration::randomize_sequence_start).
+    sequence_key: u64,
+    /// See [`SnowflakeConfiguration::random_sequence`](SnowflakeConfiguration::random_sequence).
+    used_sequences: Option<UsedSequences>,
+    hooks: Hooks,
+    #[cfg(feature = "tracing")]
+    slow_wait: Duration,
+    #[cfg(feature = "persistence")]
+    state_file: Option<StateFile>,
+    /// Only held, see [`SnowflakeGenerator::with_guarded_identifier`](SnowflakeGenerator::with_guarded_identifier).
+    #[allow(dead_code)]
+    guard: Option<Arc<IdentifierGuard>>,
+    /// See [`SnowflakeGenerator::with_fair_admission`](SnowflakeGenerator::with_fair_admission).
+    #[cfg(feature = "async")]
+    fair: Option<fair::FairQueue>,
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    sleeper: S,
+}
+
+/// How long before timestamp runs out [`GeneratorEvent::TimestampOverflowNear`](GeneratorEvent::TimestampOverflowNear) is reported.
+const OVERFLOW_NEAR: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How often [`GeneratorEvent::TimestampOverflowNear`](GeneratorEvent::TimestampOverflowNear) is reported.
+const OVERFLOW_NEAR_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Waits longer than this are reported by default, see [`SnowflakeGenerator::with_slow_wait_threshold`](SnowflakeGenerator::with_slow_wait_threshold).
+#[cfg(feature = "tracing")]
+const DEFAULT_SLOW_WAIT: Duration = Duration::from_millis(50);
+
+/// Size of cache line, 128 bytes on some CPUs but 64 bytes is common enough.
+#[cfg_attr(loom, allow(dead_code))]
+const CACHE_LINE: usize = 64;
+
+/// Taking a whole cache line, so no other data shares it.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// State words of shards, owned by this generator, or mapped from a file shared with other processes.
+#[derive(Debug, Clone)]
+enum Shards {
+    Owned(Arc<[CachePadded<AtomicU64>]>),
+    #[cfg(all(feature = "shm", unix, not(loom)))]
+    Mapped(Arc<shm::Mapping>),
+}
+
+impl Deref for Shards {
+    type Target = [CachePadded<AtomicU64>];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Shards::Owned(it) => it,
+            #[cfg(all(feature = "shm", unix, not(loom)))]
+            Shards::Mapped(it) => it.shards(),
+        }
+    }
+}
+
+/// Copying configuration, sharding, sleeper and callbacks of [`SnowflakeGenerator::on_event`](SnowflakeGenerator::on_event),
+/// but starting from scratch: sequence numbers, statistics, floor and state file are not carried over.
+/// State shared in memory by `SharedSnowflakeGenerator` (feature `shm`) is the exception, clones keep generating from it.
+/// So is [`IdentifierGuard`](IdentifierGuard) taken by [`SnowflakeGenerator::with_guarded_identifier`](SnowflakeGenerator::with_guarded_identifier), held until all of them are dropped.
+///
+/// **The clone generates the same [`Snowflake`](Snowflake) as the original**, as they share the identifier but not the sequence.
+/// Share one generator with [`Arc`](Arc) instead, or clone with another identifier by [`SnowflakeGenerator::clone_with_identifier`](SnowflakeGenerator::clone_with_identifier).
+impl<S> Clone for SnowflakeGenerator<S>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        self.clone_with_cfg(self.cfg.clone())
+    }
+}
+
+#[cfg(not(loom))]
+const _: () = assert!(std::mem::align_of::<CachePadded<AtomicU64>>() == CACHE_LINE);
+#[cfg(not(loom))]
+const _: () = assert!(std::mem::size_of::<CachePadded<AtomicU64>>() == CACHE_LINE);
+
+impl SnowflakeGenerator {
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) (or [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator)) with builder.
+    pub fn builder() -> SnowflakeGeneratorBuilder {
+        SnowflakeGeneratorBuilder::new()
+    }
+
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) with `cfg`, without validation.
+    pub fn with_cfg(cfg: SnowflakeConfiguration) -> Self {
+        Self::sharded(1, cfg)
+    }
+
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) with sequence numbers of each tick split into `shards` slices, without validation.
+    ///
+    /// Every thread claims from its own slice and steals from the others once it's used up,
+    /// so threads no longer contend on the same atomic. [`Snowflake`](Snowflake) are still unique in the same layout,
+    /// but only increasing within one slice, and the clock going backwards is only detected per slice.
+    ///
+    /// `shards` is clamped to `1..=max_sequence + 1`, and blocks of [`SnowflakeGenerator::reserve_block`](SnowflakeGenerator::reserve_block)
+    /// can't exceed one slice.
+    ///
+    /// ```
+    /// # use snowflake_ng::{SnowflakeConfiguration, SnowflakeGenerator};
+    /// // 256 sequence numbers per shard with the default layout.
+    /// let generator = SnowflakeGenerator::sharded(16, SnowflakeConfiguration::default());
+    /// ```
+    pub fn sharded(shards: u8, cfg: SnowflakeConfiguration) -> Self {
+        #[cfg(feature = "tracing")]
+        if cfg.identifier > cfg.layout.max_identifier() {
+            tracing::warn!(
+                identifier = cfg.identifier,
+                effective = cfg.identifier(),
+                "identifier truncated to the layout"
+            );
+        }
+
+        let shards = (shards as u64).clamp(1, cfg.layout.max_sequence().saturating_add(1));
+        let overflow_near = cfg
+            .layout
+            .max_timestamp()
+            .saturating_sub(OVERFLOW_NEAR.as_micros() as u64 / cfg.unit_micros());
+        let (plausible, max_jump) = (cfg.plausible_micros(), cfg.max_jump_ticks());
+        let sequence_key = if cfg.offset_sequence() {
+            rand::thread_rng().next_u64()
+        } else {
+            0
+        };
+        let used_sequences = cfg
+            .random_sequence
+            .then(|| UsedSequences::new(cfg.layout.sequence_bits()));
+        Self {
+            shards: Shards::Owned(
+                (0..shards)
+                    .map(|_| CachePadded(AtomicU64::new(0)))
+                    .collect(),
+            ),
+            cfg,
+            stats: CachePadded::default(),
+            past_timestamp_sequence: AtomicU64::new(0),
+            overflow_near,
+            plausible,
+            max_jump,
+            last_read: AtomicU64::new(0),
+            initial_state: 0,
+            sequence_key,
+            used_sequences,
+            hooks: Hooks::default(),
+            #[cfg(feature = "tracing")]
+            slow_wait: DEFAULT_SLOW_WAIT,
+            #[cfg(feature = "persistence")]
+            state_file: None,
+            guard: None,
+            #[cfg(feature = "async")]
+            fair: None,
+            sleeper: TimerSleeper,
+        }
+    }
+
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) with `cfg`, see [`SnowflakeConfiguration::validate`](SnowflakeConfiguration::validate).
+    pub fn try_with_cfg(cfg: SnowflakeConfiguration) -> Result<Self, SnowflakeError> {
+        cfg.validate()?;
+        Ok(Self::with_cfg(cfg))
+    }
+
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) with `cfg`, never generating at or below `last_seen`.
+    ///
+    /// Handy after restarting, with the max [`Snowflake`](Snowflake) of this node in database.
+    /// Until the clock passes it, the generator behaves as if the clock is behind, see [`SnowflakeConfiguration::rollback_policy`](SnowflakeConfiguration::rollback_policy).
+    ///
+    /// `last_seen` is decoded with layout of `cfg`, returning [`SnowflakeError::IdentifierMismatch`](SnowflakeError::IdentifierMismatch)
+    /// if it's generated by another identifier.
+    ///
+    /// ```
+    /// # use snowflake_ng::{provider::StdProvider, SnowflakeConfiguration, SnowflakeGenerator};
+    /// let cfg = SnowflakeConfiguration::with_identifier(1);
+    /// let last_seen = SnowflakeGenerator::with_cfg(cfg.clone()).assign_sync(&StdProvider);
+    ///
+    /// let generator = SnowflakeGenerator::with_floor(cfg, last_seen.clone()).unwrap();
+    /// assert!(generator.assign_sync(&StdProvider) > last_seen);
+    /// ```
+    pub fn with_floor(
+        cfg: SnowflakeConfiguration,
+        last_seen: Snowflake,
+    ) -> Result<Self, SnowflakeError> {
+        let layout = cfg.layout;
+        let identifier = layout.identifier_of(&last_seen);
+        if identifier != cfg.identifier() {
+            return Err(SnowflakeError::IdentifierMismatch {
+                identifier,
+                expected: cfg.identifier(),
+            });
+        }
+
+        let sequence_bits = layout.sequence_bits();
+        let mut generator = Self::with_cfg(cfg);
+        generator.resume(
+            (layout.timestamp_of(&last_seen) << (sequence_bits + 1))
+                | ((layout.rollback_of(&last_seen) as u64) << sequence_bits)
+                | layout.sequence_of(&last_seen),
+        );
+        Ok(generator)
+    }
+}
+
+impl Default for SnowflakeGenerator {
+    fn default() -> Self {
+        Self::with_cfg(SnowflakeConfiguration::default())
+    }
+}
+
+impl<S> SnowflakeGenerator<S> {
+    /// Use `sleeper` for waiting, rather than [`TimerSleeper`](TimerSleeper).
+    ///
+    /// Only async methods wait with it, synchronous ones always block current thread.
+    ///
+    /// ```
+    /// # use std::{future::Future, time::Duration};
+    /// # use snowflake_ng::{SnowflakeGenerator, Sleeper};
+    /// struct TokioSleeper;
+    ///
+    /// impl Sleeper for TokioSleeper {
+    ///     fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+    ///         tokio::time::sleep(duration)
+    ///     }
+    /// }
+    ///
+    /// let generator = SnowflakeGenerator::default().with_sleeper(TokioSleeper);
+    /// ```
+    pub fn with_sleeper<S2>(self, sleeper: S2) -> SnowflakeGenerator<S2>
+    where
+        S2: Sleeper,
+    {
+        self.replace_sleeper(sleeper)
+    }
+
+    fn replace_sleeper<S2>(self, sleeper: S2) -> SnowflakeGenerator<S2> {
+        SnowflakeGenerator {
+            shards: self.shards,
+            cfg: self.cfg,
+            stats: self.stats,
+            past_timestamp_sequence: self.past_timestamp_sequence,
+            overflow_near: self.overflow_near,
+            plausible: self.plausible,
+            max_jump: self.max_jump,
+            last_read: self.last_read,
+            initial_state: self.initial_state,
+            sequence_key: self.sequence_key,
+            used_sequences: self.used_sequences,
+            hooks: self.hooks,
+            #[cfg(feature = "tracing")]
+            slow_wait: self.slow_wait,
+            #[cfg(feature = "persistence")]
+            state_file: self.state_file,
+            guard: self.guard,
+            #[cfg(feature = "async")]
+            fair: self.fair,
+            sleeper,
+        }
+    }
+
+    /// Same as [`Clone::clone`](Clone::clone), but generating with `identifier`, for the same settings on another node.
+    pub fn clone_with_identifier(&self, identifier: u64) -> Self
+    where
+        S: Clone,
+    {
+        self.clone_with_cfg(SnowflakeConfiguration {
+            identifier,
+            ..self.cfg.clone()
+        })
+    }
+
+    /// Fresh generator of `cfg`, with everything else copied.
+    fn clone_with_cfg(&self, cfg: SnowflakeConfiguration) -> Self
+    where
+        S: Clone,
+    {
+        // Leaving mapped state with the same identifier collides with every process mapping it.
+        #[cfg(all(feature = "shm", unix, not(loom)))]
+        let mapped = match &self.shards {
+            Shards::Mapped(it) if cfg.identifier == self.cfg.identifier => Some(it.clone()),
+            _ => None,
+        };
+        // Released once the last one generating with the identifier is gone.
+        let guard = self
+            .guard
+            .clone()
+            .filter(|_| cfg.identifier == self.cfg.identifier);
+        let generator = SnowflakeGenerator {
+            hooks: self.hooks.copied(),
+            guard,
+            #[cfg(feature = "tracing")]
+            slow_wait: self.slow_wait,
+            #[cfg(feature = "async")]
+            fair: self
+                .fair
+                .as_ref()
+                .map(|it| fair::FairQueue::new(it.max_attempts)),
+            ..SnowflakeGenerator::sharded(self.shards.len() as u8, cfg)
+        };
+        #[cfg(all(feature = "shm", unix, not(loom)))]
+        let generator = match mapped {
+            Some(it) => SnowflakeGenerator {
+                shards: Shards::Mapped(it),
+                ..generator
+            },
+            None => generator,
+        };
+        generator.replace_sleeper(self.sleeper.clone())
+    }
+
+    /// Continue from state word `state` in every shard, rather than from scratch.
+    ///
+    /// Other shards may be anywhere in the tick of `state`, so the whole tick is taken as used if sharded.
+    /// So is it with randomized sequence start, since offsets of the tick are different.
+    fn resume(&mut self, state: u64) {
+        self.initial_state = if self.shards.len() == 1
+            && !(self.cfg.randomize_sequence_start || self.cfg.random_sequence)
+        {
+            state
+        } else {
+            state | self.cfg.layout.max_sequence()
+        };
+        for it in self.shards.iter() {
+            it.store(self.initial_state, Ordering::Relaxed);
+        }
+    }
+
+    /// Report assignments waiting longer than `threshold` in total, 50 milliseconds by default.
+    #[cfg(feature = "tracing")]
+    pub fn with_slow_wait_threshold(self, threshold: Duration) -> Self {
+        Self {
+            slow_wait: threshold,
+            ..self
+        }
+    }
+
+    /// Queue async assignments in FIFO order once they lose more than `max_attempts` times, racing or waiting,
+    /// so under heavy contention nobody keeps losing to newcomers.
+    ///
+    /// Only the head of the queue tries then, the next one goes once it's assigned, and newcomers queue up
+    /// behind while anyone is queued. It gives up some throughput for fairness, and synchronous assignments are left as is.
+    ///
+    /// ```
+    /// # use snowflake_ng::SnowflakeGenerator;
+    /// let generator = SnowflakeGenerator::default().with_fair_admission(16);
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn with_fair_admission(self, max_attempts: u32) -> Self {
+        Self {
+            fair: Some(fair::FairQueue::new(max_attempts)),
+            ..self
+        }
+    }
+
+    /// Configuration of this generator.
+    pub fn config(&self) -> &SnowflakeConfiguration {
+        &self.cfg
+    }
+
+    /// Effective identifier stamped into generated [`Snowflake`](Snowflake).
+    pub fn identifier(&self) -> u64 {
+        self.cfg.identifier()
+    }
+
+    /// How many times the clock was observed behind the last timestamp, including every wait for it catching up.
+    pub fn clock_behind_count(&self) -> u64 {
+        self.stats.clock_behind_count()
+    }
+
+    /// Snapshot of statistics since constructed or [`SnowflakeGenerator::reset_stats`](SnowflakeGenerator::reset_stats).
+    pub fn stats(&self) -> GeneratorStats {
+        self.stats.snapshot()
+    }
+
+    /// How many [`Snowflake`](Snowflake) are left in the current tick of `provider`, without assigning any.
+    ///
+    /// It's all sequence numbers of the tick once the clock moved past the last assignment, and 0 while the clock is behind it.
+    /// Shards are summed up, and it saturates at `u16::MAX` for layouts of more than 16 sequence bits.
+    ///
+    /// It's advisory only, others may take them (or the tick may end) before acting on it.
+    ///
+    /// ```
+    /// # use snowflake_ng::{provider::StdProvider, SnowflakeGenerator};
+    /// let generator = SnowflakeGenerator::default();
+    /// if !generator.is_saturated(&StdProvider) {
+    ///     let snowflake = generator.assign_sync(&StdProvider);
+    /// }
+    /// ```
+    pub fn remaining_capacity<T>(&self, provider: &T) -> u16
+    where
+        T: TimeProvider + ?Sized,
+    {
+        let sequence_bits = self.cfg.layout.sequence_bits();
+        let max_sequence = self.cfg.layout.max_sequence();
+        let timestamp = self.cfg.ticks_of_micros(self.cfg.now_micros(provider));
+
+        let remaining = (0..self.shards.len())
+            .map(|index| {
+                let (first, last) = self.sequences(index);
+                let current = self.shards[index].load(Ordering::Relaxed);
+                match (current >> (sequence_bits + 1)).cmp(&timestamp) {
+                    std::cmp::Ordering::Less => last - first + 1,
+                    std::cmp::Ordering::Equal => last.saturating_sub(current & max_sequence),
+                    std::cmp::Ordering::Greater => 0,
+                }
+            })
+            .fold(0u64, u64::saturating_add);
+        remaining.min(u16::MAX as u64) as u16
+    }
+
+    /// Whether nothing is left in the current tick of `provider`, see [`SnowflakeGenerator::remaining_capacity`](SnowflakeGenerator::remaining_capacity).
+    pub fn is_saturated<T>(&self, provider: &T) -> bool
+    where
+        T: TimeProvider + ?Sized,
+    {
+        self.remaining_capacity(provider) == 0
+    }
+
+    /// The latest [`Snowflake`](Snowflake) assigned, rebuilt from the state, `None` before the first assignment.
+    ///
+    /// It's of the latest reservation done, so with concurrent callers it may be one not handed out yet,
+    /// or a later one than what this caller just got. Sharded ones return the largest over shards.
+    pub fn last_assigned(&self) -> Option<Snowflake> {
+        let layout = &self.cfg.layout;
+        let sequence_bits = layout.sequence_bits();
+        self.shards
+            .iter()
+            .map(|it| it.load(Ordering::Acquire))
+            .filter(|it| *it != 0)
+            .map(|current| {
+                let timestamp = current >> (sequence_bits + 1);
+                Snowflake(layout.compose_raw(
+                    timestamp,
+                    self.cfg.identifier(),
+                    (current & layout.max_sequence()) + self.sequence_start(timestamp),
+                    (current >> sequence_bits) & 1 == 1,
+                ) as i64)
+            })
+            .max()
+    }
+
+    /// When timestamp runs out of the layout with configured `epoch` and `time_unit`,
+    /// assignment after that returns [`SnowflakeError::TimestampOverflow`](SnowflakeError::TimestampOverflow) (or panics if infallible).
+    ///
+    /// It's around 2039 for the default configuration.
+    pub fn exhaustion_date(&self) -> SystemTime {
+        UNIX_EPOCH
+            + Duration::from_micros(
+                self.cfg.epoch * 1000
+                    + (self.cfg.layout.max_timestamp() + 1) * self.cfg.unit_micros(),
+            )
+    }
+
+    /// The last millisecond since UNIX epoch timestamp fits in.
+    fn exhaustion_timestamp(&self) -> u64 {
+        self.cfg
+            .unix_timestamp_of_ticks(self.cfg.layout.max_timestamp())
+    }
+
+    /// Call `callback` on every [`GeneratorEvent`](GeneratorEvent), after all the previously registered ones.
+    ///
+    /// Callbacks are called by the assigning thread, but never inside the claiming loop, so a slow one only delays its own assignment.
+    ///
+    /// ```
+    /// # use snowflake_ng::{GeneratorEvent, SnowflakeGenerator};
+    /// let generator = SnowflakeGenerator::default();
+    ///
+    /// generator.on_event(|event| {
+    ///     if let GeneratorEvent::ClockBackwards { delta } = event {
+    ///         eprintln!("clock moved backwards by {delta:?}");
+    ///     }
+    /// });
+    /// ```
+    pub fn on_event(&self, callback: impl Fn(GeneratorEvent) + Send + Sync + 'static) {
+        self.hooks.register(Arc::new(callback));
+    }
+
+    /// Reset all statistics to 0, including [`SnowflakeGenerator::clock_behind_count`](SnowflakeGenerator::clock_behind_count).
+    pub fn reset_stats(&self) {
+        self.stats.reset()
+    }
+
+    /// Assign a [`Snowflake`](Snowflake) with [`TimeProvider`](TimeProvider)
+    ///
+    /// # Panics
+    ///
+    /// Panics where the fallible ones like [`SnowflakeGenerator::try_assign`](SnowflakeGenerator::try_assign) return an error anyway:
+    ///
+    /// - [`SnowflakeError::ImplausibleTimestamp`](SnowflakeError::ImplausibleTimestamp), the clock rejected by [`timestamp_validation`](SnowflakeConfiguration::timestamp_validation).
+    /// - [`SnowflakeError::TimestampOverflow`](SnowflakeError::TimestampOverflow), the clock past what the layout holds.
+    /// - [`SnowflakeError::StateFile`](SnowflakeError::StateFile), the state file failing to be saved.
+    #[cfg(feature = "async")]
+    pub async fn assign<T>(&self, provider: &T) -> Snowflake
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        Snowflake(
+            self.next_infallible(provider, Claim::AtMost(1))
+                .await
+                .bits(&self.cfg, 0) as i64,
+        )
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way.
+    ///
+    /// # Blocking
+    ///
+    /// It blocks current thread when sequence exhausted or the clock is behind,
+    /// so don't call it on a thread of async runtime, where it stalls every other task on that thread.
+    /// Use [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign) there instead,
+    /// or [`SnowflakeGenerator::try_assign_sync`](SnowflakeGenerator::try_assign_sync) to catch it in debug builds.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn assign_sync<T>(&self, provider: &T) -> Snowflake
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        Snowflake(
+            self.next_infallible_sync(provider, Claim::AtMost(1))
+                .bits(&self.cfg, 0) as i64,
+        )
+    }
+
+    /// Assign a [`SnowflakeU64`](SnowflakeU64) with [`TimeProvider`](TimeProvider).
+    ///
+    /// Unlike [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign), 64 bits layout like [`layouts::UNSIGNED`](layouts::UNSIGNED) is fine here.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "async")]
+    pub async fn assign_u64<T>(&self, provider: &T) -> SnowflakeU64
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        SnowflakeU64(
+            self.next_infallible(provider, Claim::AtMost(1))
+                .await
+                .bits(&self.cfg, 0),
+        )
+    }
+
+    /// Assign a new [`SnowflakeU64`](SnowflakeU64) but in synchronous way.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn assign_u64_sync<T>(&self, provider: &T) -> SnowflakeU64
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        SnowflakeU64(
+            self.next_infallible_sync(provider, Claim::AtMost(1))
+                .bits(&self.cfg, 0),
+        )
+    }
+
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign), but returning [`SnowflakeError::ClockMovedBackwards`](SnowflakeError::ClockMovedBackwards)
+    /// when the clock goes backwards beyond [`rollback_tolerance`](SnowflakeConfiguration::rollback_tolerance)
+    /// with [`RollbackPolicy::Error`](RollbackPolicy::Error), rather than silently waiting for the clock catching up.
+    ///
+    /// Generator state is untouched on error, so it can be retried once the clock recovered.
+    /// Layout with [rollback flag](SnowflakeLayout#rollback-flag) never fails.
+    ///
+    /// With [`OverflowPolicy::Error`](OverflowPolicy::Error), exhausted sequence returns [`SnowflakeError::SequenceOverflow`](SnowflakeError::SequenceOverflow) as well.
+    #[cfg(feature = "async")]
+    pub async fn try_assign<T>(&self, provider: &T) -> Result<Snowflake, SnowflakeError>
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        self.next(provider, true, Claim::AtMost(1))
+            .await
+            .map(|it| Snowflake(it.bits(&self.cfg, 0) as i64))
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way, see [`SnowflakeGenerator::try_assign`](SnowflakeGenerator::try_assign).
+    ///
+    /// With feature `tokio` in debug builds, it returns [`SnowflakeError::BlockingInAsyncRuntime`](SnowflakeError::BlockingInAsyncRuntime)
+    /// rather than blocking a thread of Tokio runtime, see [`SnowflakeGenerator::assign_sync`](SnowflakeGenerator::assign_sync).
+    /// Threads of `spawn_blocking` are reported as well, since they can't be told apart.
+    #[cfg(feature = "sync")]
+    pub fn try_assign_sync<T>(&self, provider: &T) -> Result<Snowflake, SnowflakeError>
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        self.next_sync(provider, true, Claim::AtMost(1))
+            .map(|it| Snowflake(it.bits(&self.cfg, 0) as i64))
+    }
+
+    /// Assign `n` [`Snowflake`](Snowflake) in strictly increasing order,
+    /// unless [sequence start is randomized](SnowflakeConfiguration::randomize_sequence_start).
+    ///
+    /// Sequence numbers are reserved in chunks of up to 512 per tick, so it's much cheaper than calling
+    /// [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign) `n` times.
+    ///
+    /// Dropping it half-way loses chunks reserved so far, see [cancellation](SnowflakeGenerator#cancellation).
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "async")]
+    pub async fn assign_many<T>(&self, provider: &T, n: usize) -> Vec<Snowflake>
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        let assign = async {
+            let mut snowflakes = Vec::with_capacity(n);
+
+            while snowflakes.len() < n {
+                let want = (n - snowflakes.len()).min(ASSIGN_MANY_CHUNK) as u64;
+                let reservation = self.next_infallible(provider, Claim::AtMost(want)).await;
+                snowflakes.extend(
+                    (0..reservation.count)
+                        .map(|it| Snowflake(reservation.bits(&self.cfg, it) as i64)),
+                );
+            }
+
+            snowflakes
+        };
+        #[cfg(feature = "tracing")]
+        let assign = tracing::Instrument::instrument(
+            assign,
+            tracing::debug_span!("assign_many", identifier = self.identifier(), n),
+        );
+
+        assign.await
+    }
+
+    /// Assign `n` [`Snowflake`](Snowflake) but in synchronous way.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn assign_many_sync<T>(&self, provider: &T, n: usize) -> Vec<Snowflake>
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("assign_many", identifier = self.identifier(), n).entered();
+        let mut snowflakes = Vec::with_capacity(n);
+
+        while snowflakes.len() < n {
+            let want = (n - snowflakes.len()).min(ASSIGN_MANY_CHUNK) as u64;
+            let reservation = self.next_infallible_sync(provider, Claim::AtMost(want));
+            snowflakes.extend(
+                (0..reservation.count).map(|it| Snowflake(reservation.bits(&self.cfg, it) as i64)),
+            );
+        }
+
+        snowflakes
+    }
+
+    /// Reserve `count` [`Snowflake`](Snowflake) with the same timestamp and consecutive sequence numbers.
+    ///
+    /// If current tick can't hold the whole block, it waits for the next tick rather than splitting the block.
+    /// Returning [`SnowflakeError::InvalidBlockSize`](SnowflakeError::InvalidBlockSize) if `count` is 0 or exceeds sequence numbers of one tick.
+    #[cfg(feature = "async")]
+    pub async fn reserve_block<T>(
+        &self,
+        provider: &T,
+        count: u16,
+    ) -> Result<SnowflakeBlock, SnowflakeError>
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        self.check_block_size(count)?;
+
+        let reserve = self.next_infallible(provider, Claim::Exactly(count as u64));
+        #[cfg(feature = "tracing")]
+        let reserve = tracing::Instrument::instrument(
+            reserve,
+            tracing::debug_span!("reserve_block", identifier = self.identifier(), count),
+        );
+        let reservation = reserve.await;
+        Ok(SnowflakeBlock::new(
+            reservation.bits(&self.cfg, 0) as i64,
+            count,
+        ))
+    }
+
+    /// Reserve a [`SnowflakeBlock`](SnowflakeBlock) but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn reserve_block_sync<T>(
+        &self,
+        provider: &T,
+        count: u16,
+    ) -> Result<SnowflakeBlock, SnowflakeError>
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        self.check_block_size(count)?;
+
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("reserve_block", identifier = self.identifier(), count).entered();
+        let reservation = self.next_infallible_sync(provider, Claim::Exactly(count as u64));
+        Ok(SnowflakeBlock::new(
+            reservation.bits(&self.cfg, 0) as i64,
+            count,
+        ))
+    }
+
+    fn check_block_size(&self, count: u16) -> Result<(), SnowflakeError> {
+        // The smallest slice, see `SnowflakeGenerator::sequences`.
+        let mut capacity =
+            self.cfg.layout.max_sequence().saturating_add(1) / self.shards.len() as u64;
+        if self.cfg.offset_sequence() {
+            // Half of it always fits on one side of wrapping, see `SnowflakeGenerator::fit`.
+            capacity = (capacity / 2).max(1);
+        }
+        if count == 0 || count as u64 > capacity {
+            return Err(SnowflakeError::InvalidBlockSize { count, capacity });
+        }
+
+        Ok(())
+    }
+
+    /// Endless [`Stream`](Stream) of [`Snowflake`](Snowflake) in strictly increasing order,
+    /// unless [sequence start is randomized](SnowflakeConfiguration::randomize_sequence_start).
+    ///
+    /// Sequence numbers are reserved in chunks like [`SnowflakeGenerator::assign_many`](SnowflakeGenerator::assign_many),
+    /// so timestamp of buffered [`Snowflake`](Snowflake) may lag behind the clock for slow consumers.
+    /// Dropping it anytime is fine, buffered ones are just skipped.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "async")]
+    pub fn stream<'a, T>(&'a self, provider: &'a T) -> impl Stream<Item = Snowflake> + Send + 'a
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        stream::unfold(Vec::new().into_iter(), move |mut buffer| async move {
+            if buffer.len() == 0 {
+                let reservation = self
+                    .next_infallible(provider, Claim::AtMost(ASSIGN_MANY_CHUNK as u64))
+                    .await;
+                buffer = (0..reservation.count)
+                    .map(|it| Snowflake(reservation.bits(&self.cfg, it) as i64))
+                    .collect::<Vec<_>>()
+                    .into_iter();
+            }
+
+            buffer.next().map(|it| (it, buffer))
+        })
+    }
+
+    /// Endless blocking [`Iterator`](Iterator) of [`Snowflake`](Snowflake), which sleeps current thread when needed.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn iter_sync<'a, T>(&'a self, provider: &'a T) -> SnowflakeIter<'a, T, S>
+    where
+        T: TimeProvider,
+    {
+        SnowflakeIter::new(self, provider)
+    }
+
+    /// Assign a [`Snowflake`](Snowflake) as if the clock read `timestamp` (milliseconds since UNIX epoch), bypassing [`TimeProvider`](TimeProvider).
+    ///
+    /// It shares sequence numbers with [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign), but never waits:
+    /// returning [`SnowflakeError::SequenceExhausted`](SnowflakeError::SequenceExhausted) once sequence of `timestamp` used up,
+    /// and [`SnowflakeError::TimestampInPast`](SnowflakeError::TimestampInPast) if `timestamp` is earlier than the high-water mark,
+    /// see [`SnowflakeGenerator::assign_at_with`](SnowflakeGenerator::assign_at_with) for minting those.
+    pub fn assign_at(&self, timestamp: u64) -> Result<Snowflake, SnowflakeError> {
+        self.assign_at_with(timestamp, false)
+    }
+
+    /// Same as [`SnowflakeGenerator::assign_at`](SnowflakeGenerator::assign_at), but `allow_past` lets timestamps earlier than the high-water mark through.
+    ///
+    /// Those are minted from a separate cursor which never moves backwards either, so they never collide with each other
+    /// or with later [`Snowflake`](Snowflake), but **may** collide with ones generated earlier at the same time.
+    pub fn assign_at_with(
+        &self,
+        timestamp: u64,
+        allow_past: bool,
+    ) -> Result<Snowflake, SnowflakeError> {
+        let ticks = self.cfg.ticks_at(timestamp);
+        if ticks > self.cfg.layout.max_timestamp() {
+            return Err(SnowflakeError::TimestampOverflow {
+                timestamp,
+                max: self.exhaustion_timestamp(),
+            });
+        }
+
+        let home = self.home_shard();
+        let mut reservation = self.claim_at(&self.shards[home], self.sequences(home), ticks);
+        for offset in 1..self.shards.len() {
+            if !matches!(reservation, Err(SnowflakeError::SequenceExhausted { .. })) {
+                break;
+            }
+
+            let index = (home + offset) % self.shards.len();
+            reservation = self.claim_at(&self.shards[index], self.sequences(index), ticks);
+        }
+        let reservation = match (reservation, &self.used_sequences) {
+            (Ok(it), Some(used)) => self.randomize_at(used, it),
+            (other, _) => other,
+        };
+
+        let reservation = match reservation {
+            // Backfilling shares the past cursor and never randomizes sequence.
+            Err(SnowflakeError::TimestampInPast { .. }) if allow_past => self
+                .claim_at(
+                    &self.past_timestamp_sequence,
+                    (0, self.cfg.layout.max_sequence()),
+                    ticks,
+                )
+                .map(|it| Reservation { start: 0, ..it }),
+            other => other,
+        };
+
+        let reservation = reservation?;
+        self.persist(reservation.timestamp)?;
+        Ok(Snowflake(reservation.bits(&self.cfg, 0) as i64))
+    }
+
+    /// Assign `count` [`Snowflake`](Snowflake) spread evenly across `range` in strictly increasing order, for migrating historical data.
+    ///
+    /// Timestamps never reach the high-water mark of generator, so they can't collide with later [`Snowflake`](Snowflake),
+    /// meaning at least one has to be generated before backfilling. Like [`SnowflakeGenerator::assign_at_with`](SnowflakeGenerator::assign_at_with),
+    /// they are minted from a separate cursor and each backfill starts after the last one,
+    /// so it's best done before the generator goes live to avoid colliding with earlier ones.
+    /// For [sharded](SnowflakeGenerator::sharded) generator, every shard has to generate one first.
+    ///
+    /// Returning [`SnowflakeError::BackfillRangeTooSmall`](SnowflakeError::BackfillRangeTooSmall) if `range` can't hold `count`.
+    pub fn backfill(
+        &self,
+        range: Range<SystemTime>,
+        count: usize,
+    ) -> Result<Vec<Snowflake>, SnowflakeError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let unix_millis = |it: SystemTime| {
+            it.duration_since(UNIX_EPOCH)
+                .map_or(0, |it| it.as_millis() as u64)
+        };
+        let sequence_bits = self.cfg.layout.sequence_bits();
+        let per_tick = self.cfg.layout.max_sequence() + 1;
+
+        loop {
+            // Lowest of shards, others may still assign below their own.
+            let live = self
+                .shards
+                .iter()
+                .map(|it| it.load(Ordering::Relaxed) >> (sequence_bits + 1))
+                .min()
+                .unwrap_or_default();
+            let current = self.past_timestamp_sequence.load(Ordering::Relaxed);
+            let start = self
+                .cfg
+                .ticks_at(unix_millis(range.start))
+                .max((current >> (sequence_bits + 1)) + 1);
+            let end = self.cfg.ticks_at(unix_millis(range.end)).min(live);
+
+            let span = end.saturating_sub(start);
+            let capacity = span.saturating_mul(per_tick);
+            if count as u64 > capacity {
+                return Err(SnowflakeError::BackfillRangeTooSmall { count, capacity });
+            }
+
+            // At most `ceil(count / span)` in one tick, which is no more than `per_tick`.
+            let mut snowflakes = Vec::with_capacity(count);
+            let (mut timestamp, mut sequence, mut max_sequence) = (start, 0, 0);
+            for it in 0..count as u64 {
+                let next = start + (it as u128 * span as u128 / count as u128) as u64;
+                sequence = if next == timestamp && it > 0 {
+                    sequence + 1
+                } else {
+                    0
+                };
+                timestamp = next;
+                max_sequence = max_sequence.max(sequence);
+                snowflakes.push(Snowflake(self.cfg.layout.compose_raw(
+                    timestamp,
+                    self.cfg.identifier(),
+                    sequence,
+                    false,
+                ) as i64));
+            }
+
+            let new_value = (timestamp << (sequence_bits + 1)) | sequence;
+            if self
+                .past_timestamp_sequence
+                .compare_exchange(current, new_value, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.stats.issued(count as u64, max_sequence);
+                return Ok(snowflakes);
+            }
+        }
+    }
+
+    /// Claim one sequence number of `timestamp` (in ticks) from `state` owning `(first, last)` sequence numbers, never waits.
+    fn claim_at(
+        &self,
+        state: &AtomicU64,
+        (first, last): (u64, u64),
+        timestamp: u64,
+    ) -> Result<Reservation, SnowflakeError> {
+        let sequence_bits = self.cfg.layout.sequence_bits();
+        let max_sequence = self.cfg.layout.max_sequence();
+
+        loop {
+            let current = state.load(Ordering::Relaxed);
+            let current_timestamp = current >> (sequence_bits + 1);
+            let current_rollback = (current >> sequence_bits) & 1 == 1;
+            let current_sequence = current & max_sequence;
+
+            let (sequence, rollback) = match current_timestamp.cmp(&timestamp) {
+                std::cmp::Ordering::Less => (first, false),
+                std::cmp::Ordering::Equal if current_sequence < last => {
+                    (current_sequence + 1, current_rollback)
+                }
+                std::cmp::Ordering::Equal => {
+                    return Err(SnowflakeError::SequenceExhausted {
+                        timestamp: self.cfg.unix_timestamp_of_ticks(timestamp),
+                    })
+                }
+                std::cmp::Ordering::Greater => {
+                    return Err(SnowflakeError::TimestampInPast {
+                        timestamp: self.cfg.unix_timestamp_of_ticks(timestamp),
+                        high_water_mark: self.cfg.unix_timestamp_of_ticks(current_timestamp),
+                    })
+                }
+            };
+
+            let new_value = (timestamp << (sequence_bits + 1))
+                | ((rollback as u64) << sequence_bits)
+                | sequence;
+            if state
+                .compare_exchange(current, new_value, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.stats.issued(1, sequence);
+                return Ok(Reservation {
+                    timestamp,
+                    sequence,
+                    count: 1,
+                    rollback,
+                    start: self.sequence_start(timestamp),
+                });
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn next_infallible<T>(&self, provider: &T, claim: Claim) -> Reservation
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        self.next(provider, false, claim)
+            .await
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn next_infallible_sync<T>(&self, provider: &T, claim: Claim) -> Reservation
+    where
+        T: TimeProvider + ?Sized,
+    {
+        self.next_sync(provider, false, claim)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign), but never waits.
+    ///
+    /// Fails fast with [`TryAssignError`](TryAssignError) when sequence exhausted or the clock is behind,
+    /// and gives up after a bounded number of lost races under contention.
+    pub fn try_assign_now<T>(&self, provider: &T) -> Result<Snowflake, TryAssignError>
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        for _ in 0..TRY_ASSIGN_ATTEMPTS {
+            match self.step(provider, false, Claim::AtMost(1)) {
+                Ok(Step::Assigned(it)) => return Ok(Snowflake(it.bits(&self.cfg, 0) as i64)),
+                Ok(Step::Contended) => continue,
+                Ok(Step::Exhausted { next_tick }) => {
+                    return Err(TryAssignError::SequenceExhausted {
+                        retry_after: next_tick,
+                    })
+                }
+                Ok(Step::Behind { behind_by, .. }) => {
+                    return Err(TryAssignError::ClockBehind { behind_by })
+                }
+                Err(SnowflakeError::TimestampOverflow { timestamp, max }) => {
+                    return Err(TryAssignError::TimestampOverflow { timestamp, max })
+                }
+                Err(SnowflakeError::ImplausibleTimestamp { got }) => {
+                    return Err(TryAssignError::ImplausibleTimestamp { got })
+                }
+                Err(SnowflakeError::StateFile { path, message }) => {
+                    return Err(TryAssignError::StateFile { path, message })
+                }
+                Err(err) => return Err(TryAssignError::Other(err)),
+            }
+        }
+
+        Err(TryAssignError::Contended)
+    }
+
+    /// Reserve sequence numbers in next tick, `fallible` reports rollback and overflow by [`SnowflakeError`](SnowflakeError) rather than waiting.
+    #[cfg(feature = "async")]
+    async fn next<T>(
+        &self,
+        provider: &T,
+        fallible: bool,
+        claim: Claim,
+    ) -> Result<Reservation, SnowflakeError>
+    where
+        T: TimeProvider + Sync + Send,
+        S: Sleeper,
+    {
+        let (mut attempt, mut lost) = (0, 0);
+        let (mut waited, mut exhausted_waited) = (Duration::ZERO, Duration::ZERO);
+        let mut backoff = Backoff::new(self.cfg.max_backoff, self.cfg.backoff_jitter);
+        // Place in the fair queue, leaving it once assigned.
+        let mut turn = None;
+        loop {
+            if let Some(fair) = &self.fair {
+                if turn.is_none() && (lost > fair.max_attempts || !fair.is_empty()) {
+                    turn = Some(fair.turn().await);
+                }
+            }
+
+            match self.advance(provider, fallible, claim)? {
+                // Nothing is awaited once claimed, so dropping this future never loses a reservation.
+                Progress::Assigned(it) => {
+                    self.report_wait(waited, exhausted_waited);
+                    return Ok(it);
+                }
+                Progress::Retry => lost = lost.saturating_add(1),
+                Progress::Wait { next_tick, behind } => {
+                    let duration = self.wait_duration(&mut backoff, next_tick, behind);
+                    let start = wait::Stopwatch::start();
+                    self.cfg
+                        .wait_strategy
+                        .wait(&self.sleeper, attempt, duration)
+                        .await;
+                    let elapsed = start.elapsed();
+                    self.stats.waited(elapsed);
+                    waited += elapsed;
+                    if behind.is_none() {
+                        exhausted_waited += elapsed;
+                    }
+                    attempt = attempt.saturating_add(1);
+                    lost = lost.saturating_add(1);
+                }
+            }
+        }
+    }
+
+    /// Same as [`SnowflakeGenerator::next`](SnowflakeGenerator::next), but blocking current thread.
+    #[cfg(feature = "sync")]
+    fn next_sync<T>(
+        &self,
+        provider: &T,
+        fallible: bool,
+        claim: Claim,
+    ) -> Result<Reservation, SnowflakeError>
+    where
+        T: TimeProvider + ?Sized,
+    {
+        let mut attempt = 0;
+        let (mut waited, mut exhausted_waited) = (Duration::ZERO, Duration::ZERO);
+        let mut backoff = Backoff::new(self.cfg.max_backoff, self.cfg.backoff_jitter);
+        loop {
+            match self.advance(provider, fallible, claim)? {
+                Progress::Assigned(it) => {
+                    self.report_wait(waited, exhausted_waited);
+                    return Ok(it);
+                }
+                Progress::Retry => continue,
+                Progress::Wait { next_tick, .. } if fallible && wait::in_async_runtime() => {
+                    return Err(SnowflakeError::BlockingInAsyncRuntime {
+                        retry_after: next_tick,
+                    })
+                }
+                Progress::Wait { next_tick, behind } => {
+                    let duration = self.wait_duration(&mut backoff, next_tick, behind);
+                    let start = wait::Stopwatch::start();
+                    self.cfg.wait_strategy.wait_sync(attempt, duration);
+                    let elapsed = start.elapsed();
+                    self.stats.waited(elapsed);
+                    waited += elapsed;
+                    if behind.is_none() {
+                        exhausted_waited += elapsed;
+                    }
+                    attempt = attempt.saturating_add(1);
+                }
+            }
+        }
+    }
+
+    /// How long to wait before trying again, backing off while the clock is `behind`, but never past catching up.
+    ///
+    /// It's at least until the next tick, as before any backoff.
+    #[cfg(any(feature = "async", feature = "sync"))]
+    fn wait_duration(
+        &self,
+        backoff: &mut Backoff,
+        next_tick: Duration,
+        behind: Option<Duration>,
+    ) -> Duration {
+        match behind {
+            Some(behind) => {
+                self.stats.backoff();
+                backoff.delay().min(behind).max(next_tick)
+            }
+            None => {
+                backoff.reset();
+                next_tick
+            }
+        }
+    }
+
+    /// Report one assignment waited `waited` in total, `exhausted_waited` of them for sequence exhausted.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn report_wait(&self, waited: Duration, exhausted_waited: Duration) {
+        if !exhausted_waited.is_zero() {
+            self.hooks.emit(GeneratorEvent::SequenceExhausted {
+                waited: exhausted_waited,
+            });
+        }
+
+        #[cfg(feature = "tracing")]
+        if waited > self.slow_wait {
+            tracing::warn!(
+                identifier = self.identifier(),
+                waited_ms = waited.as_millis() as u64,
+                "waited long for assignment"
+            );
+        }
+    }
+
+    /// One [`SnowflakeGenerator::step`](SnowflakeGenerator::step) with [`overflow_policy`](SnowflakeConfiguration::overflow_policy) applied.
+    fn advance<T>(
+        &self,
+        provider: &T,
+        fallible: bool,
+        claim: Claim,
+    ) -> Result<Progress, SnowflakeError>
+    where
+        T: TimeProvider + ?Sized,
+    {
+        Ok(match self.step(provider, fallible, claim)? {
+            Step::Assigned(it) => Progress::Assigned(it),
+            Step::Contended => Progress::Retry,
+            Step::Exhausted { .. }
+                if self.cfg.overflow_policy == OverflowPolicy::SpinNextMillis =>
+            {
+                hint::spin_loop();
+                Progress::Retry
+            }
+            Step::Exhausted { next_tick }
+                if fallible && self.cfg.overflow_policy == OverflowPolicy::Error =>
+            {
+                return Err(SnowflakeError::SequenceOverflow {
+                    retry_after: next_tick,
+                })
+            }
+            Step::Exhausted { next_tick } => {
+                self.stats.exhausted_wait();
+                Progress::Wait {
+                    next_tick,
+                    behind: None,
+                }
+            }
+            // Ticks behind include the current one, which ends after `next_tick`.
+            Step::Behind {
+                behind_by,
+                next_tick,
+            } => Progress::Wait {
+                next_tick,
+                behind: Some(behind_by.saturating_sub(self.cfg.time_unit) + next_tick),
+            },
+        })
+    }
+
+    /// One attempt of reserving sequence numbers, never waits.
+    ///
+    /// State is `timestamp | rollback | last sequence`, rollback bit is always reserved even if layout has no rollback flag.
+    fn step<T>(&self, provider: &T, fallible: bool, claim: Claim) -> Result<Step, SnowflakeError>
+    where
+        T: TimeProvider + ?Sized,
+    {
+        // Clock is read once, lost races only reload the state.
+        let now = self.cfg.now_micros(provider);
+        if now < self.plausible.0 || now > self.plausible.1 {
+            // Read again for the error, `now` may be saturated.
+            return Err(SnowflakeError::ImplausibleTimestamp {
+                got: provider.timestamp(),
+            });
+        }
+
+        let timestamp = self.cfg.ticks_of_micros(now);
+        if self.max_jump != u64::MAX {
+            let previous = self.last_read.fetch_max(timestamp, Ordering::Relaxed);
+            if previous != 0 && timestamp.saturating_sub(previous) > self.max_jump {
+                return Err(SnowflakeError::ImplausibleTimestamp { got: now / 1000 });
+            }
+        }
+        if timestamp >= self.overflow_near {
+            self.near_overflow(timestamp)?;
+        }
+        // Privacy mode picks one at a time.
+        let claim = match (claim, &self.used_sequences) {
+            (Claim::AtMost(_), Some(_)) => Claim::AtMost(1),
+            (claim, _) => claim,
+        };
+        let home = self.home_shard();
+        let mut plan = self.claim_shard(home, timestamp, claim);
+
+        if matches!(plan, Plan::Exhausted) {
+            // Steal from other shards, but only if they can be claimed right away.
+            let mut contended = false;
+            for offset in 1..self.shards.len() {
+                match self.claim_shard((home + offset) % self.shards.len(), timestamp, claim) {
+                    it @ (Plan::Claim(_) | Plan::Hold(_)) => {
+                        plan = it;
+                        break;
+                    }
+                    Plan::Contended => contended = true,
+                    _ => {}
+                }
+            }
+
+            if contended && matches!(plan, Plan::Exhausted) {
+                plan = Plan::Contended;
+            }
+        }
+        if let Some(used) = &self.used_sequences {
+            plan = self.randomize(used, plan);
+        }
+
+        let next_tick = || self.cfg.until_tick_end(provider, timestamp);
+        Ok(match plan {
+            Plan::Claim(it) => {
+                self.stats.issued(it.count, it.sequence + it.count - 1);
+                self.persist(it.timestamp)?;
+                Step::Assigned(it)
+            }
+            Plan::Hold(it) => {
+                self.stats.clock_behind();
+                self.stats.issued(it.count, it.sequence + it.count - 1);
+                self.persist(it.timestamp)?;
+                Step::Assigned(it)
+            }
+            Plan::Contended => Step::Contended,
+            Plan::Exhausted => Step::Exhausted {
+                next_tick: next_tick(),
+            },
+            Plan::Behind {
+                behind_by,
+                rolled_back,
+            } => {
+                self.stats.clock_behind();
+                self.hooks
+                    .emit(GeneratorEvent::ClockBackwards { delta: behind_by });
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    identifier = self.identifier(),
+                    delta_ms = behind_by.as_millis() as u64,
+                    rolled_back,
+                    "clock behind the last timestamp"
+                );
+                if rolled_back {
+                    match self.cfg.rollback_policy {
+                        RollbackPolicy::Error if fallible => {
+                            return Err(SnowflakeError::ClockMovedBackwards { delta: behind_by })
+                        }
+                        RollbackPolicy::Panic => {
+                            panic!("clock moved backwards by {behind_by:?}")
+                        }
+                        _ => {}
+                    }
+                }
+
+                Step::Behind {
+                    behind_by,
+                    next_tick: next_tick(),
+                }
+            }
+        })
+    }
+
+    /// Returning [`SnowflakeError::TimestampOverflow`](SnowflakeError::TimestampOverflow) if `timestamp` doesn't fit in layout,
+    /// or reporting it's near.
+    #[cold]
+    fn near_overflow(&self, timestamp: u64) -> Result<(), SnowflakeError> {
+        let max = self.cfg.layout.max_timestamp();
+        if timestamp > max {
+            return Err(SnowflakeError::TimestampOverflow {
+                timestamp: self.cfg.unix_timestamp_of_ticks(timestamp),
+                max: self.exhaustion_timestamp(),
+            });
+        }
+
+        let unit = self.cfg.unit_micros();
+        if self
+            .hooks
+            .overflow_due(timestamp, OVERFLOW_NEAR_INTERVAL.as_micros() as u64 / unit)
+        {
+            let remaining = (max - timestamp) * unit;
+            self.hooks.emit(GeneratorEvent::TimestampOverflowNear {
+                remaining: Duration::from_micros(remaining),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Make sure the state file (if any) covers `timestamp`, before anything of it is handed out.
+    #[cfg_attr(not(feature = "persistence"), allow(unused_variables))]
+    fn persist(&self, timestamp: u64) -> Result<(), SnowflakeError> {
+        #[cfg(feature = "persistence")]
+        if let Some(state_file) = &self.state_file {
+            return state_file.cover(timestamp);
+        }
+
+        Ok(())
+    }
+
+    /// Claim from `index`-th shard with the clock at `timestamp`, retrying lost races up to [`cas_attempts`](SnowflakeConfiguration::cas_attempts) times.
+    ///
+    /// Reusing `timestamp` across retries never hands out a stale one. Every retry plans against the state just observed,
+    /// and a claim is never below the timestamp of that state, nor below the sequence of the same tick,
+    /// so it's always after whatever the race was lost to. A stale `timestamp` only ever shows up as the clock being behind,
+    /// which is re-read below rather than trusted.
+    fn claim_shard(&self, index: usize, timestamp: u64, claim: Claim) -> Plan {
+        let sequences = self.sequences(index);
+        let cas_attempts = self.cfg.cas_attempts.max(1);
+        let mut attempt = 0;
+        let mut plan = Plan::Contended;
+        let _ = self.shards[index].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+            attempt += 1;
+            plan = match self.plan(current, sequences, timestamp, claim) {
+                // After losing a race, the state may have moved on with a fresher clock,
+                // so only trust the clock being stale with a fresh read.
+                it @ (Plan::Claim(_) | Plan::Hold(_)) if attempt <= cas_attempts => it,
+                _ if attempt > 1 => Plan::Contended,
+                it => it,
+            };
+
+            match &plan {
+                Plan::Claim(it) | Plan::Hold(it) => Some(it.state(&self.cfg.layout)),
+                _ => None,
+            }
+        });
+
+        plan
+    }
+
+    /// Shard of current thread, threads are spread across shards in turn.
+    fn home_shard(&self) -> usize {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        thread_local! {
+            static HOME: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+        }
+
+        match self.shards.len() {
+            1 => 0,
+            len => HOME.with(|it| *it) % len,
+        }
+    }
+
+    /// `(first, last)` sequence numbers owned by `index`-th shard, the last one takes the remainder.
+    fn sequences(&self, index: usize) -> (u64, u64) {
+        let max_sequence = self.cfg.layout.max_sequence();
+        let len = self.shards.len() as u64;
+        let slice = max_sequence.saturating_add(1) / len;
+        let first = index as u64 * slice;
+        if index as u64 == len - 1 {
+            (first, max_sequence)
+        } else {
+            (first, first + slice - 1)
+        }
+    }
+
+    /// Deciding what to do with state `current` owning `(first, last)` sequence numbers and the clock at `timestamp`,
+    /// without touching anything.
+    fn plan(&self, current: u64, (first, last): (u64, u64), timestamp: u64, claim: Claim) -> Plan {
+        let layout = &self.cfg.layout;
+        let sequence_bits = layout.sequence_bits();
+
+        let current_timestamp = current >> (sequence_bits + 1);
+        let current_rollback = (current >> sequence_bits) & 1 == 1;
+        let current_sequence = current & layout.max_sequence();
+        // Sequence numbers left after current one should hold at least `needed`.
+        let needed = match claim {
+            Claim::AtMost(_) => 1,
+            Claim::Exactly(count) => count,
+        };
+
+        // Ticks allowed to run ahead of the clock, 0 unless borrowing from the future.
+        let borrow_ticks = match self.cfg.overflow_policy {
+            OverflowPolicy::BorrowFuture { max_drift } => {
+                max_drift.as_micros() as u64 / self.cfg.unit_micros()
+            }
+            _ => 0,
+        };
+        let ahead = current_timestamp.saturating_sub(timestamp);
+
+        let fit = |timestamp, sequence| self.fit(timestamp, sequence, last, needed, claim);
+        // Blocks always fit in a fresh tick, see `SnowflakeGenerator::check_block_size`.
+        let fresh = |timestamp| fit(timestamp, first).unwrap_or(first);
+
+        let (timestamp, sequence, rollback) = match current_timestamp.cmp(&timestamp) {
+            std::cmp::Ordering::Less => (timestamp, fresh(timestamp), false),
+            _ if ahead <= borrow_ticks => {
+                if let Some(sequence) = fit(current_timestamp, current_sequence + 1) {
+                    (current_timestamp, sequence, current_rollback)
+                } else if ahead < borrow_ticks {
+                    let timestamp = current_timestamp + 1;
+                    (timestamp, fresh(timestamp), current_rollback)
+                } else {
+                    return Plan::Exhausted;
+                }
+            }
+            _ => {
+                let behind = ahead;
+                let behind_by = Duration::from_micros(behind * self.cfg.unit_micros());
+                let rolled_back = current_rollback || behind > self.cfg.rollback_tolerance_ticks();
+                let flagged = layout.has_rollback_flag();
+                let hold = rolled_back
+                    && (flagged || self.cfg.rollback_policy == RollbackPolicy::HoldLastTimestamp);
+
+                if !hold {
+                    return Plan::Behind {
+                        behind_by,
+                        rolled_back,
+                    };
+                }
+
+                // Clock rolled back, keep going from the last timestamp
+                let (timestamp, sequence) = match fit(current_timestamp, current_sequence + 1) {
+                    Some(sequence) => (current_timestamp, sequence),
+                    None => (current_timestamp + 1, fresh(current_timestamp + 1)),
+                };
+                return Plan::Hold(Reservation::new(
+                    timestamp,
+                    sequence,
+                    last,
+                    claim,
+                    flagged,
+                    self.sequence_start(timestamp),
+                ));
+            }
+        };
+
+        Plan::Claim(Reservation::new(
+            timestamp,
+            sequence,
+            last,
+            claim,
+            rollback,
+            self.sequence_start(timestamp),
+        ))
+    }
+
+    /// First sequence number from `sequence` on which `needed` ones fit in `last` of `timestamp`, `None` if none.
+    ///
+    /// With randomized sequence start, blocks skip to where sequence wraps around rather than crossing it.
+    fn fit(
+        &self,
+        timestamp: u64,
+        mut sequence: u64,
+        last: u64,
+        needed: u64,
+        claim: Claim,
+    ) -> Option<u64> {
+        if self.cfg.offset_sequence() && matches!(claim, Claim::Exactly(_)) {
+            let per_tick = self.cfg.layout.max_sequence() + 1;
+            let offset = (sequence + self.sequence_start(timestamp)) % per_tick;
+            if offset + needed > per_tick {
+                sequence += per_tick - offset;
+            }
+        }
+
+        (sequence <= last && last - sequence + 1 >= needed).then_some(sequence)
+    }
+
+    /// Offset sequence of `timestamp` starts at, 0 unless randomized.
+    fn sequence_start(&self, timestamp: u64) -> u64 {
+        if !self.cfg.offset_sequence() {
+            return 0;
+        }
+
+        // Mixed, so neighbouring ticks have unrelated offsets.
+        identifier::mix(timestamp ^ self.sequence_key) & self.cfg.layout.max_sequence()
+    }
+}
+
+/// Sequence numbers claimed by one [`SnowflakeGenerator::step`](SnowflakeGenerator::step).
+struct Reservation {
+    timestamp: u64,
+    sequence: u64,
+    count: u64,
+    rollback: bool,
+    /// Offset added to `sequence` when composing, see [`SnowflakeConfiguration::randomize_sequence_start`](SnowflakeConfiguration::randomize_sequence_start).
+    start: u64,
+}
+
+impl Reservation {
+    /// Claiming from `sequence` of `timestamp`, as many as `claim` and `last` sequence number allow.
+    fn new(
+        timestamp: u64,
+        sequence: u64,
+        last: u64,
+        claim: Claim,
+        rollback: bool,
+        start: u64,
+    ) -> Self {
+        let count = match claim {
+            Claim::AtMost(want) => want.clamp(1, last - sequence + 1),
+            Claim::Exactly(count) => count,
+        };
+
+        Self {
+            timestamp,
+            sequence,
+            count,
+            rollback,
+            start,
+        }
+    }
+
+    /// Generator state after claiming this reservation.
+    fn state(&self, layout: &SnowflakeLayout) -> u64 {
+        let sequence_bits = layout.sequence_bits();
+        (self.timestamp << (sequence_bits + 1))
+            | ((self.rollback as u64) << sequence_bits)
+            | (self.sequence + self.count - 1)
+    }
+
+    /// Composed bits of `offset`-th reserved sequence number.
+    fn bits(&self, cfg: &SnowflakeConfiguration, offset: u64) -> u64 {
+        // Sequence wraps around by the mask of layout.
+        cfg.layout.compose_raw(
+            self.timestamp,
+            cfg.identifier(),
+            self.sequence + offset + self.start,
+            self.rollback,
+        )
+    }
+}
+
+/// How many sequence numbers to claim in one [`SnowflakeGenerator::step`](SnowflakeGenerator::step).
+#[derive(Clone, Copy)]
+enum Claim {
+    /// As many as left in current tick, but at most this many.
+    AtMost(u64),
+    /// Exactly this many in the same tick, or none.
+    Exactly(u64),
+}
+
+/// Sequence numbers claimed at most by one step of [`SnowflakeGenerator::assign_many`](SnowflakeGenerator::assign_many).
+const ASSIGN_MANY_CHUNK: usize = 512;
+
+/// Shortest wait for the next tick, so it never spins with zero-duration sleeps.
+const MIN_WAIT: Duration = Duration::from_micros(20);
+
+/// Lost races before [`SnowflakeGenerator::try_assign_now`](SnowflakeGenerator::try_assign_now) gives up.
+const TRY_ASSIGN_ATTEMPTS: usize = 64;
+
+/// Outcome of [`SnowflakeGenerator::advance`](SnowflakeGenerator::advance).
+enum Progress {
+    /// Reserved sequence numbers.
+    Assigned(Reservation),
+    /// Try again right now.
+    Retry,
+    /// Wait, then try again.
+    Wait {
+        next_tick: Duration,
+        /// How long until the clock catches up, `None` if sequence exhausted.
+        behind: Option<Duration>,
+    },
+}
+
+/// Decision of [`SnowflakeGenerator::plan`](SnowflakeGenerator::plan) on one observed state.
+enum Plan {
+    /// Claim these sequence numbers.
+    Claim(Reservation),
+    /// Claim these sequence numbers, holding the last timestamp since the clock is behind.
+    Hold(Reservation),
+    /// Lost too many races, or the clock read may be stale.
+    Contended,
+    /// Sequence exhausted in current tick.
+    Exhausted,
+    /// Clock behind the last timestamp, beyond tolerance if `rolled_back`.
+    Behind {
+        behind_by: Duration,
+        rolled_back: bool,
+    },
+}
+
+/// Outcome of [`SnowflakeGenerator::step`](SnowflakeGenerator::step).
+enum Step {
+    /// Reserved sequence numbers.
+    Assigned(Reservation),
+    /// Lost the race, try again.
+    Contended,
+    /// Sequence exhausted in current tick.
+    Exhausted { next_tick: Duration },
+    /// Clock behind the last timestamp.
+    Behind {
+        behind_by: Duration,
+        next_tick: Duration,
+    },
+}
+
+/// Persisted [`SnowflakeGenerator`](SnowflakeGenerator).
+///
+/// Designed for easier contextualization.
+///
+/// # Thread safety
+///
+/// YES. You can use [`::std::sync::Arc`](::std::sync::Arc) to send data between threads safety.
+///
+/// # Clone
+///
+/// Clone is cheap. If you clone it, it equals invoke [`Arc::clone`](Arc::clone) three times.
+#[derive(Debug)]
+pub struct PersistedSnowflakeGenerator<T, S = TimerSleeper> {
+    generator: Arc<SnowflakeGenerator<S>>,
+    provider: Arc<T>,
+}
+
+impl<T, S> PersistedSnowflakeGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+{
+    /// Constructing new [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) from already instanced [`SnowflakeGenerator`](SnowflakeGenerator) and [`TimeProvider`](TimeProvider)
+    ///
+    /// The cost very low, please relax to constructing your [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator).
+    ///
+    /// # Thread safety
+    ///
+    /// Yes, `time_provider` must be send and sync between threads and [`SnowflakeGenerator`](SnowflakeGenerator) are already thread safe.
+    pub fn new(generator: Arc<SnowflakeGenerator<S>>, provider: Arc<T>) -> Self {
+        Self {
+            generator,
+            provider,
+        }
+    }
+
+    /// Configuration of inner [`SnowflakeGenerator`](SnowflakeGenerator).
+    pub fn config(&self) -> &SnowflakeConfiguration {
+        self.generator.config()
+    }
+
+    /// Effective identifier stamped into generated [`Snowflake`](Snowflake).
+    pub fn identifier(&self) -> u64 {
+        self.generator.identifier()
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake)
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "async")]
+    pub async fn assign(&self) -> Snowflake
+    where
+        S: Sleeper,
+    {
+        self.generator.assign(self.provider.as_ref()).await
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn assign_sync(&self) -> Snowflake {
+        self.generator.assign_sync(self.provider.as_ref())
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake), see [`SnowflakeGenerator::try_assign`](SnowflakeGenerator::try_assign).
+    #[cfg(feature = "async")]
+    pub async fn try_assign(&self) -> Result<Snowflake, SnowflakeError>
+    where
+        S: Sleeper,
+    {
+        self.generator.try_assign(self.provider.as_ref()).await
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way, see [`SnowflakeGenerator::try_assign_sync`](SnowflakeGenerator::try_assign_sync).
+    #[cfg(feature = "sync")]
+    pub fn try_assign_sync(&self) -> Result<Snowflake, SnowflakeError> {
+        self.generator.try_assign_sync(self.provider.as_ref())
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) without waiting, see [`SnowflakeGenerator::try_assign_now`](SnowflakeGenerator::try_assign_now).
+    pub fn try_assign_now(&self) -> Result<Snowflake, TryAssignError> {
+        self.generator.try_assign_now(self.provider.as_ref())
+    }
+
+    /// Assign `n` [`Snowflake`](Snowflake), see [`SnowflakeGenerator::assign_many`](SnowflakeGenerator::assign_many).
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "async")]
+    pub async fn assign_many(&self, n: usize) -> Vec<Snowflake>
+    where
+        S: Sleeper,
+    {
+        self.generator.assign_many(self.provider.as_ref(), n).await
+    }
+
+    /// Assign `n` [`Snowflake`](Snowflake) but in synchronous way.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn assign_many_sync(&self, n: usize) -> Vec<Snowflake> {
+        self.generator.assign_many_sync(self.provider.as_ref(), n)
+    }
+
+    /// Reserve a [`SnowflakeBlock`](SnowflakeBlock), see [`SnowflakeGenerator::reserve_block`](SnowflakeGenerator::reserve_block).
+    #[cfg(feature = "async")]
+    pub async fn reserve_block(&self, count: u16) -> Result<SnowflakeBlock, SnowflakeError>
+    where
+        S: Sleeper,
+    {
+        self.generator
+            .reserve_block(self.provider.as_ref(), count)
+            .await
+    }
+
+    /// Reserve a [`SnowflakeBlock`](SnowflakeBlock) but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn reserve_block_sync(&self, count: u16) -> Result<SnowflakeBlock, SnowflakeError> {
+        self.generator
+            .reserve_block_sync(self.provider.as_ref(), count)
+    }
+
+    /// Endless [`Stream`](Stream) of [`Snowflake`](Snowflake), see [`SnowflakeGenerator::stream`](SnowflakeGenerator::stream).
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "async")]
+    pub fn stream(&self) -> impl Stream<Item = Snowflake> + Send + '_
+    where
+        S: Sleeper,
+    {
+        self.generator.stream(self.provider.as_ref())
+    }
+
+    /// Endless blocking [`Iterator`](Iterator) of [`Snowflake`](Snowflake), see [`SnowflakeGenerator::iter_sync`](SnowflakeGenerator::iter_sync).
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn iter_sync(&self) -> SnowflakeIter<'_, T, S> {
+        self.generator.iter_sync(self.provider.as_ref())
+    }
+
+    /// Assign a new [`SnowflakeU64`](SnowflakeU64).
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "async")]
+    pub async fn assign_u64(&self) -> SnowflakeU64
+    where
+        S: Sleeper,
+    {
+        self.generator.assign_u64(self.provider.as_ref()).await
+    }
+
+    /// Assign a new [`SnowflakeU64`](SnowflakeU64) but in synchronous way.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`SnowflakeGenerator::assign`](SnowflakeGenerator::assign).
+    #[cfg(feature = "sync")]
+    pub fn assign_u64_sync(&self) -> SnowflakeU64 {
+        self.generator.assign_u64_sync(self.provider.as_ref())
+    }
+}
+
+impl<T, S> Clone for PersistedSnowflakeGenerator<T, S> {
+    fn clone(&self) -> Self {
+        Self {
+            generator: self.generator.clone(),
+            provider: self.provider.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "async")]
+    use std::future::{self, Future};
+    use std::{collections::HashSet, sync::Arc};
+
+    #[cfg(feature = "async")]
+    use parking_lot::RwLock;
+    use provider::{StdProvider, STD_PROVIDER};
+
+    use super::*;
+
+    /// Trusting test providers, whose timestamps are far before 2024.
+    fn test_cfg(identifier: u64) -> SnowflakeConfiguration {
+        SnowflakeConfiguration::with_identifier(identifier)
+            .with_timestamp_validation(TimestampValidation::Off)
+    }
+
+    #[test]
+    fn test_fill_timestamp() {
+        // Case1
+        let sid = 0u64;
+        let timestamp = 0b101010;
+        let expected = 42 << 22;
+        let result = fill_timestamp(sid, timestamp);
+        assert_eq!(result, expected);
+
+        // Case2
+        let sid = 0u64;
+        let timestamp = (1u64 << 42) - 1;
+        let expected = ((1u64 << 41) - 1) << 22;
+        let result = fill_timestamp(sid, timestamp);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_fill_identifier() {
+        // Case1
+        let sid = 0u64;
+        let identifier = 0b110101;
+        let expected = 53 << 12;
+        let result = fill_identifier(sid, identifier);
+        assert_eq!(result, expected);
+
+        // Case2
+        let sid = 0u64;
+        let identifier = (1u64 << 11) - 1;
+        let expected = ((1u64 << 10) - 1) << 12;
+        let result = fill_identifier(sid, identifier);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_fill_sequence() {
+        // Case1
+        let sid = 0u64;
+        let sequence = 0b1001;
+        let expected = 9;
+        let result = fill_sequence(sid, sequence);
+        assert_eq!(result, expected);
+
+        // Case2
+        let sid = 0u64;
+        let sequence = (1u64 << 13) - 1;
+        let expected = (1u64 << 12) - 1;
+        let result = fill_sequence(sid, sequence);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_filling() {
+        let sid = 0u64;
+        let timestamp = 0b10101010101010101010101010101010101010101u64;
+        let identifier = 0b110101u64;
+        let sequence = 0b1001u64;
+
+        let expected = (timestamp << 22) | (identifier << 12) | sequence;
+
+        let result = filling(sid, timestamp, identifier, sequence);
         assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_decompose() {
+        let snowflake = Snowflake(filling(0, 123456u64, 42u64, 7u64) as i64);
+        assert_eq!(snowflake.timestamp(), 123456);
+        assert_eq!(snowflake.identifier(), 42);
+        assert_eq!(snowflake.sequence(), 7);
+        assert_eq!(snowflake.unix_timestamp(1000), 124456);
+    }
+
+    #[test]
+    fn test_epoch() {
+        // 2020-01-01T00:00:00Z
+        const EPOCH: u64 = 1577836800000;
+
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(1).with_epoch(EPOCH));
+
+        let snowflake = generator.assign_sync(&provider::FixedProvider::new(EPOCH + 1234));
+        assert_eq!(snowflake.timestamp(), 1234);
+        assert_eq!(snowflake.unix_timestamp(EPOCH), EPOCH + 1234);
+
+        let now = STD_PROVIDER.timestamp();
+        let snowflakes = (0..10000)
+            .map(|_| generator.assign_sync(&STD_PROVIDER))
+            .collect::<Vec<_>>();
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert!(snowflakes[0].timestamp() >= now - EPOCH);
+        assert!(snowflakes[0].unix_timestamp(EPOCH) >= now);
+    }
+
+    #[test]
+    fn test_try_with_identifier() {
+        assert!(SnowflakeConfiguration::try_with_identifier(1023).is_ok());
+
+        let err = SnowflakeConfiguration::try_with_identifier(1024).unwrap_err();
+        assert_eq!(
+            err,
+            SnowflakeError::IdentifierOutOfRange {
+                identifier: 1024,
+                max: 1023
+            }
+        );
+        assert!(err.to_string().contains("0..=1023"));
+
+        let layout = SnowflakeLayout::new(44, 6, 13).unwrap();
+        assert!(SnowflakeGenerator::try_with_cfg(test_cfg(63).with_layout(layout)).is_ok());
+        assert!(SnowflakeGenerator::try_with_cfg(test_cfg(64).with_layout(layout)).is_err());
+    }
+
+    #[test]
+    fn test_datacenter_worker() {
+        let cfg = SnowflakeConfiguration::with_datacenter_worker(7, 30).unwrap();
+        assert_eq!(cfg.identifier(), (7 << 5) | 30);
+        assert!(cfg.validate().is_ok());
+
+        let snowflake = SnowflakeGenerator::with_cfg(cfg.clone()).assign_sync(&STD_PROVIDER);
+        assert_eq!(snowflake.datacenter(IdentifierSplit::default()), 7);
+        assert_eq!(snowflake.worker(IdentifierSplit::default()), 30);
+        assert_eq!(cfg.datacenter_of(&snowflake), Some(7));
+        assert_eq!(cfg.worker_of(&snowflake), Some(30));
+        assert_eq!(test_cfg(3).datacenter_of(&snowflake), None);
+
+        assert_eq!(
+            SnowflakeConfiguration::with_datacenter_worker(32, 0).unwrap_err(),
+            SnowflakeError::DatacenterOutOfRange {
+                datacenter: 32,
+                max: 31
+            }
+        );
+        assert_eq!(
+            SnowflakeConfiguration::with_datacenter_worker(0, 40).unwrap_err(),
+            SnowflakeError::WorkerOutOfRange {
+                worker: 40,
+                max: 31
+            }
+        );
+
+        // Split has to fit in layout.
+        let split = IdentifierSplit::new(2, 4).unwrap();
+        let layout = SnowflakeLayout::new(44, 6, 13).unwrap();
+        let cfg = SnowflakeConfiguration::with_split_datacenter_worker(split, 3, 15)
+            .unwrap()
+            .with_layout(layout);
+        let snowflake = SnowflakeGenerator::try_with_cfg(cfg.clone())
+            .unwrap()
+            .assign_sync(&STD_PROVIDER);
+        assert_eq!(cfg.datacenter_of(&snowflake), Some(3));
+        assert_eq!(cfg.worker_of(&snowflake), Some(15));
+        assert_eq!(
+            SnowflakeConfiguration::with_datacenter_worker(1, 1)
+                .unwrap()
+                .with_layout(layout)
+                .validate(),
+            Err(SnowflakeError::InvalidIdentifierSplit {
+                datacenter_bits: 5,
+                worker_bits: 5
+            })
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&cfg).unwrap();
+            assert!(json.contains(r#""identifier_split":{"datacenter_bits":2,"worker_bits":4}"#));
+            assert_eq!(
+                serde_json::from_str::<SnowflakeConfiguration>(&json).unwrap(),
+                cfg
+            );
+        }
+    }
+
+    #[test]
+    fn test_per_process() {
+        let cfg = SnowflakeConfiguration::per_process();
+        assert!(cfg.identifier <= 1023);
+        assert_eq!(SnowflakeConfiguration::per_process(), cfg);
+        assert_eq!(
+            SnowflakeConfiguration::process_fingerprint() & 1023,
+            cfg.identifier
+        );
+
+        // Overlapping processes differ by PID.
+        let mask = layouts::DEFAULT.max_identifier();
+        assert_ne!(
+            fingerprint("host", 1000, 42) & mask,
+            fingerprint("host", 1001, 42) & mask
+        );
+        assert_eq!(fingerprint("host", 1000, 42), fingerprint("host", 1000, 42));
+    }
+
+    #[test]
+    fn test_default_identifier() {
+        for _ in 0..100 {
+            let cfg = SnowflakeConfiguration::default();
+            assert!(cfg.identifier <= 1023);
+            assert_eq!(cfg.identifier(), cfg.identifier);
+
+            let identifier = cfg.identifier();
+            let generator = SnowflakeGenerator::with_cfg(cfg);
+            assert_eq!(
+                generator.assign_sync(&STD_PROVIDER).identifier(),
+                identifier
+            );
+        }
+
+        assert_eq!(test_cfg(5000).identifier(), 904);
+    }
+
+    #[test]
+    fn test_getters() {
+        let generator = Arc::new(SnowflakeGenerator::with_cfg(test_cfg(42).with_epoch(1000)));
+        assert_eq!(generator.identifier(), 42);
+        assert_eq!(generator.config().epoch, 1000);
+        assert_eq!(generator.assign_sync(&STD_PROVIDER).identifier(), 42);
+
+        let persist = PersistedSnowflakeGenerator::new(generator, Arc::new(StdProvider));
+        assert_eq!(persist.identifier(), 42);
+        assert_eq!(persist.config().layout, layouts::DEFAULT);
+        assert_eq!(persist.assign_sync().identifier(), 42);
+    }
+
+    #[test]
+    fn test_configuration_clone_eq() {
+        let cfg = test_cfg(42).with_epoch(1000);
+        assert_eq!(cfg.clone(), cfg);
+        assert_ne!(cfg, test_cfg(43));
+
+        let set = HashSet::from([cfg.clone(), cfg]);
+        assert_eq!(set.len(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_configuration_serde() {
+        let cfg = SnowflakeConfiguration::with_identifier(42)
+            .with_epoch(1577836800000)
+            .with_layout(SnowflakeLayout::new(44, 6, 13).unwrap());
+        let json = serde_json::to_string(&cfg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"identifier":42,"epoch":1577836800000,"layout":{"timestamp_bits":44,"identifier_bits":6,"sequence_bits":13},"time_unit":{"secs":0,"nanos":1000000},"rollback_tolerance":{"secs":0,"nanos":5000000},"rollback_policy":"error","overflow_policy":"wait_next_millis","wait_strategy":"sleep","max_backoff":{"secs":0,"nanos":250000000},"backoff_jitter":true,"cas_attempts":16,"timestamp_validation":"window","randomize_sequence_start":false,"random_sequence":false}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<SnowflakeConfiguration>(&json).unwrap(),
+            cfg
+        );
+
+        assert_eq!(
+            serde_json::from_str::<SnowflakeConfiguration>(r#"{"identifier":1023}"#).unwrap(),
+            SnowflakeConfiguration::with_identifier(1023)
+        );
+
+        for invalid in [
+            r#"{"identifier":1024}"#,
+            r#"{"identifier":64,"layout":{"timestamp_bits":44,"identifier_bits":6,"sequence_bits":13}}"#,
+            r#"{"identifier":1,"layout":{"timestamp_bits":44,"identifier_bits":6,"sequence_bits":14}}"#,
+            r#"{"epoch":0}"#,
+        ] {
+            assert!(serde_json::from_str::<SnowflakeConfiguration>(invalid).is_err());
+        }
+
+        let err = serde_json::from_str::<SnowflakeConfiguration>(r#"{"identifier":1024}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("0..=1023"), "{err}");
+    }
+
+    #[test]
+    fn test_js_safe() {
+        const EPOCH: u64 = 1577836800000;
+        let cfg = SnowflakeConfiguration::js_safe(200, EPOCH);
+        assert!(cfg.validate().is_ok());
+        assert!(SnowflakeConfiguration::js_safe(256, EPOCH)
+            .validate()
+            .is_err());
+
+        let now = STD_PROVIDER.timestamp();
+        let generator = SnowflakeGenerator::with_cfg(cfg.clone());
+        // 4096 per second, so don't exhaust it.
+        let snowflakes = (0..4000)
+            .map(|_| generator.assign_sync(&STD_PROVIDER))
+            .collect::<Vec<_>>();
+
+        for snowflake in snowflakes {
+            assert!(*snowflake < 1 << 53);
+            assert_eq!(*snowflake as f64 as i64, *snowflake);
+
+            let layout = layouts::JS_SAFE_53;
+            assert_eq!(layout.identifier_of(&snowflake), 200);
+            assert!(cfg.unix_timestamp_of(&snowflake).abs_diff(now) < 60_000);
+        }
+    }
+
+    #[test]
+    fn test_invalid_time_unit() {
+        for time_unit in [Duration::ZERO, Duration::from_nanos(1500)] {
+            assert_eq!(
+                test_cfg(1).with_time_unit(time_unit).validate(),
+                Err(SnowflakeError::InvalidTimeUnit { time_unit })
+            );
+        }
+    }
+
+    #[test]
+    fn test_ticks() {
+        let cfg = test_cfg(1)
+            .with_epoch(1000)
+            .with_time_unit(Duration::from_millis(10));
+
+        let ticks = |timestamp| {
+            cfg.ticks_of_micros(cfg.now_micros(&provider::FixedProvider::new(timestamp)))
+        };
+        assert_eq!(ticks(1000), 0);
+        assert_eq!(ticks(1234), 23);
+        assert_eq!(ticks(1), 0);
+        assert_eq!(
+            cfg.until_tick_end(&provider::FixedProvider::new(1234), 23),
+            Duration::from_millis(6)
+        );
+        assert_eq!(
+            cfg.until_tick_end(&provider::FixedProvider::new(1), 0),
+            Duration::from_millis(10)
+        );
+    }
+
+    #[test]
+    fn test_precise_wait() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = provider::MockProvider::new(100_000);
+        provider.advance(Duration::from_micros(100));
+
+        generator.assign_many_sync(&provider, 4096);
+        let wait = |generator: &SnowflakeGenerator| match generator.step(
+            &provider,
+            false,
+            Claim::AtMost(1),
+        ) {
+            Ok(Step::Exhausted { next_tick }) => next_tick,
+            _ => panic!("sequence should be exhausted"),
+        };
+        assert_eq!(wait(&generator), Duration::from_micros(900));
+
+        provider.advance(Duration::from_micros(899));
+        assert_eq!(wait(&generator), MIN_WAIT);
+
+        provider.set(100_001);
+        assert_eq!(generator.assign_sync(&provider).timestamp(), 100_001);
+    }
+
+    #[test]
+    fn test_ten_millis() {
+        const EPOCH: u64 = 1577836800000;
+        let cfg = test_cfg(5)
+            .with_epoch(EPOCH)
+            .with_time_unit(Duration::from_millis(10));
+        let generator = SnowflakeGenerator::with_cfg(cfg.clone());
+
+        // 4096 per tick, so generator has to wait for next tick several times.
+        let now = STD_PROVIDER.timestamp();
+        let snowflakes = (0..4096 * 3)
+            .map(|_| generator.assign_sync(&STD_PROVIDER))
+            .collect::<Vec<_>>();
+
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert!(snowflakes.iter().all(|it| it.identifier() == 5));
+        assert!(snowflakes
+            .iter()
+            .all(|it| cfg.unix_timestamp_of(it).abs_diff(now) < 60_000));
+        // 10ms tick makes 41bit timestamp last about 700 years.
+        assert!(snowflakes[0].timestamp().abs_diff((now - EPOCH) / 10) < 6_000);
+    }
+
+    #[test]
+    fn test_micros() {
+        const EPOCH: u64 = 1704067200000;
+        let cfg = test_cfg(9)
+            .with_epoch(EPOCH)
+            .with_layout(layouts::MICROSECOND)
+            .with_time_unit(Duration::from_micros(1));
+        assert!(cfg.validate().is_ok());
+
+        let generator = SnowflakeGenerator::with_cfg(cfg.clone());
+        let now = STD_PROVIDER.timestamp_micros();
+        let first = generator.assign_sync(&STD_PROVIDER);
+        std::thread::sleep(Duration::from_micros(100));
+        let second = generator.assign_sync(&STD_PROVIDER);
+
+        let layout = layouts::MICROSECOND;
+        assert!(layout.timestamp_of(&first) < layout.timestamp_of(&second));
+        assert_eq!(layout.identifier_of(&second), 9);
+        assert!(cfg.unix_timestamp_micros_of(&first).abs_diff(now) < 60_000_000);
+
+        // 256 per microsecond, so sequence exhausts quickly and waits for next tick.
+        let snowflakes = (0..10000)
+            .map(|_| generator.assign_sync(&STD_PROVIDER))
+            .collect::<Vec<_>>();
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let cfg = test_cfg(1023).with_layout(layouts::UNSIGNED);
+        let generator = SnowflakeGenerator::with_cfg(cfg);
+
+        // Bit 63 is set once timestamp reaches 2^41 ticks, about 2039 with UNIX epoch.
+        let boundary = provider::FixedProvider::new(1 << 41);
+        let snowflake = generator.assign_u64_sync(&boundary);
+        assert_eq!(*snowflake, (1 << 63) | (1023 << 12));
+        assert_eq!(snowflake.timestamp(), 1 << 41);
+        assert_eq!(snowflake.identifier(), 1023);
+        assert_eq!(
+            Snowflake::try_from(snowflake.clone()),
+            Err(SnowflakeError::SignBitSet { bits: *snowflake })
+        );
+        assert_eq!(snowflake.to_string().parse(), Ok(snowflake));
+
+        let snowflake = generator.assign_u64_sync(&provider::FixedProvider::new((1 << 42) - 1));
+        assert!(snowflake.timestamp() == (1 << 42) - 1 && snowflake.sequence() == 0);
+        assert!(snowflake > SnowflakeU64::from(i64::MAX as u64));
+
+        // Below the boundary, converting both ways is lossless.
+        let below = SnowflakeU64::from((1 << 63) - 1);
+        let signed = Snowflake::try_from(below.clone()).unwrap();
+        assert_eq!(*signed, i64::MAX);
+        assert_eq!(SnowflakeU64::try_from(signed), Ok(below));
+
+        let negative = layouts::UNSIGNED.compose(1 << 41, 0, 0);
+        assert!(*negative < 0);
+        assert!(SnowflakeU64::try_from(negative).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_unsigned_serde() {
+        let snowflake = SnowflakeU64::MAX;
+        let json = serde_json::to_string(&snowflake).unwrap();
+        assert_eq!(json, u64::MAX.to_string());
+        assert_eq!(
+            serde_json::from_str::<SnowflakeU64>(&json).unwrap(),
+            snowflake
+        );
+    }
+
+    #[test]
+    fn test_rollback_flag() {
+        let layout = layouts::DEFAULT.with_rollback_flag().unwrap();
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(7).with_layout(layout));
+        let provider = provider::MockProvider::new(100_000);
+        let mut snowflakes = Vec::new();
+
+        snowflakes.extend((0..100).map(|_| generator.assign_sync(&provider)));
+        assert!(snowflakes.iter().all(|it| !layout.rollback_of(it)));
+
+        // Rolled back for 5 seconds, keep going without waiting even if sequence exhausted.
+        provider.set(95_000);
+        let started = std::time::Instant::now();
+        let rolled_back = (0..10_000)
+            .map(|_| generator.assign_sync(&provider))
+            .collect::<Vec<_>>();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(rolled_back.iter().all(|it| layout.rollback_of(it)));
+        assert!(rolled_back.iter().all(|it| layout.identifier_of(it) == 7));
+        assert!(rolled_back
+            .iter()
+            .all(|it| layout.timestamp_of(it) >= 100_000));
+        assert!(rolled_back.windows(2).all(|it| it[0] < it[1]));
+        snowflakes.extend(rolled_back);
+
+        // Clock is still behind, but moving forward.
+        provider.set(99_000);
+        let snowflake = generator.assign_sync(&provider);
+        assert!(layout.rollback_of(&snowflake));
+        snowflakes.push(snowflake);
+
+        // Caught up, flag cleared.
+        provider.set(100_010);
+        let snowflake = generator.assign_sync(&provider);
+        assert!(!layout.rollback_of(&snowflake));
+        assert_eq!(layout.timestamp_of(&snowflake), 100_010);
+        snowflakes.push(snowflake);
+
+        let len = snowflakes.len();
+        assert_eq!(snowflakes.into_iter().collect::<HashSet<_>>().len(), len);
+    }
+
+    #[test]
+    fn test_rollback_within_tolerance() {
+        let layout = layouts::DEFAULT.with_rollback_flag().unwrap();
+        let generator = Arc::new(SnowflakeGenerator::with_cfg(
+            test_cfg(7)
+                .with_layout(layout)
+                .with_rollback_tolerance(Duration::from_millis(50)),
+        ));
+        let provider = Arc::new(provider::MockProvider::new(100_000));
+        let first = generator.assign_sync(provider.as_ref());
+
+        // Small rollback waits for the clock instead of setting flag.
+        provider.set(99_990);
+        let handle = {
+            let generator = generator.clone();
+            let provider = provider.clone();
+            std::thread::spawn(move || generator.assign_sync(provider.as_ref()))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        provider.set(100_001);
+
+        let second = handle.join().unwrap();
+        assert!(!layout.rollback_of(&second));
+        assert!(first < second);
+    }
+
+    #[test]
+    fn test_with_floor() {
+        let layout = layouts::DEFAULT;
+        let floor = layout.compose(100_000, 7, 5);
+        let generator = SnowflakeGenerator::with_floor(test_cfg(7), floor.clone()).unwrap();
+
+        let provider = provider::MockProvider::new(99_000);
+        assert!(matches!(
+            generator.try_assign_sync(&provider),
+            Err(SnowflakeError::ClockMovedBackwards { .. })
+        ));
+        assert_eq!(
+            generator.try_assign_now(&provider),
+            Err(TryAssignError::ClockBehind {
+                behind_by: Duration::from_secs(1)
+            })
+        );
+
+        // Within tolerance, waits for the clock catching up.
+        provider.set(99_997);
+        let next = std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.assign_sync(&provider));
+            std::thread::sleep(Duration::from_millis(20));
+            provider.set(100_000);
+            handle.join().unwrap()
+        });
+        assert!(next > floor);
+        assert_eq!((next.timestamp(), next.sequence()), (100_000, 6));
+
+        assert_eq!(
+            SnowflakeGenerator::with_floor(test_cfg(8), floor).unwrap_err(),
+            SnowflakeError::IdentifierMismatch {
+                identifier: 7,
+                expected: 8
+            }
+        );
+    }
+
+    #[test]
+    fn test_clone() {
+        let generator = SnowflakeGenerator::sharded(4, test_cfg(3));
+        let events = Arc::new(AtomicU64::new(0));
+        generator.on_event({
+            let events = events.clone();
+            move |_| {
+                events.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let provider = provider::FixedProvider::new(100_000);
+        let original = generator.assign_many_sync(&provider, 100);
+
+        // Another identifier never collides.
+        let other = generator.clone_with_identifier(4);
+        assert_eq!(other.identifier(), 4);
+        assert_eq!(other.shards.len(), 4);
+        let others = other.assign_many_sync(&provider, 100);
+        assert!(others.iter().all(|it| !original.contains(it)));
+
+        // Same identifier starting from scratch, which collides.
+        let cloned = generator.clone();
+        assert_eq!(cloned.config(), generator.config());
+        assert_eq!(cloned.stats(), GeneratorStats::default());
+        assert_eq!(cloned.assign_sync(&provider), original[0]);
+
+        // Callbacks are carried over.
+        assert!(cloned
+            .try_assign_now(&provider::FixedProvider::new(99_999))
+            .is_err());
+        assert_eq!(events.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_try_assign_rollback() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(7));
+        let provider = provider::MockProvider::new(100_000);
+        let first = generator.try_assign_sync(&provider).unwrap();
+
+        // Within tolerance, still fine once clock catches up.
+        provider.set(99_997);
+        let second = std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.try_assign_sync(&provider));
+            std::thread::sleep(Duration::from_millis(20));
+            provider.set(100_001);
+            handle.join().unwrap()
+        })
+        .unwrap();
+        assert!(first < second);
+
+        provider.set(90_001);
+        assert_eq!(
+            generator.try_assign_sync(&provider),
+            Err(SnowflakeError::ClockMovedBackwards {
+                delta: Duration::from_secs(10)
+            })
+        );
+
+        // Resumes cleanly after the clock recovered.
+        provider.set(100_002);
+        let third = generator.try_assign_sync(&provider).unwrap();
+        assert!(second < third);
+        assert_eq!(third.timestamp(), 100_002);
+        assert_eq!(third.sequence(), 0);
+    }
+
+    #[test]
+    fn test_rollback_policy() {
+        let cfg = test_cfg(7);
+        let provider = provider::MockProvider::new(100_000);
+
+        // 2ms rollback is absorbed by waiting.
+        let generator = SnowflakeGenerator::with_cfg(cfg.clone());
+        let first = generator.try_assign_sync(&provider).unwrap();
+        provider.set(99_998);
+        let second = std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.try_assign_sync(&provider));
+            while generator.clock_behind_count() == 0 {
+                std::thread::yield_now();
+            }
+            provider.set(100_001);
+            handle.join().unwrap()
+        })
+        .unwrap();
+        assert!(first < second);
+
+        // 10s rollback with Error policy.
+        provider.set(90_001);
+        assert_eq!(
+            generator.try_assign_sync(&provider),
+            Err(SnowflakeError::ClockMovedBackwards {
+                delta: Duration::from_secs(10)
+            })
+        );
+
+        // 10s rollback with HoldLastTimestamp policy.
+        let generator = SnowflakeGenerator::with_cfg(
+            cfg.clone()
+                .with_rollback_policy(RollbackPolicy::HoldLastTimestamp),
+        );
+        provider.set(100_000);
+        let mut snowflakes = vec![generator.assign_sync(&provider)];
+        provider.set(90_000);
+        snowflakes.extend((0..10_000).map(|_| generator.try_assign_sync(&provider).unwrap()));
+        provider.set(200_000);
+        snowflakes.push(generator.assign_sync(&provider));
+
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[1].timestamp(), 100_000);
+        assert_eq!(snowflakes.last().unwrap().timestamp(), 200_000);
+        assert_eq!(generator.clock_behind_count(), 10_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "clock moved backwards")]
+    fn test_rollback_panic() {
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(7).with_rollback_policy(RollbackPolicy::Panic));
+        generator.assign_sync(&provider::FixedProvider::new(100_000));
+        generator.assign_sync(&provider::FixedProvider::new(90_000));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_overflow_wait() {
+        let clock = provider::MockProvider::new(100_000);
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(3)).with_sleeper(RecordingSleeper::new(&clock));
+
+        let snowflakes = futures::executor::block_on(async {
+            let mut snowflakes = Vec::new();
+            for _ in 0..4097 {
+                snowflakes.push(generator.try_assign(&clock).await.unwrap());
+            }
+            snowflakes
+        });
+
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[4095].timestamp(), 100_000);
+        assert_eq!(snowflakes[4096].timestamp(), 100_001);
+        // Waiting out the rest of the exhausted tick, once.
+        assert_eq!(
+            *generator.sleeper.slept.read(),
+            vec![Duration::from_millis(1)]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_stats() {
+        let clock = provider::MockProvider::new(100_000);
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(3)).with_sleeper(RecordingSleeper::new(&clock));
+        assert_eq!(generator.stats(), GeneratorStats::default());
+
+        futures::executor::block_on(async {
+            for _ in 0..4097 {
+                generator.assign(&clock).await;
+            }
+        });
+
+        let stats = generator.stats();
+        assert_eq!(stats.issued, 4097);
+        assert_eq!(stats.max_sequence, 4095);
+        assert_eq!(stats.exhausted_waits, 1);
+        assert_eq!(stats.clock_behind, 0);
+        assert_eq!(
+            *generator.sleeper.slept.read(),
+            vec![Duration::from_millis(1)]
+        );
+
+        futures::executor::block_on(generator.assign_many(&clock, 10));
+        assert_eq!(generator.stats().issued, 4107);
+
+        generator.reset_stats();
+        assert_eq!(generator.stats(), GeneratorStats::default());
+    }
+
+    #[test]
+    fn test_remaining_capacity() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = provider::MockProvider::new(100_000);
+        assert_eq!(generator.remaining_capacity(&provider), 4096);
+
+        generator.assign_sync(&provider);
+        assert_eq!(generator.remaining_capacity(&provider), 4095);
+        generator.assign_many_sync(&provider, 4000);
+        assert_eq!(generator.remaining_capacity(&provider), 95);
+        generator.assign_many_sync(&provider, 95);
+        assert_eq!(generator.remaining_capacity(&provider), 0);
+        assert!(generator.is_saturated(&provider));
+
+        // Back to full on the next tick, and nothing while the clock is behind.
+        provider.set(100_001);
+        assert_eq!(generator.remaining_capacity(&provider), 4096);
+        generator.assign_sync(&provider);
+        provider.set(100_000);
+        assert!(generator.is_saturated(&provider));
+
+        // Summed up over shards.
+        let sharded = SnowflakeGenerator::sharded(4, test_cfg(3));
+        provider.set(100_002);
+        sharded.assign_many_sync(&provider, 10);
+        assert_eq!(sharded.remaining_capacity(&provider), 4086);
+    }
+
+    #[test]
+    fn test_last_assigned() {
+        let provider = provider::MockProvider::new(100_000);
+        for generator in [
+            SnowflakeGenerator::with_cfg(test_cfg(3)),
+            SnowflakeGenerator::sharded(4, test_cfg(3)),
+        ] {
+            assert_eq!(generator.last_assigned(), None);
+
+            // Crossing ticks.
+            let mut snowflakes = generator.assign_many_sync(&provider, 100);
+            provider.set(provider.timestamp() + 1);
+            snowflakes.extend((0..100).map(|_| generator.assign_sync(&provider)));
+            assert_eq!(generator.last_assigned(), snowflakes.iter().max().cloned());
+        }
+
+        // Wrapping around within a tick, so not always the largest.
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(3).with_randomize_sequence_start(true));
+        let snowflake = generator.assign_sync(&provider);
+        assert_eq!(generator.last_assigned(), Some(snowflake));
+    }
+
+    #[test]
+    fn test_timestamp_overflow() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = provider::MockProvider::new((1 << 41) - 1);
+        let last = generator.assign_sync(&provider);
+        assert_eq!(last.timestamp(), (1 << 41) - 1);
+
+        provider.set(1 << 41);
+        let overflow = SnowflakeError::TimestampOverflow {
+            timestamp: 1 << 41,
+            max: (1 << 41) - 1,
+        };
+        assert_eq!(generator.try_assign_sync(&provider), Err(overflow.clone()));
+        assert_eq!(generator.assign_at(1 << 41), Err(overflow));
+        assert_eq!(
+            generator.try_assign_now(&provider),
+            Err(TryAssignError::TimestampOverflow {
+                timestamp: 1 << 41,
+                max: (1 << 41) - 1
+            })
+        );
+        assert!(std::panic::catch_unwind(|| generator.assign_sync(&provider)).is_err());
+    }
+
+    #[test]
+    fn test_exhaustion_date() {
+        let exhaustion = SnowflakeGenerator::with_cfg(test_cfg(3))
+            .exhaustion_date()
+            .duration_since(UNIX_EPOCH)
+            .unwrap();
+        // 2039-09-07
+        assert_eq!(exhaustion, Duration::from_millis(1 << 41));
+        assert!((2_177_452_800..2_208_988_800).contains(&exhaustion.as_secs()));
+
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3).with_epoch(1_577_836_800_000));
+        assert_eq!(
+            generator.exhaustion_date(),
+            UNIX_EPOCH + Duration::from_millis(1_577_836_800_000 + (1 << 41))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_validation() {
+        let generator = SnowflakeGenerator::with_cfg(SnowflakeConfiguration::with_identifier(3));
+        let seconds = provider::FixedProvider::new(1_750_000_000);
+        let nanos = provider::FixedProvider::new(1_750_000_000_000_000_000);
+
+        for provider in [&seconds, &nanos] {
+            assert_eq!(
+                generator.try_assign_sync(provider),
+                Err(SnowflakeError::ImplausibleTimestamp {
+                    got: provider.timestamp()
+                })
+            );
+            assert_eq!(
+                generator.try_assign_now(provider),
+                Err(TryAssignError::ImplausibleTimestamp {
+                    got: provider.timestamp()
+                })
+            );
+        }
+        assert!(std::panic::catch_unwind(|| generator.assign_sync(&seconds)).is_err());
+        generator.assign_sync(&STD_PROVIDER);
+
+        // Before epoch.
+        let tomorrow = STD_PROVIDER.timestamp() + 24 * 60 * 60 * 1000;
+        let generator = SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration::with_identifier(3).with_epoch(tomorrow),
+        );
+        assert!(generator.try_assign_sync(&STD_PROVIDER).is_err());
+
+        let generator = SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration::with_identifier(3)
+                .with_timestamp_validation(TimestampValidation::Off),
+        );
+        generator.assign_sync(&seconds);
+    }
+
+    #[test]
+    fn test_timestamp_validation_jump() {
+        let cfg = SnowflakeConfiguration::with_identifier(3).with_timestamp_validation(
+            TimestampValidation::WindowAndJump {
+                max_jump: Duration::from_secs(60),
+            },
+        );
+        let generator = SnowflakeGenerator::with_cfg(cfg.clone());
+        let provider = provider::MockProvider::new(1_750_000_000_000);
+        generator.assign_sync(&provider);
+
+        provider.set(1_750_000_000_000 + 30_000);
+        generator.assign_sync(&provider);
+        provider.set(1_750_000_000_000 + 120_000);
+        assert_eq!(
+            generator.try_assign_sync(&provider),
+            Err(SnowflakeError::ImplausibleTimestamp {
+                got: 1_750_000_000_000 + 120_000
+            })
+        );
+        // Staying there, it's taken as the clock.
+        assert_eq!(
+            generator.try_assign_sync(&provider).unwrap().timestamp(),
+            1_750_000_000_000 + 120_000
+        );
+
+        #[cfg(feature = "serde")]
+        assert_eq!(
+            serde_json::from_str::<SnowflakeConfiguration>(
+                r#"{"identifier":3,"timestamp_validation":{"window_and_jump":{"max_jump":{"secs":60,"nanos":0}}}}"#
+            )
+            .unwrap(),
+            cfg
+        );
+    }
+
+    #[test]
+    fn test_timestamp_validation_idle() {
+        let generator = SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration::with_identifier(3).with_timestamp_validation(
+                TimestampValidation::WindowAndJump {
+                    max_jump: Duration::from_secs(60),
+                },
+            ),
+        );
+        let clock = provider::MockProvider::new(1_750_000_000_000);
+        let before = generator.assign_sync(&clock);
+
+        // Idle over a weekend, only the first read after is rejected.
+        clock.advance(Duration::from_secs(2 * 24 * 3600));
+        assert!(matches!(
+            generator.try_assign_sync(&clock),
+            Err(SnowflakeError::ImplausibleTimestamp { .. })
+        ));
+        let after = generator.assign_sync(&clock);
+        assert!(after > before);
+        clock.advance(Duration::from_secs(30));
+        assert!(generator.assign_sync(&clock) > after);
+    }
+
+    #[test]
+    fn test_on_event() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = provider::MockProvider::new(100_000);
+        let events = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let counted = Arc::new(AtomicU64::new(0));
+        generator.on_event({
+            let events = events.clone();
+            move |event| events.lock().push(event)
+        });
+        generator.on_event({
+            let counted = counted.clone();
+            move |_| {
+                counted.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        for _ in 0..100 {
+            generator.try_assign_now(&provider).unwrap();
+        }
+        assert!(events.lock().is_empty());
+
+        provider.set(99_990);
+        assert!(generator.try_assign_now(&provider).is_err());
+        assert_eq!(
+            events.lock().drain(..).collect::<Vec<_>>(),
+            [GeneratorEvent::ClockBackwards {
+                delta: Duration::from_millis(10)
+            }]
+        );
+
+        provider.set(200_000);
+        std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.assign_many_sync(&provider, 4097));
+            std::thread::sleep(Duration::from_millis(50));
+            provider.set(200_001);
+            handle.join().unwrap()
+        });
+        match events.lock().drain(..).collect::<Vec<_>>()[..] {
+            [GeneratorEvent::SequenceExhausted { waited }] => {
+                assert!(waited >= Duration::from_millis(10))
+            }
+            ref it => panic!("unexpected events {it:?}"),
+        }
+
+        let max = layouts::DEFAULT.max_timestamp();
+        let day = 24 * 60 * 60 * 1000;
+        provider.set(max - 10 * day);
+        generator.assign_sync(&provider);
+        provider.set(max - 10 * day + 1000);
+        generator.assign_sync(&provider);
+        // Once per hour.
+        provider.set(max - 9 * day);
+        generator.assign_sync(&provider);
+        assert_eq!(
+            events.lock().drain(..).collect::<Vec<_>>(),
+            [
+                GeneratorEvent::TimestampOverflowNear {
+                    remaining: Duration::from_millis(10 * day)
+                },
+                GeneratorEvent::TimestampOverflowNear {
+                    remaining: Duration::from_millis(9 * day)
+                }
+            ]
+        );
+        assert_eq!(counted.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_overflow_spin() {
+        let generator = SnowflakeGenerator::with_cfg(
+            test_cfg(3).with_overflow_policy(OverflowPolicy::SpinNextMillis),
+        );
+        let provider = provider::MockProvider::new(100_000);
+
+        let snowflakes = std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.assign_many_sync(&provider, 4097));
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!handle.is_finished());
+            provider.set(100_001);
+            handle.join().unwrap()
+        });
+
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[4096].timestamp(), 100_001);
+        assert_eq!(snowflakes[4096].sequence(), 0);
+    }
+
+    #[test]
+    fn test_overflow_error() {
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(3).with_overflow_policy(OverflowPolicy::Error));
+        let provider = provider::FixedProvider::new(100_000);
+
+        let snowflakes = (0..4096)
+            .map(|_| generator.try_assign_sync(&provider).unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(snowflakes.len(), 4096);
+        assert!(matches!(
+            generator.try_assign_sync(&provider),
+            Err(SnowflakeError::SequenceOverflow { retry_after }) if retry_after == Duration::from_millis(1)
+        ));
+
+        let provider = provider::MockProvider::new(100_000);
+        std::thread::scope(|scope| {
+            // Infallible one keeps waiting.
+            let handle = scope.spawn(|| generator.assign_sync(&provider));
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!handle.is_finished());
+            provider.set(100_001);
+            assert_eq!(handle.join().unwrap().timestamp(), 100_001);
+        });
+    }
+
+    #[test]
+    fn test_overflow_borrow_future() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3).with_overflow_policy(
+            OverflowPolicy::BorrowFuture {
+                max_drift: Duration::from_millis(3),
+            },
+        ));
+        let provider = provider::FixedProvider::new(100_000);
+
+        // Frozen clock, but 4 milliseconds worth of sequence numbers without waiting.
+        let snowflakes = generator.assign_many_sync(&provider, 4 * 4096);
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[4096].timestamp(), 100_001);
+        assert_eq!(snowflakes[4 * 4096 - 1].timestamp(), 100_003);
+        assert_eq!(generator.clock_behind_count(), 0);
+
+        assert_eq!(
+            generator.try_assign_now(&provider),
+            Err(TryAssignError::SequenceExhausted {
+                retry_after: Duration::from_millis(1)
+            })
+        );
+        let snowflake = generator.assign_sync(&provider::FixedProvider::new(100_001));
+        assert_eq!(snowflake.timestamp(), 100_004);
+        assert!(snowflake > snowflakes[4 * 4096 - 1]);
+    }
+
+    #[test]
+    fn test_wait_strategy() {
+        for strategy in [
+            WaitStrategy::Sleep,
+            WaitStrategy::YieldTask,
+            WaitStrategy::Spin {
+                max_iterations: 1000,
+            },
+            WaitStrategy::Hybrid,
+        ] {
+            let generator = SnowflakeGenerator::with_cfg(test_cfg(3).with_wait_strategy(strategy));
+            let provider = provider::MockProvider::new(100_000);
+
+            let (snowflakes, iterated) = std::thread::scope(|scope| {
+                let snowflakes = scope.spawn(|| generator.assign_many_sync(&provider, 5000));
+                let iterated = scope.spawn(|| {
+                    generator
+                        .iter_sync(&provider)
+                        .take(1000)
+                        .collect::<Vec<_>>()
+                });
+                std::thread::sleep(Duration::from_millis(20));
+                provider.set(100_001);
+                (snowflakes.join().unwrap(), iterated.join().unwrap())
+            });
+
+            let unique = snowflakes.iter().chain(&iterated).collect::<HashSet<_>>();
+            assert_eq!(unique.len(), 6000, "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn test_wait_strategy_spin_fallback() {
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(3).with_wait_strategy(WaitStrategy::Spin {
+                max_iterations: 100,
+            }));
+        let provider = provider::MockProvider::new(100_000);
+
+        generator.assign_many_sync(&provider, 4096);
+        std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.assign_sync(&provider));
+            std::thread::sleep(Duration::from_millis(50));
+            // 100 spins, then about one read per millisecond of sleeping.
+            let reads = provider.reads();
+            assert!(reads < 1000, "{reads} reads");
+            provider.set(100_001);
+            assert_eq!(handle.join().unwrap().timestamp(), 100_001);
+        });
+    }
+
+    #[test]
+    fn test_cas_attempts() {
+        // Room for all of them in one tick, so nothing but lost races could read the clock again.
+        let layout = SnowflakeLayout::new(39, 2, 22).unwrap();
+        let generator = SnowflakeGenerator::with_cfg(
+            test_cfg(3).with_layout(layout).with_cas_attempts(u32::MAX),
+        );
+        let provider = provider::MockProvider::new(100_000);
+
+        let snowflakes = std::thread::scope(|scope| {
+            (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..10_000)
+                            .map(|_| generator.assign_sync(&provider))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .collect::<HashSet<_>>()
+        });
+
+        assert_eq!(snowflakes.len(), 80_000);
+        // Once per assignment, however many races lost.
+        assert_eq!(provider.reads(), 80_000);
+    }
+
+    /// Recording requested durations, and moving `clock` forward rather than sleeping.
+    #[cfg(feature = "async")]
+    struct RecordingSleeper {
+        clock: provider::MockProvider,
+        slept: RwLock<Vec<Duration>>,
+    }
+
+    #[cfg(feature = "async")]
+    impl RecordingSleeper {
+        fn new(clock: &provider::MockProvider) -> Self {
+            Self {
+                clock: clock.clone(),
+                slept: RwLock::new(Vec::new()),
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    impl Sleeper for RecordingSleeper {
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+            self.slept.write().push(duration);
+            self.clock.advance(duration);
+            future::ready(())
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_sleeper() {
+        let clock = provider::MockProvider::new(100_000);
+        clock.advance(Duration::from_micros(250));
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(3)).with_sleeper(RecordingSleeper::new(&clock));
+
+        let snowflakes = futures::executor::block_on(generator.assign_many(&clock, 3 * 4096));
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[3 * 4096 - 1].timestamp(), 100_002);
+        assert_eq!(
+            *generator.sleeper.slept.read(),
+            vec![Duration::from_micros(750), Duration::from_millis(1)]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_assign_cancelled() {
+        // 4 sequence numbers per tick, so it waits often.
+        let layout = SnowflakeLayout::new(51, 10, 2).unwrap();
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3).with_layout(layout));
+        let provider = crate::provider::StdProvider;
+
+        let mut snowflakes = Vec::new();
+        for _ in 0..2000 {
+            tokio::select! {
+                biased;
+                it = generator.assign(&provider) => snowflakes.push(it),
+                _ = std::future::ready(()) => {}
+            }
+            tokio::select! {
+                it = generator.assign(&provider) => snowflakes.push(it),
+                _ = tokio::task::yield_now() => {}
+            }
+        }
+        snowflakes.push(generator.assign(&provider).await);
+
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        // Cancelled ones never reserved anything.
+        assert_eq!(generator.stats().issued, snowflakes.len() as u64);
+    }
+
+    /// Clock of Tokio, reading `.1` milliseconds at `.0`.
+    #[cfg(feature = "tokio")]
+    struct TokioTestProvider(tokio::time::Instant, u64);
 
-        // Case2
-        let sid = 0u64;
-        let sequence = (1u64 << 13) - 1;
-        let expected = (1u64 << 12) - 1;
-        let result = fill_sequence(sid, sequence);
-        assert_eq!(result, expected);
+    #[cfg(feature = "tokio")]
+    impl TimeProvider for TokioTestProvider {
+        fn timestamp(&self) -> u64 {
+            self.1 + self.0.elapsed().as_millis() as u64
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_tokio_exhausted() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = TokioTestProvider(tokio::time::Instant::now(), 100_000);
+
+        let snowflakes = generator.assign_many(&provider, 3 * 4096).await;
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[3 * 4096 - 1].timestamp(), 100_002);
+        assert_eq!(provider.0.elapsed(), Duration::from_millis(2));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_tokio_clock_behind() {
+        // Backing off 1 millisecond at most, the clock is found behind at every millisecond.
+        let generator = SnowflakeGenerator::with_cfg(
+            test_cfg(3)
+                .with_max_backoff(Duration::from_millis(1))
+                .with_backoff_jitter(false),
+        );
+        let provider = TokioTestProvider(tokio::time::Instant::now(), 100_000);
+
+        let ahead = generator.assign_at(100_003).unwrap();
+        let snowflake = generator.assign(&provider).await;
+        assert!(snowflake > ahead);
+        assert_eq!(snowflake.timestamp(), 100_003);
+        assert_eq!(provider.0.elapsed(), Duration::from_millis(3));
+        assert_eq!(generator.clock_behind_count(), 3);
+
+        tokio::time::advance(Duration::from_millis(10)).await;
+        assert_eq!(generator.assign(&provider).await.timestamp(), 100_013);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_tokio_clock_behind_backoff() {
+        /// Counting reads of [`TokioTestProvider`](TokioTestProvider).
+        struct Counting(TokioTestProvider, AtomicU64);
+
+        impl TimeProvider for Counting {
+            fn timestamp(&self) -> u64 {
+                self.1.fetch_add(1, Ordering::Relaxed);
+                self.0.timestamp()
+            }
+        }
+
+        let generator = SnowflakeGenerator::with_cfg(
+            test_cfg(3).with_rollback_tolerance(Duration::from_secs(10)),
+        );
+        let provider = Counting(
+            TokioTestProvider(tokio::time::Instant::now(), 100_000),
+            AtomicU64::new(0),
+        );
+
+        // Restored 5 seconds ahead of the clock.
+        let ahead = generator.assign_at(105_000).unwrap();
+        let snowflake = generator.assign(&provider).await;
+        assert!(snowflake > ahead);
+        assert_eq!(snowflake.timestamp(), 105_000);
+        // Never sleeping past catching up.
+        assert_eq!(provider.0 .0.elapsed(), Duration::from_secs(5));
+
+        // Rather than 5000 waits of a millisecond, each reading the clock twice.
+        let backoffs = generator.stats().backoffs;
+        assert!((20..60).contains(&backoffs), "{backoffs} backoffs");
+        let reads = provider.1.load(Ordering::Relaxed);
+        assert!(reads < 200, "{reads} reads");
+
+        // Starting over from 1 millisecond the next time.
+        generator.assign_at(105_100).unwrap();
+        assert_eq!(generator.assign(&provider).await.timestamp(), 105_100);
+        assert_eq!(provider.0 .0.elapsed(), Duration::from_millis(5100));
+        let backoffs = generator.stats().backoffs - backoffs;
+        assert!((5..15).contains(&backoffs), "{backoffs} backoffs");
+    }
+
+    #[cfg(all(feature = "tokio", feature = "sync", debug_assertions))]
+    #[tokio::test]
+    async fn test_try_assign_sync_in_runtime() {
+        let layout = SnowflakeLayout::new(51, 10, 2).unwrap();
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(1).with_layout(layout));
+        let provider = provider::MockProvider::new(100_000);
+
+        for _ in 0..4 {
+            generator.try_assign_sync(&provider).unwrap();
+        }
+        assert!(matches!(
+            generator.try_assign_sync(&provider),
+            Err(SnowflakeError::BlockingInAsyncRuntime { .. })
+        ));
+
+        provider.set(100_001);
+        let snowflake = generator.try_assign_sync(&provider).unwrap();
+        assert_eq!(layout.timestamp_of(&snowflake), 100_001);
     }
 
     #[test]
-    fn test_filling() {
-        let sid = 0u64;
-        let timestamp = 0b10101010101010101010101010101010101010101u64;
-        let identifier = 0b110101u64;
-        let sequence = 0b1001u64;
+    fn test_try_assign_now() {
+        let layout = SnowflakeLayout::new(51, 10, 2).unwrap();
+        let generator = PersistedSnowflakeGenerator::new(
+            Arc::new(SnowflakeGenerator::with_cfg(
+                test_cfg(1).with_layout(layout),
+            )),
+            Arc::new(provider::MockProvider::new(100_000)),
+        );
 
-        let expected = (timestamp << 22) | (identifier << 12) | sequence;
+        let snowflakes = (0..4)
+            .map(|_| generator.try_assign_now().unwrap())
+            .collect::<Vec<_>>();
+        assert!(snowflakes
+            .iter()
+            .all(|it| layout.timestamp_of(it) == 100_000));
+        assert_eq!(
+            generator.try_assign_now(),
+            Err(TryAssignError::SequenceExhausted {
+                retry_after: Duration::from_millis(1)
+            })
+        );
 
-        let result = filling(sid, timestamp, identifier, sequence);
-        assert_eq!(result, expected);
+        generator.provider.set(100_001);
+        let snowflake = generator.try_assign_now().unwrap();
+        assert_eq!(layout.timestamp_of(&snowflake), 100_001);
+        assert_eq!(layout.sequence_of(&snowflake), 0);
+
+        generator.provider.set(99_998);
+        assert_eq!(
+            generator.try_assign_now(),
+            Err(TryAssignError::ClockBehind {
+                behind_by: Duration::from_millis(3)
+            })
+        );
+        generator.provider.set(100_002);
+        assert!(generator.try_assign_now().unwrap() > snowflake);
+    }
+
+    #[test]
+    fn test_fixed_provider() {
+        let generator = SnowflakeGenerator::default();
+        let provider =
+            provider::FixedProvider::at(UNIX_EPOCH + Duration::from_millis(1_704_067_200_000));
+        assert_eq!(provider, provider::FixedProvider::new(1_704_067_200_000));
+
+        let mut snowflakes = HashSet::new();
+        while let Ok(snowflake) = generator.try_assign_now(&provider) {
+            assert_eq!(snowflake.timestamp(), 1_704_067_200_000);
+            assert!(snowflakes.insert(snowflake));
+        }
+        assert_eq!(snowflakes.len(), 4096);
+        assert!(matches!(
+            generator.try_assign_now(&provider),
+            Err(TryAssignError::SequenceExhausted { .. })
+        ));
+    }
+
+    #[test]
+    fn test_assign_many() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = provider::MockProvider::new(100_000);
+
+        let first = generator.assign_sync(&provider);
+        let snowflakes = std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.assign_many_sync(&provider, 5000));
+            // 4095 left in this millisecond, so it has to wait for next one.
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!handle.is_finished());
+            provider.set(100_001);
+            handle.join().unwrap()
+        });
+
+        assert_eq!(snowflakes.len(), 5000);
+        assert!(first < snowflakes[0]);
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[0].sequence(), 1);
+        assert_eq!(snowflakes[4094].timestamp(), 100_000);
+        assert_eq!(snowflakes[4095].timestamp(), 100_001);
+        assert_eq!(snowflakes[4095].sequence(), 0);
+
+        assert!(generator.assign_many_sync(&provider, 0).is_empty());
+    }
+
+    #[test]
+    fn test_assign_at() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+
+        let snowflakes = (0..4096)
+            .map(|_| generator.assign_at(100_000).unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(snowflakes.len(), 4096);
+        assert!(snowflakes.iter().all(|it| it.timestamp() == 100_000));
+        assert_eq!(
+            generator.assign_at(100_000),
+            Err(SnowflakeError::SequenceExhausted { timestamp: 100_000 })
+        );
+
+        // Shares state with assign.
+        let live = generator.assign_sync(&provider::FixedProvider::new(100_001));
+        assert_eq!(generator.assign_at(100_001).unwrap().0, live.0 + 1);
+
+        assert_eq!(
+            generator.assign_at(99_999),
+            Err(SnowflakeError::TimestampInPast {
+                timestamp: 99_999,
+                high_water_mark: 100_001
+            })
+        );
+        let past = generator.assign_at_with(99_999, true).unwrap();
+        assert_eq!(past.timestamp(), 99_999);
+        assert_eq!(past.sequence(), 0);
+        assert!(generator.assign_at_with(99_999, true).unwrap() > past);
+        assert!(generator.assign_at_with(99_998, true).is_err());
+        assert_eq!(generator.assign_at(100_001).unwrap().sequence(), 2);
+    }
+
+    #[test]
+    fn test_backfill() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+
+        // Nothing generated yet, so no room below the high-water mark.
+        assert_eq!(
+            generator.backfill(at(100_000)..at(100_010), 1),
+            Err(SnowflakeError::BackfillRangeTooSmall {
+                count: 1,
+                capacity: 0
+            })
+        );
+
+        generator.assign_sync(&provider::FixedProvider::new(100_005));
+        assert_eq!(
+            generator.backfill(at(100_000)..at(100_010), 5 * 4096 + 1),
+            Err(SnowflakeError::BackfillRangeTooSmall {
+                count: 5 * 4096 + 1,
+                capacity: 5 * 4096
+            })
+        );
+
+        let snowflakes = generator.backfill(at(100_000)..at(100_010), 10).unwrap();
+        assert_eq!(snowflakes.len(), 10);
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+        assert_eq!(snowflakes[0].timestamp(), 100_000);
+        assert_eq!(snowflakes[1].timestamp(), 100_000);
+        assert_eq!(snowflakes[1].sequence(), 1);
+        assert_eq!(snowflakes[9].timestamp(), 100_004);
+
+        assert!(generator.backfill(at(100_000)..at(100_010), 1).is_err());
+
+        // The next backfill starts after the last one.
+        generator.assign_sync(&provider::FixedProvider::new(100_010));
+        let snowflakes = generator.backfill(at(100_000)..at(100_010), 4096).unwrap();
+        assert_eq!(snowflakes[0].timestamp(), 100_005);
+        assert_eq!(snowflakes[4095].timestamp(), 100_009);
+        assert!(generator
+            .backfill(at(100_000)..at(100_010), 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_backfill_concurrent() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let first = generator.assign_sync(&STD_PROVIDER);
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+        let (live, backfilled) = std::thread::scope(|scope| {
+            let live = scope.spawn(|| {
+                (0..10_000)
+                    .map(|_| generator.assign_sync(&STD_PROVIDER))
+                    .collect::<Vec<_>>()
+            });
+            let backfilled = scope.spawn(|| {
+                (0..4)
+                    .flat_map(|it| {
+                        generator
+                            .backfill(at(it * 10)..at(it * 10 + 10), 5000)
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            });
+            (live.join().unwrap(), backfilled.join().unwrap())
+        });
+
+        assert!(backfilled.iter().all(|it| *it < first));
+        assert!(live.iter().all(|it| *it > first));
+        let all = live.iter().chain(&backfilled).collect::<HashSet<_>>();
+        assert_eq!(all.len(), 30_000);
+    }
+
+    #[test]
+    fn test_reserve_block() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = provider::MockProvider::new(100_000);
+
+        assert_eq!(
+            generator.reserve_block_sync(&provider, 4097),
+            Err(SnowflakeError::InvalidBlockSize {
+                count: 4097,
+                capacity: 4096
+            })
+        );
+        assert!(generator.reserve_block_sync(&provider, 0).is_err());
+
+        let block = generator.reserve_block_sync(&provider, 4000).unwrap();
+        assert_eq!(block.len(), 4000);
+        assert_eq!(block.start().sequence(), 0);
+        assert!(block
+            .iter()
+            .all(|it| it.timestamp() == 100_000 && it.identifier() == 3));
+        assert_eq!(*block.get(3999).unwrap(), *block.start() + 3999);
+
+        // Only 96 left, waiting for next millisecond rather than splitting.
+        let block = std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.reserve_block_sync(&provider, 100));
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!handle.is_finished());
+            provider.set(100_001);
+            handle.join().unwrap()
+        })
+        .unwrap();
+        assert_eq!(block.start().timestamp(), 100_001);
+        assert_eq!(block.start().sequence(), 0);
+        assert_eq!(generator.assign_sync(&provider).sequence(), 100);
+    }
+
+    #[test]
+    fn test_randomize_sequence_start() {
+        let generator =
+            SnowflakeGenerator::with_cfg(test_cfg(3).with_randomize_sequence_start(true));
+        let provider = provider::MockProvider::new(100_000);
+
+        // Still all of the tick before waiting.
+        let snowflakes = (0..4096)
+            .map(|_| generator.try_assign_now(&provider).unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(snowflakes.len(), 4096);
+        assert!(snowflakes.iter().all(|it| it.timestamp() == 100_000));
+        assert!(matches!(
+            generator.try_assign_now(&provider),
+            Err(TryAssignError::SequenceExhausted { .. })
+        ));
+
+        let starts = (1..=64)
+            .map(|it| {
+                provider.set(100_000 + it);
+                generator.try_assign_now(&provider).unwrap().sequence()
+            })
+            .collect::<HashSet<_>>();
+        assert!(starts.len() > 1);
+        assert!(starts.iter().any(|it| *it != 0));
+
+        // Blocks never wrap around.
+        assert_eq!(
+            generator.reserve_block_sync(&provider, 2049),
+            Err(SnowflakeError::InvalidBlockSize {
+                count: 2049,
+                capacity: 2048
+            })
+        );
+        for it in 0..64 {
+            provider.set(200_000 + it);
+            let block = generator.reserve_block_sync(&provider, 2048).unwrap();
+            let last = block.get(2047).unwrap();
+            assert_eq!(last.timestamp(), 200_000 + it);
+            assert_eq!(last.sequence(), block.start().sequence() + 2047);
+        }
+    }
+
+    #[test]
+    fn test_random_sequence() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3).with_random_sequence(true));
+        let provider = provider::MockProvider::new(100_000);
+
+        let snowflakes = (0..4096)
+            .map(|_| generator.try_assign_now(&provider).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(snowflakes.iter().collect::<HashSet<_>>().len(), 4096);
+        assert!(snowflakes.iter().all(|it| it.timestamp() == 100_000));
+        assert!(matches!(
+            generator.try_assign_now(&provider),
+            Err(TryAssignError::SequenceExhausted { .. })
+        ));
+        // Far from counting up, a random order descends about half of the time.
+        let descents = snowflakes.windows(2).filter(|it| it[0] > it[1]).count();
+        assert!(descents > 1000, "{descents}");
+
+        // Next tick starts over, still after the last one.
+        provider.set(100_001);
+        let next = generator.assign_many_sync(&provider, 4096);
+        assert_eq!(next.iter().collect::<HashSet<_>>().len(), 4096);
+        assert!(next
+            .iter()
+            .all(|it| snowflakes.iter().all(|last| it > last)));
+
+        provider.set(100_002);
+        let block = generator.reserve_block_sync(&provider, 4096).unwrap();
+        assert_eq!(block.start().sequence(), 0);
+        assert_eq!(block.get(4095).unwrap().timestamp(), 100_002);
+
+        // Shards share the bitmap.
+        let generator = SnowflakeGenerator::sharded(4, test_cfg(3).with_random_sequence(true));
+        let snowflakes = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..5000)
+                            .map(|_| generator.assign_sync(&STD_PROVIDER))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .collect::<HashSet<_>>()
+        });
+        assert_eq!(snowflakes.len(), 20_000);
+
+        let cfg = test_cfg(3)
+            .with_layout(SnowflakeLayout::new(30, 2, 21).unwrap())
+            .with_random_sequence(true);
+        assert_eq!(
+            cfg.validate(),
+            Err(SnowflakeError::RandomSequenceTooWide {
+                sequence_bits: 21,
+                max: 20
+            })
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_reserve_block_multithread() {
+        let generator = Arc::new(SnowflakeGenerator::default());
+
+        let tasks = (0..100).map(|task| {
+            let generator = generator.clone();
+            tokio::spawn(async move {
+                let mut blocks = Vec::new();
+                for count in 1..=20 {
+                    blocks.push(
+                        generator
+                            .reserve_block(&STD_PROVIDER, (task + count) as u16 * 10)
+                            .await
+                            .unwrap(),
+                    );
+                    generator.assign(&STD_PROVIDER).await;
+                }
+                blocks
+            })
+        });
+
+        let blocks = futures::future::join_all(tasks)
+            .await
+            .into_iter()
+            .flat_map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        let total = blocks.iter().map(SnowflakeBlock::len).sum::<usize>();
+        assert_eq!(
+            blocks.into_iter().flatten().collect::<HashSet<_>>().len(),
+            total
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_stream() {
+        use futures::StreamExt;
+
+        let generator = SnowflakeGenerator::default();
+        let snowflakes = generator
+            .stream(&STD_PROVIDER)
+            .take(10_000)
+            .collect::<Vec<_>>()
+            .await;
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+
+        let persisted =
+            PersistedSnowflakeGenerator::new(Arc::new(generator), Arc::new(StdProvider));
+        let (first, second) = futures::join!(
+            persisted.stream().take(10_000).collect::<Vec<_>>(),
+            persisted.stream().take(10_000).collect::<Vec<_>>()
+        );
+        assert!(first.windows(2).all(|it| it[0] < it[1]));
+        assert!(second.windows(2).all(|it| it[0] < it[1]));
+
+        let all = snowflakes
+            .into_iter()
+            .chain(first)
+            .chain(second)
+            .collect::<HashSet<_>>();
+        assert_eq!(all.len(), 30_000);
+
+        // Dropped halfway doesn't wedge the generator.
+        let mut stream = Box::pin(persisted.stream());
+        stream.next().await.unwrap();
+        drop(stream);
+        persisted.assign().await;
+    }
+
+    #[test]
+    fn test_before_epoch() {
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(1).with_epoch(1000));
+
+        let snowflake = generator.assign_sync(&provider::FixedProvider::new(10));
+        assert_eq!(snowflake.timestamp(), 0);
     }
 
+    #[cfg(feature = "async")]
     #[tokio::test]
     async fn test_assign() {
         let generator = Arc::new(SnowflakeGenerator::default());
@@ -398,6 +4684,7 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "async")]
     #[tokio::test]
     async fn test_assign_multithread() {
         let generator = Arc::new(SnowflakeGenerator::default());
@@ -405,13 +4692,24 @@ mod tests {
         let mut handles = vec![];
         let id_set = Arc::new(RwLock::new(HashSet::new()));
 
-        for _ in 0..1000 {
+        for task in 0..1000 {
             let generator = Arc::clone(&generator);
             let id_set = Arc::clone(&id_set);
             let handle = tokio::spawn(async move {
-                for _ in 0..1000 {
-                    let id = generator.assign(&STD_PROVIDER).await;
-                    let mut set = id_set.write();
+                // Interleave single and batch assignment.
+                let ids = if task % 2 == 0 {
+                    let mut ids = Vec::with_capacity(1000);
+                    for _ in 0..1000 {
+                        ids.push(generator.assign(&STD_PROVIDER).await);
+                    }
+                    ids
+                } else {
+                    generator.assign_many(&STD_PROVIDER, 1000).await
+                };
+                assert!(ids.windows(2).all(|it| it[0] < it[1]));
+
+                let mut set = id_set.write();
+                for id in ids {
                     if set.contains(&id) {
                         panic!("Duplicate `Snowflake` generated!");
                     }
@@ -430,6 +4728,154 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_assign_sync_multithread() {
+        let generator = SnowflakeGenerator::default();
+
+        let ids = std::thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|task| {
+                    let generator = &generator;
+                    scope.spawn(move || {
+                        // Interleave single and batch assignment.
+                        let ids = if task % 2 == 0 {
+                            (0..20_000)
+                                .map(|_| generator.assign_sync(&STD_PROVIDER))
+                                .collect::<Vec<_>>()
+                        } else {
+                            generator.assign_many_sync(&STD_PROVIDER, 20_000)
+                        };
+                        assert!(ids.windows(2).all(|it| it[0] < it[1]));
+                        ids
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .collect::<HashSet<_>>()
+        });
+
+        assert_eq!(ids.len(), 8 * 20_000, "Duplicate `Snowflake` generated!");
+    }
+
+    #[test]
+    fn test_generator_padded() {
+        // Shards stored side by side never share the cache line of their state.
+        let shards = (0..4)
+            .map(|it| SnowflakeGenerator::with_cfg(test_cfg(it)))
+            .collect::<Vec<_>>();
+        for pair in shards.windows(2) {
+            let distance = (&*pair[1].shards[0] as *const AtomicU64 as usize)
+                .abs_diff(&*pair[0].shards[0] as *const AtomicU64 as usize);
+            assert!(distance >= CACHE_LINE);
+        }
+
+        for (identifier, generator) in shards.iter().enumerate() {
+            assert_eq!(
+                generator.assign_sync(&STD_PROVIDER).identifier(),
+                identifier as u64
+            );
+        }
+
+        let generator = SnowflakeGenerator::default();
+        assert_eq!(generator.config().layout, layouts::DEFAULT);
+        let snowflakes = generator.assign_many_sync(&STD_PROVIDER, 10_000);
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+    }
+
+    #[test]
+    fn test_sharded() {
+        let generator = SnowflakeGenerator::sharded(16, test_cfg(5));
+        let provider = provider::MockProvider::new(100_000);
+
+        for timestamp in 100_000..100_003 {
+            provider.set(timestamp);
+
+            let snowflakes = std::thread::scope(|scope| {
+                let handles = (0..32)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut snowflakes = Vec::new();
+                            loop {
+                                match generator.try_assign_now(&provider) {
+                                    Ok(it) => snowflakes.push(it),
+                                    Err(TryAssignError::Contended) => continue,
+                                    Err(TryAssignError::SequenceExhausted { .. }) => {
+                                        return snowflakes
+                                    }
+                                    Err(err) => panic!("{err}"),
+                                }
+                            }
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                handles
+                    .into_iter()
+                    .flat_map(|it| it.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+
+            assert_eq!(snowflakes.len(), 4096);
+            assert!(snowflakes.iter().all(|it| it.timestamp() == timestamp));
+            assert!(snowflakes.iter().all(|it| it.identifier() == 5));
+            assert_eq!(
+                snowflakes.iter().collect::<HashSet<_>>().len(),
+                4096,
+                "Duplicate `Snowflake` generated!"
+            );
+        }
+
+        assert_eq!(
+            generator.reserve_block_sync(&provider, 257).unwrap_err(),
+            SnowflakeError::InvalidBlockSize {
+                count: 257,
+                capacity: 256
+            }
+        );
+        assert_eq!(
+            SnowflakeGenerator::sharded(0, SnowflakeConfiguration::default())
+                .shards
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_assign_sync_contended() {
+        // Frozen clock, so every thread races on the same tick.
+        let layout = SnowflakeLayout::new(41, 0, 22).unwrap();
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(0).with_layout(layout));
+        let provider = provider::MockProvider::new(100_000);
+
+        let mut sequences = std::thread::scope(|scope| {
+            let handles = (0..16)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let ids = (0..10_000)
+                            .map(|_| generator.assign_sync(&provider))
+                            .collect::<Vec<_>>();
+                        assert!(ids.windows(2).all(|it| it[0] < it[1]));
+                        ids
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .inspect(|it| assert_eq!(layout.timestamp_of(it), 100_000))
+                .map(|it| layout.sequence_of(&it))
+                .collect::<Vec<_>>()
+        });
+
+        sequences.sort_unstable();
+        assert_eq!(sequences, (0..16 * 10_000).collect::<Vec<_>>());
+        assert_eq!(generator.clock_behind_count(), 0);
+    }
+
     #[test]
     fn test_persists() {
         let binding = Arc::new(SnowflakeGenerator::default());
@@ -442,6 +4888,47 @@ mod tests {
         assert_eq!(snowflakes.len(), 1000);
     }
 
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_assign_layout_multithread() {
+        for layout in [
+            SnowflakeLayout::new(44, 6, 13).unwrap(),
+            SnowflakeLayout::new(40, 16, 7).unwrap(),
+        ] {
+            // 40bit timestamp since UNIX epoch overflowed in 2004
+            let cfg = test_cfg(5).with_epoch(1577836800000).with_layout(layout);
+            let generator = Arc::new(SnowflakeGenerator::with_cfg(cfg));
+
+            let tasks = (0..100).map(|_| {
+                let generator = generator.clone();
+                tokio::spawn(async move {
+                    let mut snowflakes = Vec::with_capacity(1000);
+                    for _ in 0..1000 {
+                        snowflakes.push(generator.assign(&STD_PROVIDER).await);
+                    }
+                    snowflakes
+                })
+            });
+
+            let snowflakes = futures::future::join_all(tasks)
+                .await
+                .into_iter()
+                .flat_map(Result::unwrap)
+                .collect::<Vec<_>>();
+
+            let now = STD_PROVIDER.timestamp();
+            assert!(snowflakes.iter().all(|it| layout.identifier_of(it) == 5));
+            assert!(snowflakes
+                .iter()
+                .all(|it| (layout.timestamp_of(it) + 1577836800000).abs_diff(now) < 60_000));
+            assert_eq!(
+                snowflakes.into_iter().collect::<HashSet<_>>().len(),
+                100 * 1000
+            );
+        }
+    }
+
+    #[cfg(feature = "async")]
     #[tokio::test]
     async fn test_persists_multithread() {
         let binding = Arc::new(SnowflakeGenerator::default());
@@ -459,4 +4946,218 @@ mod tests {
 
         assert_eq!(snowflakes.len(), 1000);
     }
+
+    /// Fields of events and names of spans captured.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct CapturingLayer {
+        events: Arc<parking_lot::Mutex<Vec<std::collections::HashMap<String, String>>>>,
+        spans: Arc<parking_lot::Mutex<Vec<String>>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl<S> tracing_subscriber::Layer<S> for CapturingLayer
+    where
+        S: tracing::Subscriber,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _: &tracing::span::Id,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            self.spans.lock().push(attrs.metadata().name().to_string());
+        }
+
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Visitor(std::collections::HashMap<String, String>);
+
+            impl tracing::field::Visit for Visitor {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                    self.0
+                        .insert(field.name().to_string(), format!("{value:?}"));
+                }
+            }
+
+            let mut visitor = Visitor(Default::default());
+            event.record(&mut visitor);
+            self.events.lock().push(visitor.0);
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    fn capturing() -> (CapturingLayer, impl tracing::Subscriber) {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let layer = CapturingLayer::default();
+        (layer.clone(), tracing_subscriber::registry().with(layer))
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_tracing_clock_behind() {
+        let (layer, subscriber) = capturing();
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3));
+        let provider = provider::MockProvider::new(100_000);
+
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..100 {
+                generator.try_assign_now(&provider).unwrap();
+            }
+            assert!(layer.events.lock().is_empty());
+
+            provider.set(99_990);
+            assert!(generator.try_assign_now(&provider).is_err());
+        });
+
+        let events = layer.events.lock();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["identifier"], "3");
+        assert_eq!(events[0]["delta_ms"], "10");
+        assert_eq!(events[0]["rolled_back"], "true");
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_tracing_slow_wait() {
+        let (layer, subscriber) = capturing();
+        let generator = SnowflakeGenerator::with_cfg(test_cfg(3))
+            .with_slow_wait_threshold(Duration::from_millis(10));
+        let provider = provider::MockProvider::new(100_000);
+
+        std::thread::scope(|scope| {
+            let handle = scope.spawn(|| {
+                tracing::subscriber::with_default(subscriber, || {
+                    generator.assign_many_sync(&provider, 4097);
+                })
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            provider.set(100_001);
+            handle.join().unwrap()
+        });
+
+        let events = layer.events.lock();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["identifier"], "3");
+        assert!(events[0]["waited_ms"].parse::<u64>().unwrap() >= 10);
+        assert_eq!(*layer.spans.lock(), ["assign_many"]);
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_tracing_truncated() {
+        let (layer, subscriber) = capturing();
+
+        tracing::subscriber::with_default(subscriber, || {
+            SnowflakeGenerator::with_cfg(test_cfg(1023));
+            assert!(layer.events.lock().is_empty());
+            SnowflakeGenerator::with_cfg(test_cfg(5000));
+        });
+
+        let events = layer.events.lock();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["identifier"], "5000");
+        assert_eq!(events[0]["effective"], "904");
+    }
+}
+
+/// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom_tests`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::{sync::Arc, thread};
+
+    use super::*;
+
+    struct LoomTestProvider(AtomicU64);
+
+    impl TimeProvider for LoomTestProvider {
+        fn timestamp(&self) -> u64 {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    fn generator(sequence_bits: u32) -> SnowflakeGenerator {
+        SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration::with_identifier(0)
+                .with_layout(SnowflakeLayout::new(63 - sequence_bits, 0, sequence_bits).unwrap())
+                .with_timestamp_validation(TimestampValidation::Off),
+        )
+    }
+
+    fn assign(generator: &SnowflakeGenerator, provider: &LoomTestProvider) -> Option<u64> {
+        loop {
+            match generator.try_assign_now(provider) {
+                Ok(it) => return Some(it.0 as u64),
+                Err(TryAssignError::Contended) => thread::yield_now(),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_assign() {
+        loom::model(|| {
+            let generator = Arc::new(generator(2));
+            let provider = Arc::new(LoomTestProvider(AtomicU64::new(100)));
+
+            let handle = {
+                let (generator, provider) = (generator.clone(), provider.clone());
+                thread::spawn(move || {
+                    [assign(&generator, &provider), assign(&generator, &provider)]
+                })
+            };
+            let local = [assign(&generator, &provider), assign(&generator, &provider)];
+            let remote = handle.join().unwrap();
+
+            // 4 sequence numbers in one tick, just enough for everybody.
+            let mut all = local
+                .into_iter()
+                .chain(remote)
+                .map(Option::unwrap)
+                .collect::<Vec<_>>();
+            assert!(local[0] < local[1] && remote[0] < remote[1]);
+            all.sort_unstable();
+            all.dedup();
+            assert_eq!(all.len(), 4);
+        });
+    }
+
+    #[test]
+    fn test_concurrent_assign_rollover() {
+        loom::model(|| {
+            let generator = Arc::new(generator(1));
+            let provider = Arc::new(LoomTestProvider(AtomicU64::new(100)));
+            let first = assign(&generator, &provider).unwrap();
+
+            // Clock moves to the next tick while the other thread drains the current one.
+            let handle = {
+                let (generator, provider) = (generator.clone(), provider.clone());
+                thread::spawn(move || {
+                    provider.0.store(101, Ordering::Relaxed);
+                    assign(&generator, &provider)
+                })
+            };
+            let local = [assign(&generator, &provider), assign(&generator, &provider)];
+            let remote = handle.join().unwrap();
+
+            assert!(local.iter().flatten().all(|it| *it > first));
+            if let [Some(a), Some(b)] = local {
+                assert!(a < b);
+            }
+
+            let mut all = local
+                .into_iter()
+                .chain([remote, Some(first)])
+                .flatten()
+                .collect::<Vec<_>>();
+            let len = all.len();
+            all.sort_unstable();
+            all.dedup();
+            assert_eq!(all.len(), len, "Duplicate `Snowflake` generated!");
+        });
+    }
 }
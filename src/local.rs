@@ -0,0 +1,279 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    cell::RefCell,
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError, Weak,
+    },
+};
+
+#[cfg(any(feature = "async", feature = "sync"))]
+use crate::{Snowflake, TimeProvider};
+use crate::{SnowflakeConfiguration, SnowflakeError, SnowflakeGenerator};
+
+/// One [`SnowflakeGenerator`](SnowflakeGenerator) per thread, each with its own identifier from a range.
+///
+/// Threads never contend with each other, so it scales with threads for sync-heavy workloads.
+/// Generator of a thread is created on its first assignment, and goes back to the facade when the thread exits,
+/// so the next new thread picks it up with the same identifier. Once all of them are taken,
+/// assignment on new threads returns [`SnowflakeError::IdentifierRangeExhausted`](SnowflakeError::IdentifierRangeExhausted).
+///
+/// Dropping the facade releases all identifiers, so a new facade can use the same range again.
+///
+/// ```
+/// # use snowflake_ng::{provider::STD_PROVIDER, SnowflakeConfiguration, ThreadLocalSnowflake};
+/// let snowflakes = ThreadLocalSnowflake::new(SnowflakeConfiguration::default(), 0..8).unwrap();
+///
+/// std::thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| snowflakes.assign_sync(&STD_PROVIDER).unwrap());
+///     }
+/// });
+/// ```
+#[derive(Debug)]
+pub struct ThreadLocalSnowflake {
+    id: usize,
+    pool: Arc<Pool>,
+}
+
+#[derive(Debug)]
+struct Pool {
+    cfg: SnowflakeConfiguration,
+    identifiers: Range<u64>,
+    state: Mutex<PoolState>,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    /// Next identifier never handed out.
+    next: u64,
+    /// Generators left behind by exited threads.
+    idle: Vec<Arc<SnowflakeGenerator>>,
+}
+
+/// Generator borrowed by current thread, returned to its pool when the thread exits.
+struct Lease {
+    id: usize,
+    generator: Arc<SnowflakeGenerator>,
+    pool: Weak<Pool>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            pool.state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .idle
+                .push(self.generator.clone());
+        }
+    }
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static LEASES: RefCell<Vec<Lease>> = const { RefCell::new(Vec::new()) };
+}
+
+impl ThreadLocalSnowflake {
+    /// Constructing [`ThreadLocalSnowflake`](ThreadLocalSnowflake) with `base_cfg`, whose identifier is replaced by ones from `identifiers`.
+    ///
+    /// Returning [`SnowflakeError::IdentifierOutOfRange`](SnowflakeError::IdentifierOutOfRange) if `identifiers` doesn't fit in layout.
+    pub fn new(
+        base_cfg: SnowflakeConfiguration,
+        identifiers: Range<u64>,
+    ) -> Result<Self, SnowflakeError> {
+        if let Some(last) = identifiers.end.checked_sub(1) {
+            SnowflakeConfiguration {
+                identifier: last,
+                ..base_cfg.clone()
+            }
+            .validate()?;
+        }
+
+        Ok(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            pool: Arc::new(Pool {
+                cfg: base_cfg,
+                state: Mutex::new(PoolState {
+                    next: identifiers.start,
+                    idle: Vec::new(),
+                }),
+                identifiers,
+            }),
+        })
+    }
+
+    /// Assign a [`Snowflake`](Snowflake) with generator of current thread.
+    #[cfg(feature = "async")]
+    pub async fn assign<T>(&self, provider: &T) -> Result<Snowflake, SnowflakeError>
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        // Task may move to another thread while waiting, but sharing one generator is fine.
+        let generator = self.local()?;
+        Ok(generator.assign(provider).await)
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) with generator of current thread, but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn assign_sync<T>(&self, provider: &T) -> Result<Snowflake, SnowflakeError>
+    where
+        T: TimeProvider + Sync + Send,
+    {
+        Ok(self.local()?.assign_sync(provider))
+    }
+
+    /// Generator of current thread, taking one from the pool on the first call.
+    fn local(&self) -> Result<Arc<SnowflakeGenerator>, SnowflakeError> {
+        LEASES.with(|leases| {
+            let mut leases = leases.borrow_mut();
+            if let Some(lease) = leases.iter().find(|it| it.id == self.id) {
+                return Ok(lease.generator.clone());
+            }
+
+            // Forget leases of dropped facades.
+            leases.retain(|it| it.pool.strong_count() > 0);
+
+            let generator = self.pool.take()?;
+            leases.push(Lease {
+                id: self.id,
+                generator: generator.clone(),
+                pool: Arc::downgrade(&self.pool),
+            });
+            Ok(generator)
+        })
+    }
+}
+
+impl Pool {
+    fn take(&self) -> Result<Arc<SnowflakeGenerator>, SnowflakeError> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(generator) = state.idle.pop() {
+            return Ok(generator);
+        }
+
+        if state.next >= self.identifiers.end {
+            return Err(SnowflakeError::IdentifierRangeExhausted {
+                start: self.identifiers.start,
+                end: self.identifiers.end,
+            });
+        }
+
+        let identifier = state.next;
+        state.next += 1;
+        Ok(Arc::new(SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration {
+                identifier,
+                ..self.cfg.clone()
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Barrier};
+
+    use crate::provider::STD_PROVIDER;
+
+    use super::*;
+
+    #[test]
+    fn test_thread_local() {
+        let snowflakes =
+            ThreadLocalSnowflake::new(SnowflakeConfiguration::default(), 8..12).unwrap();
+        let barrier = Barrier::new(4);
+
+        let identifiers = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let ids = (0..1000)
+                            .map(|_| snowflakes.assign_sync(&STD_PROVIDER).unwrap())
+                            .collect::<Vec<_>>();
+                        assert!(ids.windows(2).all(|it| it[0] < it[1]));
+                        assert!(ids.iter().all(|it| it.identifier() == ids[0].identifier()));
+                        barrier.wait();
+                        ids[0].identifier()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|it| it.join().unwrap())
+                .collect::<HashSet<_>>()
+        });
+
+        assert_eq!(identifiers, (8..12).collect());
+    }
+
+    #[test]
+    fn test_thread_local_exhausted() {
+        let snowflakes =
+            ThreadLocalSnowflake::new(SnowflakeConfiguration::default(), 0..2).unwrap();
+        // Keep all threads alive, so none of their generators goes back.
+        let barrier = Barrier::new(3);
+
+        let results = std::thread::scope(|scope| {
+            let handles = (0..3)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let result = snowflakes.assign_sync(&STD_PROVIDER);
+                        barrier.wait();
+                        result
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|it| it.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(results.iter().filter(|it| it.is_ok()).count(), 2);
+        assert!(
+            results.contains(&Err(SnowflakeError::IdentifierRangeExhausted {
+                start: 0,
+                end: 2
+            }))
+        );
+
+        // Threads exited, so their generators are reused.
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| snowflakes.assign_sync(&STD_PROVIDER).unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn test_thread_local_drop() {
+        let cfg = SnowflakeConfiguration::default();
+        let snowflakes = ThreadLocalSnowflake::new(cfg.clone(), 0..1).unwrap();
+        assert_eq!(
+            snowflakes.assign_sync(&STD_PROVIDER).unwrap().identifier(),
+            0
+        );
+        drop(snowflakes);
+
+        // Current thread still holds the lease of dropped one.
+        let snowflakes = ThreadLocalSnowflake::new(cfg.clone(), 0..1).unwrap();
+        assert_eq!(
+            snowflakes.assign_sync(&STD_PROVIDER).unwrap().identifier(),
+            0
+        );
+
+        assert!(ThreadLocalSnowflake::new(cfg, 1000..1025).is_err());
+    }
+}
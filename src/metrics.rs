@@ -0,0 +1,185 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::Arc;
+
+use prometheus::{
+    core::{Collector, Desc, Describer},
+    proto::MetricFamily,
+    Counter, IntCounter, Opts, Registry,
+};
+
+use crate::SnowflakeGenerator;
+
+/// Prometheus collector publishing [`GeneratorStats`](crate::GeneratorStats) of a [`SnowflakeGenerator`](SnowflakeGenerator).
+///
+/// Statistics are read on scrape, so nothing is added to assignment.
+/// Every metric is labeled by `identifier` of the generator, so several generators can share one registry.
+///
+/// | Metric | Source |
+/// | --- | --- |
+/// | `snowflake_ids_total` | [`GeneratorStats::issued`](crate::GeneratorStats::issued) |
+/// | `snowflake_sequence_exhaustion_total` | [`GeneratorStats::exhausted_waits`](crate::GeneratorStats::exhausted_waits) |
+/// | `snowflake_clock_backwards_total` | [`GeneratorStats::clock_behind`](crate::GeneratorStats::clock_behind) |
+/// | `snowflake_wait_seconds_total` | [`GeneratorStats::waited`](crate::GeneratorStats::waited) |
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use prometheus::Registry;
+/// # use snowflake_ng::{SnowflakeGenerator, SnowflakeMetrics};
+/// let registry = Registry::new();
+/// let generator = Arc::new(SnowflakeGenerator::default());
+///
+/// SnowflakeMetrics::register(&registry, &generator).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SnowflakeMetrics<S> {
+    generator: Arc<SnowflakeGenerator<S>>,
+    opts: [Opts; 4],
+    descs: Vec<Desc>,
+}
+
+impl<S> SnowflakeMetrics<S>
+where
+    S: Send + Sync + 'static,
+{
+    /// Register metrics of `generator` to `registry`.
+    ///
+    /// Returning error of [`Registry::register`](Registry::register), e.g. a generator of the same identifier is registered already.
+    pub fn register(
+        registry: &Registry,
+        generator: &Arc<SnowflakeGenerator<S>>,
+    ) -> prometheus::Result<()> {
+        registry.register(Box::new(Self::new(generator.clone())?))
+    }
+
+    fn new(generator: Arc<SnowflakeGenerator<S>>) -> prometheus::Result<Self> {
+        let identifier = generator.identifier().to_string();
+        let opts = [
+            ("snowflake_ids_total", "Snowflakes issued."),
+            (
+                "snowflake_sequence_exhaustion_total",
+                "Waits for the next tick because sequence exhausted.",
+            ),
+            (
+                "snowflake_clock_backwards_total",
+                "Times the clock was observed behind the last timestamp.",
+            ),
+            ("snowflake_wait_seconds_total", "Time spent waiting."),
+        ]
+        .map(|(name, help)| Opts::new(name, help).const_label("identifier", identifier.clone()));
+        let descs = opts
+            .iter()
+            .map(Opts::describe)
+            .collect::<prometheus::Result<_>>()?;
+
+        Ok(Self {
+            generator,
+            opts,
+            descs,
+        })
+    }
+}
+
+impl<S> Collector for SnowflakeMetrics<S>
+where
+    S: Send + Sync,
+{
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let stats = self.generator.stats();
+        let [ids, exhaustion, clock_backwards, wait] = &self.opts;
+
+        // Options are validated when constructing, so these never fail.
+        let counter = |opts: &Opts, value: u64| {
+            let counter = IntCounter::with_opts(opts.clone()).expect("options are validated");
+            counter.inc_by(value);
+            counter.collect()
+        };
+        let seconds = Counter::with_opts(wait.clone()).expect("options are validated");
+        seconds.inc_by(stats.waited.as_secs_f64());
+
+        [
+            counter(ids, stats.issued),
+            counter(exhaustion, stats.exhausted_waits),
+            counter(clock_backwards, stats.clock_behind),
+            seconds.collect(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use prometheus::proto::MetricFamily;
+
+    use crate::{provider::MockProvider, SnowflakeConfiguration, TimestampValidation};
+
+    use super::*;
+
+    fn value(families: &[MetricFamily], name: &str, identifier: &str) -> f64 {
+        let family = families.iter().find(|it| it.name() == name).unwrap();
+        let metric = family
+            .get_metric()
+            .iter()
+            .find(|it| {
+                it.get_label()
+                    .iter()
+                    .any(|it| it.name() == "identifier" && it.value() == identifier)
+            })
+            .unwrap();
+        metric.get_counter().get_value()
+    }
+
+    #[test]
+    fn test_metrics() {
+        let registry = Registry::new();
+        let generator = Arc::new(SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration::with_identifier(3)
+                .with_timestamp_validation(TimestampValidation::Off),
+        ));
+        let idle = Arc::new(SnowflakeGenerator::with_cfg(
+            SnowflakeConfiguration::with_identifier(4),
+        ));
+        SnowflakeMetrics::register(&registry, &generator).unwrap();
+        SnowflakeMetrics::register(&registry, &idle).unwrap();
+        assert!(SnowflakeMetrics::register(&registry, &generator).is_err());
+
+        // Sequence exhausted in a frozen tick, until the clock moves.
+        let provider = MockProvider::new(100_000);
+        std::thread::scope(|scope| {
+            let handle = scope.spawn(|| generator.assign_many_sync(&provider, 4097));
+            std::thread::sleep(Duration::from_millis(50));
+            provider.set(100_001);
+            handle.join().unwrap()
+        });
+
+        let families = registry.gather();
+        assert_eq!(value(&families, "snowflake_ids_total", "3"), 4097.0);
+        assert!(value(&families, "snowflake_sequence_exhaustion_total", "3") > 0.0);
+        assert_eq!(
+            value(&families, "snowflake_clock_backwards_total", "3"),
+            0.0
+        );
+        assert!(value(&families, "snowflake_wait_seconds_total", "3") > 0.0);
+        assert_eq!(value(&families, "snowflake_ids_total", "4"), 0.0);
+
+        // Read on every scrape.
+        generator.assign_sync(&provider);
+        assert_eq!(
+            value(&registry.gather(), "snowflake_ids_total", "3"),
+            4098.0
+        );
+    }
+}
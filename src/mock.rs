@@ -0,0 +1,173 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+
+use crate::{Snowflake, SnowflakeSource};
+
+/// [`SnowflakeSource`](SnowflakeSource) handing out scripted [`Snowflake`](Snowflake) in order, for testing code branching on them.
+///
+/// Once the script is exhausted, assigning through [`SnowflakeSource`](SnowflakeSource) panics,
+/// and [`MockSnowflakeSource::try_assign`](MockSnowflakeSource::try_assign) returns [`ScriptExhausted`](ScriptExhausted).
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use snowflake_ng::{MockSnowflakeSource, SnowflakeSource};
+/// let mock = Arc::new(MockSnowflakeSource::sequential_from(100));
+/// let source: Arc<dyn SnowflakeSource> = mock.clone();
+///
+/// assert_eq!(*source.assign_sync(), 100);
+/// assert_eq!(*source.assign_sync(), 101);
+/// assert_eq!(mock.calls(), 2);
+/// ```
+#[derive(Debug)]
+pub struct MockSnowflakeSource {
+    script: Mutex<Script>,
+    calls: AtomicUsize,
+}
+
+#[derive(Debug)]
+enum Script {
+    Scripted(VecDeque<Snowflake>),
+    Sequential(i64),
+}
+
+impl MockSnowflakeSource {
+    /// Handing out `script` in order.
+    pub fn new(script: impl IntoIterator<Item = Snowflake>) -> Self {
+        Self::with_script(Script::Scripted(script.into_iter().collect()))
+    }
+
+    /// Handing out `start`, `start + 1`... as raw bits, never exhausted until `i64::MAX`.
+    pub fn sequential_from(start: i64) -> Self {
+        Self::with_script(Script::Sequential(start))
+    }
+
+    fn with_script(script: Script) -> Self {
+        Self {
+            script: Mutex::new(script),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Next scripted [`Snowflake`](Snowflake), or [`ScriptExhausted`](ScriptExhausted).
+    pub fn try_assign(&self) -> Result<Snowflake, ScriptExhausted> {
+        let calls = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut script = self.script.lock().unwrap_or_else(PoisonError::into_inner);
+        match &mut *script {
+            Script::Scripted(it) => it.pop_front(),
+            Script::Sequential(next) => {
+                let current = *next;
+                next.checked_add(1).map(|it| {
+                    *next = it;
+                    Snowflake(current)
+                })
+            }
+        }
+        .ok_or(ScriptExhausted { calls })
+    }
+
+    /// How many times assigned, including the exhausted ones.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// How many scripted [`Snowflake`](Snowflake) are left, `None` if sequential.
+    pub fn remaining(&self) -> Option<usize> {
+        match &*self.script.lock().unwrap_or_else(PoisonError::into_inner) {
+            Script::Scripted(it) => Some(it.len()),
+            Script::Sequential(_) => None,
+        }
+    }
+
+    fn assign_or_panic(&self) -> Snowflake {
+        self.try_assign().unwrap_or_else(|err| panic!("{err}"))
+    }
+}
+
+impl SnowflakeSource for MockSnowflakeSource {
+    #[cfg(feature = "async")]
+    fn assign(&self) -> BoxFuture<'_, Snowflake> {
+        Box::pin(std::future::ready(self.assign_or_panic()))
+    }
+
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake {
+        self.assign_or_panic()
+    }
+}
+
+/// Script of [`MockSnowflakeSource`](MockSnowflakeSource) is exhausted at the `calls`-th call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScriptExhausted {
+    pub calls: usize,
+}
+
+impl fmt::Display for ScriptExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "script of mock exhausted at call {}", self.calls)
+    }
+}
+
+impl Error for ScriptExhausted {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_script() {
+        let mock = Arc::new(MockSnowflakeSource::new([
+            Snowflake(3),
+            Snowflake(1),
+            Snowflake(2),
+        ]));
+        let source: Arc<dyn SnowflakeSource> = mock.clone();
+
+        assert_eq!(source.assign_sync(), Snowflake(3));
+        assert_eq!(source.assign_sync(), Snowflake(1));
+        assert_eq!(mock.remaining(), Some(1));
+        assert_eq!(mock.try_assign(), Ok(Snowflake(2)));
+        assert_eq!(mock.try_assign(), Err(ScriptExhausted { calls: 4 }));
+        assert_eq!(mock.calls(), 4);
+
+        let panicked =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| source.assign_sync()));
+        assert!(panicked.is_err());
+        assert_eq!(mock.calls(), 5);
+    }
+
+    #[test]
+    fn test_mock_sequential() {
+        let mock = MockSnowflakeSource::sequential_from(i64::MAX - 2);
+        assert_eq!(mock.remaining(), None);
+        assert_eq!(mock.assign_sync(), Snowflake(i64::MAX - 2));
+        assert_eq!(mock.assign_sync(), Snowflake(i64::MAX - 1));
+        assert_eq!(mock.try_assign(), Err(ScriptExhausted { calls: 3 }));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_mock_async() {
+        let source: Arc<dyn SnowflakeSource> = Arc::new(MockSnowflakeSource::sequential_from(7));
+        assert_eq!(source.assign().await, Snowflake(7));
+        assert_eq!(source.assign_sync(), Snowflake(8));
+    }
+}
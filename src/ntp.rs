@@ -0,0 +1,406 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{provider::StdProvider, TimeProvider};
+
+/// Seconds from NTP epoch (1900) to UNIX epoch.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// One request and reply with an NTP server, in microseconds since UNIX epoch.
+///
+/// `originate` and `destination` are read from the local clock, the others from the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SntpExchange {
+    /// Local time sending the request.
+    pub originate: u64,
+    /// Server time receiving the request.
+    pub receive: u64,
+    /// Server time sending the reply.
+    pub transmit: u64,
+    /// Local time receiving the reply.
+    pub destination: u64,
+}
+
+impl SntpExchange {
+    /// How far the server is ahead of the local clock.
+    pub fn offset(&self) -> i64 {
+        let there = self.receive as i64 - self.originate as i64;
+        let back = self.transmit as i64 - self.destination as i64;
+        (there + back) / 2
+    }
+
+    /// Time on the network, not counting the server processing it.
+    pub fn round_trip(&self) -> Duration {
+        let total = self.destination.saturating_sub(self.originate);
+        let processing = self.transmit.saturating_sub(self.receive);
+        Duration::from_micros(total.saturating_sub(processing))
+    }
+}
+
+/// Exchanging with an NTP server for [`NtpProvider`](NtpProvider), see [`UdpSntpClient`](UdpSntpClient).
+pub trait SntpClient: Send + 'static {
+    /// Exchange once, blocking.
+    fn exchange(&mut self) -> io::Result<SntpExchange>;
+}
+
+/// [`SntpClient`](SntpClient) of SNTPv4 over UDP.
+#[derive(Debug)]
+pub struct UdpSntpClient {
+    socket: UdpSocket,
+}
+
+impl UdpSntpClient {
+    /// Exchanging with `server`, port 123 unless given, waiting up to `timeout` for each reply.
+    pub fn connect(server: &str, timeout: Duration) -> io::Result<Self> {
+        let address = match server.to_socket_addrs() {
+            Ok(mut it) => it.next(),
+            Err(_) => (server, 123).to_socket_addrs()?.next(),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address of NTP server"))?;
+
+        let socket = UdpSocket::bind(if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        socket.connect(address)?;
+        socket.set_read_timeout(Some(timeout))?;
+        Ok(Self { socket })
+    }
+}
+
+impl SntpClient for UdpSntpClient {
+    fn exchange(&mut self) -> io::Result<SntpExchange> {
+        let mut request = [0u8; 48];
+        // Leap indicator 0, version 4, mode 3 (client).
+        request[0] = 0b00_100_011;
+        let originate = StdProvider.timestamp_micros();
+        request[40..].copy_from_slice(&to_ntp(originate).to_be_bytes());
+        self.socket.send(&request)?;
+
+        let mut reply = [0u8; 48];
+        loop {
+            let read = self.socket.recv(&mut reply)?;
+            let destination = StdProvider.timestamp_micros();
+            // Stray replies of earlier requests timed out.
+            if read < 48 || reply[24..32] != request[40..] {
+                continue;
+            }
+            if reply[0] & 0b111 != 4 || reply[1] == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a valid reply of NTP server",
+                ));
+            }
+
+            let at = |offset: usize| {
+                from_ntp(u64::from_be_bytes(
+                    reply[offset..offset + 8].try_into().unwrap(),
+                ))
+            };
+            return Ok(SntpExchange {
+                originate,
+                receive: at(32),
+                transmit: at(40),
+                destination,
+            });
+        }
+    }
+}
+
+/// Microseconds since UNIX epoch into NTP timestamp, rounding up so it's the same once converted back.
+fn to_ntp(micros: u64) -> u64 {
+    let seconds = micros / 1_000_000 + NTP_UNIX_OFFSET;
+    let fraction = ((micros % 1_000_000) << 32).div_ceil(1_000_000);
+    (seconds << 32) | fraction
+}
+
+/// NTP timestamp into microseconds since UNIX epoch.
+fn from_ntp(timestamp: u64) -> u64 {
+    let seconds = (timestamp >> 32).saturating_sub(NTP_UNIX_OFFSET);
+    let micros = ((timestamp & 0xFFFF_FFFF) * 1_000_000) >> 32;
+    seconds * 1_000_000 + micros
+}
+
+/// [`TimeProvider`](TimeProvider) of time of an NTP server, for hosts whose clock isn't trusted.
+///
+/// The time synced is carried on with [`Instant`](Instant) elapsed since, in a thread resyncing every `resync_interval`
+/// until dropped. Failing to sync keeps the last one, or the local clock if it never did, reported by `tracing` warnings with feature `tracing`.
+///
+/// A resync moving time backwards looks like the clock going backwards to generators, see [`MonotonicProvider`](crate::provider::MonotonicProvider).
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use snowflake_ng::{provider::NtpProvider, SnowflakeGenerator};
+/// let provider = NtpProvider::spawn("pool.ntp.org", Duration::from_secs(600)).unwrap();
+/// let snowflake = SnowflakeGenerator::default().assign_sync(&provider);
+/// println!("{:?} off by {:?}", provider.last_sync(), provider.estimated_error());
+/// ```
+#[derive(Debug)]
+pub struct NtpProvider<C = UdpSntpClient> {
+    shared: Arc<Shared<C>>,
+    /// Dropping it stops the thread.
+    _stop: mpsc::Sender<()>,
+}
+
+#[derive(Debug)]
+struct Shared<C> {
+    client: Mutex<C>,
+    start: Instant,
+    /// Microseconds since UNIX epoch at `start`.
+    anchor: AtomicU64,
+    synced: Mutex<Option<Synced>>,
+    failures: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Synced {
+    at: Instant,
+    error: Duration,
+}
+
+impl NtpProvider {
+    /// Syncing with `server` now and every `resync_interval` after that, see [`UdpSntpClient::connect`](UdpSntpClient::connect).
+    ///
+    /// Only an unknown server is an error, the first sync failing is not.
+    pub fn spawn(server: &str, resync_interval: Duration) -> io::Result<Self> {
+        Ok(Self::spawn_with(
+            UdpSntpClient::connect(server, Duration::from_secs(2))?,
+            resync_interval,
+        ))
+    }
+}
+
+impl<C> NtpProvider<C>
+where
+    C: SntpClient,
+{
+    /// Same as [`NtpProvider::spawn`](NtpProvider::spawn), but exchanging with `client`.
+    pub fn spawn_with(client: C, resync_interval: Duration) -> Self {
+        let shared = Arc::new(Shared {
+            client: Mutex::new(client),
+            start: Instant::now(),
+            anchor: AtomicU64::new(StdProvider.timestamp_micros()),
+            synced: Mutex::new(None),
+            failures: AtomicU64::new(0),
+        });
+        let _ = shared.resync();
+
+        let (stop, stopped) = mpsc::channel();
+        let weak = Arc::downgrade(&shared);
+        thread::Builder::new()
+            .name("snowflake-ng-ntp".to_string())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(resync_interval)
+                {
+                    let Some(shared) = weak.upgrade() else {
+                        break;
+                    };
+                    let _ = shared.resync();
+                }
+            })
+            .expect("failed to spawn thread syncing with NTP server");
+
+        Self {
+            shared,
+            _stop: stop,
+        }
+    }
+
+    /// Sync now, keeping the last one on error.
+    pub fn resync(&self) -> io::Result<()> {
+        self.shared.resync()
+    }
+
+    /// When synced last, or `None` if never.
+    pub fn last_sync(&self) -> Option<Instant> {
+        self.shared.synced().map(|it| it.at)
+    }
+
+    /// Up to how far off it was synced last, which is half the round trip, or `None` if never.
+    ///
+    /// Drift of the local oscillator since is not counted.
+    pub fn estimated_error(&self) -> Option<Duration> {
+        self.shared.synced().map(|it| it.error)
+    }
+
+    /// How many syncs failed so far.
+    pub fn failures(&self) -> u64 {
+        self.shared.failures.load(Ordering::Relaxed)
+    }
+}
+
+impl<C> Shared<C>
+where
+    C: SntpClient,
+{
+    fn resync(&self) -> io::Result<()> {
+        let exchanged = self
+            .client
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .exchange();
+        let elapsed = self.start.elapsed().as_micros() as u64;
+        let exchange = match exchanged {
+            Ok(it) => it,
+            Err(err) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %err, "failed to sync with NTP server, keeping the last one");
+                return Err(err);
+            }
+        };
+
+        let now = exchange
+            .destination
+            .saturating_add_signed(exchange.offset());
+        self.anchor
+            .store(now.saturating_sub(elapsed), Ordering::Relaxed);
+        *self.synced.lock().unwrap_or_else(PoisonError::into_inner) = Some(Synced {
+            at: Instant::now(),
+            error: exchange.round_trip() / 2,
+        });
+        Ok(())
+    }
+
+    fn synced(&self) -> Option<Synced> {
+        *self.synced.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<C> TimeProvider for NtpProvider<C> {
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        self.shared.anchor.load(Ordering::Relaxed) + self.shared.start.elapsed().as_micros() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Replying as scripted, server ahead by `offset` microseconds.
+    struct ScriptedTestClient(Arc<Mutex<VecDeque<io::Result<i64>>>>);
+
+    impl SntpClient for ScriptedTestClient {
+        fn exchange(&mut self) -> io::Result<SntpExchange> {
+            let offset = self.0.lock().unwrap().pop_front().unwrap()?;
+            let originate = StdProvider.timestamp_micros();
+            // 10ms each way, 1ms of processing.
+            let receive = originate.saturating_add_signed(offset) + 10_000;
+            Ok(SntpExchange {
+                originate,
+                receive,
+                transmit: receive + 1_000,
+                destination: originate + 21_000,
+            })
+        }
+    }
+
+    fn scripted(
+        script: impl IntoIterator<Item = io::Result<i64>>,
+    ) -> NtpProvider<ScriptedTestClient> {
+        NtpProvider::spawn_with(
+            ScriptedTestClient(Arc::new(Mutex::new(script.into_iter().collect()))),
+            Duration::from_secs(3600),
+        )
+    }
+
+    fn ahead_by(provider: &impl TimeProvider) -> i64 {
+        provider.timestamp() as i64 - StdProvider.timestamp() as i64
+    }
+
+    #[test]
+    fn test_ntp_exchange() {
+        let exchange = SntpExchange {
+            originate: 1_000_000,
+            receive: 6_010_000,
+            transmit: 6_011_000,
+            destination: 1_021_000,
+        };
+        assert_eq!(exchange.offset(), 5_000_000);
+        assert_eq!(exchange.round_trip(), Duration::from_millis(20));
+
+        let micros = 1_704_067_200_123_456;
+        assert_eq!(from_ntp(to_ntp(micros)), micros);
+        assert_eq!(to_ntp(0) >> 32, NTP_UNIX_OFFSET);
+    }
+
+    #[test]
+    fn test_ntp_provider() {
+        let provider = scripted([
+            Ok(5_000_000),
+            Err(io::ErrorKind::TimedOut.into()),
+            Ok(-3_000_000),
+        ]);
+        // Server time at the destination, 21ms after the request.
+        assert!((5_016..5_026).contains(&ahead_by(&provider)));
+        let synced = provider.last_sync().unwrap();
+        assert_eq!(provider.estimated_error(), Some(Duration::from_millis(10)));
+
+        // Failure keeps the last one.
+        assert!(provider.resync().is_err());
+        assert_eq!(provider.failures(), 1);
+        assert_eq!(provider.last_sync(), Some(synced));
+        assert!((5_016..5_026).contains(&ahead_by(&provider)));
+
+        provider.resync().unwrap();
+        assert!((-2_984..-2_974).contains(&ahead_by(&provider)));
+        assert!(provider.last_sync().unwrap() > synced);
+    }
+
+    #[test]
+    fn test_ntp_never_synced() {
+        let provider = scripted([Err(io::ErrorKind::TimedOut.into())]);
+        assert_eq!(provider.last_sync(), None);
+        assert_eq!(provider.estimated_error(), None);
+        assert_eq!(provider.failures(), 1);
+        assert!(ahead_by(&provider).abs() <= 5);
+    }
+
+    #[test]
+    fn test_udp_sntp_client() {
+        // Server ahead by 1 second.
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut request = [0u8; 48];
+            let (_, peer) = server.recv_from(&mut request).unwrap();
+            let now = to_ntp(StdProvider.timestamp_micros() + 1_000_000).to_be_bytes();
+            let mut reply = [0u8; 48];
+            reply[0] = 0b00_100_100;
+            reply[1] = 1;
+            reply[24..32].copy_from_slice(&request[40..]);
+            reply[32..40].copy_from_slice(&now);
+            reply[40..48].copy_from_slice(&now);
+            server.send_to(&reply, peer).unwrap();
+        });
+
+        let mut client =
+            UdpSntpClient::connect(&address.to_string(), Duration::from_secs(2)).unwrap();
+        let exchange = client.exchange().unwrap();
+        assert!((995_000..1_005_000).contains(&exchange.offset()));
+        assert!(exchange.round_trip() < Duration::from_millis(100));
+    }
+}
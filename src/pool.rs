@@ -0,0 +1,346 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+#[cfg(any(feature = "async", feature = "sync"))]
+use crate::ASSIGN_MANY_CHUNK;
+use crate::{
+    Claim, Reservation, Snowflake, SnowflakeConfiguration, SnowflakeError, SnowflakeGenerator,
+    Step, TimeProvider, TryAssignError,
+};
+
+/// Several [`SnowflakeGenerator`](SnowflakeGenerator) with distinct identifiers, for more than one identifier can assign in a tick.
+///
+/// Assignment goes round robin, and skips members whose sequence is exhausted,
+/// so `n` members hold `n` times the sequence numbers of one tick. It only waits once all of them are exhausted.
+///
+/// [`Snowflake`](Snowflake) of one member are increasing, but not across members.
+///
+/// ```
+/// # use snowflake_ng::{provider::StdProvider, GeneratorPool};
+/// let pool = GeneratorPool::new(vec![1, 2, 3, 4], StdProvider).unwrap();
+///
+/// let snowflakes = pool.assign_many_sync(16384);
+/// ```
+#[derive(Debug)]
+pub struct GeneratorPool<T> {
+    members: Box<[Member]>,
+    provider: T,
+    cursor: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Member {
+    generator: SnowflakeGenerator,
+    assigned: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+/// Statistics of one member of [`GeneratorPool`](GeneratorPool).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PoolMemberStats {
+    /// Effective identifier of this member.
+    pub identifier: u64,
+    /// How many [`Snowflake`](Snowflake) assigned.
+    pub assigned: u64,
+    /// How many times it was skipped because sequence exhausted.
+    pub exhausted: u64,
+    /// See [`SnowflakeGenerator::clock_behind_count`](SnowflakeGenerator::clock_behind_count).
+    pub clock_behind: u64,
+}
+
+impl<T> GeneratorPool<T>
+where
+    T: TimeProvider + Send + Sync,
+{
+    /// Constructing [`GeneratorPool`](GeneratorPool) of default configuration, with one member per identifier.
+    pub fn new(identifiers: Vec<u64>, provider: T) -> Result<Self, SnowflakeError> {
+        Self::with_cfg(SnowflakeConfiguration::default(), identifiers, provider)
+    }
+
+    /// Constructing [`GeneratorPool`](GeneratorPool) with `base_cfg`, whose identifier is replaced by each of `identifiers`.
+    ///
+    /// Returning [`SnowflakeError::EmptyPool`](SnowflakeError::EmptyPool) without any identifier,
+    /// and [`SnowflakeError::DuplicateIdentifier`](SnowflakeError::DuplicateIdentifier) if two of them are the same.
+    pub fn with_cfg(
+        base_cfg: SnowflakeConfiguration,
+        identifiers: Vec<u64>,
+        provider: T,
+    ) -> Result<Self, SnowflakeError> {
+        if identifiers.is_empty() {
+            return Err(SnowflakeError::EmptyPool);
+        }
+
+        let mut seen = HashSet::with_capacity(identifiers.len());
+        let members = identifiers
+            .into_iter()
+            .map(|identifier| {
+                if !seen.insert(identifier) {
+                    return Err(SnowflakeError::DuplicateIdentifier { identifier });
+                }
+
+                Ok(Member {
+                    generator: SnowflakeGenerator::try_with_cfg(SnowflakeConfiguration {
+                        identifier,
+                        ..base_cfg.clone()
+                    })?,
+                    assigned: AtomicU64::new(0),
+                    exhausted: AtomicU64::new(0),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            members,
+            provider,
+            cursor: AtomicUsize::new(0),
+        })
+    }
+
+    /// How many members in this pool.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Whether this pool is empty, it never is.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Statistics of every member, in the order of identifiers given.
+    pub fn stats(&self) -> Vec<PoolMemberStats> {
+        self.members
+            .iter()
+            .map(|it| PoolMemberStats {
+                identifier: it.generator.identifier(),
+                assigned: it.assigned.load(Ordering::Relaxed),
+                exhausted: it.exhausted.load(Ordering::Relaxed),
+                clock_behind: it.generator.clock_behind_count(),
+            })
+            .collect()
+    }
+
+    /// Assign a [`Snowflake`](Snowflake), waiting only if all members are exhausted.
+    #[cfg(feature = "async")]
+    pub async fn assign(&self) -> Snowflake {
+        let (member, snowflake) = match self.claim(1) {
+            Ok((member, it)) => (
+                member,
+                Snowflake(it.bits(member.generator.config(), 0) as i64),
+            ),
+            Err(member) => (member, member.generator.assign(&self.provider).await),
+        };
+        member.assigned.fetch_add(1, Ordering::Relaxed);
+        snowflake
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn assign_sync(&self) -> Snowflake {
+        let (member, snowflake) = match self.claim(1) {
+            Ok((member, it)) => (
+                member,
+                Snowflake(it.bits(member.generator.config(), 0) as i64),
+            ),
+            Err(member) => (member, member.generator.assign_sync(&self.provider)),
+        };
+        member.assigned.fetch_add(1, Ordering::Relaxed);
+        snowflake
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) without waiting, see [`SnowflakeGenerator::try_assign_now`](SnowflakeGenerator::try_assign_now).
+    ///
+    /// Error is the one of the last member tried.
+    pub fn try_assign_now(&self) -> Result<Snowflake, TryAssignError> {
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let mut error = TryAssignError::Contended;
+
+        for offset in 0..self.members.len() {
+            let member = &self.members[(start + offset) % self.members.len()];
+            match member.generator.try_assign_now(&self.provider) {
+                Ok(it) => {
+                    member.assigned.fetch_add(1, Ordering::Relaxed);
+                    return Ok(it);
+                }
+                Err(err) => {
+                    if matches!(err, TryAssignError::SequenceExhausted { .. }) {
+                        member.exhausted.fetch_add(1, Ordering::Relaxed);
+                    }
+                    error = err;
+                }
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Assign `n` [`Snowflake`](Snowflake), split across members in chunks.
+    #[cfg(feature = "async")]
+    pub async fn assign_many(&self, n: usize) -> Vec<Snowflake> {
+        let mut snowflakes = Vec::with_capacity(n);
+
+        while snowflakes.len() < n {
+            let want = (n - snowflakes.len()).min(ASSIGN_MANY_CHUNK) as u64;
+            let (member, reservation) = match self.claim(want) {
+                Ok(it) => it,
+                Err(member) => (
+                    member,
+                    member
+                        .generator
+                        .next_infallible(&self.provider, Claim::AtMost(want))
+                        .await,
+                ),
+            };
+            extend(&mut snowflakes, member, &reservation);
+        }
+
+        snowflakes
+    }
+
+    /// Assign `n` [`Snowflake`](Snowflake) but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn assign_many_sync(&self, n: usize) -> Vec<Snowflake> {
+        let mut snowflakes = Vec::with_capacity(n);
+
+        while snowflakes.len() < n {
+            let want = (n - snowflakes.len()).min(ASSIGN_MANY_CHUNK) as u64;
+            let (member, reservation) = match self.claim(want) {
+                Ok(it) => it,
+                Err(member) => (
+                    member,
+                    member
+                        .generator
+                        .next_infallible_sync(&self.provider, Claim::AtMost(want)),
+                ),
+            };
+            extend(&mut snowflakes, member, &reservation);
+        }
+
+        snowflakes
+    }
+
+    /// Claim up to `want` from the next member able to assign right now,
+    /// or returning the first member tried for waiting if none of them can.
+    #[cfg_attr(not(any(feature = "async", feature = "sync")), allow(dead_code))]
+    fn claim(&self, want: u64) -> Result<(&Member, Reservation), &Member> {
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+
+        for offset in 0..self.members.len() {
+            let member = &self.members[(start + offset) % self.members.len()];
+            loop {
+                match member
+                    .generator
+                    .step(&self.provider, false, Claim::AtMost(want))
+                {
+                    Ok(Step::Assigned(it)) => return Ok((member, it)),
+                    Ok(Step::Contended) => continue,
+                    Ok(Step::Exhausted { .. }) => {
+                        member.exhausted.fetch_add(1, Ordering::Relaxed);
+                        break;
+                    }
+                    Ok(Step::Behind { .. }) => break,
+                    // Invalid timestamp, which panics when waiting on it.
+                    Err(_) => break,
+                }
+            }
+        }
+
+        Err(&self.members[start % self.members.len()])
+    }
+}
+
+#[cfg_attr(not(any(feature = "async", feature = "sync")), allow(dead_code))]
+fn extend(snowflakes: &mut Vec<Snowflake>, member: &Member, reservation: &Reservation) {
+    let cfg = member.generator.config();
+    member
+        .assigned
+        .fetch_add(reservation.count, Ordering::Relaxed);
+    snowflakes.extend((0..reservation.count).map(|it| Snowflake(reservation.bits(cfg, it) as i64)));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{provider::FixedProvider, TimestampValidation};
+
+    use super::*;
+
+    /// Trusting [`FixedProvider`](FixedProvider) of timestamps far before 2024.
+    fn frozen(identifiers: Vec<u64>) -> GeneratorPool<FixedProvider> {
+        GeneratorPool::with_cfg(
+            SnowflakeConfiguration::default().with_timestamp_validation(TimestampValidation::Off),
+            identifiers,
+            FixedProvider::new(100_000),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pool() {
+        let identifiers = vec![3, 1, 4, 2];
+        let pool = frozen(identifiers.clone());
+
+        let mut snowflakes = Vec::new();
+        while let Ok(it) = pool.try_assign_now() {
+            snowflakes.push(it);
+        }
+
+        assert_eq!(snowflakes.len(), 4096 * 4);
+        assert_eq!(
+            snowflakes.iter().collect::<HashSet<_>>().len(),
+            4096 * 4,
+            "Duplicate `Snowflake` generated!"
+        );
+        assert!(snowflakes.iter().all(|it| it.timestamp() == 100_000));
+        assert_eq!(
+            snowflakes
+                .iter()
+                .map(|it| it.identifier())
+                .collect::<HashSet<_>>(),
+            identifiers.iter().copied().collect()
+        );
+
+        let stats = pool.stats();
+        assert_eq!(
+            stats.iter().map(|it| it.identifier).collect::<Vec<_>>(),
+            identifiers
+        );
+        assert!(stats.iter().all(|it| it.assigned == 4096));
+        assert!(stats.iter().all(|it| it.exhausted > 0));
+    }
+
+    #[test]
+    fn test_pool_assign_many() {
+        let pool = frozen(vec![7, 8, 9]);
+
+        // Frozen clock, so it would never return if any member waited.
+        let snowflakes = pool.assign_many_sync(4096 * 3);
+        assert_eq!(
+            snowflakes.iter().collect::<HashSet<_>>().len(),
+            4096 * 3,
+            "Duplicate `Snowflake` generated!"
+        );
+        assert!(pool.stats().iter().all(|it| it.assigned == 4096));
+        assert!(pool.try_assign_now().is_err());
+    }
+
+    #[test]
+    fn test_pool_invalid() {
+        assert_eq!(
+            GeneratorPool::new(vec![], FixedProvider::new(0)).unwrap_err(),
+            SnowflakeError::EmptyPool
+        );
+        assert_eq!(
+            GeneratorPool::new(vec![1, 2, 1], FixedProvider::new(0)).unwrap_err(),
+            SnowflakeError::DuplicateIdentifier { identifier: 1 }
+        );
+        assert!(GeneratorPool::new(vec![1024], FixedProvider::new(0)).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::{Mutex, PoisonError};
+
+use rand::Rng;
+
+use crate::{Plan, Reservation, SnowflakeError, SnowflakeGenerator};
+
+/// Widest sequence [`SnowflakeConfiguration::random_sequence`](crate::SnowflakeConfiguration::random_sequence) supports,
+/// the bitmap takes 128 KiB then.
+pub(crate) const MAX_RANDOM_SEQUENCE_BITS: u32 = 20;
+
+/// Random picks of one sequence number before scanning for a free one.
+const PICK_ATTEMPTS: usize = 8;
+
+/// Sequence numbers handed out in the latest tick, for picking them at random without collision.
+#[derive(Debug)]
+pub(crate) struct UsedSequences(Mutex<Bitmap>);
+
+#[derive(Debug)]
+struct Bitmap {
+    tick: u64,
+    words: Vec<u64>,
+}
+
+/// Why nothing is picked by [`UsedSequences::pick`](UsedSequences::pick).
+#[derive(Debug)]
+enum Unpicked {
+    /// Tick is already left behind, with the bitmap of the later one.
+    Stale { tick: u64 },
+    /// No free run long enough in the tick.
+    Full,
+}
+
+impl UsedSequences {
+    pub(crate) fn new(sequence_bits: u32) -> Self {
+        assert!(
+            sequence_bits <= MAX_RANDOM_SEQUENCE_BITS,
+            "random sequence supports at most {MAX_RANDOM_SEQUENCE_BITS} sequence bits, got {sequence_bits}"
+        );
+
+        let words = (1usize << sequence_bits).div_ceil(64);
+        Self(Mutex::new(Bitmap {
+            tick: 0,
+            words: vec![0; words],
+        }))
+    }
+
+    /// Mark `count` consecutive unused sequence numbers of `tick` (out of `per_tick`) at random, returning the first one.
+    ///
+    /// Moving to a later tick clears the bitmap, so the earlier ones can't be picked anymore.
+    fn pick(&self, tick: u64, count: u64, per_tick: u64) -> Result<u64, Unpicked> {
+        let mut bitmap = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        match bitmap.tick.cmp(&tick) {
+            std::cmp::Ordering::Less => {
+                bitmap.tick = tick;
+                bitmap.words.fill(0);
+            }
+            std::cmp::Ordering::Equal => {}
+            std::cmp::Ordering::Greater => return Err(Unpicked::Stale { tick: bitmap.tick }),
+        }
+
+        let mut rng = rand::thread_rng();
+        let from = rng.gen_range(0..per_tick);
+        // Retry on collision, it's rare until the tick is nearly used up.
+        let picked = match count {
+            1 => (0..PICK_ATTEMPTS)
+                .map(|_| rng.gen_range(0..per_tick))
+                .find(|it| !bitmap.used(*it)),
+            _ => None,
+        };
+        let first = picked
+            .or_else(|| bitmap.run(from, per_tick, count))
+            .or_else(|| bitmap.run(0, (from + count - 1).min(per_tick), count))
+            .ok_or(Unpicked::Full)?;
+
+        for it in first..first + count {
+            bitmap.words[it as usize / 64] |= 1 << (it % 64);
+        }
+        Ok(first)
+    }
+}
+
+impl Bitmap {
+    fn used(&self, sequence: u64) -> bool {
+        self.words[sequence as usize / 64] & (1 << (sequence % 64)) != 0
+    }
+
+    /// First run of `count` unused sequence numbers in `from..to`.
+    fn run(&self, from: u64, to: u64, count: u64) -> Option<u64> {
+        let mut len = 0;
+        for it in from..to {
+            len = if self.used(it) { 0 } else { len + 1 };
+            if len == count {
+                return Some(it + 1 - count);
+            }
+        }
+
+        None
+    }
+}
+
+impl<S> SnowflakeGenerator<S> {
+    /// Replace sequence numbers reserved by `plan` with random ones in privacy mode.
+    ///
+    /// Reserving still goes through the shards, so a tick never hands out more than it holds.
+    pub(crate) fn randomize(&self, used: &UsedSequences, plan: Plan) -> Plan {
+        match plan {
+            Plan::Claim(it) => match self.pick(used, it) {
+                Ok(it) => Plan::Claim(it),
+                Err(Unpicked::Stale { .. }) => Plan::Contended,
+                Err(Unpicked::Full) => Plan::Exhausted,
+            },
+            Plan::Hold(it) => match self.pick(used, it) {
+                Ok(it) => Plan::Hold(it),
+                Err(Unpicked::Stale { .. }) => Plan::Contended,
+                Err(Unpicked::Full) => Plan::Exhausted,
+            },
+            other => other,
+        }
+    }
+
+    /// Same as [`SnowflakeGenerator::randomize`](SnowflakeGenerator::randomize), but for [`SnowflakeGenerator::assign_at_with`](SnowflakeGenerator::assign_at_with).
+    pub(crate) fn randomize_at(
+        &self,
+        used: &UsedSequences,
+        reservation: Reservation,
+    ) -> Result<Reservation, SnowflakeError> {
+        let timestamp = reservation.timestamp;
+        self.pick(used, reservation).map_err(|err| match err {
+            Unpicked::Stale { tick } => SnowflakeError::TimestampInPast {
+                timestamp: self.cfg.unix_timestamp_of_ticks(timestamp),
+                high_water_mark: self.cfg.unix_timestamp_of_ticks(tick),
+            },
+            Unpicked::Full => SnowflakeError::SequenceExhausted {
+                timestamp: self.cfg.unix_timestamp_of_ticks(timestamp),
+            },
+        })
+    }
+
+    fn pick(
+        &self,
+        used: &UsedSequences,
+        reservation: Reservation,
+    ) -> Result<Reservation, Unpicked> {
+        let max_sequence = self.cfg.layout.max_sequence();
+        let first = used.pick(reservation.timestamp, reservation.count, max_sequence + 1)?;
+        Ok(Reservation {
+            // Composing adds `start` to the reserved sequence number.
+            start: first.wrapping_sub(reservation.sequence) & max_sequence,
+            ..reservation
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick() {
+        let used = UsedSequences::new(4);
+        let mut picked = (0..16)
+            .map(|_| used.pick(100, 1, 16).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(used.pick(100, 1, 16), Err(Unpicked::Full)));
+        picked.sort();
+        assert_eq!(picked, (0..16).collect::<Vec<_>>());
+
+        // Earlier tick is gone, later one starts over.
+        assert!(matches!(
+            used.pick(99, 1, 16),
+            Err(Unpicked::Stale { tick: 100 })
+        ));
+        let first = used.pick(101, 16, 16).unwrap();
+        assert_eq!(first, 0);
+        assert!(matches!(used.pick(101, 1, 16), Err(Unpicked::Full)));
+    }
+}
@@ -5,8 +5,16 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::time::UNIX_EPOCH;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
+#[cfg(feature = "ntp")]
+pub use crate::ntp::{NtpProvider, SntpClient, SntpExchange, UdpSntpClient};
 use crate::TimeProvider;
 
 /// [std::time::SystemTime] based [TimeProvider]
@@ -30,10 +38,824 @@ impl TimeProvider for StdProvider {
             .unwrap()
             .as_millis() as u64
     }
+
+    fn timestamp_micros(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as u64
+    }
 }
 unsafe impl Sync for StdProvider {}
 unsafe impl Send for StdProvider {}
 
+/// [`TimeProvider`](TimeProvider) only moving when told, for testing exhaustion, rollover and the clock going backwards without sleeping.
+///
+/// Clones share the same clock, so one can be given to the generator while the test holds another.
+/// It's kept in microseconds, so sub-millisecond waits can be tested too.
+///
+/// With [`MockProvider::stepping`](MockProvider::stepping), it also moves 1 millisecond forward every so many reads.
+/// Reading is counted rather than the time, so that's only deterministic when nothing else reads it concurrently.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use snowflake_ng::{provider::MockProvider, SnowflakeGenerator};
+/// let clock = MockProvider::new(1_704_067_200_000);
+/// let generator = SnowflakeGenerator::default();
+/// assert_eq!(generator.assign_sync(&clock).timestamp(), 1_704_067_200_000);
+///
+/// clock.advance(Duration::from_millis(5));
+/// assert_eq!(generator.assign_sync(&clock).timestamp(), 1_704_067_200_005);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockProvider(Arc<MockClock>);
+
+#[derive(Debug, Default)]
+struct MockClock {
+    /// Microseconds since UNIX epoch.
+    now: AtomicU64,
+    /// Stepping 1 millisecond every that many reads, never if 0.
+    reads_per_millis: u64,
+    reads: AtomicU64,
+}
+
+impl MockProvider {
+    /// Starting at `timestamp` milliseconds since UNIX epoch, only moving when told.
+    pub fn new(timestamp: u64) -> Self {
+        Self(Arc::new(MockClock {
+            now: AtomicU64::new(timestamp.saturating_mul(1000)),
+            ..Default::default()
+        }))
+    }
+
+    /// Starting at `timestamp` milliseconds since UNIX epoch, stepping 1 millisecond forward every `reads_per_millis` reads.
+    ///
+    /// `reads_per_millis` of 0 is treated as 1.
+    pub fn stepping(timestamp: u64, reads_per_millis: u64) -> Self {
+        Self(Arc::new(MockClock {
+            now: AtomicU64::new(timestamp.saturating_mul(1000)),
+            reads_per_millis: reads_per_millis.max(1),
+            reads: AtomicU64::new(0),
+        }))
+    }
+
+    /// How many times it's read.
+    pub fn reads(&self) -> u64 {
+        self.0.reads.load(Ordering::Relaxed)
+    }
+
+    /// Move to `timestamp` milliseconds since UNIX epoch, backwards too.
+    pub fn set(&self, timestamp: u64) {
+        self.set_micros(timestamp.saturating_mul(1000))
+    }
+
+    /// Same as [`MockProvider::set`](MockProvider::set), but in microseconds.
+    pub fn set_micros(&self, timestamp: u64) {
+        self.0.now.store(timestamp, Ordering::SeqCst)
+    }
+
+    /// Move forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.0
+            .now
+            .fetch_add(duration.as_micros() as u64, Ordering::SeqCst);
+    }
+}
+
+impl TimeProvider for MockProvider {
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        let clock = &*self.0;
+        let reads = clock.reads.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = clock.now.load(Ordering::SeqCst);
+        if clock.reads_per_millis > 0 && reads.is_multiple_of(clock.reads_per_millis) {
+            clock.now.fetch_add(1000, Ordering::SeqCst);
+        }
+        now
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) always reading the same timestamp, in milliseconds since UNIX epoch.
+///
+/// It's for exhausting a millisecond on purpose, or benchmarking without reading the clock.
+///
+/// **Never use it in production**: once all 4096 sequence numbers (of the default layout) are issued,
+/// [`SnowflakeGenerator::assign`](crate::SnowflakeGenerator::assign) waits for a tick never coming, forever.
+///
+/// ```
+/// # use snowflake_ng::{provider::FixedProvider, SnowflakeGenerator};
+/// let generator = SnowflakeGenerator::default();
+/// assert_eq!(generator.assign_sync(&FixedProvider::new(1_704_067_200_000)).timestamp(), 1_704_067_200_000);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedProvider(u64);
+
+impl FixedProvider {
+    /// Always reading `timestamp` milliseconds since UNIX epoch.
+    pub fn new(timestamp: u64) -> Self {
+        Self(timestamp)
+    }
+
+    /// Always reading `time`, or UNIX epoch if it's earlier.
+    pub fn at(time: SystemTime) -> Self {
+        Self::new(
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        )
+    }
+}
+
+impl TimeProvider for FixedProvider {
+    fn timestamp(&self) -> u64 {
+        self.0
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        self.0.saturating_mul(1000)
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) counting from `epoch` (milliseconds since UNIX epoch) instead, reading `inner`.
+///
+/// Time before `epoch` saturates at 0, unless it's [strict](OffsetProvider::strict).
+///
+/// It's the same as [`epoch`](crate::SnowflakeConfiguration::epoch), so don't set both, they add up.
+/// With the default [`TimestampValidation::Window`](crate::TimestampValidation::Window), its timestamps are rejected as before 2024,
+/// so turn it [`TimestampValidation::Off`](crate::TimestampValidation::Off).
+///
+/// ```
+/// # use snowflake_ng::{provider::{FixedProvider, OffsetProvider}, SnowflakeConfiguration, SnowflakeGenerator, TimestampValidation};
+/// let provider = OffsetProvider::new(FixedProvider::new(1_704_067_200_005), 1_704_067_200_000);
+/// let generator = SnowflakeGenerator::with_cfg(
+///     SnowflakeConfiguration::with_identifier(1).with_timestamp_validation(TimestampValidation::Off),
+/// );
+/// assert_eq!(generator.assign_sync(&provider).timestamp(), 5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OffsetProvider<T> {
+    inner: T,
+    epoch: u64,
+    strict: bool,
+}
+
+impl<T> OffsetProvider<T>
+where
+    T: TimeProvider,
+{
+    /// Reading `inner` since `epoch` milliseconds since UNIX epoch.
+    pub fn new(inner: T, epoch: u64) -> Self {
+        Self {
+            inner,
+            epoch,
+            strict: false,
+        }
+    }
+
+    /// Panic in debug builds if `inner` reads the time before `epoch`, rather than saturating silently.
+    ///
+    /// Release builds saturate anyway.
+    pub fn strict(self) -> Self {
+        Self {
+            strict: true,
+            ..self
+        }
+    }
+
+    /// The epoch counting from, in milliseconds since UNIX epoch.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// The provider wrapped.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn since_epoch(&self, now: u64, epoch: u64) -> u64 {
+        debug_assert!(
+            !self.strict || now >= epoch,
+            "time {now} is before epoch {epoch}"
+        );
+        now.saturating_sub(epoch)
+    }
+}
+
+impl<T> TimeProvider for OffsetProvider<T>
+where
+    T: TimeProvider,
+{
+    fn timestamp(&self) -> u64 {
+        self.since_epoch(self.inner.timestamp(), self.epoch)
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        self.since_epoch(
+            self.inner.timestamp_micros(),
+            self.epoch.saturating_mul(1000),
+        )
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) counting in `unit` instead of milliseconds, reading `inner`.
+///
+/// Generator takes each tick for a millisecond, so timestamps embedded are ticks since UNIX epoch,
+/// like 10ms ticks of Sonyflake. Waiting for the next tick on exhaustion still sleeps about 1ms at a time, until the tick changes.
+/// [`time_unit`](crate::SnowflakeConfiguration::time_unit) does the same without those wakeups, so don't set both.
+///
+/// Like [`OffsetProvider`](OffsetProvider), its timestamps are rejected as before 2024 by the default
+/// [`TimestampValidation::Window`](crate::TimestampValidation::Window).
+///
+/// ```
+/// # use std::time::Duration;
+/// # use snowflake_ng::provider::{FixedProvider, ScaledProvider};
+/// # use snowflake_ng::TimeProvider;
+/// let provider = ScaledProvider::new(FixedProvider::new(1_704_067_200_005), Duration::from_millis(10));
+/// assert_eq!(provider.timestamp(), 170_406_720_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScaledProvider<T> {
+    inner: T,
+    unit: u64,
+}
+
+impl<T> ScaledProvider<T>
+where
+    T: TimeProvider,
+{
+    /// Reading `inner` in ticks of `unit`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `unit` is shorter than 1 microsecond.
+    pub fn new(inner: T, unit: Duration) -> Self {
+        let unit = unit.as_micros() as u64;
+        assert!(unit > 0, "`unit` must be at least 1 microsecond");
+        Self { inner, unit }
+    }
+
+    /// Length of a tick.
+    pub fn unit(&self) -> Duration {
+        Duration::from_micros(self.unit)
+    }
+
+    /// The provider wrapped.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> TimeProvider for ScaledProvider<T>
+where
+    T: TimeProvider,
+{
+    fn timestamp(&self) -> u64 {
+        self.inner.timestamp_micros() / self.unit
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) never going backwards, reading the latest time ever read while `inner` is behind.
+///
+/// It keeps assigning through small regressions like NTP slews, using up the same millisecond till `inner` catches up,
+/// rather than waiting or [rolling back](crate::SnowflakeConfiguration::rollback_tolerance).
+/// Reading is a single `fetch_max`.
+///
+/// ```
+/// # use snowflake_ng::{provider::{MockProvider, MonotonicProvider}, TimeProvider};
+/// let clock = MockProvider::new(100);
+/// let provider = MonotonicProvider::new(clock.clone());
+/// assert_eq!(provider.timestamp(), 100);
+///
+/// clock.set(95);
+/// assert_eq!(provider.timestamp(), 100);
+/// ```
+#[derive(Debug)]
+pub struct MonotonicProvider<T> {
+    inner: T,
+    /// Latest microseconds read.
+    latest: AtomicU64,
+}
+
+impl<T> MonotonicProvider<T>
+where
+    T: TimeProvider,
+{
+    /// Never reading earlier than read last time from `inner`.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            latest: AtomicU64::new(0),
+        }
+    }
+
+    /// The provider wrapped.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> TimeProvider for MonotonicProvider<T>
+where
+    T: TimeProvider,
+{
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        let now = self.inner.timestamp_micros();
+        self.latest.fetch_max(now, Ordering::Relaxed).max(now)
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) reading the wall clock once, then counting [`Instant`](Instant) elapsed since.
+///
+/// Steps of the wall clock, like NTP setting it, never reach it, and it never goes backwards.
+/// It drifts with the local oscillator though, so [re-anchor](AnchoredProvider::reanchor_every) now and then to follow the wall clock,
+/// which only ever moves it forward.
+///
+/// ```
+/// # use snowflake_ng::{provider::{AnchoredProvider, StdProvider}, TimeProvider};
+/// let provider = AnchoredProvider::new();
+/// assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) < 1000);
+/// ```
+#[derive(Debug)]
+pub struct AnchoredProvider<W = StdProvider> {
+    wall: W,
+    instant: Instant,
+    /// Microseconds since UNIX epoch at `instant`, only raised by re-anchoring.
+    anchor: AtomicU64,
+    /// Re-anchoring every this microseconds.
+    every: Option<u64>,
+    /// Microseconds since `instant` anchored last.
+    anchored: AtomicU64,
+}
+
+impl AnchoredProvider {
+    /// Anchored to [`StdProvider`](StdProvider) now.
+    pub fn new() -> Self {
+        Self::with_wall(StdProvider)
+    }
+}
+
+impl Default for AnchoredProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W> AnchoredProvider<W>
+where
+    W: TimeProvider,
+{
+    /// Anchored to `wall` now, and re-anchored to it later if asked.
+    pub fn with_wall(wall: W) -> Self {
+        let anchor = wall.timestamp_micros();
+        Self {
+            wall,
+            instant: Instant::now(),
+            anchor: AtomicU64::new(anchor),
+            every: None,
+            anchored: AtomicU64::new(0),
+        }
+    }
+
+    /// Read the wall clock again at most every `every`, moving forward if it's ahead.
+    ///
+    /// Wall clock behind is ignored, so it's never going backwards.
+    pub fn reanchor_every(self, every: Duration) -> Self {
+        Self {
+            every: Some(every.as_micros() as u64),
+            ..self
+        }
+    }
+}
+
+impl<W> TimeProvider for AnchoredProvider<W>
+where
+    W: TimeProvider,
+{
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        let elapsed = self.instant.elapsed().as_micros() as u64;
+        if let Some(every) = self.every {
+            let anchored = self.anchored.load(Ordering::Relaxed);
+            // Only one re-anchoring at once.
+            if elapsed.saturating_sub(anchored) >= every
+                && self
+                    .anchored
+                    .compare_exchange(anchored, elapsed, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            {
+                let anchor = self.wall.timestamp_micros().saturating_sub(elapsed);
+                self.anchor.fetch_max(anchor, Ordering::Relaxed);
+            }
+        }
+
+        self.anchor.load(Ordering::Relaxed) + elapsed
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) reading the time cached by a Tokio task, refreshing it from [`StdProvider`](StdProvider) every `resolution`.
+///
+/// Reading is a single relaxed load rather than a syscall, for millions of [`Snowflake`](crate::Snowflake) per second.
+/// It's behind by up to `resolution`, plus however late the task is scheduled, Tokio timers firing at 1ms granularity at best.
+/// Used-up milliseconds keep waiting until it's refreshed, the sequence covers the rest.
+///
+/// Dropping it stops the task.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use snowflake_ng::{provider::{CachedProvider, StdProvider}, TimeProvider};
+/// # #[tokio::main]
+/// # async fn main() {
+/// let provider = CachedProvider::spawn(Duration::from_millis(1));
+/// assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) < 1000);
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct CachedProvider {
+    /// Microseconds since UNIX epoch.
+    now: Arc<AtomicU64>,
+    refresher: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "tokio")]
+impl CachedProvider {
+    /// Caching the time now, and refreshing it every `resolution` in a task spawned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resolution` is zero, or called outside of Tokio runtime.
+    pub fn spawn(resolution: Duration) -> Self {
+        assert!(!resolution.is_zero(), "`resolution` must be positive");
+
+        let now = Arc::new(AtomicU64::new(StdProvider.timestamp_micros()));
+        let refresher = tokio::spawn({
+            let now = now.clone();
+            async move {
+                let mut interval = tokio::time::interval(resolution);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    now.store(StdProvider.timestamp_micros(), Ordering::Relaxed);
+                }
+            }
+        });
+        Self { now, refresher }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl TimeProvider for CachedProvider {
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        self.now.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Drop for CachedProvider {
+    fn drop(&mut self) {
+        self.refresher.abort();
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) of [`quanta::Clock`](quanta::Clock), anchored to [`StdProvider`](StdProvider) once constructed.
+///
+/// Reading TSC is cheaper and finer than [`SystemTime::now`](SystemTime::now), and never goes backwards.
+/// Like [`AnchoredProvider`](AnchoredProvider), it doesn't follow the wall clock being set after that.
+///
+/// ```
+/// # use snowflake_ng::{provider::{QuantaProvider, StdProvider}, TimeProvider};
+/// let provider = QuantaProvider::new();
+/// assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) < 1000);
+/// ```
+#[cfg(feature = "quanta")]
+#[derive(Debug, Clone)]
+pub struct QuantaProvider {
+    clock: quanta::Clock,
+    start: quanta::Instant,
+    /// Microseconds since UNIX epoch at `start`.
+    anchor: u64,
+}
+
+#[cfg(feature = "quanta")]
+impl QuantaProvider {
+    /// Anchored to [`StdProvider`](StdProvider) now, with a new [`quanta::Clock`](quanta::Clock).
+    pub fn new() -> Self {
+        Self::with_clock(quanta::Clock::new())
+    }
+
+    /// Anchored to [`StdProvider`](StdProvider) now, reading `clock`.
+    pub fn with_clock(clock: quanta::Clock) -> Self {
+        Self {
+            start: clock.now(),
+            anchor: StdProvider.timestamp_micros(),
+            clock,
+        }
+    }
+
+    /// The clock reading, for reusing it elsewhere.
+    pub fn clock(&self) -> &quanta::Clock {
+        &self.clock
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Default for QuantaProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl TimeProvider for QuantaProvider {
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        self.anchor + self.clock.now().duration_since(self.start).as_micros() as u64
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) of [`coarsetime::Clock`](coarsetime::Clock).
+///
+/// [`CoarseProvider::new`](CoarseProvider::new) reads [`Clock::now_since_epoch`](coarsetime::Clock::now_since_epoch), still a syscall.
+/// [Cached](CoarseProvider::cached) reads [`Clock::recent_since_epoch`](coarsetime::Clock::recent_since_epoch) instead, a single load,
+/// refreshed by [`coarsetime::Updater`](coarsetime::Updater) in a thread. It's behind by up to a period, typically 1 to 4ms,
+/// and [`Snowflake`](crate::Snowflake) assigned in the meantime share a millisecond, till its sequence is used up.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use snowflake_ng::{provider::{CoarseProvider, StdProvider}, TimeProvider};
+/// let provider = CoarseProvider::cached(Duration::from_millis(1)).unwrap();
+/// assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) < 1000);
+/// ```
+#[cfg(feature = "coarsetime")]
+#[derive(Debug, Default)]
+pub struct CoarseProvider {
+    updater: Option<coarsetime::Updater>,
+}
+
+#[cfg(feature = "coarsetime")]
+impl CoarseProvider {
+    /// Reading the clock on every call.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reading the time cached, starting [`coarsetime::Updater`](coarsetime::Updater) refreshing it every `period` (at least 1ms) until dropped.
+    ///
+    /// The cache is shared by the whole process, so others refreshing it may make it finer.
+    pub fn cached(period: Duration) -> std::io::Result<Self> {
+        let updater = coarsetime::Updater::new((period.as_millis() as u64).max(1)).start()?;
+        Ok(Self {
+            updater: Some(updater),
+        })
+    }
+}
+
+#[cfg(feature = "coarsetime")]
+impl TimeProvider for CoarseProvider {
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        match self.updater {
+            Some(_) => coarsetime::Clock::recent_since_epoch().as_micros(),
+            None => coarsetime::Clock::now_since_epoch().as_micros(),
+        }
+    }
+}
+
+#[cfg(feature = "coarsetime")]
+impl Drop for CoarseProvider {
+    fn drop(&mut self) {
+        if let Some(updater) = self.updater.take() {
+            let _ = updater.stop();
+        }
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) reading `CLOCK_REALTIME_COARSE` on Linux, cheaper than [`StdProvider`](StdProvider) reading `CLOCK_REALTIME`.
+///
+/// It's as fine as a jiffy of the kernel, typically 1 to 4ms, and its sequence covers [`Snowflake`](crate::Snowflake) within.
+/// On other platforms, it's [`StdProvider`](StdProvider).
+///
+/// ```
+/// # use snowflake_ng::{provider::{LinuxCoarseProvider, StdProvider}, TimeProvider};
+/// assert!(LinuxCoarseProvider.timestamp().abs_diff(StdProvider.timestamp()) < 1000);
+/// ```
+#[cfg(feature = "coarse-clock")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinuxCoarseProvider;
+
+#[cfg(feature = "coarse-clock")]
+impl TimeProvider for LinuxCoarseProvider {
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    #[cfg(target_os = "linux")]
+    fn timestamp_micros(&self) -> u64 {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `now` is valid for writes, and the clock always exists since Linux 2.6.32.
+        if unsafe { libc::clock_gettime(libc::CLOCK_REALTIME_COARSE, &mut now) } != 0 {
+            return StdProvider.timestamp_micros();
+        }
+        now.tv_sec as u64 * 1_000_000 + now.tv_nsec as u64 / 1000
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn timestamp_micros(&self) -> u64 {
+        StdProvider.timestamp_micros()
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) of `Date.now()` in JavaScript, for browsers and Node.js where [`StdProvider`](StdProvider) panics.
+///
+/// It's the wall clock, going backwards if set. See [`JsPerformanceProvider`](JsPerformanceProvider) for one never going backwards.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsDateProvider;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl TimeProvider for JsDateProvider {
+    fn timestamp(&self) -> u64 {
+        js_sys::Date::now() as u64
+    }
+}
+
+/// [`TimeProvider`](TimeProvider) of `performance.now()` in JavaScript, anchored to `Date.now()` once constructed.
+///
+/// Like [`AnchoredProvider`](AnchoredProvider), it never goes backwards, and doesn't follow the wall clock being set after that.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy)]
+pub struct JsPerformanceProvider {
+    /// Milliseconds since UNIX epoch at `performance.now()` of 0.
+    anchor: f64,
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl JsPerformanceProvider {
+    /// Anchored to `Date.now()` now.
+    pub fn new() -> Self {
+        Self {
+            anchor: js_sys::Date::now() - performance_now(),
+        }
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl Default for JsPerformanceProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl TimeProvider for JsPerformanceProvider {
+    fn timestamp(&self) -> u64 {
+        self.timestamp_micros() / 1000
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        ((self.anchor + performance_now()) * 1000.0) as u64
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    /// Milliseconds since the page or process started, never going backwards.
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    pub(crate) fn performance_now() -> f64;
+}
+
+/// [`TimeProvider`](TimeProvider) counting ticks read by `ticks`, like a hardware timer of an embedded device.
+///
+/// Ticks are converted at `ticks_per_second` to milliseconds, since [`TickProvider::starting_at`](TickProvider::starting_at) (UNIX epoch by default).
+///
+/// # Wrapping
+///
+/// A counter narrower than 64 bits wraps around, going back to 0 as if the clock went backwards.
+/// With [`TickProvider::wrapping_at_bits`](TickProvider::wrapping_at_bits), it counts wraps instead, as long as it's read at least once per half a wrap,
+/// e.g. every 17 minutes for a 32-bit counter of 2MHz. Missed wraps are lost for good.
+///
+/// ```
+/// # use snowflake_ng::{provider::TickProvider, TimeProvider};
+/// let provider = TickProvider::new(|| 2_500, 1_000).starting_at(1_704_067_200_000);
+/// assert_eq!(provider.timestamp(), 1_704_067_202_500);
+/// ```
+#[derive(Debug)]
+pub struct TickProvider<F> {
+    ticks: F,
+    ticks_per_second: u64,
+    start: u64,
+    /// Mask of bits of the counter, if it wraps.
+    mask: Option<u64>,
+    /// Ticks read last, wraps added.
+    last: AtomicU64,
+}
+
+impl<F> TickProvider<F>
+where
+    F: Fn() -> u64,
+{
+    /// Reading `ticks`, counting `ticks_per_second`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ticks_per_second` is 0.
+    pub fn new(ticks: F, ticks_per_second: u64) -> Self {
+        assert!(ticks_per_second > 0, "`ticks_per_second` must be positive");
+        Self {
+            ticks,
+            ticks_per_second,
+            start: 0,
+            mask: None,
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Tick 0 is at `start` milliseconds since UNIX epoch, e.g. synced once at boot.
+    pub fn starting_at(self, start: u64) -> Self {
+        Self { start, ..self }
+    }
+
+    /// The counter is `bits` wide, counting wraps of it from now on, see [wrapping](TickProvider#wrapping).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is 0 or more than 64.
+    pub fn wrapping_at_bits(self, bits: u32) -> Self {
+        assert!((1..=64).contains(&bits), "`bits` must be within 1..=64");
+        let mask = u64::MAX >> (64 - bits);
+        Self {
+            mask: Some(mask),
+            last: AtomicU64::new((self.ticks)() & mask),
+            ..self
+        }
+    }
+
+    /// Ticks since tick 0, wraps added.
+    fn ticks(&self) -> u64 {
+        let Some(mask) = self.mask else {
+            return (self.ticks)();
+        };
+        let raw = (self.ticks)() & mask;
+        let mut last = self.last.load(Ordering::Acquire);
+        loop {
+            // More than half a wrap ahead is behind, others read later.
+            let delta = raw.wrapping_sub(last) & mask;
+            if delta == 0 || delta > mask / 2 {
+                return last;
+            }
+            let ticks = last.wrapping_add(delta);
+            match self
+                .last
+                .compare_exchange_weak(last, ticks, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return ticks,
+                Err(current) => last = current,
+            }
+        }
+    }
+}
+
+impl<F> TimeProvider for TickProvider<F>
+where
+    F: Fn() -> u64,
+{
+    fn timestamp(&self) -> u64 {
+        let millis = self.ticks() as u128 * 1000 / self.ticks_per_second as u128;
+        self.start.saturating_add(millis as u64)
+    }
+
+    fn timestamp_micros(&self) -> u64 {
+        let micros = self.ticks() as u128 * 1_000_000 / self.ticks_per_second as u128;
+        self.start
+            .saturating_mul(1000)
+            .saturating_add(micros as u64)
+    }
+}
+
 #[cfg(feature = "chrono")]
 #[derive(Debug)]
 pub struct ChronoProvider;
@@ -43,6 +865,10 @@ impl TimeProvider for ChronoProvider {
     fn timestamp(&self) -> u64 {
         chrono::Local::now().timestamp_millis() as u64
     }
+
+    fn timestamp_micros(&self) -> u64 {
+        chrono::Local::now().timestamp_micros() as u64
+    }
 }
 
 #[cfg(feature = "chrono")]
@@ -59,9 +885,311 @@ impl TimeProvider for TimeCrateProvider {
     fn timestamp(&self) -> u64 {
         (time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64
     }
+
+    fn timestamp_micros(&self) -> u64 {
+        (time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000) as u64
+    }
 }
 
 #[cfg(feature = "time")]
 unsafe impl Sync for TimeCrateProvider {}
 #[cfg(feature = "time")]
 unsafe impl Send for TimeCrateProvider {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_provider() {
+        let clock = MockProvider::new(100);
+        let shared = clock.clone();
+        shared.advance(Duration::from_micros(1_500));
+        assert_eq!(clock.timestamp(), 101);
+        assert_eq!(clock.timestamp_micros(), 101_500);
+        shared.set(50);
+        assert_eq!(clock.timestamp(), 50);
+        assert_eq!(clock.reads(), 3);
+
+        // Stepping every 2 reads, moving when told as well.
+        let clock = MockProvider::stepping(100, 2);
+        let read = (0..4).map(|_| clock.timestamp()).collect::<Vec<_>>();
+        assert_eq!(read, [100, 100, 101, 101]);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(clock.timestamp(), 112);
+        assert_eq!(clock.reads(), 5);
+    }
+
+    #[test]
+    fn test_offset_provider() {
+        let provider =
+            OffsetProvider::new(FixedProvider::new(1_704_067_200_042), 1_704_067_200_000);
+        assert_eq!(provider.timestamp(), 42);
+        assert_eq!(provider.timestamp_micros(), 42_000);
+        assert_eq!(provider.epoch(), 1_704_067_200_000);
+        assert_eq!(provider.inner(), &FixedProvider::new(1_704_067_200_042));
+
+        // Right at the epoch, and before it.
+        let at = OffsetProvider::new(FixedProvider::new(1_000), 1_000).strict();
+        assert_eq!(at.timestamp(), 0);
+        let before = OffsetProvider::new(FixedProvider::new(999), 1_000);
+        assert_eq!(before.timestamp(), 0);
+        assert_eq!(before.timestamp_micros(), 0);
+
+        // Sub-millisecond precision of the inner one is kept.
+        let clock = MockProvider::new(1_000);
+        clock.advance(Duration::from_micros(1_250));
+        let provider = OffsetProvider::new(clock, 1_000);
+        assert_eq!(provider.timestamp(), 1);
+        assert_eq!(provider.timestamp_micros(), 1_250);
+    }
+
+    #[test]
+    fn test_scaled_provider() {
+        let clock = MockProvider::new(1_704_067_200_000);
+        let provider = ScaledProvider::new(clock.clone(), Duration::from_millis(10));
+        assert_eq!(provider.unit(), Duration::from_millis(10));
+        let tick = provider.timestamp();
+        assert_eq!(tick, 170_406_720_000);
+        assert_eq!(provider.timestamp_micros(), tick * 1000);
+
+        clock.advance(Duration::from_millis(9));
+        assert_eq!(provider.timestamp(), tick);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(provider.timestamp(), tick + 1);
+
+        // Ticks embedded as they are.
+        #[cfg(feature = "sync")]
+        {
+            let generator = crate::SnowflakeGenerator::with_cfg(
+                crate::SnowflakeConfiguration::with_identifier(1)
+                    .with_timestamp_validation(crate::TimestampValidation::Off),
+            );
+            assert_eq!(generator.assign_sync(&provider).timestamp(), tick + 1);
+        }
+    }
+
+    #[test]
+    fn test_monotonic_provider() {
+        let clock = MockProvider::new(100);
+        let provider = MonotonicProvider::new(clock.clone());
+        let read = [100, 95, 101].map(|it| {
+            clock.set(it);
+            provider.timestamp()
+        });
+        assert_eq!(read, [100, 100, 101]);
+
+        // Keeps assigning through the regression.
+        #[cfg(feature = "sync")]
+        {
+            let generator = crate::SnowflakeGenerator::with_cfg(
+                crate::SnowflakeConfiguration::with_identifier(1)
+                    .with_timestamp_validation(crate::TimestampValidation::Off),
+            );
+            let mut snowflakes = std::collections::HashSet::new();
+            for now in [200, 195, 199, 201] {
+                clock.set(now);
+                for snowflake in generator.assign_many_sync(&provider, 1000) {
+                    assert_eq!(snowflake.timestamp(), now.max(200));
+                    assert!(snowflakes.insert(snowflake));
+                }
+            }
+            assert_eq!(snowflakes.len(), 4000);
+            assert_eq!(generator.stats().clock_behind, 0);
+        }
+    }
+
+    #[test]
+    fn test_anchored_provider() {
+        let provider = AnchoredProvider::new();
+        assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) <= 50);
+
+        // Wall clock steps never reach it.
+        let wall = MockProvider::new(100_000);
+        let provider = AnchoredProvider::with_wall(wall.clone());
+        let start = provider.timestamp();
+        assert!((100_000..100_050).contains(&start));
+        wall.set(50_000);
+        wall.set(500_000);
+        let mut last = provider.timestamp_micros();
+        for _ in 0..1000 {
+            let now = provider.timestamp_micros();
+            assert!(now >= last);
+            last = now;
+        }
+        assert!(last / 1000 < 100_100);
+
+        // Re-anchored forward only.
+        let provider = AnchoredProvider::with_wall(wall.clone()).reanchor_every(Duration::ZERO);
+        wall.set(400_000);
+        let before = provider.timestamp();
+        assert!((500_000..500_050).contains(&before));
+        wall.set(600_000);
+        let after = provider.timestamp();
+        assert!((600_000..600_050).contains(&after));
+        wall.set(550_000);
+        assert!(provider.timestamp() >= after);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_cached_provider() {
+        let provider = std::sync::Arc::new(CachedProvider::spawn(Duration::from_millis(1)));
+        let start = provider.timestamp();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(provider.timestamp() > start);
+        assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) <= 3);
+
+        let generator = std::sync::Arc::new(crate::SnowflakeGenerator::default());
+        let tasks = (0..8)
+            .map(|_| {
+                let (generator, provider) = (generator.clone(), provider.clone());
+                tokio::spawn(async move { generator.assign_many(provider.as_ref(), 10_000).await })
+            })
+            .collect::<Vec<_>>();
+        let mut snowflakes = std::collections::HashSet::new();
+        for task in tasks {
+            for snowflake in task.await.unwrap() {
+                assert!(snowflakes.insert(snowflake));
+            }
+        }
+        assert_eq!(snowflakes.len(), 80_000);
+
+        // Refresher stops once dropped.
+        let now = std::sync::Arc::downgrade(&provider.now);
+        drop(std::sync::Arc::into_inner(provider));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(now.upgrade().is_none());
+    }
+
+    #[cfg(feature = "quanta")]
+    #[test]
+    fn test_quanta_provider() {
+        let provider = QuantaProvider::new();
+        assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) <= 50);
+
+        let mut last = provider.timestamp_micros();
+        for _ in 0..10_000 {
+            let now = provider.timestamp_micros();
+            assert!(now >= last);
+            last = now;
+        }
+
+        #[cfg(feature = "sync")]
+        {
+            let generator = crate::SnowflakeGenerator::default();
+            let snowflakes = generator.assign_many_sync(&provider, 10_000);
+            assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+            assert!(snowflakes[0].timestamp().abs_diff(StdProvider.timestamp()) <= 50);
+        }
+    }
+
+    #[cfg(feature = "coarsetime")]
+    #[test]
+    fn test_coarse_provider() {
+        let providers = [
+            CoarseProvider::new(),
+            CoarseProvider::cached(Duration::from_millis(1)).unwrap(),
+        ];
+        for provider in &providers {
+            assert!(provider.timestamp().abs_diff(StdProvider.timestamp()) <= 10);
+
+            let mut last = provider.timestamp_micros();
+            for _ in 0..10_000 {
+                let now = provider.timestamp_micros();
+                assert!(now >= last);
+                last = now;
+            }
+
+            #[cfg(feature = "sync")]
+            {
+                let generator = crate::SnowflakeGenerator::default();
+                let snowflakes = generator.assign_many_sync(provider, 20_000);
+                let unique = snowflakes.iter().collect::<std::collections::HashSet<_>>();
+                assert_eq!(unique.len(), 20_000);
+            }
+        }
+    }
+
+    #[cfg(all(feature = "coarse-clock", target_os = "linux"))]
+    #[test]
+    fn test_linux_coarse_provider() {
+        assert!(
+            LinuxCoarseProvider
+                .timestamp()
+                .abs_diff(StdProvider.timestamp())
+                <= 10
+        );
+
+        let mut last = LinuxCoarseProvider.timestamp_micros();
+        for _ in 0..10_000 {
+            let now = LinuxCoarseProvider.timestamp_micros();
+            assert!(now >= last);
+            last = now;
+        }
+
+        #[cfg(feature = "sync")]
+        {
+            let generator = crate::SnowflakeGenerator::default();
+            let snowflakes = generator.assign_many_sync(&LinuxCoarseProvider, 20_000);
+            let unique = snowflakes.iter().collect::<std::collections::HashSet<_>>();
+            assert_eq!(unique.len(), 20_000);
+        }
+    }
+
+    #[test]
+    fn test_tick_provider() {
+        // 32768Hz of a real-time clock.
+        let ticks = std::sync::Arc::new(AtomicU64::new(0));
+        let counter = ticks.clone();
+        let provider =
+            TickProvider::new(move || counter.load(Ordering::SeqCst), 32_768).starting_at(1_000);
+        assert_eq!(provider.timestamp(), 1_000);
+        ticks.store(32_768 * 3 + 16_384, Ordering::SeqCst);
+        assert_eq!(provider.timestamp(), 4_500);
+        assert_eq!(provider.timestamp_micros(), 4_500_000);
+        ticks.store(33, Ordering::SeqCst);
+        assert_eq!(provider.timestamp(), 1_001);
+        assert_eq!(provider.timestamp_micros(), 1_001_007);
+
+        // No overflow of the conversion even near the end.
+        let provider = TickProvider::new(|| u64::MAX, 1_000_000_000);
+        assert_eq!(provider.timestamp(), u64::MAX / 1_000_000);
+    }
+
+    #[test]
+    fn test_tick_provider_wrapping() {
+        // 16-bit counter of 1kHz, wrapping every 65.536s.
+        let ticks = std::sync::Arc::new(AtomicU64::new(0xFF00));
+        let counter = ticks.clone();
+        let provider =
+            TickProvider::new(move || counter.load(Ordering::SeqCst), 1_000).wrapping_at_bits(16);
+        assert_eq!(provider.timestamp(), 0xFF00);
+
+        let mut last = 0xFF00;
+        for raw in [0xFFFF, 0x0010, 0x7000, 0xE000, 0x1000] {
+            ticks.store(raw, Ordering::SeqCst);
+            let now = provider.timestamp();
+            assert!(now > last);
+            last = now;
+        }
+        assert_eq!(last, 2 * 0x10000 + 0x1000);
+
+        // A read older than the last one doesn't count as a wrap.
+        ticks.store(0x0FFF, Ordering::SeqCst);
+        assert_eq!(provider.timestamp(), last);
+
+        // Unmasked bits ignored.
+        ticks.store(0xABCD_1001, Ordering::SeqCst);
+        assert_eq!(provider.timestamp(), last + 1);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic = "before epoch"]
+    fn test_offset_provider_strict() {
+        OffsetProvider::new(FixedProvider::new(999), 1_000)
+            .strict()
+            .timestamp();
+    }
+}
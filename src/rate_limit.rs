@@ -0,0 +1,244 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+#[cfg(feature = "async")]
+use crate::Sleeper;
+use crate::{PersistedSnowflakeGenerator, TimeProvider, TimerSleeper};
+#[cfg(any(feature = "async", feature = "sync"))]
+use crate::{Snowflake, SnowflakeError};
+
+/// [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) issuing at most `max_per_second` [`Snowflake`](Snowflake).
+///
+/// It's a token bucket holding [`burst`](RateLimitedGenerator::with_burst) tokens, refilled at `max_per_second` by the clock of provider.
+/// Limiting only decides when to assign, so [`Snowflake`](Snowflake) are as unique as the inner generator's.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use snowflake_ng::{provider::StdProvider, PersistedSnowflakeGenerator, RateLimitedGenerator, SnowflakeGenerator};
+/// let persisted = PersistedSnowflakeGenerator::new(Arc::new(SnowflakeGenerator::default()), Arc::new(StdProvider));
+/// let limited = RateLimitedGenerator::new(persisted, 1000);
+///
+/// let snowflake = limited.assign_sync();
+/// ```
+#[derive(Debug)]
+pub struct RateLimitedGenerator<T, S = TimerSleeper> {
+    generator: PersistedSnowflakeGenerator<T, S>,
+    /// Nanoseconds between two tokens.
+    interval: u64,
+    /// How far ahead of the clock `tat` can be, in nanoseconds.
+    tolerance: u64,
+    /// Theoretical arrival time of the next one in nanoseconds since UNIX epoch, see GCRA.
+    tat: AtomicU64,
+}
+
+impl<T, S> RateLimitedGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+{
+    /// Limiting `generator` to `max_per_second`, with a burst of `max_per_second` as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_per_second` is 0.
+    pub fn new(generator: PersistedSnowflakeGenerator<T, S>, max_per_second: u32) -> Self {
+        assert!(max_per_second > 0, "`max_per_second` must be positive");
+
+        let interval = (1_000_000_000 / max_per_second as u64).max(1);
+        Self {
+            generator,
+            interval,
+            tolerance: interval * max_per_second as u64,
+            tat: AtomicU64::new(0),
+        }
+    }
+
+    /// Let up to `burst` [`Snowflake`](Snowflake) through right away after idling, rather than `max_per_second`.
+    ///
+    /// `burst` of 0 is treated as 1.
+    pub fn with_burst(self, burst: u32) -> Self {
+        Self {
+            tolerance: self.interval * burst.max(1) as u64,
+            ..self
+        }
+    }
+
+    /// Assign a [`Snowflake`](Snowflake), waiting until the budget allows.
+    #[cfg(feature = "async")]
+    pub async fn assign(&self) -> Snowflake
+    where
+        S: Sleeper,
+    {
+        while let Err(retry_after) = self.acquire() {
+            self.generator.generator.sleeper.sleep(retry_after).await;
+        }
+
+        self.generator.assign().await
+    }
+
+    /// Assign a new [`Snowflake`](Snowflake) but in synchronous way, blocking current thread until the budget allows.
+    #[cfg(feature = "sync")]
+    pub fn assign_sync(&self) -> Snowflake {
+        while let Err(retry_after) = self.acquire() {
+            std::thread::sleep(retry_after);
+        }
+
+        self.generator.assign_sync()
+    }
+
+    /// Assign a [`Snowflake`](Snowflake) if the budget allows, or returning [`SnowflakeError::RateLimited`](SnowflakeError::RateLimited).
+    ///
+    /// Otherwise it's [`PersistedSnowflakeGenerator::try_assign`](PersistedSnowflakeGenerator::try_assign).
+    #[cfg(feature = "async")]
+    pub async fn try_assign(&self) -> Result<Snowflake, SnowflakeError>
+    where
+        S: Sleeper,
+    {
+        self.acquire()
+            .map_err(|retry_after| SnowflakeError::RateLimited { retry_after })?;
+        self.generator.try_assign().await
+    }
+
+    /// Same as [`RateLimitedGenerator::try_assign`](RateLimitedGenerator::try_assign), but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn try_assign_sync(&self) -> Result<Snowflake, SnowflakeError> {
+        self.acquire()
+            .map_err(|retry_after| SnowflakeError::RateLimited { retry_after })?;
+        self.generator.try_assign_sync()
+    }
+
+    /// Take a token, or returning how long until the next one.
+    fn acquire(&self) -> Result<(), Duration> {
+        let now = self
+            .generator
+            .provider
+            .timestamp_micros()
+            .saturating_mul(1000);
+
+        let mut tat = self.tat.load(Ordering::Relaxed);
+        loop {
+            let next = tat.max(now) + self.interval;
+            if next - now > self.tolerance {
+                return Err(Duration::from_nanos(next - now - self.tolerance));
+            }
+
+            match self
+                .tat
+                .compare_exchange_weak(tat, next, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return Ok(()),
+                Err(current) => tat = current,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use crate::{
+        provider::FixedProvider, SnowflakeConfiguration, SnowflakeGenerator, TimestampValidation,
+    };
+
+    use super::*;
+
+    fn limited<T>(provider: T, max_per_second: u32) -> RateLimitedGenerator<T>
+    where
+        T: TimeProvider + Send + Sync,
+    {
+        RateLimitedGenerator::new(
+            PersistedSnowflakeGenerator::new(
+                Arc::new(SnowflakeGenerator::with_cfg(
+                    SnowflakeConfiguration::with_identifier(3)
+                        .with_timestamp_validation(TimestampValidation::Off),
+                )),
+                Arc::new(provider),
+            ),
+            max_per_second,
+        )
+    }
+
+    #[cfg(feature = "tokio")]
+    struct TokioTestProvider(tokio::time::Instant);
+
+    #[cfg(feature = "tokio")]
+    impl TimeProvider for TokioTestProvider {
+        fn timestamp(&self) -> u64 {
+            100_000 + self.0.elapsed().as_millis() as u64
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limited() {
+        let limited = limited(TokioTestProvider(tokio::time::Instant::now()), 100);
+
+        let mut snowflakes = Vec::new();
+        for _ in 0..400 {
+            snowflakes.push(limited.assign().await);
+        }
+
+        let issued_in = |second: u64| {
+            snowflakes
+                .iter()
+                .filter(|it| (it.timestamp() - 100_000) / 1000 == second)
+                .count()
+        };
+        // Burst passes right away, then exactly 100 per second.
+        assert_eq!(
+            snowflakes
+                .iter()
+                .filter(|it| it.timestamp() == 100_000)
+                .count(),
+            100
+        );
+        assert_eq!(issued_in(1), 100);
+        assert_eq!(issued_in(2), 100);
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+
+        assert_eq!(
+            limited.try_assign().await,
+            Err(SnowflakeError::RateLimited {
+                retry_after: Duration::from_millis(10)
+            })
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_concurrent() {
+        let limited = limited(FixedProvider::new(100_000), 1000).with_burst(500);
+
+        let snowflakes = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..1000)
+                            .filter_map(|_| limited.try_assign_sync().ok())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(snowflakes.len(), 500);
+        assert_eq!(snowflakes.iter().collect::<HashSet<_>>().len(), 500);
+        assert!(matches!(
+            limited.try_assign_sync(),
+            Err(SnowflakeError::RateLimited { .. })
+        ));
+    }
+}
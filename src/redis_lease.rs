@@ -0,0 +1,580 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use redis::{aio::MultiplexedConnection, Client, Script};
+use tokio::{sync::OnceCell, time::Instant};
+
+use crate::{
+    lease::keep_alive::{KeepAlive, OnLeaseLost},
+    Heartbeat, HeartbeatStore, IdError, IdentifierLease, IdentifierProvider, LeaseWatch,
+};
+
+/// Claiming the first slot of `KEYS[1]:0` to `KEYS[1]:ARGV[3]-1` missing, with token `ARGV[1]` expiring in `ARGV[2]` milliseconds.
+static CLAIM: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        for slot = 0, tonumber(ARGV[3]) - 1 do
+            if redis.call('SET', KEYS[1] .. ':' .. slot, ARGV[1], 'NX', 'PX', ARGV[2]) then
+                return slot
+            end
+        end
+        return -1
+        ",
+    )
+});
+
+/// Extending `KEYS[1]` to `ARGV[2]` milliseconds only if it's still held with token `ARGV[1]`.
+static REFRESH: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+        end
+        return 0
+        ",
+    )
+});
+
+/// Deleting `KEYS[1]` only if it's still held with token `ARGV[1]`.
+static RELEASE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0
+        ",
+    )
+});
+
+/// [`IdentifierProvider`](IdentifierProvider) leasing the lowest free identifier from Redis, shared by the fleet.
+///
+/// Identifier `n` is held by key `{namespace}:{n}` expiring after `ttl`, which is refreshed every third of `ttl`
+/// by a Tokio task until released. Dropping the lease releases it too, if Tokio runtime is still there.
+///
+/// Keys are generated inside a Lua script, so it needs a single Redis rather than a cluster.
+///
+/// Once the lease can't be refreshed before expiring, someone else may claim it, so stop generating with it:
+/// watch it with [`RedisIdentifierProvider::acquire_watched`](RedisIdentifierProvider::acquire_watched)
+/// or [`RedisIdentifierProvider::on_lease_lost`](RedisIdentifierProvider::on_lease_lost).
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use snowflake_ng::{RedisIdentifierProvider, SnowflakeGenerator};
+/// # async fn run() {
+/// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+/// let provider = RedisIdentifierProvider::new(client, "snowflake:billing", Duration::from_secs(30));
+/// let (lease, mut watch) = provider.acquire_watched().await.unwrap();
+///
+/// tokio::spawn(async move {
+///     if let Some(err) = watch.lost().await {
+///         // Stop generating.
+///     }
+/// });
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RedisIdentifierProvider {
+    client: Client,
+    leasing: Leasing,
+}
+
+#[derive(Clone)]
+struct Leasing {
+    namespace: String,
+    ttl: Duration,
+    slots: u64,
+    on_lost: Option<OnLeaseLost>,
+}
+
+impl RedisIdentifierProvider {
+    /// Leasing identifiers `0..1024` under `namespace`, expiring after `ttl` without refreshing.
+    pub fn new(client: Client, namespace: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            client,
+            leasing: Leasing {
+                namespace: namespace.into(),
+                ttl,
+                slots: 1024,
+                on_lost: None,
+            },
+        }
+    }
+
+    /// Leasing identifiers `0..slots` instead, for layout other than the default.
+    pub fn with_slots(mut self, slots: u64) -> Self {
+        self.leasing.slots = slots;
+        self
+    }
+
+    /// Call `callback` with the identifier once a lease is lost.
+    pub fn on_lease_lost(mut self, callback: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.leasing.on_lost = Some(Arc::new(callback));
+        self
+    }
+
+    /// Same as [`IdentifierProvider::acquire`](IdentifierProvider::acquire), but also watching whether the lease is lost.
+    pub async fn acquire_watched(&self) -> Result<(IdentifierLease, LeaseWatch), IdError> {
+        let connection = self
+            .client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(backend)?;
+        acquire_in(RedisStore(connection), &self.leasing).await
+    }
+}
+
+impl fmt::Debug for RedisIdentifierProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisIdentifierProvider")
+            .field("namespace", &self.leasing.namespace)
+            .field("ttl", &self.leasing.ttl)
+            .field("slots", &self.leasing.slots)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdentifierProvider for RedisIdentifierProvider {
+    async fn acquire(&self) -> Result<IdentifierLease, IdError> {
+        self.acquire_watched().await.map(|(lease, _)| lease)
+    }
+}
+
+/// Key and token of one lease.
+#[derive(Debug)]
+struct Slot {
+    key: String,
+    token: String,
+}
+
+/// Commands of leasing, faked in tests.
+trait LeaseStore: Clone + Send + Sync + 'static {
+    /// Claim the lowest free one of `0..slots`, `None` if all of them are taken.
+    fn claim<'a>(
+        &'a self,
+        namespace: &'a str,
+        token: &'a str,
+        ttl: Duration,
+        slots: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, IdError>>;
+
+    /// Extend it to `ttl`, `false` if it's not held anymore.
+    fn refresh<'a>(&'a self, slot: &'a Slot, ttl: Duration)
+        -> BoxFuture<'a, Result<bool, IdError>>;
+
+    fn release<'a>(&'a self, slot: &'a Slot) -> BoxFuture<'a, Result<(), IdError>>;
+}
+
+#[derive(Clone)]
+struct RedisStore(MultiplexedConnection);
+
+impl LeaseStore for RedisStore {
+    fn claim<'a>(
+        &'a self,
+        namespace: &'a str,
+        token: &'a str,
+        ttl: Duration,
+        slots: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, IdError>> {
+        let mut connection = self.0.clone();
+        Box::pin(async move {
+            let mut invocation = CLAIM.prepare_invoke();
+            invocation
+                .key(namespace)
+                .arg(token)
+                .arg(ttl.as_millis() as u64)
+                .arg(slots);
+            let slot = invocation
+                .invoke_async::<i64>(&mut connection)
+                .await
+                .map_err(backend)?;
+            Ok(u64::try_from(slot).ok())
+        })
+    }
+
+    fn refresh<'a>(
+        &'a self,
+        slot: &'a Slot,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<bool, IdError>> {
+        let mut connection = self.0.clone();
+        Box::pin(async move {
+            let mut invocation = REFRESH.prepare_invoke();
+            invocation
+                .key(&slot.key)
+                .arg(&slot.token)
+                .arg(ttl.as_millis() as u64);
+            let refreshed = invocation
+                .invoke_async::<i64>(&mut connection)
+                .await
+                .map_err(backend)?;
+            Ok(refreshed == 1)
+        })
+    }
+
+    fn release<'a>(&'a self, slot: &'a Slot) -> BoxFuture<'a, Result<(), IdError>> {
+        let mut connection = self.0.clone();
+        Box::pin(async move {
+            let mut invocation = RELEASE.prepare_invoke();
+            invocation.key(&slot.key).arg(&slot.token);
+            invocation
+                .invoke_async::<i64>(&mut connection)
+                .await
+                .map(|_| ())
+                .map_err(backend)
+        })
+    }
+}
+
+async fn acquire_in<S>(
+    store: S,
+    leasing: &Leasing,
+) -> Result<(IdentifierLease, LeaseWatch), IdError>
+where
+    S: LeaseStore,
+{
+    let token = format!("{:032x}", rand::random::<u128>());
+    let claimed = Instant::now();
+    let identifier = store
+        .claim(&leasing.namespace, &token, leasing.ttl, leasing.slots)
+        .await?
+        .ok_or(IdError::Exhausted)?;
+
+    let slot = Arc::new(Slot {
+        key: format!("{}:{identifier}", leasing.namespace),
+        token,
+    });
+    let (keep_alive, watch) =
+        KeepAlive::new(identifier, leasing.ttl, claimed, leasing.on_lost.clone());
+    let ttl = leasing.ttl;
+    let handle = keep_alive.spawn(
+        {
+            let (store, slot) = (store.clone(), slot.clone());
+            move || {
+                let (store, slot) = (store.clone(), slot.clone());
+                async move { store.refresh(&slot, ttl).await }
+            }
+        },
+        move || {
+            let (store, slot) = (store.clone(), slot.clone());
+            Box::pin(async move { store.release(&slot).await })
+        },
+    );
+    Ok((IdentifierLease::new(identifier, handle), watch))
+}
+
+/// [`HeartbeatStore`](HeartbeatStore) of Redis, a hash `{namespace}:{identifier}` of instances to their heartbeats.
+///
+/// The hash expires after `retention` (1 hour by default) without heartbeats,
+/// and heartbeats older than that by the clock of the writer are removed.
+#[derive(Clone)]
+pub struct RedisHeartbeatStore {
+    client: Client,
+    namespace: String,
+    retention: Duration,
+    connection: Arc<OnceCell<MultiplexedConnection>>,
+}
+
+impl RedisHeartbeatStore {
+    /// Heartbeating under `namespace`.
+    pub fn new(client: Client, namespace: impl Into<String>) -> Self {
+        Self {
+            client,
+            namespace: namespace.into(),
+            retention: Duration::from_secs(3600),
+            connection: Arc::default(),
+        }
+    }
+
+    /// Keep heartbeats for `retention` instead, which should be well beyond the interval.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+}
+
+impl fmt::Debug for RedisHeartbeatStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisHeartbeatStore")
+            .field("namespace", &self.namespace)
+            .field("retention", &self.retention)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HeartbeatStore for RedisHeartbeatStore {
+    fn beat(
+        &self,
+        identifier: u64,
+        heartbeat: Heartbeat,
+    ) -> BoxFuture<'_, Result<Vec<Heartbeat>, IdError>> {
+        Box::pin(async move {
+            let mut connection = self
+                .connection
+                .get_or_try_init(|| self.client.get_multiplexed_async_connection())
+                .await
+                .map_err(backend)?
+                .clone();
+            let key = format!("{}:{identifier}", self.namespace);
+            let (heartbeats,): (HashMap<String, u64>,) = redis::pipe()
+                .atomic()
+                .hset(
+                    &key,
+                    format!("{:032x}", heartbeat.instance),
+                    heartbeat.timestamp,
+                )
+                .ignore()
+                .pexpire(&key, self.retention.as_millis() as i64)
+                .ignore()
+                .hgetall(&key)
+                .query_async(&mut connection)
+                .await
+                .map_err(backend)?;
+
+            let oldest = heartbeat
+                .timestamp
+                .saturating_sub(self.retention.as_millis() as u64);
+            let (fresh, stale): (Vec<_>, Vec<_>) = heartbeats
+                .into_iter()
+                .partition(|(_, timestamp)| *timestamp >= oldest);
+            if !stale.is_empty() {
+                let fields = stale.into_iter().map(|(it, _)| it).collect::<Vec<_>>();
+                redis::cmd("HDEL")
+                    .arg(&key)
+                    .arg(fields)
+                    .query_async::<()>(&mut connection)
+                    .await
+                    .map_err(backend)?;
+            }
+
+            Ok(fresh
+                .into_iter()
+                .filter_map(|(instance, timestamp)| {
+                    Some(Heartbeat {
+                        instance: u128::from_str_radix(&instance, 16).ok()?,
+                        timestamp,
+                    })
+                })
+                .collect())
+        })
+    }
+}
+
+fn backend(err: redis::RedisError) -> IdError {
+    IdError::Backend {
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Mutex,
+        },
+    };
+
+    use super::*;
+
+    /// Keys of Redis with expiry, on the clock of Tokio.
+    #[derive(Clone, Default)]
+    struct Fake {
+        keys: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl Fake {
+        fn holder(&self, key: &str) -> Option<String> {
+            let keys = self.keys.lock().unwrap();
+            keys.get(key)
+                .filter(|(_, expiry)| *expiry > Instant::now())
+                .map(|(token, _)| token.clone())
+        }
+
+        fn check(&self) -> Result<(), IdError> {
+            match self.failing.load(Ordering::Relaxed) {
+                true => Err(IdError::Backend {
+                    message: "connection refused".to_owned(),
+                }),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl LeaseStore for Fake {
+        fn claim<'a>(
+            &'a self,
+            namespace: &'a str,
+            token: &'a str,
+            ttl: Duration,
+            slots: u64,
+        ) -> BoxFuture<'a, Result<Option<u64>, IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                let now = Instant::now();
+                let mut keys = self.keys.lock().unwrap();
+                let slot = (0..slots).find(|slot| {
+                    keys.get(&format!("{namespace}:{slot}"))
+                        .is_none_or(|(_, expiry)| *expiry <= now)
+                });
+                if let Some(slot) = slot {
+                    keys.insert(format!("{namespace}:{slot}"), (token.to_owned(), now + ttl));
+                }
+                Ok(slot)
+            })
+        }
+
+        fn refresh<'a>(
+            &'a self,
+            slot: &'a Slot,
+            ttl: Duration,
+        ) -> BoxFuture<'a, Result<bool, IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                let held = self.holder(&slot.key).as_ref() == Some(&slot.token);
+                if held {
+                    let mut keys = self.keys.lock().unwrap();
+                    keys.insert(slot.key.clone(), (slot.token.clone(), Instant::now() + ttl));
+                }
+                Ok(held)
+            })
+        }
+
+        fn release<'a>(&'a self, slot: &'a Slot) -> BoxFuture<'a, Result<(), IdError>> {
+            Box::pin(async move {
+                self.check()?;
+                if self.holder(&slot.key).as_ref() == Some(&slot.token) {
+                    self.keys.lock().unwrap().remove(&slot.key);
+                }
+                Ok(())
+            })
+        }
+    }
+
+    fn leasing(slots: u64) -> Leasing {
+        Leasing {
+            namespace: "ids".to_owned(),
+            ttl: Duration::from_secs(30),
+            slots,
+            on_lost: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire_release() {
+        let store = Fake::default();
+        let leasing = leasing(2);
+        let (first, _) = acquire_in(store.clone(), &leasing).await.unwrap();
+        let (second, _) = acquire_in(store.clone(), &leasing).await.unwrap();
+        assert_eq!((first.identifier(), second.identifier()), (0, 1));
+        assert!(matches!(
+            acquire_in(store.clone(), &leasing).await,
+            Err(IdError::Exhausted)
+        ));
+
+        // Kept alive well beyond ttl.
+        tokio::time::sleep(Duration::from_secs(300)).await;
+        assert!(store.holder("ids:0").is_some());
+
+        first.release().await.unwrap();
+        assert_eq!(store.holder("ids:0"), None);
+        let (reclaimed, _) = acquire_in(store.clone(), &leasing).await.unwrap();
+        assert_eq!(reclaimed.identifier(), 0);
+        assert!(first.release().await.is_err());
+        assert!(store.holder("ids:0").is_some());
+
+        // Dropping releases too.
+        drop(second);
+        tokio::task::yield_now().await;
+        assert_eq!(store.holder("ids:1"), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_contention() {
+        let store = Fake::default();
+        let leasing = leasing(16);
+        let claims = (0..2)
+            .map(|_| {
+                let (store, leasing) = (store.clone(), leasing.clone());
+                tokio::spawn(async move {
+                    let mut leases = Vec::new();
+                    while let Ok((lease, _)) = acquire_in(store.clone(), &leasing).await {
+                        leases.push(lease);
+                    }
+                    leases
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut identifiers = Vec::new();
+        for claim in claims {
+            identifiers.extend(claim.await.unwrap().iter().map(|it| it.identifier()));
+        }
+        identifiers.sort();
+        assert_eq!(identifiers, (0..16).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lease_lost() {
+        let store = Fake::default();
+        let lost = Arc::new(AtomicU64::new(u64::MAX));
+        let leasing = Leasing {
+            on_lost: Some(Arc::new({
+                let lost = lost.clone();
+                move |it| lost.store(it, Ordering::Relaxed)
+            })),
+            ..leasing(4)
+        };
+        let (_first, _) = acquire_in(store.clone(), &leasing).await.unwrap();
+        let (second, mut watch) = acquire_in(store.clone(), &leasing).await.unwrap();
+        assert_eq!(watch.check(), Ok(()));
+
+        // Taken by someone else after expiring.
+        store.keys.lock().unwrap().insert(
+            "ids:1".to_owned(),
+            ("other".to_owned(), Instant::now() + Duration::from_secs(60)),
+        );
+        assert_eq!(watch.lost().await, Some(IdError::Lost { identifier: 1 }));
+        assert_eq!(watch.check(), Err(IdError::Lost { identifier: 1 }));
+        assert_eq!(lost.load(Ordering::Relaxed), 1);
+
+        // Releasing the lost one leaves the new holder alone.
+        second.release().await.unwrap();
+        assert_eq!(store.holder("ids:1").as_deref(), Some("other"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backend_down() {
+        let store = Fake::default();
+        let leasing = leasing(4);
+        let started = Instant::now();
+        let (_lease, mut watch) = acquire_in(store.clone(), &leasing).await.unwrap();
+
+        // Retrying until ttl since the last refresh.
+        store.failing.store(true, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        assert_eq!(watch.check(), Ok(()));
+        assert_eq!(watch.lost().await, Some(IdError::Lost { identifier: 0 }));
+        assert_eq!(started.elapsed(), leasing.ttl);
+
+        // Released before lost.
+        store.failing.store(false, Ordering::Relaxed);
+        let (lease, mut watch) = acquire_in(store.clone(), &leasing).await.unwrap();
+        lease.release().await.unwrap();
+        assert_eq!(watch.lost().await, None);
+    }
+}
@@ -0,0 +1,332 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Ordering, PersistedSnowflakeGenerator, SnowflakeError, SnowflakeGeneratorBuilder, TimeProvider,
+};
+
+/// [`PersistedSnowflakeGenerator`](PersistedSnowflakeGenerator) per key, like a tenant, each with its own identifier from a pool.
+///
+/// Generators are built from the template on the first [`GeneratorRegistry::get_or_create`](GeneratorRegistry::get_or_create) of a key,
+/// and the same one is returned for the key after that, even for concurrent calls.
+/// Once all identifiers of the pool are taken, new keys get [`SnowflakeError::IdentifierRangeExhausted`](SnowflakeError::IdentifierRangeExhausted).
+///
+/// Generators not asked for within ttl (10 minutes by default) are evicted, giving their identifiers back to the pool.
+/// Only ones nobody else holds (clones included) are evicted, so an identifier never has two live generators. The next generator
+/// taking it continues from where the evicted one stopped.
+///
+/// ```
+/// # use snowflake_ng::{provider::StdProvider, GeneratorRegistry, SnowflakeGenerator};
+/// let registry = GeneratorRegistry::new(SnowflakeGenerator::builder().provider(StdProvider)).with_identifiers(0..64);
+///
+/// let generator = registry.get_or_create("tenant-a").unwrap();
+/// assert_eq!(generator.identifier(), 0);
+/// assert_eq!(registry.get_or_create("tenant-b").unwrap().identifier(), 1);
+/// assert_eq!(registry.get_or_create("tenant-a").unwrap().identifier(), 0);
+/// ```
+#[derive(Debug)]
+pub struct GeneratorRegistry<T> {
+    template: SnowflakeGeneratorBuilder<Arc<T>>,
+    identifiers: Range<u64>,
+    ttl: Duration,
+    state: Mutex<RegistryState<T>>,
+}
+
+#[derive(Debug)]
+struct RegistryState<T> {
+    generators: HashMap<String, Entry<T>>,
+    /// Next identifier never handed out.
+    next: u64,
+    /// Identifiers given back, with the last state word of their generators.
+    free: BTreeMap<u64, u64>,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    generator: Arc<PersistedSnowflakeGenerator<T>>,
+    used: Instant,
+}
+
+impl<T> GeneratorRegistry<T>
+where
+    T: TimeProvider + Send + Sync,
+{
+    /// Building generators from `template`, whose identifier is replaced by ones from the pool.
+    ///
+    /// The pool holds all identifiers of its layout by default.
+    pub fn new(template: SnowflakeGeneratorBuilder<Arc<T>>) -> Self {
+        let max_identifier = template.cfg().layout.max_identifier();
+        Self {
+            template,
+            identifiers: 0..max_identifier.saturating_add(1),
+            ttl: Duration::from_secs(10 * 60),
+            state: Mutex::new(RegistryState {
+                generators: HashMap::new(),
+                next: 0,
+                free: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Taking identifiers from `identifiers` instead, e.g. a slice of the layout reserved for this node.
+    pub fn with_identifiers(self, identifiers: Range<u64>) -> Self {
+        let state = self
+            .state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        Self {
+            state: Mutex::new(RegistryState {
+                next: identifiers.start,
+                ..state
+            }),
+            identifiers,
+            ..self
+        }
+    }
+
+    /// Evict generators not asked for within `ttl` instead.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        Self { ttl, ..self }
+    }
+
+    /// Generator of `key`, building one with a free identifier if there is none.
+    ///
+    /// Idle generators are evicted first if the pool is used up.
+    pub fn get_or_create(
+        &self,
+        key: impl Into<String>,
+    ) -> Result<Arc<PersistedSnowflakeGenerator<T>>, SnowflakeError> {
+        let key = key.into();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        if let Some(entry) = state.generators.get_mut(&key) {
+            entry.used = now;
+            return Ok(entry.generator.clone());
+        }
+
+        if state.free.is_empty() && state.next >= self.identifiers.end {
+            self.evict(&mut state, now);
+        }
+        let (identifier, last) = match state.free.pop_first() {
+            Some(it) => it,
+            None if state.next < self.identifiers.end => {
+                state.next += 1;
+                (state.next - 1, 0)
+            }
+            None => {
+                return Err(SnowflakeError::IdentifierRangeExhausted {
+                    start: self.identifiers.start,
+                    end: self.identifiers.end,
+                })
+            }
+        };
+
+        let generator = match self
+            .template
+            .clone()
+            .identifier(identifier)
+            .build_resumed(last)
+        {
+            Ok(it) => Arc::new(it),
+            Err(err) => {
+                state.free.insert(identifier, last);
+                return Err(err);
+            }
+        };
+        state.generators.insert(
+            key,
+            Entry {
+                generator: generator.clone(),
+                used: now,
+            },
+        );
+        Ok(generator)
+    }
+
+    /// Evict idle generators now, returning how many are evicted.
+    pub fn evict_idle(&self) -> usize {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.evict(&mut state, Instant::now())
+    }
+
+    /// How many generators are there.
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .generators
+            .len()
+    }
+
+    /// Whether there is no generator.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn evict(&self, state: &mut RegistryState<T>, now: Instant) -> usize {
+        let RegistryState {
+            generators, free, ..
+        } = state;
+        let before = generators.len();
+        generators.retain(|_, entry| {
+            // Clones of the generator share the inner one, so they're held too.
+            if now.duration_since(entry.used) < self.ttl
+                || Arc::strong_count(&entry.generator) > 1
+                || Arc::strong_count(&entry.generator.generator) > 1
+            {
+                return true;
+            }
+
+            let last = entry
+                .generator
+                .generator
+                .shards
+                .iter()
+                .map(|it| it.load(Ordering::Relaxed))
+                .max()
+                .unwrap_or_default();
+            free.insert(entry.generator.identifier(), last);
+            false
+        });
+
+        before - generators.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{provider::FixedProvider, SnowflakeGenerator, TimestampValidation};
+
+    fn pooled(identifiers: Range<u64>) -> GeneratorRegistry<FixedProvider> {
+        GeneratorRegistry::new(
+            SnowflakeGenerator::builder()
+                .timestamp_validation(TimestampValidation::Off)
+                .provider(FixedProvider::new(100_000)),
+        )
+        .with_identifiers(identifiers)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_registry_concurrent() {
+        let registry = Arc::new(pooled(10..42));
+        let tasks = (0..64)
+            .map(|it| {
+                let registry = registry.clone();
+                tokio::spawn(async move {
+                    (0..100)
+                        .map(|round| {
+                            let key = format!("tenant-{}", (it + round) % 32);
+                            let generator = registry.get_or_create(key.clone()).unwrap();
+                            (
+                                key,
+                                Arc::as_ptr(&generator) as usize,
+                                generator.identifier(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut seen = HashMap::new();
+        for task in tasks {
+            for (key, generator, identifier) in task.await.unwrap() {
+                assert_eq!(
+                    *seen.entry(key).or_insert((generator, identifier)),
+                    (generator, identifier)
+                );
+            }
+        }
+
+        assert_eq!(registry.len(), 32);
+        let identifiers = seen.values().map(|it| it.1).collect::<HashSet<_>>();
+        assert_eq!(identifiers, (10..42).collect());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_registry_eviction() {
+        let registry = pooled(0..2).with_ttl(Duration::ZERO);
+        let first = registry.get_or_create("first").unwrap();
+        let second = registry.get_or_create("second").unwrap();
+        let last = first.assign_many_sync(10).pop().unwrap();
+        assert_eq!(
+            registry.get_or_create("third").unwrap_err(),
+            SnowflakeError::IdentifierRangeExhausted { start: 0, end: 2 }
+        );
+
+        // Held ones are never evicted.
+        assert_eq!(registry.evict_idle(), 0);
+
+        // Evicted on demand, continuing after the evicted one.
+        drop(first);
+        let third = registry.get_or_create("third").unwrap();
+        assert_eq!(third.identifier(), 0);
+        assert!(third.assign_sync() > last);
+        assert_eq!(registry.len(), 2);
+
+        drop((second, third));
+        assert_eq!(registry.evict_idle(), 2);
+        assert!(registry.is_empty());
+        assert_eq!(registry.get_or_create("first").unwrap().identifier(), 0);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_registry_eviction_cloned() {
+        let registry = pooled(0..1).with_ttl(Duration::ZERO);
+        let first = registry.get_or_create("first").unwrap();
+        let cloned = (*first).clone();
+        drop(first);
+
+        // Clones keep assigning, so the identifier isn't given to another.
+        assert_eq!(registry.evict_idle(), 0);
+        assert!(matches!(
+            registry.get_or_create("second"),
+            Err(SnowflakeError::IdentifierRangeExhausted { .. })
+        ));
+
+        let last = cloned.assign_sync();
+        drop(cloned);
+        let second = registry.get_or_create("second").unwrap();
+        assert_eq!(second.identifier(), 0);
+        assert!(second.assign_sync() > last);
+    }
+
+    #[test]
+    fn test_registry_ttl() {
+        let registry = pooled(0..1).with_ttl(Duration::from_secs(3600));
+        let identifier = registry.get_or_create("first").unwrap().identifier();
+        assert_eq!(registry.evict_idle(), 0);
+        assert!(matches!(
+            registry.get_or_create("second"),
+            Err(SnowflakeError::IdentifierRangeExhausted { .. })
+        ));
+        assert_eq!(
+            registry.get_or_create("first").unwrap().identifier(),
+            identifier
+        );
+
+        // Identifier out of layout stays in the pool.
+        let invalid = pooled(1024..1025);
+        assert!(matches!(
+            invalid.get_or_create("first"),
+            Err(SnowflakeError::IdentifierOutOfRange { .. })
+        ));
+        assert!(invalid.is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
+};
+
+use memmap2::MmapRaw;
+
+use crate::{
+    CachePadded, Shards, SnowflakeConfiguration, SnowflakeError, SnowflakeGenerator,
+    SnowflakeLayout, CACHE_LINE,
+};
+
+/// "SNOWSHM" and version 1.
+const MAGIC: u64 = u64::from_be_bytes(*b"SNOWSHM\x01");
+
+/// [`SnowflakeGenerator`](SnowflakeGenerator) whose state lives in a memory-mapped file, shared by processes on one host.
+///
+/// All processes mapping the same file claim from the same state word with the same CAS loop as threads do,
+/// so they share one identifier and one sequence, rather than taking an identifier each.
+/// Put the file on `tmpfs` like `/dev/shm`, it's only touched through memory anyway.
+///
+/// The first process creating it wins, others racing to create it just open it. A file created with
+/// another identifier, epoch, layout or time unit is rejected with [`SnowflakeError::SharedState`](SnowflakeError::SharedState).
+/// The state word only holds the last timestamp and sequence, so it's still right after any process crashed,
+/// and the file can be kept across restarts.
+///
+/// Backfilling with [`SnowflakeGenerator::assign_at`](SnowflakeGenerator::assign_at) and the statistics are still per process,
+/// and sequence randomized with [`SnowflakeConfiguration::randomize_sequence_start`](SnowflakeConfiguration::randomize_sequence_start)
+/// or [`SnowflakeConfiguration::random_sequence`](SnowflakeConfiguration::random_sequence) can't be shared.
+///
+/// Only available on Unix, with feature `shm`.
+///
+/// ```
+/// # use snowflake_ng::{provider::StdProvider, SharedSnowflakeGenerator, SnowflakeConfiguration};
+/// # let path = std::env::temp_dir().join(format!("snowflake-ng-doc-{}", std::process::id()));
+/// // In every worker process.
+/// let generator = SharedSnowflakeGenerator::create(&path, SnowflakeConfiguration::with_identifier(1)).unwrap();
+/// let snowflake = generator.assign_sync(&StdProvider);
+///
+/// // Or without configuration, once it's created.
+/// let other = SharedSnowflakeGenerator::open(&path).unwrap();
+/// assert!(other.assign_sync(&StdProvider) > snowflake);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+///
+/// Clones keep generating from the shared state.
+#[derive(Debug, Clone)]
+pub struct SharedSnowflakeGenerator {
+    generator: SnowflakeGenerator,
+    path: PathBuf,
+}
+
+impl SharedSnowflakeGenerator {
+    /// Generating with `cfg` from state at `path`, creating it if missing.
+    pub fn create(
+        path: impl AsRef<Path>,
+        cfg: SnowflakeConfiguration,
+    ) -> Result<Self, SnowflakeError> {
+        let path = path.as_ref();
+        cfg.validate()?;
+        if cfg.randomize_sequence_start || cfg.random_sequence {
+            return Err(error(path, "randomized sequence can't be shared"));
+        }
+
+        let header = Header::of(&cfg);
+        let mapping = match Mapping::open(path) {
+            Err(SnowflakeError::SharedState { .. }) if !path.exists() => {
+                Mapping::create(path, &header)?
+            }
+            it => it?,
+        };
+        if mapping.header != header {
+            return Err(error(path, "created with another configuration"));
+        }
+
+        Ok(Self::with_mapping(cfg, mapping, path))
+    }
+
+    /// Generating from state at `path` created by [`SharedSnowflakeGenerator::create`](SharedSnowflakeGenerator::create),
+    /// with identifier, epoch, layout and time unit it's created with, and the rest default.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, SnowflakeError> {
+        let path = path.as_ref();
+        let mapping = Mapping::open(path)?;
+        let header = mapping.header;
+        let cfg = SnowflakeConfiguration {
+            epoch: header.epoch,
+            layout: header.layout,
+            time_unit: Duration::from_micros(header.unit_micros),
+            ..SnowflakeConfiguration::with_identifier(header.identifier)
+        };
+
+        Ok(Self::with_mapping(cfg, mapping, path))
+    }
+
+    fn with_mapping(cfg: SnowflakeConfiguration, mapping: Mapping, path: &Path) -> Self {
+        let mut generator = SnowflakeGenerator::sharded(mapping.header.shards as u8, cfg);
+        generator.shards = Shards::Mapped(Arc::new(mapping));
+        Self {
+            generator,
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Where the state is.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The generator, still generating from the shared state, e.g. for wrapping it in [`PersistedSnowflakeGenerator`](crate::PersistedSnowflakeGenerator).
+    pub fn into_inner(self) -> SnowflakeGenerator {
+        self.generator
+    }
+}
+
+impl Deref for SharedSnowflakeGenerator {
+    type Target = SnowflakeGenerator;
+
+    fn deref(&self) -> &Self::Target {
+        &self.generator
+    }
+}
+
+/// What shapes [`Snowflake`](crate::Snowflake), written once on the first cache line of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    identifier: u64,
+    epoch: u64,
+    unit_micros: u64,
+    layout: SnowflakeLayout,
+    shards: u64,
+}
+
+impl Header {
+    fn of(cfg: &SnowflakeConfiguration) -> Self {
+        Self {
+            identifier: cfg.identifier(),
+            epoch: cfg.epoch,
+            unit_micros: cfg.unit_micros(),
+            layout: cfg.layout,
+            shards: 1,
+        }
+    }
+
+    fn encode(&self) -> [u64; 6] {
+        let layout = self.layout;
+        [
+            MAGIC,
+            self.identifier,
+            self.epoch,
+            self.unit_micros,
+            layout.timestamp_bits() as u64
+                | (layout.identifier_bits() as u64) << 8
+                | (layout.sequence_bits() as u64) << 16
+                | (layout.has_rollback_flag() as u64) << 24,
+            self.shards,
+        ]
+    }
+
+    fn decode(words: [u64; 6]) -> Option<Self> {
+        let [magic, identifier, epoch, unit_micros, layout, shards] = words;
+        if magic != MAGIC || unit_micros == 0 || !(1..=u8::MAX as u64).contains(&shards) {
+            return None;
+        }
+
+        let bits = |offset: u32| (layout >> offset) as u8 as u32;
+        let (timestamp_bits, identifier_bits, sequence_bits) = (bits(0), bits(8), bits(16));
+        let layout = match bits(24) {
+            0 => SnowflakeLayout::new_unsigned(timestamp_bits, identifier_bits, sequence_bits),
+            _ => SnowflakeLayout::new_unsigned(timestamp_bits, identifier_bits + 1, sequence_bits)
+                .and_then(SnowflakeLayout::with_rollback_flag),
+        };
+        Some(Self {
+            identifier,
+            epoch,
+            unit_micros,
+            layout: layout.ok()?,
+            shards,
+        })
+    }
+}
+
+/// The file mapped, header followed by a cache line of every shard.
+#[derive(Debug)]
+pub(crate) struct Mapping {
+    map: MmapRaw,
+    header: Header,
+}
+
+impl Mapping {
+    fn open(path: &Path) -> Result<Self, SnowflakeError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|err| error(path, err))?;
+        let map = MmapRaw::map_raw(&file).map_err(|err| error(path, err))?;
+
+        let header = (map.len() >= CACHE_LINE)
+            .then(|| {
+                // Never written after it's published.
+                let words = map.as_ptr().cast::<[u64; 6]>();
+                Header::decode(unsafe { words.read() })
+            })
+            .flatten()
+            .filter(|it| map.len() == CACHE_LINE * (1 + it.shards as usize))
+            .ok_or_else(|| error(path, "not a shared state"))?;
+        Ok(Self { map, header })
+    }
+
+    /// Write a file aside and link it at `path`, so it's never seen half written, and only the first one is linked.
+    fn create(path: &Path, header: &Header) -> Result<Self, SnowflakeError> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(format!(".{:016x}.tmp", rand::random::<u64>()));
+
+        let write = || -> io::Result<()> {
+            let mut file = fs::File::create(&temporary)?;
+            let bytes = header
+                .encode()
+                .iter()
+                .flat_map(|it| it.to_ne_bytes())
+                .collect::<Vec<_>>();
+            file.write_all(&bytes)?;
+            // Zero state words, as any fresh generator.
+            file.set_len((CACHE_LINE * (1 + header.shards as usize)) as u64)?;
+            match fs::hard_link(&temporary, path) {
+                Err(err) if err.kind() != io::ErrorKind::AlreadyExists => Err(err),
+                _ => Ok(()),
+            }
+        };
+        let written = write();
+        let _ = fs::remove_file(&temporary);
+        written.map_err(|err| error(path, err))?;
+
+        Self::open(path)
+    }
+
+    pub(crate) fn shards(&self) -> &[CachePadded<AtomicU64>] {
+        // Mapping is page aligned, so is every cache line after the header.
+        let first = unsafe { self.map.as_mut_ptr().add(CACHE_LINE) };
+        unsafe { std::slice::from_raw_parts(first.cast(), self.header.shards as usize) }
+    }
+}
+
+fn error(path: &Path, message: impl ToString) -> SnowflakeError {
+    SnowflakeError::SharedState {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, env, process::Command, thread};
+
+    use super::*;
+    use crate::{layouts, provider::StdProvider, testing::temporary};
+
+    #[test]
+    fn test_shared_threads() {
+        let path = temporary("shm-threads");
+        let snowflakes = thread::scope(|scope| {
+            let workers = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        // Each maps on its own, first one creates.
+                        let generator = SharedSnowflakeGenerator::create(
+                            &path,
+                            SnowflakeConfiguration::with_identifier(3),
+                        )
+                        .unwrap();
+                        (0..5000)
+                            .map(|_| generator.assign_sync(&StdProvider))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(snowflakes.iter().collect::<HashSet<_>>().len(), 8 * 5000);
+        assert!(snowflakes.iter().all(|it| it.identifier() == 3));
+
+        // Continuing after all of them are gone.
+        let last = snowflakes.iter().max().unwrap();
+        let generator = SharedSnowflakeGenerator::open(&path).unwrap();
+        assert_eq!(generator.identifier(), 3);
+        assert!(generator.assign_sync(&StdProvider) > *last);
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Run by [`test_shared_processes`](test_shared_processes) in children, writing what it generated.
+    #[test]
+    fn test_shared_child() {
+        let Ok(path) = env::var("SNOWFLAKE_TEST_SHM") else {
+            return;
+        };
+        let generator = SharedSnowflakeGenerator::open(&path).unwrap();
+        let snowflakes = (0..20_000)
+            .map(|_| generator.assign_sync(&StdProvider).to_string())
+            .collect::<Vec<_>>();
+        let output = format!("{path}.{}", std::process::id());
+        fs::write(output, snowflakes.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn test_shared_processes() {
+        let path = temporary("shm-processes");
+        let cfg = SnowflakeConfiguration {
+            layout: layouts::DEFAULT.with_rollback_flag().unwrap(),
+            ..SnowflakeConfiguration::with_identifier(5)
+        };
+        SharedSnowflakeGenerator::create(&path, cfg).unwrap();
+
+        let children = (0..4)
+            .map(|_| {
+                Command::new(env::current_exe().unwrap())
+                    .args([
+                        "--exact",
+                        "shm::tests::test_shared_child",
+                        "--test-threads=1",
+                    ])
+                    .env("SNOWFLAKE_TEST_SHM", &path)
+                    .spawn()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut snowflakes = HashSet::new();
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+            let output = format!("{}.{}", path.display(), child.id());
+            for line in fs::read_to_string(&output).unwrap().lines() {
+                assert!(snowflakes.insert(line.to_string()), "duplicated {line}");
+            }
+            fs::remove_file(output).unwrap();
+        }
+
+        assert_eq!(snowflakes.len(), 4 * 20_000);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shared_cloned() {
+        let path = temporary("shm-cloned");
+        let generator =
+            SharedSnowflakeGenerator::create(&path, SnowflakeConfiguration::with_identifier(4))
+                .unwrap();
+        let cloned = generator.clone();
+        let derefed = SnowflakeGenerator::clone(&generator);
+        let opened = SharedSnowflakeGenerator::open(&path).unwrap();
+        drop(generator);
+
+        let mut snowflakes = HashSet::new();
+        for _ in 0..5000 {
+            assert!(snowflakes.insert(cloned.assign_sync(&StdProvider)));
+            assert!(snowflakes.insert(derefed.assign_sync(&StdProvider)));
+            assert!(snowflakes.insert(opened.assign_sync(&StdProvider)));
+        }
+        assert_eq!(cloned.path(), path);
+
+        // With another identifier, it's on its own.
+        let other = cloned.clone_with_identifier(5);
+        assert!(matches!(other.shards, Shards::Owned(_)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shared_rejected() {
+        let path = temporary("shm-rejected");
+        assert!(matches!(
+            SharedSnowflakeGenerator::open(&path),
+            Err(SnowflakeError::SharedState { .. })
+        ));
+
+        SharedSnowflakeGenerator::create(&path, SnowflakeConfiguration::with_identifier(1))
+            .unwrap();
+        let Err(SnowflakeError::SharedState { message, .. }) =
+            SharedSnowflakeGenerator::create(&path, SnowflakeConfiguration::with_identifier(2))
+        else {
+            panic!("created with identifier 1");
+        };
+        assert_eq!(message, "created with another configuration");
+
+        let randomized = SnowflakeConfiguration {
+            randomize_sequence_start: true,
+            ..SnowflakeConfiguration::with_identifier(1)
+        };
+        assert!(SharedSnowflakeGenerator::create(&path, randomized).is_err());
+
+        // Nor something else.
+        fs::write(&path, "not a shared state").unwrap();
+        let Err(SnowflakeError::SharedState { message, .. }) =
+            SharedSnowflakeGenerator::open(&path)
+        else {
+            panic!("opened garbage");
+        };
+        assert_eq!(message, "not a shared state");
+        fs::remove_file(&path).unwrap();
+    }
+}
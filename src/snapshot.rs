@@ -0,0 +1,191 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::{Ordering, SnowflakeConfiguration, SnowflakeError, SnowflakeGenerator, TimeProvider};
+
+/// State of [`SnowflakeGenerator`](SnowflakeGenerator) taken by [`SnowflakeGenerator::snapshot`](SnowflakeGenerator::snapshot),
+/// for replaying or moving it to another process.
+///
+/// With feature `serde`, it can be serialized and deserialized, configuration is validated the same way as
+/// [`SnowflakeConfiguration`](SnowflakeConfiguration#serde).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeneratorState {
+    config: SnowflakeConfiguration,
+    shards: u64,
+    /// Highest state word of shards, `timestamp | rollback | last sequence`.
+    state: u64,
+}
+
+impl GeneratorState {
+    /// Configuration of the generator.
+    pub fn config(&self) -> &SnowflakeConfiguration {
+        &self.config
+    }
+
+    /// Timestamp of the last [`Snowflake`](crate::Snowflake) in milliseconds since UNIX epoch (truncated to tick),
+    /// `None` if nothing generated yet.
+    pub fn timestamp(&self) -> Option<u64> {
+        (self.state != 0).then(|| {
+            self.config
+                .unix_timestamp_of_ticks(self.state >> (self.config.layout.sequence_bits() + 1))
+        })
+    }
+
+    /// Sequence number of the last [`Snowflake`](crate::Snowflake), highest of all shards.
+    pub fn sequence(&self) -> u64 {
+        self.state & self.config.layout.max_sequence()
+    }
+}
+
+impl<S> SnowflakeGenerator<S> {
+    /// Take [`GeneratorState`](GeneratorState) of this generator, see [`SnowflakeGenerator::restore`](SnowflakeGenerator::restore).
+    ///
+    /// [`Snowflake`](crate::Snowflake) generated after taking it are not included, so stop using this generator first.
+    /// Ones of [`SnowflakeGenerator::assign_at_with`](SnowflakeGenerator::assign_at_with) and [`SnowflakeGenerator::backfill`](SnowflakeGenerator::backfill)
+    /// in the past are not tracked either.
+    pub fn snapshot(&self) -> GeneratorState {
+        GeneratorState {
+            config: self.cfg.clone(),
+            shards: self.shards.len() as u64,
+            state: self
+                .shards
+                .iter()
+                .map(|it| it.load(Ordering::Relaxed))
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl SnowflakeGenerator {
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) continuing from `state`, so it never generates at or before it.
+    ///
+    /// Returning [`SnowflakeError::StateInFuture`](SnowflakeError::StateInFuture) if `state` is ahead of `provider`
+    /// beyond [`rollback_tolerance`](SnowflakeConfiguration::rollback_tolerance), which means the clocks disagree.
+    ///
+    /// **Two live generators restored from the same `state` collide**, as they share the identifier and continue from the same point.
+    /// Restore only once, after the original one stopped.
+    ///
+    /// ```
+    /// # use snowflake_ng::{provider::StdProvider, SnowflakeGenerator};
+    /// let generator = SnowflakeGenerator::default();
+    /// let last = generator.assign_sync(&StdProvider);
+    ///
+    /// let restored = SnowflakeGenerator::restore(generator.snapshot(), &StdProvider).unwrap();
+    /// assert!(restored.assign_sync(&StdProvider) > last);
+    /// ```
+    pub fn restore<T>(state: GeneratorState, provider: &T) -> Result<Self, SnowflakeError>
+    where
+        T: TimeProvider + ?Sized,
+    {
+        let GeneratorState {
+            config,
+            shards,
+            state,
+        } = state;
+        let sequence_bits = config.layout.sequence_bits();
+        let timestamp = state >> (sequence_bits + 1);
+        let now = config.ticks_of_micros(config.now_micros(provider));
+        if timestamp > now.saturating_add(config.rollback_tolerance_ticks()) {
+            return Err(SnowflakeError::StateInFuture {
+                timestamp: config.unix_timestamp_of_ticks(timestamp),
+                now: config.unix_timestamp_of_ticks(now),
+            });
+        }
+
+        let mut generator = Self::sharded(shards.min(u8::MAX as u64) as u8, config);
+        if state != 0 {
+            generator.resume(state);
+        }
+
+        Ok(generator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{provider::FixedProvider, TimestampValidation, TryAssignError};
+
+    use super::*;
+
+    fn cfg() -> SnowflakeConfiguration {
+        SnowflakeConfiguration::with_identifier(3)
+            .with_timestamp_validation(TimestampValidation::Off)
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let provider = FixedProvider::new(100_000);
+        let generator = SnowflakeGenerator::with_cfg(cfg());
+        assert_eq!(generator.snapshot().timestamp(), None);
+
+        let snowflakes = generator.assign_many_sync(&provider, 10);
+        let state = generator.snapshot();
+        assert_eq!(state.timestamp(), Some(100_000));
+        assert_eq!(state.sequence(), 9);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(
+                serde_json::from_str::<GeneratorState>(&json).unwrap(),
+                state
+            );
+        }
+
+        // Continuing with the next sequence number.
+        let restored = SnowflakeGenerator::restore(state.clone(), &provider).unwrap();
+        assert_eq!(restored.snapshot(), state);
+        let next = restored.try_assign_now(&provider).unwrap();
+        assert!(next > snowflakes[9]);
+        assert_eq!((next.timestamp(), next.sequence()), (100_000, 10));
+
+        // Clock is behind, but within tolerance.
+        let restored =
+            SnowflakeGenerator::restore(state.clone(), &FixedProvider::new(99_996)).unwrap();
+        assert!(matches!(
+            restored.try_assign_now(&FixedProvider::new(99_996)),
+            Err(TryAssignError::ClockBehind { .. })
+        ));
+        assert!(
+            restored
+                .try_assign_now(&FixedProvider::new(100_001))
+                .unwrap()
+                > snowflakes[9]
+        );
+
+        assert_eq!(
+            SnowflakeGenerator::restore(state, &FixedProvider::new(99_000)).unwrap_err(),
+            SnowflakeError::StateInFuture {
+                timestamp: 100_000,
+                now: 99_000
+            }
+        );
+    }
+
+    #[test]
+    fn test_snapshot_sharded() {
+        let provider = FixedProvider::new(100_000);
+        let generator = SnowflakeGenerator::sharded(4, cfg());
+        let last = generator.assign_sync(&provider);
+
+        let restored = SnowflakeGenerator::restore(generator.snapshot(), &provider).unwrap();
+        assert_eq!(restored.snapshot().shards, 4);
+        // The whole tick is taken.
+        assert!(matches!(
+            restored.try_assign_now(&provider),
+            Err(TryAssignError::SequenceExhausted { .. })
+        ));
+        assert!(
+            restored
+                .try_assign_now(&FixedProvider::new(100_001))
+                .unwrap()
+                > last
+        );
+    }
+}
@@ -0,0 +1,526 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#[cfg(feature = "sync")]
+use std::thread;
+#[cfg(any(feature = "async", feature = "sync"))]
+use std::time::Duration;
+use std::{
+    fmt,
+    ops::Deref,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use rand::RngCore;
+
+use crate::{encoding::DecodeError, SnowflakeError, TimeProvider};
+#[cfg(feature = "async")]
+use crate::{Sleeper, TimerSleeper};
+
+/// 128bit [`Snowflake`](crate::Snowflake), for deployments outgrowing 1024 identifiers.
+///
+/// Ordering is the same as [`Snowflake`](crate::Snowflake): timestamp first, then identifier, then sequence number.
+///
+/// # Serde
+///
+/// With feature `serde`, it's (de)serialized as decimal string, since most JSON consumers can't hold 128bit integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Snowflake128(u128);
+
+impl Snowflake128 {
+    /// Timestamp part with [default layout](SnowflakeLayout128::DEFAULT), relative to the epoch of generator.
+    pub fn timestamp(&self) -> u64 {
+        SnowflakeLayout128::DEFAULT.timestamp_of(self)
+    }
+
+    /// Identifier part with [default layout](SnowflakeLayout128::DEFAULT).
+    pub fn identifier(&self) -> u64 {
+        SnowflakeLayout128::DEFAULT.identifier_of(self)
+    }
+
+    /// Sequence number part with [default layout](SnowflakeLayout128::DEFAULT).
+    pub fn sequence(&self) -> u64 {
+        SnowflakeLayout128::DEFAULT.sequence_of(self)
+    }
+
+    /// Big-endian bytes, byte order is the same as [`Snowflake128`](Snowflake128) order.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Inverse of [`Snowflake128::to_bytes`](Snowflake128::to_bytes).
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(u128::from_be_bytes(bytes))
+    }
+
+    /// Lowercase hex padded to 32 characters, so string order is the same as [`Snowflake128`](Snowflake128) order.
+    pub fn to_hex(&self) -> String {
+        format!("{:032x}", self.0)
+    }
+
+    /// Inverse of [`Snowflake128::to_hex`](Snowflake128::to_hex), accepts uppercase and unpadded input.
+    pub fn from_hex(input: &str) -> Result<Self, DecodeError> {
+        if input.is_empty() {
+            return Err(DecodeError::Empty);
+        }
+        if input.len() > 32 {
+            return Err(DecodeError::TooLong {
+                len: input.len(),
+                max: 32,
+            });
+        }
+
+        let mut value = 0u128;
+        for (index, character) in input.chars().enumerate() {
+            let digit = character
+                .to_digit(16)
+                .ok_or(DecodeError::InvalidCharacter { character, index })?;
+            value = (value << 4) | digit as u128;
+        }
+
+        Ok(Self(value))
+    }
+}
+
+impl From<Snowflake128> for u128 {
+    fn from(value: Snowflake128) -> Self {
+        value.0
+    }
+}
+
+impl From<u128> for Snowflake128 {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl Deref for Snowflake128 {
+    type Target = u128;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for Snowflake128 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Snowflake128 {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Snowflake128 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Snowflake128 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Bit layout of [`Snowflake128`](Snowflake128).
+///
+/// Timestamp and sequence number share one 64bit atomic in generator, so they must fit in 64 bits together,
+/// and identifier is at most 64 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SnowflakeLayout128 {
+    timestamp_bits: u32,
+    identifier_bits: u32,
+    sequence_bits: u32,
+}
+
+impl SnowflakeLayout128 {
+    /// 48bit timestamp, 32bit identifier and 16bit sequence.
+    pub const DEFAULT: SnowflakeLayout128 = SnowflakeLayout128 {
+        timestamp_bits: 48,
+        identifier_bits: 32,
+        sequence_bits: 16,
+    };
+
+    /// Constructing [`SnowflakeLayout128`](SnowflakeLayout128), returning [`SnowflakeError::InvalidLayout128`](SnowflakeError::InvalidLayout128)
+    /// if there is no timestamp bit, timestamp and sequence exceed 64 bits, or identifier exceeds 64 bits.
+    pub const fn new(
+        timestamp_bits: u32,
+        identifier_bits: u32,
+        sequence_bits: u32,
+    ) -> Result<Self, SnowflakeError> {
+        if timestamp_bits == 0 || timestamp_bits + sequence_bits > 64 || identifier_bits > 64 {
+            return Err(SnowflakeError::InvalidLayout128 {
+                timestamp_bits,
+                identifier_bits,
+                sequence_bits,
+            });
+        }
+
+        Ok(Self {
+            timestamp_bits,
+            identifier_bits,
+            sequence_bits,
+        })
+    }
+
+    /// Bits of timestamp.
+    pub const fn timestamp_bits(&self) -> u32 {
+        self.timestamp_bits
+    }
+
+    /// Bits of identifier.
+    pub const fn identifier_bits(&self) -> u32 {
+        self.identifier_bits
+    }
+
+    /// Bits of sequence number.
+    pub const fn sequence_bits(&self) -> u32 {
+        self.sequence_bits
+    }
+
+    /// Largest timestamp can be stored.
+    pub const fn max_timestamp(&self) -> u64 {
+        mask(self.timestamp_bits)
+    }
+
+    /// Largest identifier can be stored.
+    pub const fn max_identifier(&self) -> u64 {
+        mask(self.identifier_bits)
+    }
+
+    /// Largest sequence number can be stored.
+    pub const fn max_sequence(&self) -> u64 {
+        mask(self.sequence_bits)
+    }
+
+    /// Composing [`Snowflake128`](Snowflake128), every part is truncated to its bits.
+    pub const fn compose(&self, timestamp: u64, identifier: u64, sequence: u64) -> Snowflake128 {
+        let timestamp = (timestamp & self.max_timestamp()) as u128;
+        let identifier = (identifier & self.max_identifier()) as u128;
+        let sequence = (sequence & self.max_sequence()) as u128;
+
+        Snowflake128(
+            (timestamp << (self.identifier_bits + self.sequence_bits))
+                | (identifier << self.sequence_bits)
+                | sequence,
+        )
+    }
+
+    /// Timestamp part of `snowflake`.
+    pub const fn timestamp_of(&self, snowflake: &Snowflake128) -> u64 {
+        (snowflake.0 >> (self.identifier_bits + self.sequence_bits)) as u64 & self.max_timestamp()
+    }
+
+    /// Identifier part of `snowflake`.
+    pub const fn identifier_of(&self, snowflake: &Snowflake128) -> u64 {
+        (snowflake.0 >> self.sequence_bits) as u64 & self.max_identifier()
+    }
+
+    /// Sequence number part of `snowflake`.
+    pub const fn sequence_of(&self, snowflake: &Snowflake128) -> u64 {
+        snowflake.0 as u64 & self.max_sequence()
+    }
+}
+
+impl Default for SnowflakeLayout128 {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+const fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Generating [`Snowflake128`](Snowflake128), behaves the same as [`SnowflakeGenerator`](crate::SnowflakeGenerator).
+#[derive(Debug)]
+pub struct SnowflakeGenerator128 {
+    timestamp_sequence: AtomicU64,
+    identifier: u64,
+    epoch: u64,
+    layout: SnowflakeLayout128,
+}
+
+impl SnowflakeGenerator128 {
+    /// Constructing generator with `identifier`, `epoch` (milliseconds since UNIX epoch) and `layout`,
+    /// returning [`SnowflakeError::IdentifierOutOfRange`](SnowflakeError::IdentifierOutOfRange) if `identifier` doesn't fit in `layout`.
+    pub fn new(
+        identifier: u64,
+        epoch: u64,
+        layout: SnowflakeLayout128,
+    ) -> Result<Self, SnowflakeError> {
+        let max = layout.max_identifier();
+        if identifier > max {
+            return Err(SnowflakeError::IdentifierOutOfRange { identifier, max });
+        }
+
+        Ok(Self {
+            timestamp_sequence: AtomicU64::new(0),
+            identifier,
+            epoch,
+            layout,
+        })
+    }
+
+    /// Identifier stamped into generated [`Snowflake128`](Snowflake128).
+    pub fn identifier(&self) -> u64 {
+        self.identifier
+    }
+
+    /// Epoch in milliseconds since UNIX epoch.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Bit layout of generated [`Snowflake128`](Snowflake128).
+    pub fn layout(&self) -> SnowflakeLayout128 {
+        self.layout
+    }
+
+    /// Assign a [`Snowflake128`](Snowflake128) with [`TimeProvider`](TimeProvider)
+    #[cfg(feature = "async")]
+    pub async fn assign<P>(&self, provider: &P) -> Snowflake128
+    where
+        P: TimeProvider + Sync + Send,
+    {
+        loop {
+            match self.assign_now(provider) {
+                Some(it) => return it,
+                None => TimerSleeper.sleep(Duration::from_millis(1)).await,
+            }
+        }
+    }
+
+    /// Assign a [`Snowflake128`](Snowflake128) right now, `None` if it has to wait for next millisecond.
+    fn assign_now<P>(&self, provider: &P) -> Option<Snowflake128>
+    where
+        P: TimeProvider + Sync + Send,
+    {
+        let sequence_bits = self.layout.sequence_bits();
+        let max_sequence = self.layout.max_sequence();
+
+        loop {
+            let timestamp = provider.timestamp().saturating_sub(self.epoch);
+            let current = self.timestamp_sequence.load(Ordering::Relaxed);
+            let current_timestamp = current.checked_shr(sequence_bits).unwrap_or(0);
+            let current_sequence = current & max_sequence;
+
+            let sequence = match current_timestamp.cmp(&timestamp) {
+                std::cmp::Ordering::Less => 0,
+                std::cmp::Ordering::Equal if current_sequence < max_sequence => {
+                    current_sequence + 1
+                }
+                // Sequence reached MAX or clock behind, waiting for next millisecond
+                _ => return None,
+            };
+
+            let new_value = timestamp.checked_shl(sequence_bits).unwrap_or(0) | sequence;
+            if self
+                .timestamp_sequence
+                .compare_exchange(current, new_value, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return Some(self.layout.compose(timestamp, self.identifier, sequence));
+            }
+        }
+    }
+
+    /// Assign a new [`Snowflake128`](Snowflake128) but in synchronous way.
+    #[cfg(feature = "sync")]
+    pub fn assign_sync<P>(&self, provider: &P) -> Snowflake128
+    where
+        P: TimeProvider + Sync + Send,
+    {
+        loop {
+            match self.assign_now(provider) {
+                Some(it) => return it,
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+    }
+}
+
+impl Default for SnowflakeGenerator128 {
+    fn default() -> Self {
+        let layout = SnowflakeLayout128::DEFAULT;
+        let identifier = rand::thread_rng().next_u64() & layout.max_identifier();
+        Self::new(identifier, 0, layout).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "async")]
+    use std::{collections::HashSet, sync::Arc};
+
+    use crate::provider::STD_PROVIDER;
+
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(
+            SnowflakeLayout128::new(48, 32, 16),
+            Ok(SnowflakeLayout128::DEFAULT)
+        );
+        assert!(SnowflakeLayout128::new(64, 64, 0).is_ok());
+
+        for (timestamp_bits, identifier_bits, sequence_bits) in
+            [(0, 32, 16), (48, 32, 17), (40, 65, 8)]
+        {
+            assert_eq!(
+                SnowflakeLayout128::new(timestamp_bits, identifier_bits, sequence_bits),
+                Err(SnowflakeError::InvalidLayout128 {
+                    timestamp_bits,
+                    identifier_bits,
+                    sequence_bits
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_compose() {
+        let layout = SnowflakeLayout128::DEFAULT;
+        let snowflake = layout.compose(1234, 100_000, 42);
+        assert_eq!(*snowflake, (1234 << 48) | (100_000 << 16) | 42);
+        assert_eq!(snowflake.timestamp(), 1234);
+        assert_eq!(snowflake.identifier(), 100_000);
+        assert_eq!(snowflake.sequence(), 42);
+
+        let layout = SnowflakeLayout128::new(64, 64, 0).unwrap();
+        let snowflake = layout.compose(u64::MAX, 1, 0);
+        assert_eq!(layout.timestamp_of(&snowflake), u64::MAX);
+        assert_eq!(layout.identifier_of(&snowflake), 1);
+
+        // Ordering follows timestamp first.
+        let layout = SnowflakeLayout128::DEFAULT;
+        assert!(layout.compose(1, 0, 0) > layout.compose(0, u32::MAX as u64, 65535));
+    }
+
+    #[test]
+    fn test_encodings() {
+        let snowflake = SnowflakeLayout128::DEFAULT.compose(1234, 100_000, 42);
+
+        assert_eq!(Snowflake128::from_bytes(snowflake.to_bytes()), snowflake);
+        assert_eq!(snowflake.to_hex().len(), 32);
+        assert_eq!(Snowflake128::from_hex(&snowflake.to_hex()), Ok(snowflake));
+        assert_eq!(
+            Snowflake128::from_hex(&snowflake.to_hex().to_uppercase()),
+            Ok(snowflake)
+        );
+        assert_eq!(Snowflake128::from_hex("ff"), Ok(Snowflake128(255)));
+        assert_eq!(snowflake.to_string().parse(), Ok(snowflake));
+
+        assert_eq!(Snowflake128::from_hex(""), Err(DecodeError::Empty));
+        assert_eq!(
+            Snowflake128::from_hex(&"0".repeat(33)),
+            Err(DecodeError::TooLong { len: 33, max: 32 })
+        );
+        assert_eq!(
+            Snowflake128::from_hex("0g"),
+            Err(DecodeError::InvalidCharacter {
+                character: 'g',
+                index: 1
+            })
+        );
+
+        let small = Snowflake128(1);
+        let large = Snowflake128(u128::MAX - 1);
+        assert!(small.to_hex() < large.to_hex());
+        assert!(small.to_bytes() < large.to_bytes());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let snowflake = Snowflake128(u128::MAX);
+        let json = serde_json::to_string(&snowflake).unwrap();
+        assert_eq!(json, format!("\"{}\"", u128::MAX));
+        assert_eq!(
+            serde_json::from_str::<Snowflake128>(&json).unwrap(),
+            snowflake
+        );
+        assert!(serde_json::from_str::<Snowflake128>("\"-1\"").is_err());
+        assert!(serde_json::from_str::<Snowflake128>("1").is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        let layout = SnowflakeLayout128::new(48, 8, 16).unwrap();
+        assert_eq!(
+            SnowflakeGenerator128::new(256, 0, layout).unwrap_err(),
+            SnowflakeError::IdentifierOutOfRange {
+                identifier: 256,
+                max: 255
+            }
+        );
+        assert!(SnowflakeGenerator128::default().identifier() <= u32::MAX as u64);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_assign_multithread() {
+        const EPOCH: u64 = 1577836800000;
+        let identifier = 1 << 20;
+        let generator = Arc::new(
+            SnowflakeGenerator128::new(identifier, EPOCH, SnowflakeLayout128::DEFAULT).unwrap(),
+        );
+
+        let tasks = (0..100).map(|_| {
+            let generator = generator.clone();
+            tokio::spawn(async move {
+                let mut snowflakes = Vec::with_capacity(1000);
+                for _ in 0..1000 {
+                    snowflakes.push(generator.assign(&STD_PROVIDER).await);
+                }
+                snowflakes
+            })
+        });
+
+        let snowflakes = futures::future::join_all(tasks)
+            .await
+            .into_iter()
+            .flat_map(Result::unwrap)
+            .collect::<Vec<_>>();
+
+        let now = STD_PROVIDER.timestamp();
+        assert!(snowflakes.iter().all(|it| it.identifier() == identifier));
+        assert!(snowflakes
+            .iter()
+            .all(|it| (it.timestamp() + EPOCH).abs_diff(now) < 60_000));
+        assert_eq!(
+            snowflakes.into_iter().collect::<HashSet<_>>().len(),
+            100 * 1000
+        );
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_assign_sync() {
+        let generator = SnowflakeGenerator128::default();
+        let snowflakes = (0..10000)
+            .map(|_| generator.assign_sync(&STD_PROVIDER))
+            .collect::<Vec<_>>();
+
+        assert!(snowflakes.windows(2).all(|it| it[0] < it[1]));
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#[cfg(feature = "async")]
+use futures::future::BoxFuture;
+
+#[cfg(feature = "async")]
+use crate::Sleeper;
+#[cfg(any(feature = "async", feature = "sync"))]
+use crate::Snowflake;
+use crate::{PersistedSnowflakeGenerator, RateLimitedGenerator, TimeProvider};
+
+/// Anything handing out [`Snowflake`](Snowflake), so application code can depend on `Arc<dyn SnowflakeSource>`
+/// and substitute a deterministic one in tests.
+///
+/// It's object safe, async assignment is boxed for that.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use snowflake_ng::{provider::StdProvider, PersistedSnowflakeGenerator, Snowflake, SnowflakeGenerator, SnowflakeSource};
+/// fn create_user(ids: &dyn SnowflakeSource) -> Snowflake {
+///     ids.assign_sync()
+/// }
+///
+/// let ids: Arc<dyn SnowflakeSource> = Arc::new(PersistedSnowflakeGenerator::new(
+///     Arc::new(SnowflakeGenerator::default()),
+///     Arc::new(StdProvider),
+/// ));
+/// create_user(&*ids);
+/// ```
+pub trait SnowflakeSource: Send + Sync {
+    /// Assign a [`Snowflake`](Snowflake).
+    #[cfg(feature = "async")]
+    fn assign(&self) -> BoxFuture<'_, Snowflake>;
+
+    /// Assign a [`Snowflake`](Snowflake) but in synchronous way.
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake;
+}
+
+#[cfg(feature = "async")]
+impl<T, S> SnowflakeSource for PersistedSnowflakeGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+    S: Sleeper,
+{
+    fn assign(&self) -> BoxFuture<'_, Snowflake> {
+        Box::pin(PersistedSnowflakeGenerator::assign(self))
+    }
+
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake {
+        PersistedSnowflakeGenerator::assign_sync(self)
+    }
+}
+
+#[cfg(not(feature = "async"))]
+impl<T, S> SnowflakeSource for PersistedSnowflakeGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+    S: Send + Sync,
+{
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake {
+        PersistedSnowflakeGenerator::assign_sync(self)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T, S> SnowflakeSource for RateLimitedGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+    S: Sleeper,
+{
+    fn assign(&self) -> BoxFuture<'_, Snowflake> {
+        Box::pin(RateLimitedGenerator::assign(self))
+    }
+
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake {
+        RateLimitedGenerator::assign_sync(self)
+    }
+}
+
+#[cfg(not(feature = "async"))]
+impl<T, S> SnowflakeSource for RateLimitedGenerator<T, S>
+where
+    T: TimeProvider + Send + Sync,
+    S: Send + Sync,
+{
+    #[cfg(feature = "sync")]
+    fn assign_sync(&self) -> Snowflake {
+        RateLimitedGenerator::assign_sync(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    };
+
+    use crate::{global::GlobalSource, SnowflakeConfiguration, SnowflakeGenerator};
+
+    use super::*;
+
+    /// Handing out 1, 2, 3...
+    struct CountingTestSource(AtomicI64);
+
+    impl SnowflakeSource for CountingTestSource {
+        #[cfg(feature = "async")]
+        fn assign(&self) -> BoxFuture<'_, Snowflake> {
+            Box::pin(async { self.assign_sync() })
+        }
+
+        fn assign_sync(&self) -> Snowflake {
+            Snowflake(self.0.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+    }
+
+    fn sources() -> Vec<Arc<dyn SnowflakeSource>> {
+        let persisted = PersistedSnowflakeGenerator::new(
+            Arc::new(SnowflakeGenerator::with_cfg(
+                SnowflakeConfiguration::with_identifier(3),
+            )),
+            Arc::new(crate::provider::StdProvider),
+        );
+
+        vec![
+            Arc::new(persisted.clone()),
+            Arc::new(RateLimitedGenerator::new(persisted, 1000)),
+            Arc::new(GlobalSource),
+            Arc::new(CountingTestSource(AtomicI64::new(0))),
+        ]
+    }
+
+    #[test]
+    fn test_source() {
+        for source in sources() {
+            let first = source.assign_sync();
+            assert!(source.assign_sync() > first);
+        }
+
+        let counting = CountingTestSource(AtomicI64::new(0));
+        let ids: &dyn SnowflakeSource = &counting;
+        assert_eq!(*ids.assign_sync(), 1);
+        assert_eq!(*ids.assign_sync(), 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_source_async() {
+        for source in sources() {
+            let first = source.assign().await;
+            assert!(source.assign().await > first);
+        }
+    }
+}
@@ -0,0 +1,706 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{collections::HashSet, fmt, sync::Arc, time::Duration};
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use futures::future::BoxFuture;
+use sqlx::{PgPool, SqlitePool};
+use tokio::{sync::OnceCell, time::Instant};
+
+use crate::{
+    identifier,
+    lease::keep_alive::{KeepAlive, OnLeaseLost},
+    Heartbeat, HeartbeatStore, IdError, IdentifierLease, IdentifierProvider, LeaseWatch,
+};
+
+/// Database of [`SqlIdentifierProvider`](SqlIdentifierProvider), converted from its pool.
+#[derive(Debug, Clone)]
+pub enum SqlPool {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+impl From<PgPool> for SqlPool {
+    fn from(value: PgPool) -> Self {
+        Self::Postgres(value)
+    }
+}
+
+impl From<SqlitePool> for SqlPool {
+    fn from(value: SqlitePool) -> Self {
+        Self::Sqlite(value)
+    }
+}
+
+/// [`IdentifierProvider`](IdentifierProvider) claiming rows of a table, for where a database is the only thing shared.
+///
+/// The table is created if missing:
+///
+/// ```sql
+/// CREATE TABLE {table_name} (identifier SMALLINT PRIMARY KEY, owner TEXT NOT NULL, heartbeat TIMESTAMP NOT NULL)
+/// ```
+///
+/// The lowest identifier without row or with heartbeat older than `stale_after` (30 seconds by default) is claimed
+/// in a transaction, then its heartbeat is updated every third of `stale_after` by a Tokio task until released.
+/// Heartbeats are UTC of the local clock, so clocks of the fleet should be kept in sync.
+///
+/// Once heartbeats keep failing until stale, or the row is claimed by someone else, the lease is lost:
+/// watch it with [`SqlIdentifierProvider::acquire_watched`](SqlIdentifierProvider::acquire_watched)
+/// or [`SqlIdentifierProvider::on_lease_lost`](SqlIdentifierProvider::on_lease_lost).
+///
+/// ```no_run
+/// # use snowflake_ng::{IdentifierProvider, SqlIdentifierProvider};
+/// # async fn run() {
+/// let pool = sqlx::PgPool::connect("postgres://localhost/app").await.unwrap();
+/// let provider = SqlIdentifierProvider::new(pool, "snowflake_identifiers");
+/// let lease = provider.acquire().await.unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SqlIdentifierProvider {
+    table: Table,
+    leasing: Leasing,
+}
+
+#[derive(Clone)]
+struct Leasing {
+    stale_after: Duration,
+    capacity: u64,
+    on_lost: Option<OnLeaseLost>,
+}
+
+impl SqlIdentifierProvider {
+    /// Claiming identifiers `0..1024` in table `table_name` of `pool`, Postgres or SQLite.
+    pub fn new(pool: impl Into<SqlPool>, table_name: impl Into<String>) -> Self {
+        Self {
+            table: Table {
+                pool: pool.into(),
+                name: table_name.into(),
+            },
+            leasing: Leasing {
+                stale_after: Duration::from_secs(30),
+                capacity: 1024,
+                on_lost: None,
+            },
+        }
+    }
+
+    /// Claiming identifiers `0..capacity` instead, at most `0..32768` of `SMALLINT`.
+    pub fn with_capacity(mut self, capacity: u64) -> Self {
+        self.leasing.capacity = capacity.min(1 << 15);
+        self
+    }
+
+    /// Rows without heartbeat for `stale_after` are reclaimable, which is also how long heartbeats may keep failing.
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.leasing.stale_after = stale_after;
+        self
+    }
+
+    /// Call `callback` with the identifier once a lease is lost.
+    pub fn on_lease_lost(mut self, callback: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.leasing.on_lost = Some(Arc::new(callback));
+        self
+    }
+
+    /// Same as [`IdentifierProvider::acquire`](IdentifierProvider::acquire), but also watching whether the lease is lost.
+    pub async fn acquire_watched(&self) -> Result<(IdentifierLease, LeaseWatch), IdError> {
+        acquire_in(&self.table, &self.leasing).await
+    }
+}
+
+impl fmt::Debug for SqlIdentifierProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqlIdentifierProvider")
+            .field("pool", &self.table.pool)
+            .field("table_name", &self.table.name)
+            .field("stale_after", &self.leasing.stale_after)
+            .field("capacity", &self.leasing.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdentifierProvider for SqlIdentifierProvider {
+    async fn acquire(&self) -> Result<IdentifierLease, IdError> {
+        self.acquire_watched().await.map(|(lease, _)| lease)
+    }
+}
+
+/// Statements on the table, same for both databases except how a transaction begins.
+#[derive(Clone)]
+struct Table {
+    pool: SqlPool,
+    name: String,
+}
+
+impl Table {
+    fn checked_name(&self) -> Result<&str, IdError> {
+        let plain = !self.name.is_empty()
+            && !self.name.starts_with(|it: char| it.is_ascii_digit())
+            && self
+                .name
+                .chars()
+                .all(|it| it.is_ascii_alphanumeric() || it == '_' || it == '.');
+        match plain {
+            true => Ok(&self.name),
+            false => Err(IdError::Backend {
+                message: format!("table name {:?} is not a plain SQL identifier", self.name),
+            }),
+        }
+    }
+
+    async fn create(&self) -> Result<(), IdError> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} \
+             (identifier SMALLINT PRIMARY KEY, owner TEXT NOT NULL, heartbeat TIMESTAMP NOT NULL)",
+            self.checked_name()?
+        );
+        match &self.pool {
+            SqlPool::Postgres(pool) => sqlx::query(&sql).execute(pool).await.map(|_| ()),
+            SqlPool::Sqlite(pool) => sqlx::query(&sql).execute(pool).await.map(|_| ()),
+        }
+        .map_err(backend)
+    }
+
+    /// Claim the lowest of `0..capacity` without fresh heartbeat since `stale_before`, `None` if all of them are taken.
+    async fn claim(
+        &self,
+        owner: &str,
+        capacity: u64,
+        now: NaiveDateTime,
+        stale_before: NaiveDateTime,
+    ) -> Result<Option<u64>, IdError> {
+        let name = self.checked_name()?;
+        let taken = format!("SELECT identifier FROM {name} WHERE heartbeat >= $1");
+        // Losing the race to an insert or a fresh heartbeat affects no row.
+        let upsert = format!(
+            "INSERT INTO {name} (identifier, owner, heartbeat) VALUES ($1, $2, $3) \
+             ON CONFLICT (identifier) DO UPDATE SET owner = excluded.owner, heartbeat = excluded.heartbeat \
+             WHERE {name}.heartbeat < $4"
+        );
+
+        loop {
+            let claimed = match &self.pool {
+                SqlPool::Postgres(pool) => {
+                    let mut transaction = pool.begin().await.map_err(backend)?;
+                    let taken = sqlx::query_scalar::<_, i16>(&taken)
+                        .bind(stale_before)
+                        .fetch_all(&mut *transaction)
+                        .await
+                        .map_err(backend)?;
+                    let Some(identifier) = lowest_free(capacity, &taken) else {
+                        return Ok(None);
+                    };
+                    let upserted = sqlx::query(&upsert)
+                        .bind(identifier)
+                        .bind(owner)
+                        .bind(now)
+                        .bind(stale_before)
+                        .execute(&mut *transaction)
+                        .await
+                        .map_err(backend)?;
+                    transaction.commit().await.map_err(backend)?;
+                    (upserted.rows_affected() == 1).then_some(identifier)
+                }
+                // Taking the write lock now, rather than failing to upgrade to it after reading.
+                SqlPool::Sqlite(pool) => {
+                    let mut transaction =
+                        pool.begin_with("BEGIN IMMEDIATE").await.map_err(backend)?;
+                    let taken = sqlx::query_scalar::<_, i16>(&taken)
+                        .bind(stale_before)
+                        .fetch_all(&mut *transaction)
+                        .await
+                        .map_err(backend)?;
+                    let Some(identifier) = lowest_free(capacity, &taken) else {
+                        return Ok(None);
+                    };
+                    let upserted = sqlx::query(&upsert)
+                        .bind(identifier)
+                        .bind(owner)
+                        .bind(now)
+                        .bind(stale_before)
+                        .execute(&mut *transaction)
+                        .await
+                        .map_err(backend)?;
+                    transaction.commit().await.map_err(backend)?;
+                    (upserted.rows_affected() == 1).then_some(identifier)
+                }
+            };
+
+            if let Some(identifier) = claimed {
+                return Ok(Some(identifier as u64));
+            }
+        }
+    }
+
+    /// Update heartbeat to `now`, `false` if it's claimed by someone else.
+    async fn heartbeat(
+        &self,
+        identifier: u64,
+        owner: &str,
+        now: NaiveDateTime,
+    ) -> Result<bool, IdError> {
+        let sql = format!(
+            "UPDATE {} SET heartbeat = $1 WHERE identifier = $2 AND owner = $3",
+            self.checked_name()?
+        );
+        let identifier = identifier as i16;
+        match &self.pool {
+            SqlPool::Postgres(pool) => {
+                let query = sqlx::query(&sql).bind(now).bind(identifier).bind(owner);
+                query.execute(pool).await.map(|it| it.rows_affected())
+            }
+            SqlPool::Sqlite(pool) => {
+                let query = sqlx::query(&sql).bind(now).bind(identifier).bind(owner);
+                query.execute(pool).await.map(|it| it.rows_affected())
+            }
+        }
+        .map(|it| it == 1)
+        .map_err(backend)
+    }
+
+    async fn release(&self, identifier: u64, owner: &str) -> Result<(), IdError> {
+        let sql = format!(
+            "DELETE FROM {} WHERE identifier = $1 AND owner = $2",
+            self.checked_name()?
+        );
+        let identifier = identifier as i16;
+        match &self.pool {
+            SqlPool::Postgres(pool) => {
+                let query = sqlx::query(&sql).bind(identifier).bind(owner);
+                query.execute(pool).await.map(|_| ())
+            }
+            SqlPool::Sqlite(pool) => {
+                let query = sqlx::query(&sql).bind(identifier).bind(owner);
+                query.execute(pool).await.map(|_| ())
+            }
+        }
+        .map_err(backend)
+    }
+}
+
+async fn acquire_in(
+    table: &Table,
+    leasing: &Leasing,
+) -> Result<(IdentifierLease, LeaseWatch), IdError> {
+    let owner = Arc::new(format!(
+        "{}/{}/{:016x}",
+        identifier::hostname(),
+        std::process::id(),
+        rand::random::<u64>()
+    ));
+    table.create().await?;
+
+    let claimed = Instant::now();
+    let now = Utc::now().naive_utc();
+    let stale_before = TimeDelta::from_std(leasing.stale_after)
+        .ok()
+        .and_then(|it| now.checked_sub_signed(it))
+        .unwrap_or(NaiveDateTime::MIN);
+    let identifier = table
+        .claim(&owner, leasing.capacity, now, stale_before)
+        .await?
+        .ok_or(IdError::Exhausted)?;
+
+    let (keep_alive, watch) = KeepAlive::new(
+        identifier,
+        leasing.stale_after,
+        claimed,
+        leasing.on_lost.clone(),
+    );
+    let table = table.clone();
+    let handle = keep_alive.spawn(
+        {
+            let (table, owner) = (table.clone(), owner.clone());
+            move || {
+                let (table, owner) = (table.clone(), owner.clone());
+                async move {
+                    let now = Utc::now().naive_utc();
+                    table.heartbeat(identifier, &owner, now).await
+                }
+            }
+        },
+        move || {
+            let (table, owner) = (table.clone(), owner.clone());
+            Box::pin(async move { table.release(identifier, &owner).await })
+        },
+    );
+    Ok((IdentifierLease::new(identifier, handle), watch))
+}
+
+/// [`HeartbeatStore`](HeartbeatStore) of a table, created if missing:
+///
+/// ```sql
+/// CREATE TABLE {table_name} (identifier SMALLINT NOT NULL, instance TEXT NOT NULL, heartbeat BIGINT NOT NULL,
+///     PRIMARY KEY (identifier, instance))
+/// ```
+///
+/// Heartbeats older than `retention` (1 hour by default) by the clock of the writer are removed.
+#[derive(Clone)]
+pub struct SqlHeartbeatStore {
+    table: Table,
+    retention: Duration,
+    created: Arc<OnceCell<()>>,
+}
+
+impl SqlHeartbeatStore {
+    /// Heartbeating to table `table_name` of `pool`, Postgres or SQLite.
+    pub fn new(pool: impl Into<SqlPool>, table_name: impl Into<String>) -> Self {
+        Self {
+            table: Table {
+                pool: pool.into(),
+                name: table_name.into(),
+            },
+            retention: Duration::from_secs(3600),
+            created: Arc::default(),
+        }
+    }
+
+    /// Keep heartbeats for `retention` instead, which should be well beyond the interval.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+}
+
+impl fmt::Debug for SqlHeartbeatStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqlHeartbeatStore")
+            .field("pool", &self.table.pool)
+            .field("table_name", &self.table.name)
+            .field("retention", &self.retention)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HeartbeatStore for SqlHeartbeatStore {
+    fn beat(
+        &self,
+        identifier: u64,
+        heartbeat: Heartbeat,
+    ) -> BoxFuture<'_, Result<Vec<Heartbeat>, IdError>> {
+        Box::pin(async move {
+            self.created
+                .get_or_try_init(|| self.table.create_heartbeats())
+                .await?;
+            let oldest = heartbeat
+                .timestamp
+                .saturating_sub(self.retention.as_millis() as u64);
+            self.table.beat(identifier, heartbeat, oldest).await
+        })
+    }
+}
+
+impl Table {
+    async fn create_heartbeats(&self) -> Result<(), IdError> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} \
+             (identifier SMALLINT NOT NULL, instance TEXT NOT NULL, heartbeat BIGINT NOT NULL, \
+             PRIMARY KEY (identifier, instance))",
+            self.checked_name()?
+        );
+        match &self.pool {
+            SqlPool::Postgres(pool) => sqlx::query(&sql).execute(pool).await.map(|_| ()),
+            SqlPool::Sqlite(pool) => sqlx::query(&sql).execute(pool).await.map(|_| ()),
+        }
+        .map_err(backend)
+    }
+
+    /// Record `heartbeat` and remove ones older than `oldest`, returning the rest on `identifier`.
+    async fn beat(
+        &self,
+        identifier: u64,
+        heartbeat: Heartbeat,
+        oldest: u64,
+    ) -> Result<Vec<Heartbeat>, IdError> {
+        let name = self.checked_name()?;
+        let upsert = format!(
+            "INSERT INTO {name} (identifier, instance, heartbeat) VALUES ($1, $2, $3) \
+             ON CONFLICT (identifier, instance) DO UPDATE SET heartbeat = excluded.heartbeat"
+        );
+        let prune = format!("DELETE FROM {name} WHERE identifier = $1 AND heartbeat < $2");
+        let select = format!("SELECT instance, heartbeat FROM {name} WHERE identifier = $1");
+        let identifier = identifier as i16;
+        let instance = format!("{:032x}", heartbeat.instance);
+        let (timestamp, oldest) = (heartbeat.timestamp as i64, oldest as i64);
+
+        let heartbeats = match &self.pool {
+            SqlPool::Postgres(pool) => {
+                async {
+                    let mut transaction = pool.begin().await?;
+                    sqlx::query(&upsert)
+                        .bind(identifier)
+                        .bind(&instance)
+                        .bind(timestamp)
+                        .execute(&mut *transaction)
+                        .await?;
+                    sqlx::query(&prune)
+                        .bind(identifier)
+                        .bind(oldest)
+                        .execute(&mut *transaction)
+                        .await?;
+                    let heartbeats = sqlx::query_as::<_, (String, i64)>(&select)
+                        .bind(identifier)
+                        .fetch_all(&mut *transaction)
+                        .await?;
+                    transaction.commit().await.map(|_| heartbeats)
+                }
+                .await
+            }
+            SqlPool::Sqlite(pool) => {
+                async {
+                    let mut transaction = pool.begin_with("BEGIN IMMEDIATE").await?;
+                    sqlx::query(&upsert)
+                        .bind(identifier)
+                        .bind(&instance)
+                        .bind(timestamp)
+                        .execute(&mut *transaction)
+                        .await?;
+                    sqlx::query(&prune)
+                        .bind(identifier)
+                        .bind(oldest)
+                        .execute(&mut *transaction)
+                        .await?;
+                    let heartbeats = sqlx::query_as::<_, (String, i64)>(&select)
+                        .bind(identifier)
+                        .fetch_all(&mut *transaction)
+                        .await?;
+                    transaction.commit().await.map(|_| heartbeats)
+                }
+                .await
+            }
+        }
+        .map_err(backend)?;
+
+        Ok(heartbeats
+            .into_iter()
+            .filter_map(|(instance, timestamp)| {
+                Some(Heartbeat {
+                    instance: u128::from_str_radix(&instance, 16).ok()?,
+                    timestamp: timestamp as u64,
+                })
+            })
+            .collect())
+    }
+}
+
+fn lowest_free(capacity: u64, taken: &[i16]) -> Option<i16> {
+    let taken = taken.iter().copied().collect::<HashSet<_>>();
+    (0..capacity.min(1 << 15))
+        .map(|it| it as i16)
+        .find(|it| !taken.contains(it))
+}
+
+fn backend(err: sqlx::Error) -> IdError {
+    IdError::Backend {
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    /// In-memory SQLite shared by connections of every pool of `name`.
+    async fn sqlite(name: &str) -> SqlitePool {
+        SqlitePoolOptions::new()
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect(&format!("sqlite:file:{name}?mode=memory&cache=shared"))
+            .await
+            .unwrap()
+    }
+
+    fn table(pool: &SqlitePool) -> Table {
+        Table {
+            pool: pool.clone().into(),
+            name: "snowflake_identifiers".to_owned(),
+        }
+    }
+
+    fn leasing(capacity: u64, stale_after: Duration) -> Leasing {
+        Leasing {
+            stale_after,
+            capacity,
+            on_lost: None,
+        }
+    }
+
+    async fn rows(pool: &SqlitePool) -> Vec<(i16, String)> {
+        sqlx::query_as("SELECT identifier, owner FROM snowflake_identifiers ORDER BY identifier")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_claim() {
+        let pool = sqlite("claim").await;
+        let table = table(&pool);
+        table.create().await.unwrap();
+        let now = Utc::now().naive_utc();
+        let stale_before = now - TimeDelta::seconds(30);
+
+        assert_eq!(table.claim("a", 2, now, stale_before).await, Ok(Some(0)));
+        assert_eq!(table.claim("b", 2, now, stale_before).await, Ok(Some(1)));
+        assert_eq!(table.claim("c", 2, now, stale_before).await, Ok(None));
+        assert_eq!(
+            rows(&pool).await,
+            [(0, "a".to_owned()), (1, "b".to_owned())]
+        );
+
+        // Stale one is reclaimed, and its owner can't heartbeat anymore.
+        let later = now + TimeDelta::seconds(40);
+        assert_eq!(table.heartbeat(1, "b", later).await, Ok(true));
+        assert_eq!(
+            table
+                .claim("c", 2, later, later - TimeDelta::seconds(30))
+                .await,
+            Ok(Some(0))
+        );
+        assert_eq!(table.heartbeat(0, "a", later).await, Ok(false));
+        assert_eq!(table.heartbeat(0, "c", later).await, Ok(true));
+
+        // Only its owner releases it.
+        table.release(1, "a").await.unwrap();
+        table.release(0, "c").await.unwrap();
+        assert_eq!(rows(&pool).await, [(1, "b".to_owned())]);
+
+        let bad = Table {
+            name: "identifiers; DROP TABLE users".to_owned(),
+            ..table.clone()
+        };
+        assert!(matches!(bad.create().await, Err(IdError::Backend { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_heartbeat() {
+        let pool = sqlite("heartbeat").await;
+        let leasing = leasing(2, Duration::from_millis(300));
+        let (first, watch) = acquire_in(&table(&pool), &leasing).await.unwrap();
+        let (second, _) = acquire_in(&table(&pool), &leasing).await.unwrap();
+        assert_eq!((first.identifier(), second.identifier()), (0, 1));
+        assert!(matches!(
+            acquire_in(&table(&pool), &leasing).await,
+            Err(IdError::Exhausted)
+        ));
+
+        // Kept fresh well beyond staleness.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(matches!(
+            acquire_in(&table(&pool), &leasing).await,
+            Err(IdError::Exhausted)
+        ));
+        assert_eq!(watch.check(), Ok(()));
+
+        first.release().await.unwrap();
+        let (reclaimed, _) = acquire_in(&table(&pool), &leasing).await.unwrap();
+        assert_eq!(reclaimed.identifier(), 0);
+
+        drop((second, reclaimed));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rows(&pool).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reclaimed_lost() {
+        let pool = sqlite("reclaimed").await;
+        let table = table(&pool);
+        let (lease, mut watch) = acquire_in(&table, &leasing(1024, Duration::from_millis(300)))
+            .await
+            .unwrap();
+
+        // Taken over as if it went stale.
+        let future = Utc::now().naive_utc() + TimeDelta::seconds(60);
+        assert_eq!(
+            table.claim("other", 1024, future, future).await,
+            Ok(Some(lease.identifier()))
+        );
+        assert_eq!(watch.lost().await, Some(IdError::Lost { identifier: 0 }));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_race() {
+        let leasing = leasing(16, Duration::from_secs(30));
+        let claims = [sqlite("race").await, sqlite("race").await].map(|pool| {
+            let leasing = leasing.clone();
+            tokio::spawn(async move {
+                let table = table(&pool);
+                let mut leases = Vec::new();
+                while let Ok((lease, _)) = acquire_in(&table, &leasing).await {
+                    leases.push(lease);
+                }
+                leases
+            })
+        });
+
+        // Dropping leases releases them, so keep them until both are done.
+        let mut leases = Vec::new();
+        for claim in claims {
+            leases.extend(claim.await.unwrap());
+        }
+        let mut identifiers = leases.iter().map(|it| it.identifier()).collect::<Vec<_>>();
+        identifiers.sort();
+        assert_eq!(identifiers, (0..16).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_store() {
+        let pool = sqlite("heartbeats").await;
+        let store = SqlHeartbeatStore::new(pool, "snowflake_heartbeats")
+            .with_retention(Duration::from_millis(1000));
+        let beat = |instance, timestamp| Heartbeat {
+            instance,
+            timestamp,
+        };
+
+        assert_eq!(store.beat(7, beat(1, 5000)).await, Ok(vec![beat(1, 5000)]));
+        assert_eq!(store.beat(8, beat(2, 5000)).await, Ok(vec![beat(2, 5000)]));
+        let mut heartbeats = store.beat(7, beat(u128::MAX, 5500)).await.unwrap();
+        heartbeats.sort_by_key(|it| it.instance);
+        assert_eq!(heartbeats, [beat(1, 5000), beat(u128::MAX, 5500)]);
+
+        // Older than retention by the clock of the writer.
+        let mut heartbeats = store.beat(7, beat(1, 6200)).await.unwrap();
+        heartbeats.sort_by_key(|it| it.instance);
+        assert_eq!(heartbeats, [beat(1, 6200), beat(u128::MAX, 5500)]);
+        assert_eq!(
+            store.beat(7, beat(u128::MAX, 7300)).await,
+            Ok(vec![beat(u128::MAX, 7300)])
+        );
+    }
+
+    /// Against a real Postgres at `SNOWFLAKE_TEST_POSTGRES`, like `postgres://localhost/test`.
+    #[tokio::test]
+    async fn test_postgres() {
+        let Ok(url) = std::env::var("SNOWFLAKE_TEST_POSTGRES") else {
+            return;
+        };
+        let pool = PgPool::connect(&url).await.unwrap();
+        let name = format!("snowflake_test_{:x}", rand::random::<u32>());
+        let provider = SqlIdentifierProvider::new(pool.clone(), &name).with_capacity(2);
+
+        let (a, b) = tokio::join!(provider.acquire(), provider.acquire());
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_ne!(a.identifier(), b.identifier());
+        assert!(matches!(provider.acquire().await, Err(IdError::Exhausted)));
+
+        a.release().await.unwrap();
+        assert_eq!(
+            provider.acquire().await.unwrap().identifier(),
+            a.identifier()
+        );
+        sqlx::query(&format!("DROP TABLE {name}"))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{atomic, Mutex, PoisonError},
+    time::Duration,
+};
+
+use crate::{Ordering, Shards, SnowflakeConfiguration, SnowflakeError, SnowflakeGenerator};
+
+/// How far ahead the state file covers by default, see [`SnowflakeGenerator::with_state_flush_interval`](SnowflakeGenerator::with_state_flush_interval).
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// High-water mark kept in a file, see [`SnowflakeGenerator::with_state_file`](SnowflakeGenerator::with_state_file).
+///
+/// File holds microseconds since UNIX epoch of the first tick not covered, nothing is handed out from it onwards.
+#[derive(Debug)]
+pub(crate) struct StateFile {
+    path: PathBuf,
+    fsync: bool,
+    /// Ticks covered ahead on every flush.
+    interval: u64,
+    /// The first tick not covered by the file.
+    covered: atomic::AtomicU64,
+    /// Serializing flushes.
+    flushing: Mutex<()>,
+    shards: Shards,
+    epoch_micros: u64,
+    unit_micros: u64,
+    sequence_bits: u32,
+}
+
+impl StateFile {
+    /// Make sure `tick` is covered by the file, flushing if not.
+    pub(crate) fn cover(&self, tick: u64) -> Result<(), SnowflakeError> {
+        if tick < self.covered.load(atomic::Ordering::Acquire) {
+            return Ok(());
+        }
+
+        self.extend(tick)
+    }
+
+    #[cold]
+    fn extend(&self, tick: u64) -> Result<(), SnowflakeError> {
+        let _flushing = self.flushing.lock().unwrap_or_else(PoisonError::into_inner);
+        if tick < self.covered.load(atomic::Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let covered = tick.saturating_add(self.interval).saturating_add(1);
+        self.write(covered)?;
+        self.covered.store(covered, atomic::Ordering::Release);
+        Ok(())
+    }
+
+    /// Replace the file with `covered` atomically, so it's never seen half written.
+    fn write(&self, covered: u64) -> Result<(), SnowflakeError> {
+        let micros = covered
+            .saturating_mul(self.unit_micros)
+            .saturating_add(self.epoch_micros);
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let write = || -> io::Result<()> {
+            let mut file = fs::File::create(&temporary)?;
+            writeln!(file, "{micros}")?;
+            if self.fsync {
+                file.sync_all()?;
+            }
+            fs::rename(&temporary, &self.path)
+        };
+        write().map_err(|it| error(&self.path, it.to_string()))
+    }
+
+    /// The first tick not covered by file at `path`, `None` if it's missing.
+    fn load(
+        path: &Path,
+        epoch_micros: u64,
+        unit_micros: u64,
+    ) -> Result<Option<u64>, SnowflakeError> {
+        let input = match fs::read_to_string(path) {
+            Ok(it) => it,
+            Err(it) if it.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(it) => return Err(error(path, it.to_string())),
+        };
+
+        let micros = input.trim().parse::<u64>().map_err(|_| {
+            error(
+                path,
+                format!(
+                    "corrupt high-water mark {:?}, remove it to start over",
+                    input.trim()
+                ),
+            )
+        })?;
+        Ok(Some(
+            micros.saturating_sub(epoch_micros).div_ceil(unit_micros),
+        ))
+    }
+}
+
+impl Drop for StateFile {
+    fn drop(&mut self) {
+        // Nothing is handed out without being covered, so the exact mark shortens waiting after restart.
+        let Some(high) = self
+            .shards
+            .iter()
+            .map(|it| it.load(Ordering::Relaxed))
+            .filter(|it| *it != 0)
+            .map(|it| it >> (self.sequence_bits + 1))
+            .max()
+        else {
+            return;
+        };
+
+        // Nowhere to report but tracing, the covered mark is still there anyway.
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        let result = self.write(high + 1);
+        #[cfg(feature = "tracing")]
+        if let Err(err) = result {
+            tracing::warn!(%err, "failed to flush high-water mark");
+        }
+    }
+}
+
+fn error(path: &Path, message: String) -> SnowflakeError {
+    SnowflakeError::StateFile {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+impl SnowflakeGenerator {
+    /// Constructing [`SnowflakeGenerator`](SnowflakeGenerator) with `cfg`, resuming from the high-water mark saved in `path`.
+    ///
+    /// Nothing is generated at or before the saved mark, so a restart with the clock stepped backwards doesn't reissue [`Snowflake`](crate::Snowflake).
+    /// Until the clock passes it, the generator behaves as if the clock is behind, see [`SnowflakeConfiguration::rollback_policy`](SnowflakeConfiguration::rollback_policy).
+    ///
+    /// The mark is flushed ahead by [`SnowflakeGenerator::with_state_flush_interval`](SnowflakeGenerator::with_state_flush_interval),
+    /// so it still holds after a crash, and the exact one is flushed on drop.
+    /// Flushing blocks the assigning thread (even async ones), but only once per interval.
+    /// Failing to flush is reported by [`SnowflakeError::StateFile`](SnowflakeError::StateFile) (or panics if infallible).
+    ///
+    /// A missing file starts from scratch, but a corrupt one is reported by [`SnowflakeError::StateFile`](SnowflakeError::StateFile)
+    /// rather than guessing.
+    ///
+    /// ```no_run
+    /// # use snowflake_ng::{SnowflakeConfiguration, SnowflakeGenerator};
+    /// let generator = SnowflakeGenerator::with_state_file(
+    ///     SnowflakeConfiguration::with_identifier(1),
+    ///     "/var/lib/app/snowflake.state",
+    /// )
+    /// .unwrap()
+    /// .with_state_fsync(true);
+    /// ```
+    pub fn with_state_file(
+        cfg: SnowflakeConfiguration,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, SnowflakeError> {
+        let path = path.as_ref();
+        let (epoch_micros, unit_micros) = (cfg.epoch.saturating_mul(1000), cfg.unit_micros());
+        let covered = StateFile::load(path, epoch_micros, unit_micros)?.unwrap_or_default();
+
+        let mut generator = Self::with_cfg(cfg);
+        let layout = &generator.cfg.layout;
+        if covered > 0 {
+            // The last covered tick with sequence used up, as if everything of it is generated.
+            generator
+                .resume(((covered - 1) << (layout.sequence_bits() + 1)) | layout.max_sequence());
+        }
+
+        let layout = &generator.cfg.layout;
+        generator.state_file = Some(StateFile {
+            path: path.to_path_buf(),
+            fsync: false,
+            interval: DEFAULT_FLUSH_INTERVAL.as_micros() as u64 / unit_micros,
+            covered: atomic::AtomicU64::new(covered),
+            flushing: Mutex::new(()),
+            shards: generator.shards.clone(),
+            epoch_micros,
+            unit_micros,
+            sequence_bits: layout.sequence_bits(),
+        });
+        Ok(generator)
+    }
+}
+
+impl<S> SnowflakeGenerator<S> {
+    /// Call `fsync` on every flush of the state file, off by default.
+    ///
+    /// Without it, the mark survives a crash of the process but maybe not of the machine.
+    pub fn with_state_fsync(mut self, fsync: bool) -> Self {
+        if let Some(state_file) = &mut self.state_file {
+            state_file.fsync = fsync;
+        }
+        self
+    }
+
+    /// Flush the state file to cover `interval` ahead, 1 second by default.
+    ///
+    /// Longer interval flushes less often, but waits longer after restarting from a crash.
+    pub fn with_state_flush_interval(mut self, interval: Duration) -> Self {
+        if let Some(state_file) = &mut self.state_file {
+            state_file.interval = interval.as_micros() as u64 / state_file.unit_micros;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{provider::FixedProvider, testing::temporary, TimestampValidation, TryAssignError};
+
+    use super::*;
+
+    fn generator(path: &Path) -> Result<SnowflakeGenerator, SnowflakeError> {
+        SnowflakeGenerator::with_state_file(
+            SnowflakeConfiguration::with_identifier(3)
+                .with_timestamp_validation(TimestampValidation::Off),
+            path,
+        )
+    }
+
+    #[test]
+    fn test_state_file() {
+        let path = temporary("state");
+        let first = generator(&path).unwrap();
+        let issued = first.try_assign_now(&FixedProvider::new(100_000)).unwrap();
+        // Covered ahead right away.
+        assert_eq!(fs::read_to_string(&path).unwrap(), "101001000\n");
+        // Crashed, nothing flushed on drop.
+        std::mem::forget(first);
+
+        let second = generator(&path).unwrap();
+        assert_eq!(
+            second.try_assign_now(&FixedProvider::new(100_500)),
+            Err(TryAssignError::ClockBehind {
+                behind_by: Duration::from_millis(500)
+            })
+        );
+        assert!(matches!(
+            second.try_assign_sync(&FixedProvider::new(100_500)),
+            Err(SnowflakeError::ClockMovedBackwards { .. })
+        ));
+        assert!(matches!(
+            second.try_assign_now(&FixedProvider::new(101_000)),
+            Err(TryAssignError::SequenceExhausted { .. })
+        ));
+        let resumed = second.try_assign_now(&FixedProvider::new(101_001)).unwrap();
+        assert!(resumed > issued);
+        assert_eq!(resumed.timestamp(), 101_001);
+
+        // Exact mark on drop.
+        drop(second);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "101002000\n");
+        let third = generator(&path).unwrap();
+        assert!(matches!(
+            third.try_assign_now(&FixedProvider::new(101_001)),
+            Err(TryAssignError::SequenceExhausted { .. })
+        ));
+        assert!(third.try_assign_now(&FixedProvider::new(101_002)).unwrap() > resumed);
+
+        drop(third);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_state_file_invalid() {
+        let path = temporary("invalid");
+
+        // Missing one starts from scratch, and nothing is written until generated.
+        drop(generator(&path).unwrap());
+        assert!(!path.exists());
+
+        fs::write(&path, "garbage").unwrap();
+        assert!(matches!(
+            generator(&path),
+            Err(SnowflakeError::StateFile { .. })
+        ));
+
+        // Failing to flush is reported rather than handing out uncovered ones.
+        fs::remove_file(&path).unwrap();
+        let generator = generator(&path.join("nested")).unwrap();
+        assert!(matches!(
+            generator.try_assign_sync(&FixedProvider::new(100_000)),
+            Err(SnowflakeError::StateFile { .. })
+        ));
+        assert!(matches!(
+            generator.try_assign_now(&FixedProvider::new(100_000)),
+            Err(TryAssignError::StateFile { .. })
+        ));
+    }
+}
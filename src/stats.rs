@@ -0,0 +1,100 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Statistics of [`SnowflakeGenerator`](crate::SnowflakeGenerator), see [`SnowflakeGenerator::stats`](crate::SnowflakeGenerator::stats).
+///
+/// Counters are updated independently, so a snapshot taken while assigning may be slightly inconsistent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub struct GeneratorStats {
+    /// How many [`Snowflake`](crate::Snowflake) issued, including backfilled ones.
+    pub issued: u64,
+    /// How many times it waited for the next tick because sequence exhausted.
+    pub exhausted_waits: u64,
+    /// Total time spent waiting, for both exhausted sequence and the clock behind.
+    pub waited: Duration,
+    /// How many times the clock was observed behind the last timestamp, same as [`SnowflakeGenerator::clock_behind_count`](crate::SnowflakeGenerator::clock_behind_count).
+    pub clock_behind: u64,
+    /// How many times it backed off waiting for the clock behind, see [`SnowflakeConfiguration::max_backoff`](crate::SnowflakeConfiguration::max_backoff).
+    pub backoffs: u64,
+    /// The highest sequence number issued in any tick.
+    pub max_sequence: u64,
+}
+
+/// Relaxed counters behind [`GeneratorStats`](GeneratorStats).
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    issued: AtomicU64,
+    exhausted_waits: AtomicU64,
+    waited_micros: AtomicU64,
+    clock_behind: AtomicU64,
+    backoffs: AtomicU64,
+    max_sequence: AtomicU64,
+}
+
+impl Counters {
+    /// `count` issued, the last one with sequence number `last_sequence`.
+    pub(crate) fn issued(&self, count: u64, last_sequence: u64) {
+        self.issued.fetch_add(count, Ordering::Relaxed);
+        // Only a load most of the time, the max is reached soon.
+        if last_sequence > self.max_sequence.load(Ordering::Relaxed) {
+            self.max_sequence
+                .fetch_max(last_sequence, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn exhausted_wait(&self) {
+        self.exhausted_waits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn waited(&self, duration: Duration) {
+        self.waited_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn clock_behind(&self) {
+        self.clock_behind.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg_attr(not(any(feature = "async", feature = "sync")), allow(dead_code))]
+    pub(crate) fn backoff(&self) {
+        self.backoffs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn clock_behind_count(&self) -> u64 {
+        self.clock_behind.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self) -> GeneratorStats {
+        GeneratorStats {
+            issued: self.issued.load(Ordering::Relaxed),
+            exhausted_waits: self.exhausted_waits.load(Ordering::Relaxed),
+            waited: Duration::from_micros(self.waited_micros.load(Ordering::Relaxed)),
+            clock_behind: self.clock_behind.load(Ordering::Relaxed),
+            backoffs: self.backoffs.load(Ordering::Relaxed),
+            max_sequence: self.max_sequence.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for it in [
+            &self.issued,
+            &self.exhausted_waits,
+            &self.waited_micros,
+            &self.clock_behind,
+            &self.backoffs,
+            &self.max_sequence,
+        ] {
+            it.store(0, Ordering::Relaxed);
+        }
+    }
+}
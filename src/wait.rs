@@ -0,0 +1,299 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#[cfg(any(feature = "async", feature = "sync"))]
+use std::hint;
+use std::{future::Future, time::Duration};
+#[cfg(feature = "async")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "async")]
+use futures_timer::Delay;
+#[cfg(any(feature = "async", feature = "sync"))]
+use rand::Rng;
+
+/// Asynchronous sleeping of generator, see [`SnowflakeGenerator::with_sleeper`](crate::SnowflakeGenerator::with_sleeper).
+///
+/// It should be a timer of your async runtime, e.g. `tokio::time::sleep`,
+/// and [`TimerSleeper`](TimerSleeper) works everywhere.
+pub trait Sleeper: Send + Sync {
+    /// Future completes after `duration`.
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+}
+
+/// [`Sleeper`](Sleeper) of [`futures_timer::Delay`](futures_timer::Delay), which doesn't depend on any runtime.
+///
+/// Without feature `async`, it's only a placeholder of [`SnowflakeGenerator`](crate::SnowflakeGenerator) and can't sleep.
+///
+/// With feature `tokio`, it's `tokio::time::sleep` inside Tokio runtime, so it works with `tokio::time::pause`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimerSleeper;
+
+#[cfg(feature = "async")]
+impl Sleeper for TimerSleeper {
+    #[cfg(not(feature = "tokio"))]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        Delay::new(duration)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        let in_tokio = tokio::runtime::Handle::try_current().is_ok();
+        async move {
+            if in_tokio {
+                tokio::time::sleep(duration).await
+            } else {
+                Delay::new(duration).await
+            }
+        }
+    }
+}
+
+/// Spins of [`WaitStrategy::Hybrid`](WaitStrategy::Hybrid) before yielding.
+const HYBRID_SPINS: u32 = 64;
+
+/// Yields of [`WaitStrategy::Hybrid`](WaitStrategy::Hybrid) before sleeping.
+const HYBRID_YIELDS: u32 = 16;
+
+/// Yields of [`WaitStrategy::YieldTask`](WaitStrategy::YieldTask) before sleeping.
+const YIELD_TASK_YIELDS: u32 = 256;
+
+/// First wait of [`Backoff`](Backoff).
+#[cfg(any(feature = "async", feature = "sync"))]
+const BACKOFF_START: Duration = Duration::from_millis(1);
+
+/// Timing waits for [`GeneratorStats::waited`](crate::GeneratorStats::waited).
+///
+/// It's `performance.now()` with feature `wasm`, since [`Instant`](std::time::Instant) panics in browsers.
+#[cfg(any(feature = "async", feature = "sync"))]
+pub(crate) struct Stopwatch {
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    start: std::time::Instant,
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    start: f64,
+}
+
+#[cfg(any(feature = "async", feature = "sync"))]
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+            start: std::time::Instant::now(),
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            start: crate::provider::performance_now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+        return self.start.elapsed();
+        #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+        return Duration::from_secs_f64(
+            (crate::provider::performance_now() - self.start).max(0.0) / 1000.0,
+        );
+    }
+}
+
+/// Exponential backoff of waiting for the clock behind, see [`SnowflakeConfiguration::max_backoff`](crate::SnowflakeConfiguration::max_backoff).
+#[cfg(any(feature = "async", feature = "sync"))]
+#[derive(Debug)]
+pub(crate) struct Backoff {
+    next: Duration,
+    max: Duration,
+    jitter: bool,
+}
+
+#[cfg(any(feature = "async", feature = "sync"))]
+impl Backoff {
+    pub(crate) fn new(max: Duration, jitter: bool) -> Self {
+        Self {
+            next: BACKOFF_START.min(max),
+            max,
+            jitter,
+        }
+    }
+
+    /// How long to wait this time, doubling the next one up to max.
+    ///
+    /// It's jittered within the upper half if enabled, so waiters wake up apart.
+    pub(crate) fn delay(&mut self) -> Duration {
+        let current = self.next;
+        self.next = current.saturating_mul(2).min(self.max);
+        if !self.jitter {
+            return current;
+        }
+
+        let micros = current.as_micros() as u64;
+        let jitter = match micros / 2 {
+            0 => 0,
+            half => rand::thread_rng().gen_range(1..=half),
+        };
+        Duration::from_micros(micros - jitter)
+    }
+
+    /// Start over from the first wait, once the clock caught up.
+    pub(crate) fn reset(&mut self) {
+        self.next = BACKOFF_START.min(self.max);
+    }
+}
+
+/// How to wait when sequence exhausted or the clock is behind, see [`SnowflakeConfiguration::wait_strategy`](crate::SnowflakeConfiguration::wait_strategy).
+///
+/// Spinning ones fall back to sleeping once out of budget, so a stuck clock never burns a core forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum WaitStrategy {
+    /// Sleep until the next tick.
+    #[default]
+    Sleep,
+    /// Yield to other tasks (or threads in [`SnowflakeIter`](crate::SnowflakeIter)) and check again, sleeping after 256 yields.
+    YieldTask,
+    /// Spin with [`hint::spin_loop`](hint::spin_loop) and check again, sleeping after `max_iterations` spins.
+    Spin { max_iterations: u32 },
+    /// Spin for a while, then yield for a while, then sleep.
+    Hybrid,
+}
+
+/// What to do in one wait.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum WaitAction {
+    Spin,
+    Yield,
+    Sleep,
+}
+
+impl WaitStrategy {
+    /// Action of `attempt`-th wait in a row, counting from 0.
+    pub(crate) fn action(&self, attempt: u32) -> WaitAction {
+        match *self {
+            WaitStrategy::Sleep => WaitAction::Sleep,
+            WaitStrategy::YieldTask if attempt < YIELD_TASK_YIELDS => WaitAction::Yield,
+            WaitStrategy::Spin { max_iterations } if attempt < max_iterations => WaitAction::Spin,
+            WaitStrategy::Hybrid if attempt < HYBRID_SPINS => WaitAction::Spin,
+            WaitStrategy::Hybrid if attempt < HYBRID_SPINS + HYBRID_YIELDS => WaitAction::Yield,
+            _ => WaitAction::Sleep,
+        }
+    }
+
+    /// Wait once with `sleeper`, `duration` is how long until the next tick.
+    #[cfg(feature = "async")]
+    pub(crate) async fn wait<S>(&self, sleeper: &S, attempt: u32, duration: Duration)
+    where
+        S: Sleeper,
+    {
+        match self.action(attempt) {
+            WaitAction::Spin => hint::spin_loop(),
+            WaitAction::Yield => YieldNow(false).await,
+            WaitAction::Sleep => sleeper.sleep(duration).await,
+        }
+    }
+
+    /// Same as [`WaitStrategy::wait`](WaitStrategy::wait), but blocking current thread.
+    #[cfg(feature = "sync")]
+    pub(crate) fn wait_sync(&self, attempt: u32, duration: Duration) {
+        match self.action(attempt) {
+            WaitAction::Spin => hint::spin_loop(),
+            WaitAction::Yield => std::thread::yield_now(),
+            WaitAction::Sleep => std::thread::sleep(duration),
+        }
+    }
+}
+
+/// Whether current thread is inside an async runtime, so blocking it would stall other tasks.
+///
+/// Only Tokio runtime is detected, and only in debug builds, otherwise it's always `false`.
+#[cfg(feature = "sync")]
+pub(crate) fn in_async_runtime() -> bool {
+    #[cfg(all(feature = "tokio", debug_assertions))]
+    return tokio::runtime::Handle::try_current().is_ok();
+
+    #[cfg(not(all(feature = "tokio", debug_assertions)))]
+    false
+}
+
+/// Pending once, so executor gets a chance running other tasks.
+#[cfg(feature = "async")]
+struct YieldNow(bool);
+
+#[cfg(feature = "async")]
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "async", feature = "sync"))]
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_millis(250), true);
+        let delays = (0..12).map(|_| backoff.delay()).collect::<Vec<_>>();
+        for (it, delay) in delays.iter().enumerate() {
+            let base = Duration::from_millis(1 << it).min(Duration::from_millis(250));
+            assert!(*delay >= base / 2 && *delay < base, "{it}: {delay:?}");
+        }
+
+        backoff.reset();
+        assert!(backoff.delay() < Duration::from_millis(1));
+        assert_eq!(Backoff::new(Duration::ZERO, true).delay(), Duration::ZERO);
+
+        let mut backoff = Backoff::new(Duration::from_millis(250), false);
+        for it in 0..12 {
+            let base = Duration::from_millis(1 << it).min(Duration::from_millis(250));
+            assert_eq!(backoff.delay(), base);
+        }
+    }
+
+    #[test]
+    fn test_action() {
+        assert_eq!(WaitStrategy::Sleep.action(0), WaitAction::Sleep);
+        assert_eq!(WaitStrategy::YieldTask.action(0), WaitAction::Yield);
+        assert_eq!(
+            WaitStrategy::YieldTask.action(YIELD_TASK_YIELDS - 1),
+            WaitAction::Yield
+        );
+        assert_eq!(
+            WaitStrategy::YieldTask.action(YIELD_TASK_YIELDS),
+            WaitAction::Sleep
+        );
+        assert_eq!(WaitStrategy::YieldTask.action(u32::MAX), WaitAction::Sleep);
+
+        let spin = WaitStrategy::Spin { max_iterations: 3 };
+        assert_eq!(spin.action(2), WaitAction::Spin);
+        assert_eq!(spin.action(3), WaitAction::Sleep);
+        assert_eq!(
+            WaitStrategy::Spin { max_iterations: 0 }.action(0),
+            WaitAction::Sleep
+        );
+
+        assert_eq!(WaitStrategy::Hybrid.action(0), WaitAction::Spin);
+        assert_eq!(WaitStrategy::Hybrid.action(HYBRID_SPINS), WaitAction::Yield);
+        assert_eq!(
+            WaitStrategy::Hybrid.action(HYBRID_SPINS + HYBRID_YIELDS),
+            WaitAction::Sleep
+        );
+    }
+}
@@ -0,0 +1,473 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use futures::future::BoxFuture;
+use tokio::time::Instant;
+use zookeeper_client::{Acls, Client, CreateMode, SessionState};
+
+use crate::{
+    identifier,
+    lease::keep_alive::{KeepAlive, OnLeaseLost},
+    IdError, IdentifierLease, IdentifierProvider, LeaseWatch,
+};
+
+/// [`IdentifierProvider`](IdentifierProvider) of ephemeral sequential znodes, like the original snowflake of Twitter.
+///
+/// Every lease connects a session of its own and creates `{path}/id-{sequence}`, holding hostname and process ID.
+/// The identifier is the sequence modulo capacity, so once the sequence goes beyond capacity it may collide with
+/// a long-lived earlier one, [`ZooKeeperIdentifierProvider::strict`](ZooKeeperIdentifierProvider::strict) refuses that instead.
+///
+/// Being disconnected is tolerated until the session timeout, in which the client reconnects by itself.
+/// Beyond that, or once the session is expired or closed, the znode is gone and the lease is lost:
+/// watch it with [`ZooKeeperIdentifierProvider::acquire_watched`](ZooKeeperIdentifierProvider::acquire_watched)
+/// or [`ZooKeeperIdentifierProvider::on_lease_lost`](ZooKeeperIdentifierProvider::on_lease_lost).
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use snowflake_ng::ZooKeeperIdentifierProvider;
+/// # async fn run() {
+/// let provider = ZooKeeperIdentifierProvider::new(
+///     "localhost:2181",
+///     "/snowflake/billing",
+///     Duration::from_secs(10),
+/// )
+/// .strict();
+/// let (lease, mut watch) = provider.acquire_watched().await.unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ZooKeeperIdentifierProvider {
+    cluster: String,
+    leasing: Leasing,
+}
+
+#[derive(Clone)]
+struct Leasing {
+    path: String,
+    session_timeout: Duration,
+    capacity: u64,
+    strict: bool,
+    on_lost: Option<OnLeaseLost>,
+}
+
+impl ZooKeeperIdentifierProvider {
+    /// Creating znodes under `path` of `cluster`, like `host1:2181,host2:2181`, for 1024 identifiers.
+    pub fn new(
+        cluster: impl Into<String>,
+        path: impl Into<String>,
+        session_timeout: Duration,
+    ) -> Self {
+        Self {
+            cluster: cluster.into(),
+            leasing: Leasing {
+                path: path.into().trim_end_matches('/').to_owned(),
+                session_timeout,
+                capacity: 1024,
+                strict: false,
+                on_lost: None,
+            },
+        }
+    }
+
+    /// Modulo `capacity` instead, for layout other than the default.
+    pub fn with_capacity(mut self, capacity: u64) -> Self {
+        self.leasing.capacity = capacity;
+        self
+    }
+
+    /// Fail with [`IdError::Wrapped`](IdError::Wrapped) once the sequence goes beyond capacity, rather than wrapping around.
+    pub fn strict(mut self) -> Self {
+        self.leasing.strict = true;
+        self
+    }
+
+    /// Call `callback` with the identifier once a lease is lost.
+    pub fn on_lease_lost(mut self, callback: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.leasing.on_lost = Some(Arc::new(callback));
+        self
+    }
+
+    /// Same as [`IdentifierProvider::acquire`](IdentifierProvider::acquire), but also watching whether the lease is lost.
+    pub async fn acquire_watched(&self) -> Result<(IdentifierLease, LeaseWatch), IdError> {
+        let client = Client::connector()
+            .with_session_timeout(self.leasing.session_timeout)
+            .connect(&self.cluster)
+            .await
+            .map_err(backend)?;
+        // Server may negotiate another one.
+        let leasing = Leasing {
+            session_timeout: client.session_timeout(),
+            ..self.leasing.clone()
+        };
+        acquire_in(ZooKeeperSession(client), &leasing).await
+    }
+}
+
+impl fmt::Debug for ZooKeeperIdentifierProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZooKeeperIdentifierProvider")
+            .field("cluster", &self.cluster)
+            .field("path", &self.leasing.path)
+            .field("session_timeout", &self.leasing.session_timeout)
+            .field("capacity", &self.leasing.capacity)
+            .field("strict", &self.leasing.strict)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdentifierProvider for ZooKeeperIdentifierProvider {
+    async fn acquire(&self) -> Result<IdentifierLease, IdError> {
+        self.acquire_watched().await.map(|(lease, _)| lease)
+    }
+}
+
+/// One session of ZooKeeper, faked in tests.
+trait Session: Clone + Send + Sync + 'static {
+    /// Create ephemeral sequential znode `{prefix}{sequence}` and its parents if missing, returning its path.
+    fn create_sequential<'a>(
+        &'a self,
+        prefix: &'a str,
+        data: &'a [u8],
+    ) -> BoxFuture<'a, Result<String, IdError>>;
+
+    /// Delete `path`, fine if it's gone already.
+    fn delete<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<(), IdError>>;
+
+    fn state(&self) -> SessionState;
+}
+
+#[derive(Clone)]
+struct ZooKeeperSession(Client);
+
+impl Session for ZooKeeperSession {
+    fn create_sequential<'a>(
+        &'a self,
+        prefix: &'a str,
+        data: &'a [u8],
+    ) -> BoxFuture<'a, Result<String, IdError>> {
+        Box::pin(async move {
+            let options = CreateMode::EphemeralSequential.with_acls(Acls::anyone_all());
+            let created = match self.0.create(prefix, data, &options).await {
+                Err(zookeeper_client::Error::NoNode) => {
+                    let parent = prefix.rsplit_once('/').map_or("", |(it, _)| it);
+                    let directory = CreateMode::Persistent.with_acls(Acls::anyone_all());
+                    self.0.mkdir(parent, &directory).await.map_err(backend)?;
+                    self.0.create(prefix, data, &options).await
+                }
+                created => created,
+            };
+            let (_, sequence) = created.map_err(backend)?;
+            Ok(format!("{prefix}{sequence}"))
+        })
+    }
+
+    fn delete<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<(), IdError>> {
+        Box::pin(async move {
+            match self.0.delete(path, None).await {
+                Ok(()) | Err(zookeeper_client::Error::NoNode) => Ok(()),
+                Err(err) => Err(backend(err)),
+            }
+        })
+    }
+
+    fn state(&self) -> SessionState {
+        self.0.state()
+    }
+}
+
+async fn acquire_in<S>(
+    session: S,
+    leasing: &Leasing,
+) -> Result<(IdentifierLease, LeaseWatch), IdError>
+where
+    S: Session,
+{
+    let prefix = format!("{}/id-", leasing.path);
+    let holder = format!("{}/{}", identifier::hostname(), std::process::id());
+    let created = Instant::now();
+    let path = session
+        .create_sequential(&prefix, holder.as_bytes())
+        .await?;
+
+    let identifier = match parse_sequence(&prefix, &path) {
+        Ok(sequence) if leasing.strict && sequence >= leasing.capacity => Err(IdError::Wrapped {
+            sequence,
+            capacity: leasing.capacity,
+        }),
+        Ok(sequence) => Ok(sequence % leasing.capacity.max(1)),
+        Err(err) => Err(err),
+    };
+    let identifier = match identifier {
+        Ok(it) => it,
+        Err(err) => {
+            let _ = session.delete(&path).await;
+            return Err(err);
+        }
+    };
+
+    let (keep_alive, watch) = KeepAlive::new(
+        identifier,
+        leasing.session_timeout,
+        created,
+        leasing.on_lost.clone(),
+    );
+    let path = Arc::new(path);
+    let handle = keep_alive.spawn(
+        {
+            let session = session.clone();
+            move || {
+                let state = session.state();
+                async move { connected(state) }
+            }
+        },
+        move || {
+            let (session, path) = (session.clone(), path.clone());
+            Box::pin(async move { session.delete(&path).await })
+        },
+    );
+    Ok((IdentifierLease::new(identifier, handle), watch))
+}
+
+/// Whether znodes of the session are still there, unknown while disconnected.
+fn connected(state: SessionState) -> Result<bool, IdError> {
+    match state {
+        SessionState::SyncConnected | SessionState::ConnectedReadOnly => Ok(true),
+        SessionState::Disconnected => Err(IdError::Backend {
+            message: "disconnected from ZooKeeper".to_owned(),
+        }),
+        SessionState::Expired | SessionState::Closed | SessionState::AuthFailed => Ok(false),
+    }
+}
+
+/// Sequence of `{prefix}0000000042`, which has 19 digits beyond `i32::MAX`.
+fn parse_sequence(prefix: &str, path: &str) -> Result<u64, IdError> {
+    path.strip_prefix(prefix)
+        .filter(|it| !it.is_empty() && it.bytes().all(|it| it.is_ascii_digit()))
+        .and_then(|it| it.parse().ok())
+        .ok_or_else(|| IdError::Backend {
+            message: format!("{path:?} is not a sequential znode of {prefix:?}"),
+        })
+}
+
+fn backend(err: zookeeper_client::Error) -> IdError {
+    IdError::Backend {
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex,
+        },
+    };
+
+    use super::*;
+
+    /// One session of a ZooKeeper shared by other fakes, with a sequence counter as the parent znode.
+    #[derive(Clone, Default)]
+    struct Fake {
+        nodes: Arc<Mutex<BTreeSet<String>>>,
+        sequence: Arc<AtomicU64>,
+        state: Arc<Mutex<Option<SessionState>>>,
+    }
+
+    impl Fake {
+        /// Another session of the same ZooKeeper.
+        fn session(&self) -> Self {
+            Self {
+                state: Arc::default(),
+                ..self.clone()
+            }
+        }
+
+        fn set_state(&self, state: SessionState) {
+            *self.state.lock().unwrap() = Some(state);
+        }
+
+        fn nodes(&self) -> Vec<String> {
+            self.nodes.lock().unwrap().iter().cloned().collect()
+        }
+    }
+
+    impl Session for Fake {
+        fn create_sequential<'a>(
+            &'a self,
+            prefix: &'a str,
+            _data: &'a [u8],
+        ) -> BoxFuture<'a, Result<String, IdError>> {
+            Box::pin(async move {
+                let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+                let path = format!("{prefix}{sequence:010}");
+                self.nodes.lock().unwrap().insert(path.clone());
+                Ok(path)
+            })
+        }
+
+        fn delete<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<(), IdError>> {
+            Box::pin(async move {
+                self.nodes.lock().unwrap().remove(path);
+                Ok(())
+            })
+        }
+
+        fn state(&self) -> SessionState {
+            self.state
+                .lock()
+                .unwrap()
+                .unwrap_or(SessionState::SyncConnected)
+        }
+    }
+
+    fn leasing(capacity: u64, strict: bool) -> Leasing {
+        Leasing {
+            path: "/snowflake/billing".to_owned(),
+            session_timeout: Duration::from_secs(9),
+            capacity,
+            strict,
+            on_lost: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_sequence() {
+        let prefix = "/snowflake/billing/id-";
+        assert_eq!(
+            parse_sequence(prefix, "/snowflake/billing/id-0000000042"),
+            Ok(42)
+        );
+        assert_eq!(
+            parse_sequence(prefix, "/snowflake/billing/id-0000000002147483648"),
+            Ok(2147483648)
+        );
+        for path in [
+            "/snowflake/billing/id-",
+            "/snowflake/billing/id--000000001",
+            "/snowflake/billing/id-00000000x1",
+            "/snowflake/other/id-0000000001",
+        ] {
+            assert!(matches!(
+                parse_sequence(prefix, path),
+                Err(IdError::Backend { .. })
+            ));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire_release() {
+        let zookeeper = Fake::default();
+        let leasing = leasing(1024, false);
+        let (first, _) = acquire_in(zookeeper.session(), &leasing).await.unwrap();
+        let (second, _) = acquire_in(zookeeper.session(), &leasing).await.unwrap();
+        assert_eq!((first.identifier(), second.identifier()), (0, 1));
+        assert_eq!(
+            zookeeper.nodes(),
+            [
+                "/snowflake/billing/id-0000000000",
+                "/snowflake/billing/id-0000000001"
+            ]
+        );
+
+        // Nothing to refresh, but still watching the session.
+        tokio::time::sleep(Duration::from_secs(300)).await;
+        assert_eq!(zookeeper.nodes().len(), 2);
+
+        first.release().await.unwrap();
+        assert_eq!(zookeeper.nodes(), ["/snowflake/billing/id-0000000001"]);
+        // Sequence never goes back.
+        let (third, _) = acquire_in(zookeeper.session(), &leasing).await.unwrap();
+        assert_eq!(third.identifier(), 2);
+
+        drop((second, third));
+        tokio::task::yield_now().await;
+        assert!(zookeeper.nodes().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wrap() {
+        let zookeeper = Fake::default();
+        zookeeper.sequence.store(5, Ordering::Relaxed);
+        let (wrapped, _) = acquire_in(zookeeper.session(), &leasing(4, false))
+            .await
+            .unwrap();
+        assert_eq!(wrapped.identifier(), 1);
+
+        assert_eq!(
+            acquire_in(zookeeper.session(), &leasing(4, true))
+                .await
+                .err(),
+            Some(IdError::Wrapped {
+                sequence: 6,
+                capacity: 4
+            })
+        );
+        // Refused one is deleted.
+        assert_eq!(zookeeper.nodes(), ["/snowflake/billing/id-0000000005"]);
+
+        let (strict, _) = acquire_in(zookeeper.session(), &leasing(8, true))
+            .await
+            .unwrap();
+        assert_eq!(strict.identifier(), 7);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_session_lost() {
+        let zookeeper = Fake::default();
+        let lost = Arc::new(AtomicU64::new(u64::MAX));
+        let leasing = Leasing {
+            on_lost: Some(Arc::new({
+                let lost = lost.clone();
+                move |it| lost.store(it, Ordering::Relaxed)
+            })),
+            ..leasing(1024, false)
+        };
+
+        // Reconnected within session timeout.
+        let session = zookeeper.session();
+        let (_lease, mut watch) = acquire_in(session.clone(), &leasing).await.unwrap();
+        session.set_state(SessionState::Disconnected);
+        tokio::time::sleep(Duration::from_secs(7)).await;
+        session.set_state(SessionState::SyncConnected);
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(watch.check(), Ok(()));
+
+        // Disconnected beyond it, expired on the server.
+        session.set_state(SessionState::Disconnected);
+        assert_eq!(watch.lost().await, Some(IdError::Lost { identifier: 0 }));
+        assert_eq!(lost.load(Ordering::Relaxed), 0);
+
+        // Told to be expired.
+        let session = zookeeper.session();
+        let (_lease, watch) = acquire_in(session.clone(), &leasing).await.unwrap();
+        session.set_state(SessionState::Expired);
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(watch.check(), Err(IdError::Lost { identifier: 1 }));
+    }
+
+    /// Against a real ZooKeeper at `SNOWFLAKE_TEST_ZOOKEEPER`, like `localhost:2181`.
+    #[tokio::test]
+    async fn test_zookeeper() {
+        let Ok(cluster) = std::env::var("SNOWFLAKE_TEST_ZOOKEEPER") else {
+            return;
+        };
+        let path = format!("/snowflake/test-{:x}", rand::random::<u32>());
+        let provider = ZooKeeperIdentifierProvider::new(cluster, path, Duration::from_secs(6));
+
+        let (a, b) = tokio::join!(provider.acquire(), provider.acquire());
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_ne!(a.identifier(), b.identifier());
+
+        a.release().await.unwrap();
+        let c = provider.acquire().await.unwrap();
+        assert_eq!(c.identifier(), a.identifier().max(b.identifier()) + 1);
+    }
+}
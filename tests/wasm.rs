@@ -0,0 +1,54 @@
+// Copyright 2024 Krysztal Huang
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Run in Node.js with `wasm-bindgen-test-runner` of `wasm-bindgen-cli`:
+//!
+//! ```sh
+//! CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner \
+//!     cargo test --target wasm32-unknown-unknown --no-default-features --features async,wasm --test wasm
+//! ```
+
+#![cfg(all(feature = "wasm", feature = "async", target_arch = "wasm32"))]
+
+use std::{collections::HashSet, time::Duration};
+
+use snowflake_ng::{
+    provider::{JsDateProvider, JsPerformanceProvider},
+    SnowflakeConfiguration, SnowflakeGenerator, TimeProvider,
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_js_providers() {
+    let date = JsDateProvider.timestamp();
+    let performance = JsPerformanceProvider::new();
+    assert!(performance.timestamp().abs_diff(date) <= 50);
+
+    let mut last = performance.timestamp_micros();
+    for _ in 0..1000 {
+        let now = performance.timestamp_micros();
+        assert!(now >= last);
+        last = now;
+    }
+}
+
+#[wasm_bindgen_test]
+async fn test_js_assign() {
+    let generator = SnowflakeGenerator::default();
+    let first = generator.assign(&JsDateProvider).await;
+    assert!(first.timestamp().abs_diff(JsDateProvider.timestamp()) <= 50);
+
+    // Waiting for the next tick with timers of JavaScript.
+    let generator = SnowflakeGenerator::with_cfg(
+        SnowflakeConfiguration::with_identifier(1).with_time_unit(Duration::from_millis(100)),
+    );
+    let snowflakes = generator
+        .assign_many(&JsPerformanceProvider::new(), 5000)
+        .await;
+    assert_eq!(snowflakes.iter().collect::<HashSet<_>>().len(), 5000);
+    assert!(generator.stats().exhausted_waits > 0);
+}